@@ -1,5 +1,10 @@
 use crate::brain::AgentAction;
+use crate::config::{ActionPolicy, PolicyMode};
+use crate::metrics::Metrics;
+use crate::adb::{self, AdbError, Capabilities};
+use crate::perception::{parse_foreground_activity, screen_signature, AppResolver, ElementQuery, Notification, Perception, PerceptionProvider, UiElement};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::process::Command;
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -20,16 +25,246 @@ pub struct DeviceAction {
     pub params: serde_json::Value,
 }
 
+/// A `DeviceAction` queued for the companion app, awaiting its `action_result`
+#[derive(Debug, Clone)]
+struct CompanionAction {
+    action_type: String,
+    classification: String,
+    queued_at: std::time::Instant,
+    /// When the WebSocket handler actually sent it (None = still queued)
+    sent_at: Option<std::time::Instant>,
+}
+
+/// Companion actions unacknowledged this long after sending count as lost;
+/// ones still queued this long (no companion connected) are dropped
+pub const COMPANION_ACK_TIMEOUT_SECS: u64 = 15;
+
+/// Maps UI-tree coordinates onto the touch surface: `touch = ui * scale + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateCalibration {
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub scale: f64,
+}
+
+impl Default for CoordinateCalibration {
+    fn default() -> Self {
+        Self { offset_x: 0, offset_y: 0, scale: 1.0 }
+    }
+}
+
+impl CoordinateCalibration {
+    pub fn from_config(config: &crate::config::ActionConfig) -> Self {
+        Self {
+            offset_x: config.coordinate_offset.x,
+            offset_y: config.coordinate_offset.y,
+            scale: config.coordinate_scale,
+        }
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (i64, i64) {
+        (
+            (x * self.scale).round() as i64 + self.offset_x as i64,
+            (y * self.scale).round() as i64 + self.offset_y as i64,
+        )
+    }
+
+    /// UI-tree coordinates for a real touch point (`apply` in reverse)
+    pub fn invert(&self, x: f64, y: f64) -> (i64, i64) {
+        let scale = if self.scale == 0.0 { 1.0 } else { self.scale };
+        (
+            ((x - self.offset_x as f64) / scale).round() as i64,
+            ((y - self.offset_y as f64) / scale).round() as i64,
+        )
+    }
+}
+
+/// `wait_for_text` / `wait_for_element` give up after this unless `timeout_ms` is set
+pub const DEFAULT_WAIT_TIMEOUT_MS: u64 = 10_000;
+
+/// `notification_action` gives up looking for the button in the shade after this
+pub const NOTIFICATION_ACTION_TIMEOUT_MS: u64 = 3_000;
+
+/// `notification_action` labels that wipe notifications; floored to RED like replies
+const DESTRUCTIVE_NOTIFICATION_ACTIONS: &[&str] = &["clear all", "dismiss all"];
+
+/// `open_context_menu` waits up to this long for the menu after the long press
+pub const CONTEXT_MENU_SETTLE_MS: u64 = 800;
+
+/// With `verify_taps`, how long a tap gets to change the screen before it counts as missed
+const TAP_VERIFY_SETTLE_MS: u64 = 600;
+
+/// A retried tap moves this far off a first tap that already hit the element's center
+const TAP_RETRY_JITTER_PX: i32 = 3;
+
+/// ADBKeyboard, the IME behind `text_input_method = "ime"`
+const ADB_KEYBOARD_PACKAGE: &str = "com.android.adbkeyboard";
+const ADB_KEYBOARD_IME: &str = "com.android.adbkeyboard/.AdbIME";
+
+/// Pause after switching the input method before typing into it
+const IME_SWITCH_SETTLE_MS: u64 = 300;
+
+/// `read_full_screen` stops after this many dumps unless `max_pages` is set
+pub const DEFAULT_READ_PAGES: usize = 5;
+
+/// Device folder polled for `<id>.approve` / `<id>.deny` when `confirm_via_device` is on
+pub const DEVICE_CONFIRM_DIR: &str = "/sdcard/hermitdroid/confirm";
+
+/// How often the device sentinel folder is checked
+const DEVICE_CONFIRM_POLL_SECS: u64 = 2;
+
+/// `escape_to_home` presses BACK at most this many times before falling back to HOME
+pub const DEFAULT_ESCAPE_MAX_BACKS: u32 = 5;
+
+/// What `execute` returns for an action refused by safe mode
+pub const SAFE_MODE_BLOCKED: &str = "BLOCKED (safe mode)";
+
+/// The only action types safe mode runs: they read the screen, navigate
+/// back/home, scroll, wait or talk to the user. Anything else, including
+/// types the companion app handles, is refused whatever its classification.
+pub const SAFE_MODE_ACTIONS: &[&str] = &[
+    "home",
+    "go_home",
+    "back",
+    "go_back",
+    "escape_to_home",
+    "scroll_down",
+    "scroll_up",
+    "open_notifications",
+    "wait",
+    "wait_for_text",
+    "wait_for_element",
+    "find_element",
+    "where_am_i",
+    "app_state",
+    "read_full_screen",
+    "screenshot",
+    "capture",
+    "notify_user",
+];
+
+/// Whether safe mode lets `action_type` run (see `SAFE_MODE_ACTIONS`)
+pub fn safe_mode_allows(action_type: &str) -> bool {
+    SAFE_MODE_ACTIONS.contains(&action_type.trim().to_lowercase().as_str())
+}
+
+/// What `execute` returns for an action whose classification `action.policy` blocks
+pub const POLICY_BLOCKED: &str = "BLOCKED (action.policy)";
+
+/// `execute` returns this plus the action id for an action queued for confirmation
+pub const PENDING_PREFIX: &str = "PENDING:";
+
+/// What `execute` returns for a `launch_app` of an `action.blocked_apps` package
+pub const APP_BLOCKED: &str = "BLOCKED: app is on blocklist";
+
+/// Logged result of an action whose execution returned an error
+pub const ACTION_FAILED_PREFIX: &str = "FAILED: ";
+
+/// `recent_actions` looks this many times `max` entries back for failures
+const RECENT_ACTIONS_LOOKBACK: usize = 4;
+
+/// An action outside a flow's or workflow's `allowed_actions` header
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionNotAllowed {
+    pub action: String,
+    pub allowed: Vec<String>,
+}
+
+impl std::fmt::Display for ActionNotAllowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' is not in allowed_actions [{}]", self.action, self.allowed.join(", "))
+    }
+}
+
+impl std::error::Error for ActionNotAllowed {}
+
+/// Check `action` against an optional allowlist (`None` allows everything).
+/// `done` only ends the run, so it is always allowed.
+pub fn check_allowed(allowed: Option<&[String]>, action: &str) -> Result<(), ActionNotAllowed> {
+    match allowed {
+        Some(list) if action != "done" && !list.iter().any(|a| a.trim().eq_ignore_ascii_case(action)) => {
+            Err(ActionNotAllowed { action: action.to_string(), allowed: list.to_vec() })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `capture` results carry the encoded screenshot after this prefix
+pub const SCREENSHOT_PREFIX: &str = "SCREENSHOT:";
+
+/// The base64 image in a `capture` result
+pub fn screenshot_data(result: &str) -> Option<&str> {
+    result.strip_prefix(SCREENSHOT_PREFIX)
+}
+
+/// An action result fit for logs and memory: screenshots are replaced by their size
+pub fn summarize_result(result: &str) -> std::borrow::Cow<'_, str> {
+    match screenshot_data(result) {
+        Some(data) => format!("screenshot captured ({} KB)", data.len() * 3 / 4 / 1024).into(),
+        None => result.into(),
+    }
+}
+
+/// The action log is appended here (under the workspace, one `LoggedAction`
+/// JSON per line) for `hermitdroid replay`
+pub const ACTION_LOG_FILE: &str = "action_log.jsonl";
+
+/// Past this many lines, `with_log_file` trims `ACTION_LOG_FILE` to the newest half
+const ACTION_LOG_MAX_LINES: usize = 5000;
+
 #[derive(Debug, Clone)]
 pub struct ActionExecutor {
     dry_run: bool,
     adb_device: Option<String>,
     restricted_apps: Vec<String>,
-    /// If true, RED actions execute immediately (user opted in via SOUL.md boundaries)
-    auto_confirm_red: bool,
+    /// `launch_app` of these (package substrings) is refused outright, even if confirmed
+    blocked_apps: Vec<String>,
+    /// Run, confirm or refuse each classification (`action.policy`)
+    policy: ActionPolicy,
+    /// Actions with a self-reported confidence below this are queued for confirmation
+    min_confidence: f32,
+    /// Correction applied to tap/swipe coordinates before they reach ADB
+    calibration: CoordinateCalibration,
+    /// Resolves `launch_app {"app": "youtube"}` to a package
+    app_resolver: Option<AppResolver>,
+    /// Screen access for `wait_for_text` / `wait_for_element`
+    perception: Option<Perception>,
+    /// The bridge's screenshot source for `capture` (None = ADB via `perception`)
+    provider: Option<Arc<dyn PerceptionProvider>>,
+    /// Minimum gap between device actions (zero = unthrottled)
+    min_interval: std::time::Duration,
+    /// Re-tap a clickable element once when the first tap didn't change the screen
+    verify_taps: bool,
+    /// How `type_text` enters text unless the action names a `method`
+    text_input: TextInputMethod,
+    /// Whether ADBKeyboard is installed, checked on first use
+    adb_keyboard: Arc<std::sync::OnceLock<bool>>,
+    /// What the device's shell supports, from `probe_capabilities`
+    capabilities: Arc<std::sync::OnceLock<Capabilities>>,
+    /// When the last device action finished
+    last_action_at: Arc<Mutex<Option<std::time::Instant>>>,
+    /// Held while an action runs so heartbeat polls, confirmations and
+    /// dashboard runs don't interleave commands on the device
+    device_lock: Arc<Mutex<()>>,
+    /// Title for on-device `notify_user` notifications (None = log only)
+    notify_title: Option<String>,
+    /// Ask on the phone for queued confirmations; the deadline shown there (None = dashboard only)
+    device_confirm_timeout: Option<std::time::Duration>,
+    metrics: Option<Arc<Metrics>>,
     pending: Arc<Mutex<Vec<PendingConfirmation>>>,
+    /// Pending actions that were already confirmed or denied (id → approved).
+    /// Guards against a double-confirm re-running a RED action.
+    resolved: Arc<Mutex<HashMap<String, bool>>>,
     outgoing: Arc<Mutex<Vec<DeviceAction>>>,
+    /// Companion actions by id until their `action_result` arrives or they time out
+    awaiting_ack: Arc<Mutex<HashMap<String, CompanionAction>>>,
     action_log: Arc<Mutex<Vec<ActionLogEntry>>>,
+    /// Each entry is appended here, tagged with `session` (None = memory only)
+    log_file: Option<std::path::PathBuf>,
+    /// Tells this executor's entries apart in `log_file`
+    session: String,
+    /// Refuse anything that isn't GREEN
+    safe_mode: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,24 +273,305 @@ pub struct ActionLogEntry {
     pub action_type: String,
     pub classification: String,
     pub result: String,
+    /// Params as executed (`launch_app` gets its resolved `package`), for replay
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub params: serde_json::Value,
+    /// Dry-run only: resolved target and what would have happened
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<serde_json::Value>,
+}
+
+impl ActionLogEntry {
+    /// A failed, refused or unanswered action
+    pub fn failed(&self) -> bool {
+        self.classification == "BLOCKED"
+            || self.result.starts_with(ACTION_FAILED_PREFIX)
+            || self.result.starts_with("companion failed")
+            || self.result == "no response from companion"
+    }
+
+    /// What the action was aimed at: an app, text, element index or point
+    fn target(&self) -> Option<String> {
+        let p = &self.params;
+        let text = |k: &str| p[k].as_str().filter(|s| !s.is_empty());
+        if let Some(app) = text("package").or(text("app")) {
+            return Some(app.to_string());
+        }
+        if let Some(t) = text("text").or(text("query")).or(text("action")) {
+            return Some(format!("\"{}\"", t.chars().take(40).collect::<String>()));
+        }
+        if let Some(index) = p["index"].as_u64() {
+            return Some(format!("element {}", index));
+        }
+        Some(format!("({}, {})", p["x"].as_f64()?, p["y"].as_f64()?))
+    }
+}
+
+/// One line of `ACTION_LOG_FILE`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedAction {
+    /// `ActionExecutor::session` of the executor that ran it
+    #[serde(default)]
+    pub session: String,
+    #[serde(flatten)]
+    pub entry: ActionLogEntry,
+}
+
+/// Read `ACTION_LOG_FILE`, skipping lines that don't parse. A JSON array
+/// (the older whole-log format) is read as one unnamed session.
+pub fn read_action_log(path: &std::path::Path) -> anyhow::Result<Vec<LoggedAction>> {
+    let content = std::fs::read_to_string(path)?;
+    if content.trim_start().starts_with('[') {
+        let entries: Vec<ActionLogEntry> = serde_json::from_str(&content)?;
+        return Ok(entries.into_iter().map(|entry| LoggedAction { session: String::new(), entry }).collect());
+    }
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+fn append_log_line(path: &std::path::Path, line: &LoggedAction) -> anyhow::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(line)?)?;
+    Ok(())
+}
+
+/// Keep the newest `max_lines / 2` lines of a log file past `max_lines`
+fn trim_log_file(path: &std::path::Path, max_lines: usize) -> std::io::Result<()> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.len() <= max_lines {
+        return Ok(());
+    }
+    let kept = &lines[lines.len() - max_lines / 2..];
+    std::fs::write(path, kept.join("\n") + "\n")
+}
+
+/// The tick prompt's `--- Recent Actions ---` lines: up to `max` entries,
+/// oldest first. Failures in the recent past are kept over successes so the
+/// model sees what didn't work instead of retrying it. Empty when `max` is 0.
+pub fn format_recent_actions(log: &[ActionLogEntry], max: usize) -> String {
+    let window = &log[log.len().saturating_sub(max.saturating_mul(RECENT_ACTIONS_LOOKBACK))..];
+    let newest_first = (0..window.len()).rev();
+    let mut keep: Vec<usize> = newest_first.clone().filter(|&i| window[i].failed()).take(max).collect();
+    for i in newest_first {
+        if keep.len() >= max {
+            break;
+        }
+        if !keep.contains(&i) {
+            keep.push(i);
+        }
+    }
+    keep.sort_unstable();
+    keep.into_iter()
+        .map(|i| {
+            let e = &window[i];
+            let target = e.target().map(|t| format!(" {}", t)).unwrap_or_default();
+            format!("- {}{} → {}", e.action_type, target, e.result.chars().take(120).collect::<String>())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 impl ActionExecutor {
-    pub fn new(dry_run: bool, adb_device: Option<String>, restricted_apps: Vec<String>) -> Self {
+    pub fn new(
+        dry_run: bool,
+        adb_device: Option<String>,
+        restricted_apps: Vec<String>,
+        perception: Option<Perception>,
+    ) -> Self {
         Self {
             dry_run,
             adb_device,
             restricted_apps,
-            auto_confirm_red: true, // Default: auto-confirm per SOUL.md boundary rules
+            blocked_apps: Vec::new(),
+            policy: ActionPolicy::default(),
+            min_confidence: 0.0,
+            calibration: CoordinateCalibration::default(),
+            app_resolver: None,
+            perception,
+            provider: None,
+            min_interval: std::time::Duration::ZERO,
+            verify_taps: false,
+            text_input: TextInputMethod::Fast,
+            adb_keyboard: Arc::new(std::sync::OnceLock::new()),
+            capabilities: Arc::new(std::sync::OnceLock::new()),
+            last_action_at: Arc::new(Mutex::new(None)),
+            device_lock: Arc::new(Mutex::new(())),
+            notify_title: None,
+            device_confirm_timeout: None,
+            metrics: None,
             pending: Arc::new(Mutex::new(Vec::new())),
+            resolved: Arc::new(Mutex::new(HashMap::new())),
             outgoing: Arc::new(Mutex::new(Vec::new())),
+            awaiting_ack: Arc::new(Mutex::new(HashMap::new())),
             action_log: Arc::new(Mutex::new(Vec::new())),
+            log_file: None,
+            session: chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string(),
+            safe_mode: false,
+        }
+    }
+
+    pub fn with_min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: CoordinateCalibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    pub fn with_app_resolver(mut self, resolver: AppResolver) -> Self {
+        self.app_resolver = Some(resolver);
+        self
+    }
+
+    pub fn with_perception_provider(mut self, provider: Arc<dyn PerceptionProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: ActionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_min_interval_ms(mut self, ms: u64) -> Self {
+        self.min_interval = std::time::Duration::from_millis(ms);
+        self
+    }
+
+    pub fn with_verify_taps(mut self, enabled: bool) -> Self {
+        self.verify_taps = enabled;
+        self
+    }
+
+    pub fn with_text_input_method(mut self, method: TextInputMethod) -> Self {
+        self.text_input = method;
+        self
+    }
+
+    /// Whether ADBKeyboard (needed by the `ime` text input method) is on the
+    /// device. Checked once; warns when it's missing.
+    pub fn adb_keyboard_installed(&self) -> bool {
+        *self.adb_keyboard.get_or_init(|| {
+            let listed = format!("package:{}", ADB_KEYBOARD_PACKAGE);
+            let installed = self
+                .adb(&["shell", "pm", "list", "packages", ADB_KEYBOARD_PACKAGE])
+                .is_ok_and(|out| out.lines().any(|l| l.trim() == listed));
+            if !installed {
+                warn!(
+                    "⌨️  text_input_method \"ime\" needs the ADBKeyboard app ({}) — typing with `input text` instead",
+                    ADB_KEYBOARD_PACKAGE
+                );
+            }
+            installed
+        })
+    }
+
+    /// Run the `Capabilities` probe once and keep the result. An unreachable
+    /// device leaves everything assumed to work. `capabilities` runs it on
+    /// first use, so only a startup log needs to call this.
+    pub fn probe_capabilities(&self) -> Capabilities {
+        match Capabilities::probe(&self.adb_device) {
+            Ok(caps) => {
+                if caps.list().iter().all(|(_, ok)| *ok) {
+                    info!("🔧 Device capabilities: {}", caps.summary());
+                } else {
+                    warn!("🔧 Device capabilities: {} — using fallbacks", caps.summary());
+                }
+                self.capabilities.get_or_init(|| caps);
+                caps
+            }
+            Err(e) => {
+                warn!("🔧 Could not probe device capabilities: {}", e);
+                *self.capabilities.get_or_init(Capabilities::default)
+            }
+        }
+    }
+
+    /// What the device supports, probed on first use (everything in a dry run)
+    pub fn capabilities(&self) -> Capabilities {
+        if self.dry_run {
+            return Capabilities::default();
+        }
+        match self.capabilities.get() {
+            Some(caps) => *caps,
+            None => self.probe_capabilities(),
         }
     }
 
+    /// `type_text`'s `method` param, else `action.text_input_method`
+    fn text_input_for(&self, p: &serde_json::Value) -> TextInputMethod {
+        match p["method"].as_str() {
+            Some(name) => TextInputMethod::parse(name).unwrap_or_else(|| {
+                warn!("type_text: unknown method '{}', using {:?}", name, self.text_input);
+                self.text_input
+            }),
+            None => self.text_input,
+        }
+    }
+
+    /// Post `notify_user` messages as Android notifications titled `title`
+    /// (None keeps the log-only behaviour)
+    pub fn with_device_notifications(mut self, title: Option<String>) -> Self {
+        self.notify_title = title;
+        self
+    }
+
+    /// Prompt on the phone for pending confirmations (see `DEVICE_CONFIRM_DIR`).
+    /// `timeout_secs` is only shown in the prompt; expiry is `expire_stale`'s job.
+    /// `None` leaves them to the dashboard.
+    pub fn with_device_confirmation(mut self, timeout_secs: Option<u64>) -> Self {
+        self.device_confirm_timeout = timeout_secs.map(std::time::Duration::from_secs);
+        self
+    }
+
+    /// Append the action log to `path` (see `ACTION_LOG_FILE`), one line per
+    /// entry, under this executor's session id. A file past
+    /// `ACTION_LOG_MAX_LINES` is trimmed first.
+    pub fn with_log_file(mut self, path: std::path::PathBuf) -> Self {
+        if let Err(e) = trim_log_file(&path, ACTION_LOG_MAX_LINES) {
+            warn!("Could not trim {}: {}", path.display(), e);
+        }
+        self.log_file = Some(path);
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_blocked_apps(mut self, blocked_apps: Vec<String>) -> Self {
+        self.blocked_apps = blocked_apps;
+        self
+    }
+
+    /// Block every YELLOW and RED action instead of running or queuing it
+    pub fn with_safe_mode(mut self, safe_mode: bool) -> Self {
+        self.safe_mode = safe_mode;
+        self
+    }
+
+    /// The tap calibration for coordinates read off the current screen: the
+    /// global one, or none when a per-app profile (`workspace/calibration.toml`)
+    /// was already applied to that screen — the profile replaces it there
+    pub fn calibration(&self) -> CoordinateCalibration {
+        match self.perception.as_ref().and_then(Perception::screen_calibration) {
+            Some(_) => CoordinateCalibration::default(),
+            None => self.calibration,
+        }
+    }
     pub fn pending(&self) -> Arc<Mutex<Vec<PendingConfirmation>>> { self.pending.clone() }
     pub fn outgoing(&self) -> Arc<Mutex<Vec<DeviceAction>>> { self.outgoing.clone() }
     pub fn action_log(&self) -> Arc<Mutex<Vec<ActionLogEntry>>> { self.action_log.clone() }
+    /// Take this around any other burst of device commands (screen polls, flow runs)
+    pub fn device_lock(&self) -> Arc<Mutex<()>> { self.device_lock.clone() }
 
     pub async fn execute_raw(&self, action_type: &str, adb_device: &Option<String>) -> anyhow::Result<String> {
         if self.dry_run {
@@ -78,7 +594,7 @@ impl ActionExecutor {
             other => anyhow::bail!("Unknown raw action: {}", other),
         }
 
-        let out = cmd.output()?;
+        let out = adb::output(&mut cmd)?;
         if out.status.success() {
             Ok(format!("{} OK", action_type))
         } else {
@@ -88,122 +604,463 @@ impl ActionExecutor {
 
     /// Execute an action with guardrail enforcement
     pub async fn execute(&self, action: &AgentAction) -> anyhow::Result<String> {
+        let action = &*self.resolve_launch(action).await;
         let id = uuid::Uuid::new_v4().to_string()[..8].to_string();
         let classification = self.effective_classification(action);
 
-        match classification.as_str() {
-            "RED" => {
-                // Check if this involves a restricted app → always queue
-                if let Some(pkg) = action.params.get("package").and_then(|v| v.as_str()) {
-                    if self.restricted_apps.iter().any(|a| pkg.contains(a)) {
-                        self.pending.lock().await.push(PendingConfirmation {
-                            action_id: id.clone(),
-                            action: action.clone(),
-                            timestamp: chrono::Utc::now().to_rfc3339(),
-                            confirmed: None,
-                        });
-                        info!("[RED-RESTRICTED] Queued for confirmation: {} ({})", action.action_type, id);
-                        return Ok(format!("PENDING:{}", id));
-                    }
-                }
+        if let Some(refused) = self.refuse(action, &classification).await {
+            return Ok(refused);
+        }
 
-                // Auto-confirm if enabled (SOUL.md says "send messages without confirmation")
-                if self.auto_confirm_red {
-                    info!("[RED-AUTO] {}: {}", action.action_type, action.reason);
-                    if self.dry_run {
-                        return self.log_dry_run(action, &classification).await;
-                    }
-                    let result = self.do_action(action, &id).await?;
-                    self.log_action(action, "RED-AUTO", &result).await;
-                    return Ok(result);
-                }
+        if self.dry_run {
+            return self.log_dry_run(action, &classification).await;
+        }
 
-                // Otherwise queue for manual confirmation
-                self.pending.lock().await.push(PendingConfirmation {
-                    action_id: id.clone(),
-                    action: action.clone(),
-                    timestamp: chrono::Utc::now().to_rfc3339(),
-                    confirmed: None,
-                });
-                info!("[RED] Queued for confirmation: {} ({})", action.action_type, id);
-                Ok(format!("PENDING:{}", id))
+        // The model isn't sure about this one — let the user decide
+        if action.confidence < self.min_confidence {
+            self.queue_confirmation(action, &id).await;
+            info!(
+                "[LOW-CONFIDENCE] Queued for confirmation: {} ({:.2} < {:.2}) ({})",
+                action.action_type, action.confidence, self.min_confidence, id
+            );
+            return Ok(format!("{}{}", PENDING_PREFIX, id));
+        }
+
+        let Some(mode) = self.policy.mode(&classification) else {
+            warn!("Unknown classification '{}', treating as RED", classification);
+            return Ok("BLOCKED".into());
+        };
+        match mode {
+            // Refused by `refuse` above
+            PolicyMode::Block => Ok(POLICY_BLOCKED.to_string()),
+            // Restricted apps are confirmed even when the policy would run them
+            PolicyMode::Auto if self.is_restricted(action) => {
+                self.queue_confirmation(action, &id).await;
+                info!("[{}-RESTRICTED] Queued for confirmation: {} ({})", classification, action.action_type, id);
+                Ok(format!("{}{}", PENDING_PREFIX, id))
             }
-            "YELLOW" => {
-                info!("[YELLOW] {}: {}", action.action_type, action.reason);
-                if self.dry_run {
-                    return self.log_dry_run(action, &classification).await;
-                }
-                let result = self.do_action(action, &id).await?;
-                self.log_action(action, &classification, &result).await;
-                Ok(result)
+            PolicyMode::Confirm => {
+                self.queue_confirmation(action, &id).await;
+                info!("[{}] Queued for confirmation: {} ({})", classification, action.action_type, id);
+                Ok(format!("{}{}", PENDING_PREFIX, id))
             }
-            "GREEN" => {
-                if self.dry_run {
-                    return self.log_dry_run(action, &classification).await;
-                }
-                let result = self.do_action(action, &id).await?;
-                self.log_action(action, &classification, &result).await;
-                Ok(result)
+            PolicyMode::Auto if classification == "RED" => {
+                info!("[RED-AUTO] {}: {}", action.action_type, action.reason);
+                self.run_logged(action, &id, "RED-AUTO").await
             }
-            _ => {
-                warn!("Unknown classification '{}', treating as RED", classification);
-                Ok("BLOCKED".into())
+            PolicyMode::Auto => {
+                if classification == "YELLOW" {
+                    info!("[YELLOW] {}: {}", action.action_type, action.reason);
+                }
+                self.run_logged(action, &id, &classification).await
             }
         }
     }
 
-    /// Confirm a pending RED action
+    /// Refuse (and log) an action the blocklist, safe mode or `action.policy`
+    /// forbids. Runs before anything is queued, and again when a queued
+    /// action is confirmed, in case one of them changed.
+    async fn refuse(&self, action: &AgentAction, classification: &str) -> Option<String> {
+        let (tag, result) = if self.is_blocked_launch(action) {
+            ("BLOCKLIST", APP_BLOCKED)
+        } else if self.safe_mode && (classification != "GREEN" || !safe_mode_allows(&action.action_type)) {
+            ("SAFE-MODE", SAFE_MODE_BLOCKED)
+        } else if self.policy.mode(classification) == Some(PolicyMode::Block) {
+            ("POLICY", POLICY_BLOCKED)
+        } else {
+            return None;
+        };
+        warn!("[{}] Refused {} ({}) of {:?}: {}", tag, action.action_type, classification, target_app(action), action.reason);
+        self.log_action(action, "BLOCKED", result).await;
+        Some(result.to_string())
+    }
+
+    /// The action targets an `action.restricted_apps` package. Like
+    /// `is_blocked_launch`, a `launch_app` is also checked by the package its
+    /// name resolves to.
+    fn is_restricted(&self, action: &AgentAction) -> bool {
+        if self.restricted_apps.is_empty() {
+            return false;
+        }
+        let restricted = |name: &str| self.restricted_apps.iter().any(|a| name.contains(a.as_str()));
+        target_app(action).is_some_and(restricted)
+            || (action.action_type == "launch_app" && self.launch_package(action).is_ok_and(|pkg| restricted(&pkg)))
+    }
+
+    /// Confirm a pending action, logged as `<classification>-CONFIRMED`.
+    /// The entry is removed from the queue before executing, so a second
+    /// confirm of the same id (double-click, client retry) is rejected.
     pub async fn confirm(&self, action_id: &str, approved: bool) -> anyhow::Result<String> {
         let mut pending = self.pending.lock().await;
-        if let Some(p) = pending.iter_mut().find(|p| p.action_id == action_id) {
-            p.confirmed = Some(approved);
-            if approved {
-                let action = p.action.clone();
-                drop(pending);
-                let id = action_id.to_string();
-                let result = self.do_action(&action, &id).await?;
-                self.log_action(&action, "RED-CONFIRMED", &result).await;
-                Ok(result)
+        let mut resolved = self.resolved.lock().await;
+
+        if let Some(&was_approved) = resolved.get(action_id) {
+            warn!("Ignoring repeated confirmation for {}", action_id);
+            if was_approved {
+                anyhow::bail!("Action {} already executed", action_id);
             } else {
-                Ok("DENIED".into())
+                anyhow::bail!("Action {} already denied", action_id);
             }
+        }
+
+        let pos = match pending.iter().position(|p| p.action_id == action_id) {
+            Some(pos) => pos,
+            None => anyhow::bail!("No pending action: {}", action_id),
+        };
+        let mut p = pending.remove(pos);
+        p.confirmed = Some(approved);
+        resolved.insert(action_id.to_string(), approved);
+        drop(resolved);
+        drop(pending);
+
+        if !approved {
+            return Ok("DENIED".into());
+        }
+        let classification = self.effective_classification(&p.action);
+        match self.refuse(&p.action, &classification).await {
+            Some(refused) => Ok(refused),
+            None => self.run_logged(&p.action, action_id, &format!("{}-CONFIRMED", classification)).await,
+        }
+    }
+
+    /// Deny and remove pending confirmations queued more than `timeout` ago.
+    /// Returns the expired entries (with `confirmed: Some(false)`).
+    pub async fn expire_stale(&self, timeout: std::time::Duration) -> Vec<PendingConfirmation> {
+        let now = chrono::Utc::now();
+        let mut pending = self.pending.lock().await;
+        let mut resolved = self.resolved.lock().await;
+        let (stale, fresh): (Vec<_>, Vec<_>) = pending.drain(..).partition(|p| {
+            chrono::DateTime::parse_from_rfc3339(&p.timestamp)
+                .ok()
+                .and_then(|t| (now - t.with_timezone(&chrono::Utc)).to_std().ok())
+                .is_some_and(|age| age >= timeout)
+        });
+        *pending = fresh;
+        stale
+            .into_iter()
+            .map(|mut p| {
+                info!("⏱ Confirmation {} ({}) expired — denied", p.action_id, p.action.action_type);
+                resolved.insert(p.action_id.clone(), false);
+                p.confirmed = Some(false);
+                p
+            })
+            .collect()
+    }
+
+    /// The WebSocket handler sent `id` to the companion; its ack clock starts now
+    pub async fn mark_sent(&self, id: &str) {
+        if let Some(a) = self.awaiting_ack.lock().await.get_mut(id) {
+            a.sent_at = Some(std::time::Instant::now());
+        }
+    }
+
+    /// Match the companion's `action_result` to the action it answers and log
+    /// the outcome. Returns the action type, or None for an unknown id.
+    pub async fn acknowledge(&self, id: &str, success: bool, message: &str) -> Option<String> {
+        let action = self.awaiting_ack.lock().await.remove(id)?;
+        let result = if success {
+            format!("companion ok: {}", message)
         } else {
-            anyhow::bail!("No pending action: {}", action_id)
+            warn!("Companion action {} ({}) failed: {}", id, action.action_type, message);
+            format!("companion failed: {}", message)
+        };
+        self.log_companion(&action, result).await;
+        Some(action.action_type)
+    }
+
+    /// Drop companion actions sent more than `timeout` ago without an
+    /// `action_result`, logging each as "no response from companion", and
+    /// ones queued that long with no companion connected to send them to, so
+    /// a companion that connects later doesn't run stale actions.
+    /// Returns `(id, action_type)` of the expired ones.
+    pub async fn expire_unacknowledged(&self, timeout: std::time::Duration) -> Vec<(String, String)> {
+        let lost: Vec<(String, CompanionAction)> = {
+            // Same lock order as the WebSocket handler: outgoing, then awaiting
+            let mut outgoing = self.outgoing.lock().await;
+            let mut awaiting = self.awaiting_ack.lock().await;
+            let ids: Vec<String> = awaiting
+                .iter()
+                .filter(|(_, a)| a.sent_at.unwrap_or(a.queued_at).elapsed() >= timeout)
+                .map(|(id, _)| id.clone())
+                .collect();
+            outgoing.retain(|a| !ids.contains(&a.id));
+            ids.into_iter().filter_map(|id| awaiting.remove(&id).map(|a| (id, a))).collect()
+        };
+        let mut expired = Vec::new();
+        for (id, action) in lost {
+            let result = if action.sent_at.is_some() {
+                warn!("⏱ No response from companion for {} ({})", id, action.action_type);
+                "no response from companion"
+            } else {
+                warn!("⏱ Dropped {} ({}): no companion connected", id, action.action_type);
+                "not sent: no companion connected"
+            };
+            self.log_companion(&action, result.into()).await;
+            expired.push((id, action.action_type));
+        }
+        expired
+    }
+
+    async fn log_companion(&self, action: &CompanionAction, result: String) {
+        self.push_log(ActionLogEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            action_type: action.action_type.clone(),
+            classification: action.classification.clone(),
+            result,
+            params: serde_json::Value::Null,
+            preview: None,
+        }).await;
+    }
+
+    async fn push_log(&self, entry: ActionLogEntry) {
+        if let Some(path) = &self.log_file {
+            let line = LoggedAction { session: self.session.clone(), entry: entry.clone() };
+            if let Err(e) = append_log_line(path, &line) {
+                warn!("Could not write {}: {}", path.display(), e);
+            }
+        }
+        self.action_log.lock().await.push(entry);
+    }
+
+    /// Add to the pending queue, and ask on the phone too if `confirm_via_device` is on
+    async fn queue_confirmation(&self, action: &AgentAction, id: &str) {
+        self.pending.lock().await.push(PendingConfirmation {
+            action_id: id.to_string(),
+            action: action.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            confirmed: None,
+        });
+        if let Some(timeout) = self.device_confirm_timeout {
+            let this = self.clone();
+            let (action, id) = (action.clone(), id.to_string());
+            tokio::spawn(async move { this.confirm_on_device(&action, &id, timeout).await });
+        }
+    }
+
+    /// Post an approve/deny prompt on the phone and poll `DEVICE_CONFIRM_DIR`
+    /// until the user answers there, or the entry leaves the queue (dashboard
+    /// answer, or `expire_stale` after `timeout`).
+    async fn confirm_on_device(&self, action: &AgentAction, id: &str, timeout: std::time::Duration) {
+        let tag = format!("hermitdroid-{}", id);
+        let title = self.notify_title.clone().unwrap_or_else(|| "Hermitdroid".into());
+        let mut prompt = format!(
+            "Approve {}? {} — create {}/{}.approve (or .deny)",
+            action.action_type, action.reason, DEVICE_CONFIRM_DIR, id
+        );
+        if !timeout.is_zero() {
+            prompt.push_str(&format!(" within {}s", timeout.as_secs()));
+        }
+        self.adb(&["shell", "mkdir", "-p", DEVICE_CONFIRM_DIR]).ok();
+        if let Err(e) = self.post_notification(&tag, &title, &prompt) {
+            warn!("Could not post confirmation prompt for {}: {}", id, e);
+        }
+
+        let answer = loop {
+            if !self.pending.lock().await.iter().any(|p| p.action_id == id) {
+                break None; // resolved from the dashboard or expired
+            }
+            let listing = self.adb(&["shell", "ls", DEVICE_CONFIRM_DIR]).unwrap_or_default();
+            if let Some(approved) = sentinel_answer(&listing, id) {
+                info!("📱 Confirmation {} {} on device", id, if approved { "approved" } else { "denied" });
+                break Some(approved);
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(DEVICE_CONFIRM_POLL_SECS)).await;
+        };
+
+        if let Some(approved) = answer {
+            if let Err(e) = self.confirm(id, approved).await {
+                warn!("Device confirmation {} failed: {}", id, e);
+            }
+        }
+        for ext in ["approve", "deny"] {
+            self.adb(&["shell", "rm", "-f", &format!("{}/{}.{}", DEVICE_CONFIRM_DIR, id, ext)]).ok();
+        }
+        self.adb(&["shell", "cmd", "notification", "cancel", &tag]).ok();
+    }
+
+    /// `launch_app` of a blocked package, checked against both the requested
+    /// name and the package it resolves to ("my bank" → "com.example.banking")
+    fn is_blocked_launch(&self, action: &AgentAction) -> bool {
+        action.action_type == "launch_app" && target_app(action).is_some_and(|name| self.is_blocked_app(name))
+    }
+
+    /// Whether `action.blocked_apps` forbids launching `name`, a package or a
+    /// friendly name (checked as written and as resolved). For launch paths
+    /// that don't go through `execute`, such as flows.
+    pub fn is_blocked_app(&self, name: &str) -> bool {
+        if self.blocked_apps.is_empty() {
+            return false;
         }
+        let blocked = |name: &str| self.blocked_apps.iter().any(|a| name.contains(a.as_str()));
+        blocked(name)
+            || self
+                .app_resolver
+                .as_ref()
+                .and_then(|r| r.resolve_package(name))
+                .is_some_and(|pkg| blocked(&pkg))
     }
 
     /// Determine effective classification (may upgrade to RED based on restricted apps)
-    fn effective_classification(&self, action: &AgentAction) -> String {
+    pub fn effective_classification(&self, action: &AgentAction) -> String {
         let base = action.classification.to_uppercase();
         // Force RED for restricted apps
-        if let Some(pkg) = action.params.get("package").and_then(|v| v.as_str()) {
-            if self.restricted_apps.iter().any(|a| pkg.contains(a)) {
-                return "RED".into();
-            }
+        if self.is_restricted(action) {
+            return "RED".into();
+        }
+        // Settings toggles are never GREEN, whatever the model says
+        match (action.action_type.as_str(), base.as_str()) {
+            ("set_airplane", _) => "RED".into(),
+            // Sending a reply or wiping notifications can't be undone
+            ("notification_action", _) if is_destructive_notification_action(&action.params) => "RED".into(),
+            ("notification_action", "GREEN") => "YELLOW".into(),
+            ("set_wifi" | "set_bluetooth" | "set_brightness", "GREEN") => "YELLOW".into(),
+            _ => base,
         }
-        base
     }
 
     async fn log_dry_run(&self, action: &AgentAction, class: &str) -> anyhow::Result<String> {
-        let msg = format!("[DRY_RUN] {} ({})", action.action_type, class);
+        let (target, summary) = self.resolve_target(action);
+        let disposition = self.dry_run_disposition(action, class);
+        let msg = format!(
+            "[DRY_RUN] {} ({}){} — {}",
+            action.action_type, class, summary, disposition
+        );
         info!("{}", msg);
-        self.log_action(action, class, "DRY_RUN").await;
+        self.push_log(ActionLogEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            action_type: action.action_type.clone(),
+            classification: class.to_string(),
+            result: "DRY_RUN".into(),
+            params: action.params.clone(),
+            preview: Some(serde_json::json!({
+                "target": target,
+                "disposition": disposition,
+                "confidence": action.confidence,
+                "reason": action.reason,
+            })),
+        }).await;
         Ok(msg)
     }
 
+    /// What a live run would do with this action, given the guardrails
+    fn dry_run_disposition(&self, action: &AgentAction, class: &str) -> &'static str {
+        if action.confidence < self.min_confidence {
+            return "would queue for confirmation (low confidence)";
+        }
+        match self.policy.mode(class) {
+            None => "would be blocked (unknown classification)",
+            Some(PolicyMode::Block) => "would be blocked (action.policy)",
+            Some(PolicyMode::Auto) if self.is_restricted(action) => "would queue for confirmation (restricted app)",
+            Some(PolicyMode::Confirm) => "would queue for confirmation",
+            Some(PolicyMode::Auto) if class == "RED" => "would auto-confirm",
+            Some(PolicyMode::Auto) => "would execute",
+        }
+    }
+
+    /// Resolved target of an action (calibrated coordinates, package, text),
+    /// as JSON for the log plus a short suffix for console output.
+    fn resolve_target(&self, action: &AgentAction) -> (serde_json::Value, String) {
+        let p = &action.params;
+        match action.action_type.as_str() {
+            "long_press" | "open_context_menu" if matches!(PressTarget::from_params(p), Some(PressTarget::Element(_))) => {
+                (serde_json::json!({"index": p["index"]}), format!(" → [{}]", p["index"]))
+            }
+            "tap" | "long_press" | "open_context_menu" => {
+                let (x, y) = self.calibration().apply(
+                    p["x"].as_f64().unwrap_or(0.0),
+                    p["y"].as_f64().unwrap_or(0.0),
+                );
+                (serde_json::json!({"x": x, "y": y}), format!(" → ({}, {})", x, y))
+            }
+            "swipe" => {
+                let (x1, y1) = self.calibration().apply(
+                    p["x1"].as_f64().unwrap_or(0.0),
+                    p["y1"].as_f64().unwrap_or(0.0),
+                );
+                let (x2, y2) = self.calibration().apply(
+                    p["x2"].as_f64().unwrap_or(0.0),
+                    p["y2"].as_f64().unwrap_or(0.0),
+                );
+                (
+                    serde_json::json!({"from": [x1, y1], "to": [x2, y2]}),
+                    format!(" → ({}, {})→({}, {})", x1, y1, x2, y2),
+                )
+            }
+            "launch_app" => {
+                let pkg = self.launch_package(action).unwrap_or_else(|e| e.to_string());
+                (serde_json::json!({"package": pkg}), format!(" → {}", pkg))
+            }
+            "type_text" => {
+                let text = p["text"].as_str().unwrap_or("");
+                let via = match self.text_input_for(p) {
+                    TextInputMethod::Fast if needs_clipboard(text) => "clipboard",
+                    TextInputMethod::Fast => "input",
+                    TextInputMethod::Ime => "ime",
+                    TextInputMethod::Clipboard => "clipboard",
+                };
+                let into = p["index"].as_u64().map(|i| format!(" into [{}]", i)).unwrap_or_default();
+                (
+                    serde_json::json!({"text": text, "via": via, "index": p["index"]}),
+                    format!(" → \"{}\"{} (via {})", text, into, via),
+                )
+            }
+            "press_key" => {
+                let key = p["key"].as_str().unwrap_or("KEYCODE_HOME");
+                (serde_json::json!({"key": key}), format!(" → {}", key))
+            }
+            _ => (p.clone(), String::new()),
+        }
+    }
+
+    /// `do_action`, logging the outcome under `class`. Failures are logged too
+    /// (`ACTION_FAILED_PREFIX`) so the next tick's recent actions show them.
+    async fn run_logged(&self, action: &AgentAction, id: &str, class: &str) -> anyhow::Result<String> {
+        let _device = self.device_lock.lock().await;
+        // A repeated tap must not double a RED or a confirmed action
+        let may_retry = class == "GREEN" || class == "YELLOW";
+        match self.do_action(action, id, may_retry).await {
+            Ok(result) => {
+                self.log_action(action, class, &result).await;
+                Ok(result)
+            }
+            Err(e) => {
+                self.log_action(action, class, &format!("{}{}", ACTION_FAILED_PREFIX, e)).await;
+                Err(e)
+            }
+        }
+    }
+
     async fn log_action(&self, action: &AgentAction, class: &str, result: &str) {
-        self.action_log.lock().await.push(ActionLogEntry {
+        if let Some(m) = &self.metrics {
+            m.record_action(class);
+        }
+        let mut params = action.params.clone();
+        if action.action_type == "launch_app" {
+            if params.is_null() {
+                params = serde_json::json!({});
+            }
+            if let (Some(obj), Ok(pkg)) = (params.as_object_mut(), self.launch_package(action)) {
+                obj.insert("package".into(), pkg.into());
+            }
+        }
+        self.push_log(ActionLogEntry {
             timestamp: chrono::Utc::now().to_rfc3339(),
             action_type: action.action_type.clone(),
             classification: class.to_string(),
-            result: result.to_string(),
-        });
+            result: summarize_result(result).into_owned(),
+            params,
+            preview: None,
+        }).await;
     }
 
     /// Wait for the screen to settle after an action.
     /// Polls the foreground activity — if it changes, the UI transitioned.
     /// Returns early if transition detected, otherwise waits max_ms.
     async fn wait_for_settle(&self, max_ms: u64) {
+        if !self.capabilities().dumpsys_activity {
+            tokio::time::sleep(tokio::time::Duration::from_millis(max_ms)).await;
+            return;
+        }
         // Get current foreground app
         let before = self.adb(&["shell", "dumpsys", "activity", "activities"])
             .ok()
@@ -240,77 +1097,95 @@ impl ActionExecutor {
         tracing::debug!("Screen settle timeout after {}ms", start.elapsed().as_millis());
     }
 
+    /// Run one device action, spaced at least `min_interval` after the previous
+    /// one. `may_retry` lets `verify_taps` tap a second time.
+    async fn do_action(&self, action: &AgentAction, id: &str, may_retry: bool) -> anyhow::Result<String> {
+        if !self.min_interval.is_zero() {
+            let last = *self.last_action_at.lock().await;
+            if let Some(wait) = last.and_then(|t| self.min_interval.checked_sub(t.elapsed())) {
+                info!("⏱ Throttling {} for {}ms (action.min_interval_ms)", action.action_type, wait.as_millis());
+                tokio::time::sleep(wait).await;
+            }
+        }
+        let result = self.route_action(action, id, may_retry).await;
+        *self.last_action_at.lock().await = Some(std::time::Instant::now());
+        result
+    }
+
     /// Route action to the correct executor
-    async fn do_action(&self, action: &AgentAction, id: &str) -> anyhow::Result<String> {
+    async fn route_action(&self, action: &AgentAction, id: &str, may_retry: bool) -> anyhow::Result<String> {
         let p = &action.params;
         match action.action_type.as_str() {
             // --- Screen interactions ---
             "tap" => {
-                let result = self.adb(&["shell", "input", "tap",
-                    &p["x"].as_f64().unwrap_or(0.0).to_string(),
-                    &p["y"].as_f64().unwrap_or(0.0).to_string()]);
+                let (x, y) = self.point(p, "x", "y");
+                let verify = may_retry && self.verify_taps && self.capabilities().uiautomator;
+                if let Some(perception) = self.perception.as_ref().filter(|_| verify) {
+                    return self.verified_tap(perception, p, &x, &y).await;
+                }
+                let result = self.adb(&["shell", "input", "tap", &x, &y]);
                 // Reactive settle: wait until screen changes or 200ms max
                 self.wait_for_settle(200).await;
                 result
             }
 
             "long_press" => {
-                let x = p["x"].as_f64().unwrap_or(0.0);
-                let y = p["y"].as_f64().unwrap_or(0.0);
+                let (x, y) = self.press_point(&action.action_type, p).await?;
                 let ms = p["ms"].as_u64().unwrap_or(1000);
                 // Long press = swipe from same point to same point with duration
                 self.adb(&["shell", "input", "swipe",
-                    &x.to_string(), &y.to_string(),
-                    &x.to_string(), &y.to_string(),
+                    &x, &y,
+                    &x, &y,
                     &ms.to_string()])
             }
 
-            "swipe" => self.adb(&["shell", "input", "swipe",
-                &p["x1"].as_f64().unwrap_or(0.0).to_string(),
-                &p["y1"].as_f64().unwrap_or(0.0).to_string(),
-                &p["x2"].as_f64().unwrap_or(0.0).to_string(),
-                &p["y2"].as_f64().unwrap_or(0.0).to_string(),
-                &p.get("ms").or(p.get("duration_ms"))
-                    .and_then(|v| v.as_u64())
-                    .unwrap_or(300).to_string()]),
+            // --- Long press, then give the context menu / selection bar time to draw ---
+            "open_context_menu" => {
+                let (x, y) = self.press_point(&action.action_type, p).await?;
+                let ms = p["ms"].as_u64().unwrap_or(1000);
+                self.adb(&["shell", "input", "swipe", &x, &y, &x, &y, &ms.to_string()])?;
+                self.wait_for_settle(CONTEXT_MENU_SETTLE_MS).await;
+                if let Some(perception) = &self.perception {
+                    perception.invalidate_screen_cache();
+                }
+                Ok(format!("opened context menu at ({}, {})", x, y))
+            }
+
+            "swipe" => {
+                let (x1, y1) = self.point(p, "x1", "y1");
+                let (x2, y2) = self.point(p, "x2", "y2");
+                self.adb(&["shell", "input", "swipe",
+                    &x1, &y1,
+                    &x2, &y2,
+                    &p.get("ms").or(p.get("duration_ms"))
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(300).to_string()])
+            }
 
             // --- Text input ---
             "type_text" => {
-                // Brief settle to ensure field is focused
-                self.wait_for_settle(150).await;
                 let text = p["text"].as_str().unwrap_or("");
                 if text.is_empty() {
                     return Ok("type_text: empty text, skipped".into());
                 }
+                self.focus_for_typing(p).await?;
+                self.type_with(self.text_input_for(p), text).await
+            }
 
-                // Try ADB input text first (works for simple alphanumeric)
-                let escaped = text
-                    .replace('\\', "\\\\")
-                    .replace(' ', "%s")
-                    .replace('&', "\\&")
-                    .replace('<', "\\<")
-                    .replace('>', "\\>")
-                    .replace('|', "\\|")
-                    .replace(';', "\\;")
-                    .replace('(', "\\(")
-                    .replace(')', "\\)")
-                    .replace('\'', "\\'")
-                    .replace('"', "\\\"")
-                    .replace('$', "\\$")
-                    .replace('`', "\\`");
-
-                match self.adb(&["shell", "input", "text", &escaped]) {
-                    Ok(result) => Ok(result),
-                    Err(_) => {
-                        // Fallback: use ADB broadcast to type via clipboard
-                        warn!("input text failed, trying broadcast fallback for: {}", text);
-                        // Set clipboard and paste
-                        let _ = self.adb(&["shell", "input", "keyevent", "KEYCODE_MOVE_HOME"]);
-                        // Use am broadcast with the text
-                        self.adb(&["shell", "am", "broadcast", "-a",
-                            "ADB_INPUT_TEXT", "--es", "msg", text])
-                    }
+            // --- Clipboard ---
+            "set_clipboard" => {
+                let text = p["text"].as_str().unwrap_or("");
+                self.set_clipboard(text)
+            }
+
+            "paste" => {
+                // Optional {x, y}: tap the field first so it has focus
+                if p.get("x").is_some() && p.get("y").is_some() {
+                    let (x, y) = self.point(p, "x", "y");
+                    self.adb(&["shell", "input", "tap", &x, &y])?;
+                    self.wait_for_settle(150).await;
                 }
+                self.adb(&["shell", "input", "keyevent", "KEYCODE_PASTE"])
             }
 
             // --- Key events ---
@@ -321,8 +1196,8 @@ impl ActionExecutor {
 
             // --- App management ---
             "launch_app" => {
-                let pkg = p["package"].as_str().unwrap_or("");
-                let result = self.adb(&["shell", "monkey", "-p", pkg, "-c", "android.intent.category.LAUNCHER", "1"]);
+                let pkg = self.launch_package(action)?;
+                let result = self.adb(&["shell", "monkey", "-p", &pkg, "-c", "android.intent.category.LAUNCHER", "1"]);
                 // Reactive settle: wait for app to load (up to 800ms)
                 self.wait_for_settle(800).await;
                 result
@@ -339,7 +1214,7 @@ impl ActionExecutor {
                 self.adb(&["shell", "input", "keyevent", "KEYCODE_APP_SWITCH"]),
 
             "open_notifications" =>
-                self.adb(&["shell", "cmd", "statusbar", "expand-notifications"]),
+                self.expand_notifications(),
 
             "scroll_down" =>
                 self.adb(&["shell", "input", "swipe", "540", "1500", "540", "500", "300"]),
@@ -347,30 +1222,117 @@ impl ActionExecutor {
             "scroll_up" =>
                 self.adb(&["shell", "input", "swipe", "540", "500", "540", "1500", "300"]),
 
-            // --- Timing ---
+            "escape_to_home" => {
+                let max_backs = p["max_backs"].as_u64().map(|n| n as u32).unwrap_or(DEFAULT_ESCAPE_MAX_BACKS);
+                let backs = self.escape_to_home(max_backs).await?;
+                Ok(format!("on home screen after {} back(s)", backs))
+            }
+
+            // --- Timing ---
             "wait" => {
                 let ms = p["ms"].as_u64().unwrap_or(1000);
                 tokio::time::sleep(tokio::time::Duration::from_millis(ms)).await;
                 Ok(format!("waited {}ms", ms))
             }
 
-            // --- Screenshot ---
+            // --- Wait for the screen to show something (polls the UI tree) ---
+            "wait_for_text" | "wait_for_element" => {
+                let query = if action.action_type == "wait_for_text" {
+                    let text = p.get("text").and_then(|v| v.as_str())
+                        .or(action.text.as_deref())
+                        .unwrap_or("");
+                    ElementQuery::text(text)
+                } else {
+                    ElementQuery::from_params(p)
+                };
+                let timeout_ms = p["timeout_ms"].as_u64().unwrap_or(DEFAULT_WAIT_TIMEOUT_MS);
+                self.wait_for(&query, timeout_ms).await
+            }
+
+            // --- Locate an element by fuzzy text; the answer is fed back for the next tick ---
+            "find_element" => {
+                let query = p.get("query").or(p.get("text"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let editable = p["editable"].as_bool().unwrap_or(false);
+                self.find_element(query, editable).await
+            }
+
+            // --- System settings straight through the shell, no Settings UI ---
+            "set_wifi" | "set_bluetooth" | "set_airplane" | "set_brightness" =>
+                self.set_system_setting(&action.action_type, p),
+
+            // --- Cheap re-orientation: foreground app/activity and screen state ---
+            "where_am_i" | "app_state" => self.where_am_i().await,
+
+            // --- Scroll through a long screen and hand the whole list to the next tick ---
+            "read_full_screen" => {
+                let max_pages = p["max_pages"].as_u64().unwrap_or(DEFAULT_READ_PAGES as u64) as usize;
+                let Some(perception) = &self.perception else {
+                    anyhow::bail!("read_full_screen is unavailable without screen access");
+                };
+                let state = perception.capture_scrolling(max_pages).await;
+                let count = state.elements.len();
+                let tree = state.ui_tree.unwrap_or_default();
+                perception
+                    .push_user_command(format!("[read_full_screen] {} elements:\n{}", count, tree))
+                    .await;
+                Ok(format!("captured {} elements", count))
+            }
+
+            // --- Screenshot (saved to /tmp on this machine) ---
             "screenshot" => {
                 self.adb(&["shell", "screencap", "-p", "/sdcard/hermitdroid_screenshot.png"])?;
                 self.adb(&["pull", "/sdcard/hermitdroid_screenshot.png", "/tmp/hermitdroid_screenshot.png"])
             }
 
+            // --- Screenshot returned inline, for the dashboard ---
+            "capture" => {
+                let data = match (&self.provider, &self.perception) {
+                    (Some(provider), _) => provider.capture_screenshot().await,
+                    (None, Some(perception)) => perception.capture_screenshot_adb(),
+                    (None, None) => anyhow::bail!("capture is unavailable without screen access"),
+                };
+                let data = data.ok_or_else(|| anyhow::anyhow!("screencap returned no image"))?;
+                Ok(format!("{}{}", SCREENSHOT_PREFIX, data))
+            }
+
+            // --- Tap a notification's own action ("Reply", "Mark as read") ---
+            "notification_action" => {
+                let label = p.get("action").or(p.get("label"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let reply = p["text"].as_str().unwrap_or("");
+                let target = NotificationTarget {
+                    app: target_app(action).map(|a| self.launch_package(action).unwrap_or_else(|_| a.to_string())),
+                    title: p["title"].as_str().map(str::to_string),
+                };
+                self.notification_action(&target, label, reply, self.text_input_for(p)).await
+            }
+
             // --- Notifications to user (accept both "text" and "message" params) ---
             "notify_user" => {
                 let msg = p.get("text").or(p.get("message"))
                     .and_then(|v| v.as_str())
                     .unwrap_or("");
+                if let Some(title) = &self.notify_title {
+                    match self.post_notification("hermitdroid", title, msg) {
+                        Ok(_) => return Ok(format!("notified on device: {}", msg)),
+                        Err(e) => warn!("Device notification failed, logging instead: {}", e),
+                    }
+                }
                 info!("[NOTIFY_USER] {}", msg);
                 Ok(format!("notified: {}", msg))
             }
 
             _ => {
                 // Send to companion app as generic action
+                self.awaiting_ack.lock().await.insert(id.to_string(), CompanionAction {
+                    action_type: action.action_type.clone(),
+                    classification: action.classification.clone(),
+                    queued_at: std::time::Instant::now(),
+                    sent_at: None,
+                });
                 self.outgoing.lock().await.push(DeviceAction {
                     id: id.to_string(),
                     action_type: action.action_type.clone(),
@@ -381,6 +1343,414 @@ impl ActionExecutor {
         }
     }
 
+    /// `launch_app` with its app name looked up once, on a blocking thread
+    /// (listing packages runs adb), and written to `params.package`. The
+    /// checks and the launch after it then find that package in the
+    /// resolver's fresh cache instead of listing again.
+    async fn resolve_launch<'a>(&self, action: &'a AgentAction) -> std::borrow::Cow<'a, AgentAction> {
+        let name = target_app(action).filter(|_| action.action_type == "launch_app");
+        let (Some(resolver), Some(name)) = (self.app_resolver.clone(), name.map(str::to_string)) else {
+            return std::borrow::Cow::Borrowed(action);
+        };
+        let Ok(Some(pkg)) = tokio::task::spawn_blocking(move || resolver.resolve_package(&name)).await else {
+            return std::borrow::Cow::Borrowed(action);
+        };
+        let mut action = action.clone();
+        if !action.params.is_object() {
+            action.params = serde_json::json!({});
+        }
+        action.params["package"] = pkg.into();
+        std::borrow::Cow::Owned(action)
+    }
+
+    /// Package for `launch_app`: `{"package": ...}` or a friendly `{"app": "youtube"}`
+    fn launch_package(&self, action: &AgentAction) -> anyhow::Result<String> {
+        let name = target_app(action).unwrap_or("");
+        if name.is_empty() {
+            anyhow::bail!("launch_app needs a package or app name");
+        }
+        match &self.app_resolver {
+            Some(resolver) => resolver
+                .resolve_package(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown app: {}", name)),
+            None => Ok(name.to_string()),
+        }
+    }
+
+    /// Read a coordinate pair from params and apply the tap calibration
+    fn point(&self, p: &serde_json::Value, kx: &str, ky: &str) -> (String, String) {
+        let (x, y) = self.calibration().apply(
+            p[kx].as_f64().unwrap_or(0.0),
+            p[ky].as_f64().unwrap_or(0.0),
+        );
+        (x.to_string(), y.to_string())
+    }
+
+    /// `cmd clipboard` exists on recent Android builds; its failures come back on stdout
+    fn set_clipboard(&self, text: &str) -> anyhow::Result<String> {
+        if !self.capabilities().clipboard {
+            anyhow::bail!("cmd clipboard is unsupported on this device");
+        }
+        let out = self.adb(&["shell", "cmd", "clipboard", "set-primary-clip", &shell_quote(text)])?;
+        if out.contains("Exception") || out.contains("Unknown command") || out.contains("No shell command") {
+            anyhow::bail!("set clipboard failed: {}", out);
+        }
+        Ok(format!("clipboard set ({} chars)", text.chars().count()))
+    }
+
+    /// Calibrated point for `long_press` / `open_context_menu`: element
+    /// `{"index": N}` of the current screen, or raw `{"x", "y"}`
+    async fn press_point(&self, action_type: &str, p: &serde_json::Value) -> anyhow::Result<(String, String)> {
+        let (x, y) = match PressTarget::from_params(p) {
+            Some(PressTarget::Point(x, y)) => (x, y),
+            Some(PressTarget::Element(index)) => {
+                let el = self.screen_element(index).await.ok_or_else(|| {
+                    anyhow::anyhow!("{}: no element [{}] on the current screen", action_type, index)
+                })?;
+                (el.center_x as f64, el.center_y as f64)
+            }
+            None => anyhow::bail!("{} needs an element index or x/y", action_type),
+        };
+        let (x, y) = self.calibration().apply(x, y);
+        Ok((x.to_string(), y.to_string()))
+    }
+
+    /// Element `index` of the last screen state the agent saw
+    async fn screen_element(&self, index: usize) -> Option<UiElement> {
+        let screen = self.perception.as_ref()?.get_screen_state().await?;
+        screen.elements.into_iter().find(|e| e.index == index)
+    }
+
+    /// Before `type_text`: with `{"index": N}`, tap element N of the current
+    /// screen to focus it; without, warn when no editable field has focus
+    async fn focus_for_typing(&self, p: &serde_json::Value) -> anyhow::Result<()> {
+        let elements = match &self.perception {
+            Some(perception) => perception.get_screen_state().await.map(|s| s.elements).unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let Some(index) = p["index"].as_u64() else {
+            if !elements.is_empty() && !elements.iter().any(|e| e.focused && e.editable) {
+                warn!("type_text: no focused text field on screen — text may go nowhere (pass an index to focus one)");
+            }
+            // Brief settle to ensure field is focused
+            self.wait_for_settle(150).await;
+            return Ok(());
+        };
+        let Some(el) = elements.iter().find(|e| e.index as u64 == index) else {
+            anyhow::bail!("type_text: no element [{}] on the current screen", index);
+        };
+        if !el.editable {
+            warn!("type_text: element [{}] {} isn't editable — tapping it anyway", index, el.class);
+        }
+        let (x, y) = self.calibration().apply(el.center_x as f64, el.center_y as f64);
+        self.adb(&["shell", "input", "tap", &x.to_string(), &y.to_string()])?;
+        self.wait_for_settle(300).await;
+        Ok(())
+    }
+
+    /// `tap` under `action.verify_taps`: when a clickable element sat under
+    /// the point but the screen looks the same afterwards, tap it once more
+    /// near its center
+    async fn verified_tap(&self, perception: &Perception, p: &serde_json::Value, x: &str, y: &str) -> anyhow::Result<String> {
+        let (ui_x, ui_y) = (
+            p["x"].as_f64().unwrap_or(0.0).round() as i32,
+            p["y"].as_f64().unwrap_or(0.0).round() as i32,
+        );
+        // The fresh dumps below are raw UI-tree coordinates
+        let app_cal = perception.screen_calibration();
+        let (ui_x, ui_y) = app_cal.map_or((ui_x, ui_y), |cal| cal.invert(ui_x, ui_y));
+        let before = perception.dump_elements_async().await;
+        let target = clickable_element_at(&before, ui_x, ui_y).cloned();
+        let result = self.adb(&["shell", "input", "tap", x, y])?;
+        self.wait_for_settle(TAP_VERIFY_SETTLE_MS).await;
+        perception.invalidate_screen_cache();
+
+        let Some(el) = target else { return Ok(result) };
+        if screen_signature(&perception.dump_elements_async().await) != screen_signature(&before) {
+            return Ok(result);
+        }
+        let (retry_x, retry_y) = tap_retry_point(&el, ui_x, ui_y);
+        let (retry_x, retry_y) = match app_cal {
+            Some(cal) => {
+                let (x, y) = cal.apply(retry_x, retry_y);
+                (x as i64, y as i64)
+            }
+            None => self.calibration.apply(retry_x as f64, retry_y as f64),
+        };
+        info!(
+            "🎯 Tap at ({}, {}) didn't change the screen — retrying at ({}, {}) on [{}] {} \"{}\"",
+            x, y, retry_x, retry_y, el.index, el.class, el.label()
+        );
+        self.adb(&["shell", "input", "tap", &retry_x.to_string(), &retry_y.to_string()])?;
+        self.wait_for_settle(200).await;
+        perception.invalidate_screen_cache();
+        Ok(format!("no reaction at ({}, {}), retried at ({}, {})", x, y, retry_x, retry_y))
+    }
+
+    /// The `fast` text input method: `input text`, with the clipboard for
+    /// whatever it can't type
+    /// Type into the focused field with `method`
+    async fn type_with(&self, method: TextInputMethod, text: &str) -> anyhow::Result<String> {
+        match method {
+            TextInputMethod::Fast => self.type_via_input(text).await,
+            TextInputMethod::Ime => self.type_via_ime(text).await,
+            TextInputMethod::Clipboard => self.type_via_clipboard(text).await,
+        }
+    }
+
+    async fn type_via_input(&self, text: &str) -> anyhow::Result<String> {
+        // Emoji, CJK and shell metacharacters don't survive `input text`
+        if needs_clipboard(text) {
+            return self.type_via_clipboard(text).await;
+        }
+
+        // Fast path: ADB input text (only shell-safe characters get here)
+        let escaped = text.replace(' ', "%s");
+
+        match self.adb(&["shell", "input", "text", &escaped]) {
+            Ok(result) => Ok(result),
+            Err(_) => {
+                warn!("input text failed, trying clipboard fallback for: {}", text);
+                self.type_via_clipboard(text).await
+            }
+        }
+    }
+
+    /// The `ime` text input method: switch to ADBKeyboard, commit the text
+    /// base64-encoded (no escaping, any script), then restore the user's keyboard
+    async fn type_via_ime(&self, text: &str) -> anyhow::Result<String> {
+        use base64::Engine;
+        if !self.adb_keyboard_installed() {
+            return self.type_via_input(text).await;
+        }
+        let previous = self
+            .adb(&["shell", "settings", "get", "secure", "default_input_method"])
+            .unwrap_or_default();
+        let previous = previous.trim();
+        let switched = previous != ADB_KEYBOARD_IME;
+        if switched {
+            self.adb(&["shell", "ime", "enable", ADB_KEYBOARD_IME])?;
+            self.adb(&["shell", "ime", "set", ADB_KEYBOARD_IME])?;
+            tokio::time::sleep(std::time::Duration::from_millis(IME_SWITCH_SETTLE_MS)).await;
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+        let result = self.adb(&["shell", "am", "broadcast", "-a", "ADB_INPUT_B64", "--es", "msg", &encoded]);
+        if switched && !previous.is_empty() && previous != "null" {
+            if let Err(e) = self.adb(&["shell", "ime", "set", previous]) {
+                warn!("Could not switch the keyboard back to {}: {}", previous, e);
+            }
+        }
+        result?;
+        Ok(format!("typed {} chars via ADBKeyboard", text.chars().count()))
+    }
+
+    /// `set_wifi` / `set_bluetooth` / `set_airplane` / `set_brightness`: each
+    /// `system_setting_commands` alternative in turn. Some ROMs withhold the
+    /// shell permission; the error then says to use the Settings UI instead.
+    fn set_system_setting(&self, action_type: &str, p: &serde_json::Value) -> anyhow::Result<String> {
+        let (summary, commands) = system_setting_commands(action_type, p)?;
+        let mut last_error = String::new();
+        for command in &commands {
+            match self.adb(&["shell", command]) {
+                Ok(out) if !shell_refused(&out) => return Ok(summary),
+                Ok(out) => last_error = out,
+                Err(e) if e.downcast_ref::<AdbError>().is_some_and(AdbError::is_device_error) => return Err(e),
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        anyhow::bail!(
+            "{} failed: {} — not allowed over ADB on this device, change it in the Settings UI instead",
+            action_type,
+            last_error
+        )
+    }
+
+    /// Pull down the notification shade; a swipe from the top edge where
+    /// `cmd statusbar` is unsupported
+    fn expand_notifications(&self) -> anyhow::Result<String> {
+        if self.capabilities().statusbar {
+            self.adb(&["shell", "cmd", "statusbar", "expand-notifications"])
+        } else {
+            self.adb(&["shell", "input", "swipe", "540", "0", "540", "1400", "300"])
+        }
+    }
+
+    /// Paste `text` into the focused field via the clipboard, falling back
+    /// to an ADBKeyBoard broadcast when the clipboard can't be set
+    async fn type_via_clipboard(&self, text: &str) -> anyhow::Result<String> {
+        match self.set_clipboard(text) {
+            Ok(_) => {
+                self.adb(&["shell", "input", "keyevent", "KEYCODE_PASTE"])?;
+                Ok(format!("pasted {} chars", text.chars().count()))
+            }
+            Err(e) => {
+                warn!("{}, trying broadcast fallback", e);
+                self.adb(&["shell", "am", "broadcast", "-a",
+                    "ADB_INPUT_TEXT", "--es", "msg", &shell_quote(text)])
+            }
+        }
+    }
+
+    /// Poll the screen until `query` matches; errors on timeout
+    pub async fn wait_for(&self, query: &ElementQuery, timeout_ms: u64) -> anyhow::Result<String> {
+        if query.is_empty() {
+            anyhow::bail!("wait_for_element needs text, desc, resource_id or class");
+        }
+        let Some(perception) = &self.perception else {
+            anyhow::bail!("wait_for_element is unavailable without screen access");
+        };
+        let start = std::time::Instant::now();
+        match perception.wait_for_element(query, timeout_ms).await {
+            Some(el) => Ok(format!(
+                "found {} at ({}, {}) after {}ms",
+                query, el.center_x, el.center_y, start.elapsed().as_millis()
+            )),
+            None => anyhow::bail!("timed out after {}ms waiting for {}", timeout_ms, query),
+        }
+    }
+
+    /// `find_element`: best match for `query` on screen, pushed back to the
+    /// agent as a hint so the next tick can tap it by coordinates
+    pub async fn find_element(&self, query: &str, want_editable: bool) -> anyhow::Result<String> {
+        if query.trim().is_empty() {
+            anyhow::bail!("find_element needs a query");
+        }
+        let Some(perception) = &self.perception else {
+            anyhow::bail!("find_element is unavailable without screen access");
+        };
+        let Some(el) = perception.find_element(query, want_editable) else {
+            anyhow::bail!("no element matching \"{}\" on screen", query);
+        };
+        let label = [el.text.as_str(), el.desc.as_str(), el.resource_id.as_str()]
+            .into_iter()
+            .find(|s| !s.is_empty())
+            .unwrap_or("");
+        let found = format!(
+            "[{}] {} \"{}\" at ({}, {})",
+            el.index, el.class, label, el.center_x, el.center_y
+        );
+        perception
+            .push_user_command(format!("[find_element] \"{}\" → {}", query, found))
+            .await;
+        Ok(format!("found {}", found))
+    }
+
+    /// `notification_action`: `cmd notification` has no way to fire another
+    /// app's action intent, so pull down the shade and tap the button labelled
+    /// `label` on the one notification `target` names. With `reply`, type it
+    /// into the inline reply field with `method` and send it.
+    pub async fn notification_action(
+        &self,
+        target: &NotificationTarget,
+        label: &str,
+        reply: &str,
+        method: TextInputMethod,
+    ) -> anyhow::Result<String> {
+        if label.trim().is_empty() {
+            anyhow::bail!("notification_action needs an action label");
+        }
+        let Some(perception) = &self.perception else {
+            anyhow::bail!("notification_action is unavailable without screen access");
+        };
+        let posted = perception.active_notifications().await?;
+        let notification = target.pick(&posted, label).map_err(|e| anyhow::anyhow!(e))?;
+        self.expand_notifications()?;
+        let query = ElementQuery::text(label);
+        let button = match perception.wait_for_element(&query, NOTIFICATION_ACTION_TIMEOUT_MS).await {
+            Some(_) => {
+                let titles: Vec<&str> = posted.iter().map(|n| n.title.as_str()).collect();
+                action_button(&perception.dump_all_elements(), &notification.title, &titles, label)
+            }
+            None => Err(format!("no \"{}\" action in the notification shade", label)),
+        };
+        let button = match button {
+            Ok(button) => button,
+            Err(e) => {
+                let _ = if self.capabilities().statusbar {
+                    self.adb(&["shell", "cmd", "statusbar", "collapse"])
+                } else {
+                    self.adb(&["shell", "input", "keyevent", "KEYCODE_BACK"])
+                };
+                anyhow::bail!(e);
+            }
+        };
+        self.adb(&["shell", "input", "tap", &button.center_x.to_string(), &button.center_y.to_string()])?;
+        if reply.is_empty() {
+            return Ok(format!("tapped notification action \"{}\"", label));
+        }
+
+        self.wait_for_settle(300).await;
+        self.type_with(method, reply).await?;
+        // The inline reply field sends on Enter
+        self.adb(&["shell", "input", "keyevent", "KEYCODE_ENTER"])?;
+        Ok(format!("replied via notification action \"{}\": {}", label, reply))
+    }
+
+    /// `where_am_i`: foreground package, activity and screen power state, pushed
+    /// back to the agent as a hint — no UI dump involved
+    pub async fn where_am_i(&self) -> anyhow::Result<String> {
+        let Some(perception) = &self.perception else {
+            anyhow::bail!("where_am_i is unavailable without screen access");
+        };
+        let (app, activity) = parse_foreground_activity(&self.adb(&["shell", "dumpsys", "activity", "activities"])?);
+        let screen = if perception.is_screen_on() { "on" } else { "off" };
+        let state = format!("app={} activity={} screen={}", app, activity, screen);
+        perception.push_user_command(format!("[where_am_i] {}", state)).await;
+        Ok(state)
+    }
+
+    /// Press BACK until the launcher is in the foreground, up to `max_backs` times,
+    /// then HOME if that wasn't enough (apps that swallow BACK). Returns the number
+    /// of BACK presses used.
+    pub async fn escape_to_home(&self, max_backs: u32) -> anyhow::Result<u32> {
+        if self.dry_run {
+            return Ok(0);
+        }
+        if !self.capabilities().dumpsys_activity {
+            // No way to tell when the launcher is up
+            self.adb(&["shell", "input", "keyevent", "KEYCODE_HOME"])?;
+            self.wait_for_settle(800).await;
+            return Ok(0);
+        }
+        let home = self.home_package();
+        let mut backs = 0;
+        while !self.on_launcher(home.as_deref()) {
+            if backs == max_backs {
+                info!("🏠 Still not home after {} back(s) — pressing HOME", backs);
+                self.adb(&["shell", "input", "keyevent", "KEYCODE_HOME"])?;
+                self.wait_for_settle(800).await;
+                break;
+            }
+            self.adb(&["shell", "input", "keyevent", "KEYCODE_BACK"])?;
+            backs += 1;
+            self.wait_for_settle(500).await;
+        }
+        Ok(backs)
+    }
+
+    /// Package of the default home activity, e.g. `com.google.android.apps.nexuslauncher`
+    fn home_package(&self) -> Option<String> {
+        let out = self.adb(&[
+            "shell", "cmd", "package", "resolve-activity", "--brief",
+            "-a", "android.intent.action.MAIN", "-c", "android.intent.category.HOME",
+        ]).ok()?;
+        out.lines().last()?.split_once('/').map(|(pkg, _)| pkg.trim().to_string())
+    }
+
+    fn on_launcher(&self, home: Option<&str>) -> bool {
+        self.adb(&["shell", "dumpsys", "activity", "activities"])
+            .map(|raw| is_launcher(&parse_foreground_activity(&raw).0, home))
+            .unwrap_or(false)
+    }
+
+    /// Heads-up notification via `cmd notification post` (Android 10+)
+    fn post_notification(&self, tag: &str, title: &str, msg: &str) -> anyhow::Result<String> {
+        let args = notification_post_args(tag, title, msg);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        notification_post_result(self.adb(&args)?)
+    }
+
+    /// Errors are `AdbError`s (`downcast_ref` them to tell a lost device from a failed command)
     fn adb(&self, args: &[&str]) -> anyhow::Result<String> {
         let mut cmd = Command::new("adb");
         if let Some(dev) = &self.adb_device {
@@ -388,7 +1758,7 @@ impl ActionExecutor {
         }
         cmd.args(args);
 
-        let out = cmd.output()?;
+        let out = adb::output(&mut cmd)?;
         let stdout = String::from_utf8_lossy(&out.stdout).trim().to_string();
         let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
 
@@ -404,8 +1774,887 @@ impl ActionExecutor {
                 warn!("adb warning: {}", stderr);
                 Ok(stdout)
             } else {
-                anyhow::bail!("adb error: {}", if stderr.is_empty() { "unknown error".into() } else { stderr })
+                Err(AdbError::from_output(out.status.code(), &stderr).into())
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Which notification a `notification_action` is for: its app (package)
+/// and/or title, as listed in the prompt
+#[derive(Debug, Clone, Default)]
+pub struct NotificationTarget {
+    pub app: Option<String>,
+    pub title: Option<String>,
+}
+
+impl NotificationTarget {
+    /// The single posted notification from `app` / titled `title` that offers
+    /// `label`. Err when none or several match, so the wrong one is never acted on.
+    fn pick<'a>(&self, posted: &'a [Notification], label: &str) -> Result<&'a Notification, String> {
+        let has = |field: &str, wanted: &Option<String>| {
+            wanted.as_deref().is_none_or(|w| field.to_lowercase().contains(&w.trim().to_lowercase()))
+        };
+        if self.app.is_none() && self.title.is_none() {
+            return Err("notification_action needs the notification's app or title".into());
+        }
+        let matches: Vec<&Notification> = posted
+            .iter()
+            .filter(|n| has(&n.app, &self.app) && has(&n.title, &self.title))
+            .filter(|n| n.actions.iter().any(|a| a.eq_ignore_ascii_case(label.trim())))
+            .collect();
+        match matches.as_slice() {
+            [one] => Ok(one),
+            [] => Err(format!("no notification from {} offers \"{}\"", self, label)),
+            many => Err(format!(
+                "{} notifications from {} offer \"{}\" ({}) — name the title too",
+                many.len(),
+                self,
+                label,
+                many.iter().map(|n| format!("\"{}\"", n.title)).collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for NotificationTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<&str> = [self.app.as_deref(), self.title.as_deref()].into_iter().flatten().collect();
+        f.write_str(&parts.join(" / "))
+    }
+}
+
+/// The `label` button belonging to the notification titled `title` in the
+/// shade: below that title and above the next notification's (any of
+/// `titles`). Titles and labels match exactly, ignoring case, and a target
+/// whose title or buttons aren't visible is refused rather than guessed.
+fn action_button(elements: &[UiElement], title: &str, titles: &[&str], label: &str) -> Result<UiElement, String> {
+    let is = |el: &UiElement, s: &str| {
+        let s = s.trim().to_lowercase();
+        !s.is_empty() && [&el.text, &el.desc].iter().any(|t| t.trim().to_lowercase() == s)
+    };
+    let anchor = elements
+        .iter()
+        .find(|el| is(el, title) && !is(el, label))
+        .ok_or_else(|| format!("notification \"{}\" isn't visible in the shade", title))?;
+    let top = anchor.bounds[1];
+    let next = elements
+        .iter()
+        .filter(|el| el.bounds[1] > top && titles.iter().any(|t| is(el, t)))
+        .map(|el| el.bounds[1])
+        .min()
+        .unwrap_or(i32::MAX);
+    elements
+        .iter()
+        .filter(|el| is(el, label) && (top..next).contains(&el.bounds[1]))
+        .min_by_key(|el| el.bounds[1] - top)
+        .cloned()
+        .ok_or_else(|| format!("no \"{}\" action under \"{}\" in the notification shade", label, title))
+}
+
+/// A `notification_action` that sends text or clears notifications
+fn is_destructive_notification_action(p: &serde_json::Value) -> bool {
+    let label = p.get("action").or(p.get("label")).and_then(|v| v.as_str()).unwrap_or("");
+    p["text"].as_str().is_some_and(|t| !t.is_empty())
+        || DESTRUCTIVE_NOTIFICATION_ACTIONS.contains(&label.trim().to_lowercase().as_str())
+}
+
+/// App an action targets: `params.package`, then `params.app`, then the top-level `app`
+fn target_app(action: &AgentAction) -> Option<&str> {
+    action
+        .params
+        .get("package")
+        .or(action.params.get("app"))
+        .and_then(|v| v.as_str())
+        .or(action.app.as_deref())
+}
+
+/// How `type_text` gets text onto the device (`action.text_input_method`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextInputMethod {
+    /// `input text`, switching to the clipboard for text it would mangle
+    Fast,
+    /// ADBKeyboard's `ADB_INPUT_B64` broadcast; needs the ADBKeyboard app
+    Ime,
+    /// Always paste through the clipboard
+    Clipboard,
+}
+
+impl TextInputMethod {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "fast" | "input" => Some(Self::Fast),
+            "ime" | "adbkeyboard" => Some(Self::Ime),
+            "clipboard" => Some(Self::Clipboard),
+            _ => None,
+        }
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        Self::parse(s).unwrap_or_else(|| {
+            warn!("Unknown text_input_method '{}', defaulting to 'fast'", s);
+            Self::Fast
+        })
+    }
+}
+
+/// Where a `long_press` / `open_context_menu` lands
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PressTarget {
+    /// `{"index": N}` — an element of the current screen (wins over x/y)
+    Element(usize),
+    /// `{"x": .., "y": ..}` in UI coordinates, before calibration
+    Point(f64, f64),
+}
+
+impl PressTarget {
+    fn from_params(p: &serde_json::Value) -> Option<Self> {
+        let index = p["index"]
+            .as_u64()
+            .or_else(|| p["index"].as_str().and_then(|s| s.trim().parse().ok()));
+        if let Some(index) = index {
+            return Some(Self::Element(index as usize));
+        }
+        Some(Self::Point(p["x"].as_f64()?, p["y"].as_f64()?))
+    }
+}
+
+/// The smallest clickable element whose bounds hold (x, y)
+fn clickable_element_at(elements: &[UiElement], x: i32, y: i32) -> Option<&UiElement> {
+    elements
+        .iter()
+        .filter(|el| el.clickable && el.enabled)
+        .filter(|el| {
+            let [left, top, right, bottom] = el.bounds;
+            (left..=right).contains(&x) && (top..=bottom).contains(&y)
+        })
+        .min_by_key(|el| (el.bounds[2] - el.bounds[0]) as i64 * (el.bounds[3] - el.bounds[1]) as i64)
+}
+
+/// Where to re-tap `el` after a tap at (x, y) didn't register: its center,
+/// or a few px off it when (x, y) already was the center
+fn tap_retry_point(el: &UiElement, x: i32, y: i32) -> (i32, i32) {
+    let (cx, cy) = (el.center_x, el.center_y);
+    if (x - cx).abs() > TAP_RETRY_JITTER_PX || (y - cy).abs() > TAP_RETRY_JITTER_PX {
+        return (cx, cy);
+    }
+    (
+        (cx + TAP_RETRY_JITTER_PX).min(el.bounds[2]),
+        (cy + TAP_RETRY_JITTER_PX).min(el.bounds[3]),
+    )
+}
+
+/// The resolved home app, or a package that looks like a launcher when the
+/// home activity couldn't be resolved
+fn is_launcher(package: &str, home: Option<&str>) -> bool {
+    match home {
+        Some(home) => package == home,
+        None => package.contains("launcher") || package == "com.miui.home",
+    }
+}
+
+/// A user's answer in an `ls DEVICE_CONFIRM_DIR` listing: `<id>.approve` → true,
+/// `<id>.deny` → false. Deny wins if both exist.
+fn sentinel_answer(listing: &str, id: &str) -> Option<bool> {
+    let has = |ext: &str| listing.split_whitespace().any(|f| f == format!("{}.{}", id, ext));
+    if has("deny") {
+        Some(false)
+    } else if has("approve") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Whether `type_text` should go through the clipboard instead of `input text`:
+/// anything non-ASCII, or characters the device shell / `input` would mangle
+fn needs_clipboard(text: &str) -> bool {
+    const SAFE_PUNCT: &str = " .,:-_@/+=";
+    text.chars().any(|c| !(c.is_ascii_alphanumeric() || SAFE_PUNCT.contains(c)))
+        || text.contains("%s")
+}
+
+/// What a `set_*` settings action does, and the shell commands that do it:
+/// the first that works wins, the rest cover older Android versions
+fn system_setting_commands(action_type: &str, p: &serde_json::Value) -> anyhow::Result<(String, Vec<String>)> {
+    let switch = || {
+        p["enabled"]
+            .as_bool()
+            .ok_or_else(|| anyhow::anyhow!("{} needs \"enabled\": true or false", action_type))
+    };
+    let verb = |on: bool| if on { "enable" } else { "disable" };
+    Ok(match action_type {
+        "set_wifi" => {
+            let on = switch()?;
+            (
+                format!("wifi {}d", verb(on)),
+                vec![
+                    format!("svc wifi {}", verb(on)),
+                    format!("cmd wifi set-wifi-enabled {}d", verb(on)),
+                ],
+            )
+        }
+        "set_bluetooth" => {
+            let on = switch()?;
+            (
+                format!("bluetooth {}d", verb(on)),
+                vec![
+                    format!("cmd bluetooth_manager {}", verb(on)),
+                    format!("svc bluetooth {}", verb(on)),
+                ],
+            )
+        }
+        "set_airplane" => {
+            let on = switch()?;
+            (
+                format!("airplane mode {}d", verb(on)),
+                vec![format!("cmd connectivity airplane-mode {}", verb(on))],
+            )
+        }
+        "set_brightness" if p["auto"].as_bool() == Some(true) => (
+            "brightness automatic".to_string(),
+            vec!["settings put system screen_brightness_mode 1".to_string()],
+        ),
+        "set_brightness" => {
+            let level = p["level"]
+                .as_u64()
+                .ok_or_else(|| anyhow::anyhow!("set_brightness needs \"level\" (0-255) or \"auto\": true"))?
+                .min(255);
+            (
+                format!("brightness {}", level),
+                vec![format!(
+                    "settings put system screen_brightness_mode 0 && settings put system screen_brightness {}",
+                    level
+                )],
+            )
+        }
+        other => anyhow::bail!("{} is not a settings action", other),
+    })
+}
+
+/// Output of a shell command that ran but refused: a missing service or
+/// subcommand, or a permission the shell user doesn't have
+fn shell_refused(out: &str) -> bool {
+    ["Exception", "Unknown command", "No shell command", "Can't find service", "not allowed", "Permission denial"]
+        .iter()
+        .any(|marker| out.contains(marker))
+}
+
+/// `adb` arguments for a bigtext `cmd notification post`
+fn notification_post_args(tag: &str, title: &str, msg: &str) -> Vec<String> {
+    let fixed = ["shell", "cmd", "notification", "post", "-S", "bigtext", "-t"];
+    let mut args: Vec<String> = fixed.iter().map(|a| a.to_string()).collect();
+    args.extend([shell_quote(title), tag.to_string(), shell_quote(msg)]);
+    args
+}
+
+/// `cmd` exits 0 even when the notification service rejects the call
+fn notification_post_result(out: String) -> anyhow::Result<String> {
+    if out.contains("Exception") || out.contains("Unknown command") {
+        anyhow::bail!("{}", out);
+    }
+    Ok(out)
+}
+
+/// Single-quote for the device shell (`adb shell` joins its args into one command line)
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dry_run_preview_resolves_target() {
+        let executor = ActionExecutor::new(true, None, vec!["bank".into()], None);
+        let action: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "launch_app",
+            "params": {"package": "com.bank.app"},
+            "classification": "YELLOW",
+        }))
+        .unwrap();
+
+        let msg = executor.execute(&action).await.unwrap();
+        assert!(msg.contains("com.bank.app"));
+        let log = executor.action_log().lock().await.clone();
+        let preview = log[0].preview.as_ref().unwrap();
+        assert_eq!(preview["target"]["package"], "com.bank.app");
+        assert_eq!(preview["disposition"], "would queue for confirmation (restricted app)");
+        assert!(executor.pending().lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_low_confidence_action_is_gated() {
+        let executor = ActionExecutor::new(false, None, vec![], None).with_min_confidence(0.5);
+        let mut action = notify_action();
+        action.classification = "GREEN".into();
+        action.confidence = 0.2;
+
+        let result = executor.execute(&action).await.unwrap();
+        assert!(result.starts_with("PENDING:"));
+        assert_eq!(executor.pending().lock().await.len(), 1);
+        assert!(executor.action_log().lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_safe_mode_blocks_non_green() {
+        let executor = ActionExecutor::new(false, None, vec![], None).with_safe_mode(true);
+        let mut action = notify_action();
+        for class in ["RED", "yellow"] {
+            action.classification = class.into();
+            assert_eq!(executor.execute(&action).await.unwrap(), SAFE_MODE_BLOCKED);
+        }
+        assert!(executor.pending().lock().await.is_empty());
+        let log = executor.action_log().lock().await.clone();
+        assert_eq!(log.len(), 2);
+        assert!(log.iter().all(|e| e.classification == "BLOCKED"));
+
+        action.classification = "GREEN".into();
+        assert_ne!(executor.execute(&action).await.unwrap(), SAFE_MODE_BLOCKED);
+
+        // A GREEN label doesn't make a tap, text entry or companion action safe
+        for (kind, params) in [
+            ("tap", serde_json::json!({"x": 10, "y": 20})),
+            ("type_text", serde_json::json!({"text": "hi"})),
+            ("launch_app", serde_json::json!({"package": "com.example"})),
+            ("custom_companion_action", serde_json::json!({})),
+        ] {
+            let action: AgentAction =
+                serde_json::from_value(serde_json::json!({"type": kind, "params": params, "classification": "GREEN"})).unwrap();
+            assert_eq!(executor.execute(&action).await.unwrap(), SAFE_MODE_BLOCKED, "{}", kind);
+        }
+
+        // Queued before safe mode was on: confirming it still refuses
+        action.classification = "RED".into();
+        executor.pending().lock().await.push(PendingConfirmation {
+            action_id: "queued".into(),
+            action: action.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            confirmed: None,
+        });
+        assert_eq!(executor.confirm("queued", true).await.unwrap(), SAFE_MODE_BLOCKED);
+    }
+
+    #[tokio::test]
+    async fn test_policy_per_classification() {
+        let policy = ActionPolicy { green: PolicyMode::Auto, yellow: PolicyMode::Confirm, red: PolicyMode::Block };
+        let executor = ActionExecutor::new(false, None, vec!["bank".into()], None).with_policy(policy);
+        let mut action = notify_action();
+        action.classification = "RED".into();
+        assert_eq!(executor.execute(&action).await.unwrap(), POLICY_BLOCKED);
+        action.classification = "yellow".into();
+        assert!(executor.execute(&action).await.unwrap().starts_with("PENDING:"));
+        assert_eq!(executor.pending().lock().await.len(), 1);
+
+        // A blocked class is refused even when low confidence would queue it
+        let executor = executor.with_min_confidence(0.5);
+        action.classification = "RED".into();
+        action.confidence = 0.2;
+        assert_eq!(executor.execute(&action).await.unwrap(), POLICY_BLOCKED);
+        assert_eq!(executor.pending().lock().await.len(), 1);
+
+        // ...and when it was queued before the policy blocked it
+        let queued = executor.pending().lock().await[0].action_id.clone();
+        executor.pending().lock().await[0].action.classification = "RED".into();
+        assert_eq!(executor.confirm(&queued, true).await.unwrap(), POLICY_BLOCKED);
+
+        // Restricted apps still wait for confirmation when RED runs automatically
+        let executor = ActionExecutor::new(false, None, vec!["bank".into()], None);
+        let launch = AgentAction {
+            action_type: "launch_app".into(),
+            params: serde_json::json!({"package": "com.example.banking"}),
+            ..notify_action()
+        };
+        assert!(executor.execute(&launch).await.unwrap().starts_with("PENDING:"));
+        let log = executor.action_log().lock().await.clone();
+        assert!(log.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_blocked_app_launch_refused() {
+        let aliases = HashMap::from([("mybank".to_string(), "com.example.banking".to_string())]);
+        let executor = ActionExecutor::new(false, None, vec![], None)
+            .with_app_resolver(AppResolver::new(None, aliases))
+            .with_blocked_apps(vec!["banking".into()]);
+        for params in [serde_json::json!({"package": "com.example.banking"}), serde_json::json!({"app": "mybank"})] {
+            let action: AgentAction = serde_json::from_value(serde_json::json!({
+                "type": "launch_app", "params": params, "classification": "GREEN",
+            }))
+            .unwrap();
+            assert_eq!(executor.execute(&action).await.unwrap(), APP_BLOCKED);
+        }
+        let log = executor.action_log().lock().await.clone();
+        assert_eq!(log.len(), 2);
+        assert!(log.iter().all(|e| e.result == APP_BLOCKED));
+        assert!(log.iter().all(|e| e.params["package"] == "com.example.banking"));
+    }
+
+    #[test]
+    fn test_notification_action_targets_one_notification() {
+        let notif = |app: &str, title: &str| Notification {
+            id: String::new(),
+            app: app.into(),
+            title: title.into(),
+            text: String::new(),
+            timestamp: String::new(),
+            actions: vec!["Reply".into(), "Mark as read".into()],
+        };
+        let posted = [notif("com.whatsapp", "Mom"), notif("com.whatsapp", "Dad"), notif("org.telegram.messenger", "Mom")];
+        let target = |app: Option<&str>, title: Option<&str>| NotificationTarget {
+            app: app.map(str::to_string),
+            title: title.map(str::to_string),
+        };
+        assert!(target(None, None).pick(&posted, "Reply").is_err());
+        assert!(target(Some("com.whatsapp"), None).pick(&posted, "Reply").unwrap_err().contains("2 notifications"));
+        assert!(target(None, Some("Mom")).pick(&posted, "Reply").is_err());
+        assert_eq!(target(Some("com.whatsapp"), Some("mom")).pick(&posted, "reply").unwrap().title, "Mom");
+        assert!(target(Some("com.whatsapp"), Some("Mom")).pick(&posted, "Archive").is_err());
+
+        // Two "Reply" buttons in the shade: the one under Dad's notification
+        let el = |text: &str, top: i32| UiElement {
+            index: 0,
+            class: "Button".into(),
+            text: text.into(),
+            desc: String::new(),
+            resource_id: String::new(),
+            center_x: 100,
+            center_y: top + 10,
+            bounds: [0, top, 200, top + 20],
+            clickable: true,
+            editable: false,
+            focused: false,
+            scrollable: false,
+            checked: None,
+            enabled: true,
+            score: 1.0,
+        };
+        let titles = ["Mom", "Dad", "Moments"];
+        let shade = [el("Mom", 100), el("Reply", 160), el("Dad", 300), el("Reply", 360)];
+        assert_eq!(action_button(&shade, "Dad", &titles, "Reply").unwrap().bounds[1], 360);
+        assert_eq!(action_button(&shade, "mom", &titles, "Reply").unwrap().bounds[1], 160);
+        assert!(action_button(&shade, "Uncle", &titles, "Reply").is_err());
+        // "Mom" isn't anchored on "Moments", and Mom's collapsed notification
+        // has no buttons: Dad's Reply below it must not be used
+        let shade = [el("Moments", 20), el("Mom", 100), el("Dad", 300), el("Reply", 360)];
+        assert!(action_button(&shade, "Mom", &titles, "Reply").is_err());
+        assert!(action_button(&[el("Moments", 20), el("Reply", 60)], "Mom", &titles, "Reply").is_err());
+
+        let mut reply: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "notification_action",
+            "params": {"app": "com.whatsapp", "title": "Mom", "action": "Reply", "text": "on my way"},
+            "classification": "GREEN",
+        }))
+        .unwrap();
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        assert_eq!(executor.effective_classification(&reply), "RED");
+        reply.params = serde_json::json!({"app": "com.whatsapp", "action": "Mark as read"});
+        assert_eq!(executor.effective_classification(&reply), "YELLOW");
+        reply.params = serde_json::json!({"app": "com.whatsapp", "action": "Clear all"});
+        assert_eq!(executor.effective_classification(&reply), "RED");
+        assert!(reply.validate().is_ok());
+        reply.params = serde_json::json!({"action": "Reply"});
+        assert_eq!(reply.validate().unwrap_err(), "notification_action is missing app or title");
+    }
+
+    #[tokio::test]
+    async fn test_restricted_app_alias_needs_confirmation() {
+        let aliases = HashMap::from([("mybank".to_string(), "com.example.banking".to_string())]);
+        let executor = ActionExecutor::new(false, None, vec!["banking".into()], None)
+            .with_app_resolver(AppResolver::new(None, aliases));
+        let action: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "launch_app", "params": {"app": "mybank"}, "classification": "GREEN",
+        }))
+        .unwrap();
+        assert_eq!(executor.effective_classification(&action), "RED");
+        assert!(executor.execute(&action).await.unwrap().starts_with("PENDING:"));
+        assert!(executor.action_log().lock().await.is_empty());
+    }
+
+    #[test]
+    fn test_press_target_params() {
+        let parse = |v: serde_json::Value| PressTarget::from_params(&v);
+        assert_eq!(parse(serde_json::json!({"index": 5})), Some(PressTarget::Element(5)));
+        assert_eq!(parse(serde_json::json!({"index": "7", "x": 1, "y": 2})), Some(PressTarget::Element(7)));
+        assert_eq!(parse(serde_json::json!({"x": 540, "y": 1200.5})), Some(PressTarget::Point(540.0, 1200.5)));
+        assert_eq!(parse(serde_json::json!({"x": 540})), None);
+        assert_eq!(parse(serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn test_text_input_method_override() {
+        assert_eq!(TextInputMethod::parse(" IME "), Some(TextInputMethod::Ime));
+        assert_eq!(TextInputMethod::parse("typewriter"), None);
+        assert_eq!(TextInputMethod::from_str("typewriter"), TextInputMethod::Fast);
+
+        let executor = ActionExecutor::new(false, None, vec![], None).with_text_input_method(TextInputMethod::Clipboard);
+        let method = |p: serde_json::Value| executor.text_input_for(&p);
+        assert_eq!(method(serde_json::json!({"text": "hi"})), TextInputMethod::Clipboard);
+        assert_eq!(method(serde_json::json!({"text": "hi", "method": "ime"})), TextInputMethod::Ime);
+        assert_eq!(method(serde_json::json!({"text": "hi", "method": "bogus"})), TextInputMethod::Clipboard);
+    }
+
+    #[test]
+    fn test_unsupported_clipboard_skips_adb() {
+        // A dry run never probes
+        let dry = ActionExecutor::new(true, Some("no-such-device".into()), vec![], None);
+        assert_eq!(dry.capabilities(), Capabilities::default());
+        let executor = ActionExecutor::new(false, Some("no-such-device".into()), vec![], None);
+        let caps = Capabilities { statusbar: false, clipboard: false, ..Default::default() };
+        executor.capabilities.set(caps).unwrap();
+        assert_eq!(
+            caps.summary(),
+            "dumpsys activity: ok, uiautomator: ok, statusbar expand: unsupported, clipboard: unsupported"
+        );
+        let err = executor.set_clipboard("héllo").unwrap_err().to_string();
+        assert_eq!(err, "cmd clipboard is unsupported on this device");
+    }
+
+    #[test]
+    fn test_system_setting_commands() {
+        let commands = |action_type: &str, p: serde_json::Value| system_setting_commands(action_type, &p);
+        let (summary, wifi) = commands("set_wifi", serde_json::json!({"enabled": true})).unwrap();
+        assert_eq!(summary, "wifi enabled");
+        assert_eq!(wifi, vec!["svc wifi enable", "cmd wifi set-wifi-enabled enabled"]);
+        let (_, bluetooth) = commands("set_bluetooth", serde_json::json!({"enabled": false})).unwrap();
+        assert_eq!(bluetooth[0], "cmd bluetooth_manager disable");
+        let (_, airplane) = commands("set_airplane", serde_json::json!({"enabled": true})).unwrap();
+        assert_eq!(airplane, vec!["cmd connectivity airplane-mode enable"]);
+        let (summary, brightness) = commands("set_brightness", serde_json::json!({"level": 400})).unwrap();
+        assert_eq!(summary, "brightness 255");
+        assert!(brightness[0].ends_with("&& settings put system screen_brightness 255"));
+        let (_, auto) = commands("set_brightness", serde_json::json!({"auto": true})).unwrap();
+        assert_eq!(auto, vec!["settings put system screen_brightness_mode 1"]);
+        assert!(commands("set_wifi", serde_json::json!({"enabled": "yes"})).is_err());
+        assert!(commands("set_brightness", serde_json::json!({})).is_err());
+
+        assert!(shell_refused("cmd: Can't find service: bluetooth_manager"));
+        assert!(!shell_refused(""));
+
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        let mut action: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "set_airplane", "params": {"enabled": true}, "classification": "GREEN",
+        }))
+        .unwrap();
+        assert_eq!(executor.effective_classification(&action), "RED");
+        action.action_type = "set_wifi".into();
+        assert_eq!(executor.effective_classification(&action), "YELLOW");
+    }
+
+    #[test]
+    fn test_tap_retry_target() {
+        let el = |index: usize, bounds: [i32; 4], clickable: bool| UiElement {
+            index,
+            class: "Button".into(),
+            text: format!("b{}", index),
+            desc: String::new(),
+            resource_id: String::new(),
+            center_x: (bounds[0] + bounds[2]) / 2,
+            center_y: (bounds[1] + bounds[3]) / 2,
+            bounds,
+            clickable,
+            editable: false,
+            focused: false,
+            scrollable: false,
+            checked: None,
+            enabled: true,
+            score: 0.0,
+        };
+        let screen = vec![
+            el(1, [0, 0, 1080, 2400], true),
+            el(2, [100, 100, 300, 200], true),
+            el(3, [120, 120, 180, 180], false),
+        ];
+        // The innermost clickable element wins; non-clickable ones are skipped
+        let hit = clickable_element_at(&screen, 150, 150).unwrap();
+        assert_eq!(hit.index, 2);
+        assert_eq!(clickable_element_at(&screen[1..], 500, 500).map(|e| e.index), None);
+
+        // Off-center taps retry at the center, centered ones a few px off it
+        assert_eq!(tap_retry_point(hit, 101, 199), (200, 150));
+        assert_eq!(tap_retry_point(hit, 200, 150), (203, 153));
+        assert_eq!(tap_retry_point(&el(4, [0, 0, 10, 10], true), 5, 5), (8, 8));
+
+        assert_eq!(screen_signature(&screen), screen_signature(&screen.clone()));
+        assert_ne!(screen_signature(&screen), screen_signature(&screen[1..]));
+    }
+
+    #[test]
+    fn test_recent_actions_prefer_failures() {
+        let entry = |action_type: &str, params: serde_json::Value, result: &str| ActionLogEntry {
+            timestamp: String::new(),
+            action_type: action_type.into(),
+            classification: "YELLOW".into(),
+            result: result.into(),
+            params,
+            preview: None,
+        };
+        let mut log = vec![entry("tap", serde_json::json!({"x": 540, "y": 1200}), "FAILED: adb: device offline")];
+        log.extend((0..4).map(|_| entry("back", serde_json::json!({}), "ok")));
+        log.push(entry("launch_app", serde_json::json!({"app": "whatsapp", "package": "com.whatsapp"}), "ok"));
+
+        let recent = format_recent_actions(&log, 3);
+        assert_eq!(
+            recent,
+            "- tap (540, 1200) → FAILED: adb: device offline\n- back → ok\n- launch_app com.whatsapp → ok"
+        );
+        assert!(format_recent_actions(&log, 0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_capture_result_summarized_in_log() {
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        let action: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "capture", "params": {}, "classification": "GREEN", "reason": "show the screen"
+        })).unwrap();
+        let result = format!("{}{}", SCREENSHOT_PREFIX, "A".repeat(4096));
+        assert_eq!(screenshot_data(&result).map(str::len), Some(4096));
+        assert_eq!(screenshot_data("tap OK"), None);
+
+        executor.log_action(&action, "GREEN", &result).await;
+        let log = executor.action_log();
+        let log = log.lock().await;
+        assert_eq!(log[0].result, "screenshot captured (3 KB)");
+    }
+
+    #[test]
+    fn test_coordinate_calibration_applied() {
+        let executor = ActionExecutor::new(false, None, vec![], None).with_calibration(
+            CoordinateCalibration { offset_x: 10, offset_y: -25, scale: 1.0 },
+        );
+        let params = serde_json::json!({"x": 100, "y": 220, "x1": 0, "y1": 0});
+        assert_eq!(executor.point(&params, "x", "y"), ("110".into(), "195".into()));
+        assert_eq!(executor.point(&params, "x1", "y1"), ("10".into(), "-25".into()));
+
+        let scaled = CoordinateCalibration { offset_x: 0, offset_y: 0, scale: 1.5 };
+        assert_eq!(scaled.apply(100.0, 200.0), (150, 300));
+    }
+
+    #[test]
+    fn test_app_calibration_replaces_global() {
+        let profile = crate::perception::AppCalibration { x_offset: 5, y_offset: 0, x_scale: 1.0, y_scale: 1.0 };
+        let perception = Perception::new(Some("hermitdroid-test-missing".into()), vec![])
+            .with_app_calibrations(HashMap::from([("com.example.game".to_string(), profile)]));
+        let executor = ActionExecutor::new(false, None, vec![], Some(perception.clone())).with_calibration(
+            CoordinateCalibration { offset_x: 10, offset_y: -25, scale: 1.0 },
+        );
+        let mut result = crate::sanitizer::PerceptionResult {
+            screen: crate::sanitizer::SanitizedScreen {
+                elements: Vec::new(),
+                total_found: 0,
+                foreground_package: Some("com.example.game".into()),
+                needs_vision_fallback: false,
+                raw_count: 0,
+                interactive_count: 0,
+            },
+            screenshot_base64: None,
+            resolution: None,
+            formatted_text: String::new(),
+            used_vision: false,
+            screen_unchanged: false,
+            screenshot_scale: None,
+        };
+        let params = serde_json::json!({"x": 100, "y": 220});
+
+        // The game's coordinates already went through its profile
+        perception.calibrate_result(&mut result);
+        assert_eq!(executor.point(&params, "x", "y"), ("100".into(), "220".into()));
+
+        // Other apps keep the global offset
+        result.screen.foreground_package = Some("com.example.other".into());
+        perception.calibrate_result(&mut result);
+        assert_eq!(executor.point(&params, "x", "y"), ("110".into(), "195".into()));
+    }
+
+    #[tokio::test]
+    async fn test_companion_actions_acked_or_expired() {
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        let mut action = notify_action();
+        action.action_type = "open_drawer".into();
+        action.classification = "GREEN".into();
+        executor.execute(&action).await.unwrap();
+        executor.execute(&action).await.unwrap();
+
+        let sent: Vec<DeviceAction> = executor.outgoing().lock().await.drain(..).collect();
+        assert_eq!(sent.len(), 2);
+        for a in &sent {
+            executor.mark_sent(&a.id).await;
+        }
+
+        assert_eq!(executor.acknowledge(&sent[0].id, false, "no drawer").await.as_deref(), Some("open_drawer"));
+        assert!(executor.acknowledge(&sent[0].id, true, "again").await.is_none());
+        let expired = executor.expire_unacknowledged(std::time::Duration::ZERO).await;
+        assert_eq!(expired, vec![(sent[1].id.clone(), "open_drawer".to_string())]);
+
+        // Queued with no companion to send it to: dropped from the queue too
+        executor.execute(&action).await.unwrap();
+        assert!(executor.expire_unacknowledged(std::time::Duration::from_secs(60)).await.is_empty());
+        assert_eq!(executor.expire_unacknowledged(std::time::Duration::ZERO).await.len(), 1);
+        assert!(executor.outgoing().lock().await.is_empty());
+
+        let log = executor.action_log().lock().await.clone();
+        assert!(log.iter().any(|e| e.result == "companion failed: no drawer"));
+        assert!(log.iter().any(|e| e.result == "no response from companion"));
+        assert!(log.iter().any(|e| e.result == "not sent: no companion connected"));
+    }
+
+    #[tokio::test]
+    async fn test_type_text_index_must_be_on_screen() {
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        let mut action = notify_action();
+        action.action_type = "type_text".into();
+        action.classification = "YELLOW".into();
+        action.params = serde_json::json!({"text": "hello", "index": 3});
+        let err = executor.execute(&action).await.unwrap_err().to_string();
+        assert!(err.contains("no element [3]"), "{}", err);
+    }
+
+    fn notify_action() -> AgentAction {
+        serde_json::from_value(serde_json::json!({
+            "type": "notify_user",
+            "params": {"text": "hello"},
+            "classification": "RED",
+            "reason": "test",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_double_confirm_is_noop() {
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        executor.pending().lock().await.push(PendingConfirmation {
+            action_id: "abc".into(),
+            action: notify_action(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            confirmed: None,
+        });
+
+        assert!(executor.confirm("abc", true).await.is_ok());
+        assert!(executor.pending().lock().await.is_empty());
+
+        let second = executor.confirm("abc", true).await;
+        assert!(second.unwrap_err().to_string().contains("already executed"));
+        assert_eq!(executor.action_log().lock().await.len(), 1);
+
+        // A YELLOW action queued for low confidence isn't logged as RED
+        let mut action = notify_action();
+        action.classification = "YELLOW".into();
+        executor.pending().lock().await.push(PendingConfirmation {
+            action_id: "def".into(),
+            action,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            confirmed: None,
+        });
+        executor.confirm("def", true).await.unwrap();
+        let log = executor.action_log();
+        let log = log.lock().await;
+        assert_eq!(log[0].classification, "RED-CONFIRMED");
+        assert_eq!(log[1].classification, "YELLOW-CONFIRMED");
+    }
+
+    #[test]
+    fn test_device_notification_post() {
+        let args = notification_post_args("hermitdroid", "Hermit", "it's done");
+        assert_eq!(args[..4], ["shell", "cmd", "notification", "post"]);
+        assert_eq!(args[7..], ["'Hermit'", "hermitdroid", "'it'\\''s done'"]);
+        assert!(notification_post_result(String::new()).is_ok());
+        let rejected = "java.lang.SecurityException: Permission Denial".to_string();
+        assert!(notification_post_result(rejected).is_err());
+        assert!(notification_post_result("Unknown command: post".into()).is_err());
+    }
+
+    #[test]
+    fn test_type_text_routing() {
+        assert!(!needs_clipboard("hello world"));
+        assert!(!needs_clipboard("user@example.com"));
+        assert!(!needs_clipboard("See you at 5:30, ok"));
+        assert!(needs_clipboard("ok?"));
+        assert!(needs_clipboard("héllo"));
+        assert!(needs_clipboard("👍"));
+        assert!(needs_clipboard("你好"));
+        assert!(needs_clipboard("it's $5 (cash)"));
+        assert!(needs_clipboard("line one\nline two"));
+        assert!(needs_clipboard("100%s"));
+    }
+
+    #[tokio::test]
+    async fn test_expire_stale_denies_old_confirmations() {
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        for (id, age_secs) in [("old", 120), ("new", 5)] {
+            executor.pending().lock().await.push(PendingConfirmation {
+                action_id: id.into(),
+                action: notify_action(),
+                timestamp: (chrono::Utc::now() - chrono::Duration::seconds(age_secs)).to_rfc3339(),
+                confirmed: None,
+            });
+        }
+
+        let expired = executor.expire_stale(std::time::Duration::from_secs(60)).await;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].action_id, "old");
+        assert_eq!(expired[0].confirmed, Some(false));
+
+        let pending = executor.pending();
+        let pending = pending.lock().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].action_id, "new");
+        drop(pending);
+        let late = executor.confirm("old", true).await;
+        assert!(late.unwrap_err().to_string().contains("already denied"));
+    }
+
+    #[test]
+    fn test_sentinel_answer() {
+        assert_eq!(sentinel_answer("", "ab12cd34"), None);
+        assert_eq!(sentinel_answer("ab12cd34.approve\n", "ab12cd34"), Some(true));
+        assert_eq!(sentinel_answer("ab12cd34.deny", "ab12cd34"), Some(false));
+        assert_eq!(sentinel_answer("ab12cd34.approve\nab12cd34.deny", "ab12cd34"), Some(false));
+        assert_eq!(sentinel_answer("ff00ff00.approve", "ab12cd34"), None);
+    }
+
+    #[test]
+    fn test_is_launcher() {
+        let nexus = Some("com.google.android.apps.nexuslauncher");
+        assert!(is_launcher("com.google.android.apps.nexuslauncher", nexus));
+        assert!(!is_launcher("com.android.launcher3", nexus));
+        assert!(!is_launcher("com.whatsapp", nexus));
+        // Unresolved home activity → name heuristic
+        assert!(is_launcher("com.sec.android.app.launcher", None));
+        assert!(is_launcher("com.miui.home", None));
+        assert!(!is_launcher("com.whatsapp", None));
+    }
+
+    #[tokio::test]
+    async fn test_min_interval_throttles_actions() {
+        let executor = ActionExecutor::new(false, None, vec![], None).with_min_interval_ms(100);
+        let wait: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "wait",
+            "params": {"ms": 0},
+            "classification": "GREEN",
+            "reason": "test",
+        }))
+        .unwrap();
+        let start = std::time::Instant::now();
+        executor.execute(&wait).await.unwrap();
+        executor.execute(&wait).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_actions_wait_for_device_lock() {
+        let executor = ActionExecutor::new(false, None, vec![], None);
+        let wait: AgentAction = serde_json::from_value(serde_json::json!({
+            "type": "wait",
+            "params": {"ms": 0},
+            "classification": "GREEN",
+            "reason": "test",
+        }))
+        .unwrap();
+        let device_lock = executor.device_lock();
+        let held = device_lock.lock().await;
+        let short = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(short, executor.execute(&wait)).await.is_err());
+        drop(held);
+        assert!(tokio::time::timeout(short, executor.execute(&wait)).await.is_ok());
+    }
+}
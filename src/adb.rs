@@ -0,0 +1,356 @@
+use serde::Serialize;
+use std::fmt;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default for `perception.adb_timeout_secs`
+pub const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT_SECS);
+
+/// Set the timeout applied by [`output`] and [`output_async`] (from
+/// `perception.adb_timeout_secs`; 0 keeps the default)
+pub fn set_timeout(secs: u64) {
+    if secs > 0 {
+        TIMEOUT_SECS.store(secs, Ordering::Relaxed);
+    }
+}
+
+pub fn timeout() -> Duration {
+    Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed))
+}
+
+/// Why an `adb` invocation failed. Perception and action helpers return this
+/// so callers can tell a missing device from a failing command; `?` still
+/// turns it into `anyhow::Error`, and `downcast_ref::<AdbError>()` gets it back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdbError {
+    /// No device connected, or the `-s` serial isn't attached
+    NoDevice,
+    /// The device is attached but offline or unauthorized
+    Offline,
+    /// The `adb` binary isn't installed / not in PATH
+    NotFound,
+    /// adb ran but the command failed
+    CommandFailed { code: Option<i32>, stderr: String },
+    /// adb didn't answer in time
+    Timeout,
+}
+
+impl AdbError {
+    /// Classify a non-zero exit from its stderr
+    pub fn from_output(code: Option<i32>, stderr: &str) -> Self {
+        let lower = stderr.to_lowercase();
+        if lower.contains("no devices/emulators found") || (lower.contains("error: device") && lower.contains("not found")) {
+            AdbError::NoDevice
+        } else if lower.contains("device offline") || lower.contains("unauthorized") {
+            AdbError::Offline
+        } else {
+            AdbError::CommandFailed { code, stderr: stderr.trim().to_string() }
+        }
+    }
+
+    /// Failure to start `adb` at all
+    pub fn from_io(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            AdbError::NotFound
+        } else {
+            AdbError::CommandFailed { code: None, stderr: e.to_string() }
+        }
+    }
+
+    /// The device itself is unusable, not just this command
+    pub fn is_device_error(&self) -> bool {
+        !matches!(self, AdbError::CommandFailed { .. })
+    }
+
+    /// Retrying won't help until someone fixes the setup
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, AdbError::NotFound)
+    }
+
+    /// What the user can do about it
+    pub fn hint(&self) -> &'static str {
+        match self {
+            AdbError::NoDevice => "connect the phone over USB or run `adb connect <ip>:<port>`",
+            AdbError::Offline => "accept the USB debugging prompt on the phone, or run `adb reconnect`",
+            AdbError::NotFound => "install Android platform-tools and put `adb` in PATH",
+            AdbError::Timeout => "the connection stalled; check Wi-Fi/Tailscale or replug the cable",
+            AdbError::CommandFailed { .. } => "the device rejected the command",
+        }
+    }
+}
+
+impl fmt::Display for AdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdbError::NoDevice => write!(f, "adb: no device connected"),
+            AdbError::Offline => write!(f, "adb: device offline or unauthorized"),
+            AdbError::NotFound => write!(f, "adb: not found in PATH"),
+            AdbError::CommandFailed { stderr, .. } if stderr.is_empty() => write!(f, "adb error: unknown error"),
+            AdbError::CommandFailed { stderr, .. } => write!(f, "adb error: {}", stderr),
+            AdbError::Timeout => write!(f, "adb: timed out"),
+        }
+    }
+}
+
+impl std::error::Error for AdbError {}
+
+/// `cmd.output()` with the configured timeout. A hung `uiautomator dump`
+/// would otherwise block the heartbeat forever.
+pub fn output(cmd: &mut Command) -> Result<Output, AdbError> {
+    output_with_timeout(cmd, timeout())
+}
+
+/// Run `cmd` to completion, killing it from a watchdog thread if it
+/// outlives `limit`
+pub fn output_with_timeout(cmd: &mut Command, limit: Duration) -> Result<Output, AdbError> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(AdbError::from_io)?;
+    let mut stdout = child.stdout.take();
+    let mut stderr = child.stderr.take();
+    let child = Arc::new(Mutex::new(child));
+
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let watchdog = {
+        let child = child.clone();
+        std::thread::spawn(move || {
+            let expired = done_rx.recv_timeout(limit) == Err(RecvTimeoutError::Timeout);
+            if expired {
+                let _ = child.lock().unwrap().kill();
+            }
+            expired
+        })
+    };
+
+    // Drain stderr on its own thread so a chatty command can't fill the pipe
+    let err_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(e) = stderr.as_mut() {
+            let _ = e.read_to_end(&mut buf);
+        }
+        buf
+    });
+    let mut out = Vec::new();
+    if let Some(o) = stdout.as_mut() {
+        let _ = o.read_to_end(&mut out);
+    }
+    let err = err_reader.join().unwrap_or_default();
+
+    // Poll instead of `wait()` so the watchdog can still take the lock
+    let status = loop {
+        if let Some(status) = child.lock().unwrap().try_wait().map_err(AdbError::from_io)? {
+            break status;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let _ = done_tx.send(());
+    if watchdog.join().unwrap_or(false) {
+        return Err(AdbError::Timeout);
+    }
+    Ok(Output { status, stdout: out, stderr: err })
+}
+
+/// Async `output`: the child is killed when the timeout drops it
+pub async fn output_async(cmd: &mut tokio::process::Command) -> Result<Output, AdbError> {
+    tokio::time::timeout(timeout(), cmd.kill_on_drop(true).output())
+        .await
+        .map_err(|_| AdbError::Timeout)?
+        .map_err(AdbError::from_io)
+}
+
+/// The state column of `adb devices`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceState {
+    /// Ready for commands
+    Device,
+    /// The phone hasn't accepted this computer's RSA key yet
+    Unauthorized,
+    Offline,
+    /// bootloader, recovery, sideload, ...
+    Other(String),
+}
+
+impl DeviceState {
+    pub fn parse(state: &str) -> Self {
+        match state.trim() {
+            "device" => DeviceState::Device,
+            "unauthorized" => DeviceState::Unauthorized,
+            "offline" => DeviceState::Offline,
+            other => DeviceState::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            DeviceState::Device => "device",
+            DeviceState::Unauthorized => "unauthorized",
+            DeviceState::Offline => "offline",
+            DeviceState::Other(s) => s,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        *self == DeviceState::Device
+    }
+
+    /// What to do about a device that isn't ready
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            DeviceState::Device => None,
+            DeviceState::Unauthorized => Some("accept the RSA prompt on your phone"),
+            DeviceState::Offline => Some("run `adb reconnect`, or replug the cable"),
+            DeviceState::Other(_) => Some("boot the phone normally"),
+        }
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `(serial, state)` for each line of `adb devices`, e.g. `("emulator-5554", Device)`
+pub fn list_devices() -> Result<Vec<(String, DeviceState)>, AdbError> {
+    let out = output(Command::new("adb").arg("devices"))?;
+    if !out.status.success() {
+        return Err(AdbError::from_output(out.status.code(), &String::from_utf8_lossy(&out.stderr)));
+    }
+    Ok(parse_devices(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Where `Capabilities::probe` test-dumps the UI tree (removed right after)
+const PROBE_DUMP_PATH: &str = "/sdcard/hermitdroid_probe.xml";
+
+/// Which shell commands the device supports. Android versions and ROMs
+/// differ on `dumpsys`, `cmd statusbar`, `uiautomator` and `cmd clipboard`;
+/// the executor skips or substitutes what's missing instead of failing on
+/// every use. Until probed, everything is assumed to work.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Capabilities {
+    /// `dumpsys activity activities` shows the resumed activity
+    pub dumpsys_activity: bool,
+    /// `uiautomator dump` writes the UI tree
+    pub uiautomator: bool,
+    /// `cmd statusbar expand-notifications`
+    pub statusbar: bool,
+    /// `cmd clipboard set-primary-clip`
+    pub clipboard: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities { dumpsys_activity: true, uiautomator: true, statusbar: true, clipboard: true }
+    }
+}
+
+impl Capabilities {
+    /// Run each probe once. Errors only when the device itself is unreachable.
+    pub fn probe(device: &Option<String>) -> Result<Self, AdbError> {
+        let check = |args: &[&str], expect: &str| -> Result<bool, AdbError> {
+            let mut cmd = Command::new("adb");
+            if let Some(d) = device {
+                cmd.args(["-s", d]);
+            }
+            let out = match output(cmd.arg("shell").args(args)) {
+                Ok(out) => out,
+                Err(AdbError::Timeout) => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            if !out.status.success() {
+                let err = AdbError::from_output(out.status.code(), &stderr);
+                if err.is_device_error() {
+                    return Err(err);
+                }
+            }
+            Ok(String::from_utf8_lossy(&out.stdout).contains(expect) || stderr.contains(expect))
+        };
+        Ok(Capabilities {
+            dumpsys_activity: check(&["dumpsys", "activity", "activities"], "ResumedActivity")?,
+            // `adb shell` runs this through the device shell, so the probe dump is removed again
+            uiautomator: check(&["uiautomator", "dump", PROBE_DUMP_PATH, ";", "rm", "-f", PROBE_DUMP_PATH], "dumped")?,
+            statusbar: check(&["cmd", "statusbar", "help"], "expand-notifications")?,
+            clipboard: check(&["cmd", "clipboard", "help"], "set-primary-clip")?,
+        })
+    }
+
+    pub fn list(&self) -> [(&'static str, bool); 4] {
+        [
+            ("dumpsys activity", self.dumpsys_activity),
+            ("uiautomator", self.uiautomator),
+            ("statusbar expand", self.statusbar),
+            ("clipboard", self.clipboard),
+        ]
+    }
+
+    /// e.g. `dumpsys activity: ok, uiautomator: ok, statusbar expand: unsupported, clipboard: ok`
+    pub fn summary(&self) -> String {
+        self.list()
+            .iter()
+            .map(|(name, ok)| format!("{}: {}", name, if *ok { "ok" } else { "unsupported" }))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn parse_devices(raw: &str) -> Vec<(String, DeviceState)> {
+    raw.lines()
+        .skip_while(|l| !l.starts_with("List of devices"))
+        .skip(1)
+        .filter_map(|l| l.split_once('\t'))
+        .map(|(serial, state)| (serial.trim().to_string(), DeviceState::parse(state)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_adb_errors() {
+        assert_eq!(AdbError::from_output(Some(1), "error: no devices/emulators found"), AdbError::NoDevice);
+        assert_eq!(AdbError::from_output(Some(1), "error: device '192.168.1.5:5555' not found"), AdbError::NoDevice);
+        assert_eq!(AdbError::from_output(Some(1), "error: device offline"), AdbError::Offline);
+        assert_eq!(AdbError::from_output(Some(1), "error: device unauthorized."), AdbError::Offline);
+        assert!(matches!(
+            AdbError::from_output(Some(255), "/system/bin/sh: foo: inaccessible or not found"),
+            AdbError::CommandFailed { code: Some(255), .. }
+        ));
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "No such file");
+        assert!(AdbError::from_io(io).is_fatal());
+
+        let devices = parse_devices(
+            "* daemon started *\nList of devices attached\nemulator-5554\tdevice\n10.0.0.2:5555\toffline\nR58M\tunauthorized\n\n",
+        );
+        assert_eq!(devices, vec![
+            ("emulator-5554".to_string(), DeviceState::Device),
+            ("10.0.0.2:5555".to_string(), DeviceState::Offline),
+            ("R58M".to_string(), DeviceState::Unauthorized),
+        ]);
+        assert_eq!(DeviceState::Unauthorized.hint(), Some("accept the RSA prompt on your phone"));
+        assert!(DeviceState::Offline.hint().unwrap().contains("adb reconnect"));
+        assert_eq!(DeviceState::parse("recovery").as_str(), "recovery");
+    }
+
+    #[test]
+    fn test_output_times_out_slow_command() {
+        let start = std::time::Instant::now();
+        let slow = output_with_timeout(Command::new("sleep").arg("5"), Duration::from_millis(200));
+        assert_eq!(slow.unwrap_err(), AdbError::Timeout);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let fast = output_with_timeout(Command::new("echo").arg("hi"), Duration::from_secs(5)).unwrap();
+        assert!(fast.status.success());
+        assert_eq!(String::from_utf8_lossy(&fast.stdout).trim(), "hi");
+    }
+}
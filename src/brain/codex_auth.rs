@@ -0,0 +1,194 @@
+//! Codex OAuth token expiry and refresh. The `codex` CLI refreshes
+//! `~/.codex/auth.json` only while it runs; with `brain.codex_auto_refresh`
+//! we run the same refresh-token exchange ourselves and write the new
+//! tokens back, so the file stays usable by both.
+
+use super::Brain;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use std::sync::atomic::Ordering;
+use tracing::{info, warn};
+
+/// OpenAI's token endpoint and the Codex CLI's public client id
+const CODEX_REFRESH_URL: &str = "https://auth.openai.com/oauth/token";
+const CODEX_CLIENT_ID: &str = "app_EMoamEEZ73f0CkXaXp7hrann";
+
+/// A token expiring within this long is refreshed (or warned about) by the heartbeat
+const CODEX_EXPIRY_MARGIN_SECS: i64 = 15 * 60;
+
+/// `brain.codex_auth_path`, or `~/.codex/auth.json`
+pub(super) fn auth_path(custom_path: &Option<String>) -> String {
+    custom_path.clone().unwrap_or_else(|| {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/root".to_string());
+        format!("{}/.codex/auth.json", home)
+    })
+}
+
+/// The `exp` claim of a JWT access token (None for API keys and opaque tokens)
+fn token_expiry(token: &str) -> Option<DateTime<Utc>> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    DateTime::from_timestamp(claims["exp"].as_i64()?, 0)
+}
+
+/// Exchange the auth file's refresh token for new tokens at `url` and write
+/// them back (other fields untouched). Returns the new access token.
+async fn refresh_auth_file(client: &reqwest::Client, path: &str, url: &str) -> anyhow::Result<String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read Codex auth file {}: {}", path, e))?;
+    let mut auth: serde_json::Value = serde_json::from_str(&content)?;
+    let refresh_token = auth["tokens"]["refresh_token"]
+        .as_str()
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Codex auth file has no refresh_token. Run `codex login`."))?
+        .to_string();
+
+    let resp = client
+        .post(url)
+        .json(&serde_json::json!({
+            "client_id": CODEX_CLIENT_ID,
+            "grant_type": "refresh_token",
+            "refresh_token": refresh_token,
+            "scope": "openid profile email",
+        }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        anyhow::bail!(
+            "Codex token refresh failed ({}): {}. Run `codex login`.",
+            resp.status(),
+            resp.text().await.unwrap_or_default()
+        );
+    }
+    let fresh: serde_json::Value = resp.json().await?;
+    let access_token = fresh["access_token"]
+        .as_str()
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Codex token refresh returned no access_token"))?
+        .to_string();
+
+    for key in ["access_token", "refresh_token", "id_token"] {
+        if let Some(value) = fresh[key].as_str() {
+            auth["tokens"][key] = value.into();
+        }
+    }
+    auth["last_refresh"] = Utc::now().to_rfc3339().into();
+    // Write-then-rename so a running `codex` never reads half a file
+    let tmp = format!("{}.tmp", path);
+    let _ = std::fs::remove_file(&tmp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // The tokens are secrets: keep the 0600 `codex login` gives auth.json
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    std::io::Write::write_all(&mut file, serde_json::to_string_pretty(&auth)?.as_bytes())?;
+    drop(file);
+    std::fs::rename(&tmp, path)?;
+    Ok(access_token)
+}
+
+impl Brain {
+    fn is_codex(&self) -> bool {
+        matches!(self.config.backend.as_str(), "codex" | "codex_oauth")
+    }
+
+    /// Refresh the auth file's tokens and cache the new access token
+    pub(super) async fn refresh_codex_token(&self) -> anyhow::Result<String> {
+        let path = auth_path(&self.config.codex_auth_path);
+        let token = refresh_auth_file(&self.client, &path, CODEX_REFRESH_URL).await?;
+        self.cache_codex_token(&token).await;
+        info!("🔑 Codex OAuth: token refreshed ({})", path);
+        Ok(token)
+    }
+
+    /// Heartbeat check of the Codex token's expiry: refresh it when it's
+    /// about to run out (`brain.codex_auto_refresh`), else warn once per token
+    pub async fn check_codex_token(&self) {
+        if !self.is_codex() {
+            return;
+        }
+        let Some(expiry) = self.get_codex_token().await.ok().as_deref().and_then(token_expiry) else {
+            return;
+        };
+        let left = expiry - Utc::now();
+        if left.num_seconds() > CODEX_EXPIRY_MARGIN_SECS {
+            return;
+        }
+        if self.config.codex_auto_refresh {
+            if let Err(e) = self.refresh_codex_token().await {
+                warn!("🔑 {}", e);
+            }
+        } else if self.codex_expiry_warned.swap(expiry.timestamp(), Ordering::Relaxed) != expiry.timestamp() {
+            let when = if left.num_seconds() <= 0 {
+                "has expired".to_string()
+            } else {
+                format!("expires in {} min", left.num_minutes())
+            };
+            warn!(
+                "🔑 Codex OAuth: token {} — run `codex login` or set brain.codex_auto_refresh = true",
+                when
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(exp: i64) -> String {
+        let b64 = |v: serde_json::Value| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(v.to_string());
+        format!("{}.{}.sig", b64(serde_json::json!({"alg": "RS256"})), b64(serde_json::json!({"exp": exp})))
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rewrites_auth_file() {
+        use axum::{routing::post, Json, Router};
+
+        assert_eq!(token_expiry(&jwt(1_800_000_000)).map(|t| t.timestamp()), Some(1_800_000_000));
+        assert_eq!(token_expiry("sk-not-a-jwt"), None);
+
+        let app = Router::new().route(
+            "/oauth/token",
+            post(|Json(body): Json<serde_json::Value>| async move {
+                assert_eq!(body["grant_type"], "refresh_token");
+                assert_eq!(body["refresh_token"], "old-refresh");
+                Json(serde_json::json!({"access_token": "new-access", "refresh_token": "new-refresh"}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let path = std::env::temp_dir().join(format!("hermitdroid-codex-{}.json", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            r#"{"OPENAI_API_KEY": null, "tokens": {"access_token": "old", "refresh_token": "old-refresh", "id_token": "id", "account_id": "acct"}}"#,
+        )
+        .unwrap();
+
+        let client = reqwest::Client::new();
+        let token = refresh_auth_file(&client, path, &format!("http://{}/oauth/token", addr)).await.unwrap();
+        assert_eq!(token, "new-access");
+        let auth: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(auth["tokens"]["access_token"], "new-access");
+        assert_eq!(auth["tokens"]["refresh_token"], "new-refresh");
+        assert_eq!(auth["tokens"]["id_token"], "id");
+        assert_eq!(auth["tokens"]["account_id"], "acct");
+        assert!(auth["last_refresh"].is_string());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -1,11 +1,16 @@
-use crate::config::BrainConfig;
+use crate::config::{BrainConfig, Config};
 use crate::soul::BootstrapContext;
-use crate::fallback::{FallbackManager, ModelConfig, FallbackConfig};
+use crate::fallback::{ErrorClass, FallbackManager, ModelConfig, FallbackConfig};
+use crate::metrics::Metrics;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+mod codex_auth;
+mod summary;
+
 // ── Codex OAuth types ───────────────────────────────────────────────────────
 
 /// Codex OAuth token data read from ~/.codex/auth.json
@@ -42,6 +47,32 @@ pub struct Brain {
     codex_token: Arc<RwLock<Option<CachedCodexToken>>>,
     /// Model fallback manager (OpenClaw-inspired)
     fallback_mgr: Arc<RwLock<Option<FallbackManager>>>,
+    metrics: Option<Arc<Metrics>>,
+    /// Tell the model only GREEN actions will run (`action.safe_mode`)
+    safe_mode: bool,
+    /// This call expects an AgentResponse: send its schema (`brain.structured_output`)
+    structured: bool,
+    /// Set once the endpoint rejected the schema; later calls skip it
+    structured_unsupported: Arc<AtomicBool>,
+    /// Receives reply text as it arrives (`run --verbose`)
+    stream_sink: Option<DeltaSink>,
+    /// `brain.screen_summary` results by screen text hash
+    summary_cache: Arc<summary::SummaryCache>,
+    /// Expiry (unix seconds) of the Codex token already warned about
+    codex_expiry_warned: Arc<AtomicI64>,
+    /// Where action prompts are written before the call (`--dump-prompt`)
+    prompt_dump: Option<PromptDump>,
+}
+
+/// Callback for `Brain::with_stream_sink`: SSE backends (Codex) call it per
+/// text delta, the rest once with the whole reply
+#[derive(Clone)]
+pub struct DeltaSink(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl std::fmt::Debug for DeltaSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DeltaSink")
+    }
 }
 
 // ── Response types ──────────────────────────────────────────────────────────
@@ -77,9 +108,143 @@ pub struct AgentAction {
     pub text: Option<String>,
     #[serde(default)]
     pub app: Option<String>,
+    /// Model's self-assessed confidence (0.0–1.0). Absent → fully confident,
+    /// so older prompts and models keep working unchanged.
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
 }
 
 fn default_green() -> String { "GREEN".into() }
+fn default_confidence() -> f32 { 1.0 }
+
+impl AgentResponse {
+    /// For a reply that was only prose (`parse_response`'s fallback): no
+    /// actions, no message, not HEARTBEAT_OK. Returns the prose, now also the
+    /// message, so the user sees what the model said instead of nothing.
+    pub fn prose_to_message(&mut self) -> Option<&str> {
+        if !self.actions.is_empty() || self.message.is_some() {
+            return None;
+        }
+        let prose = self.reflection.as_deref().map(str::trim).filter(|r| !r.is_empty() && *r != "HEARTBEAT_OK")?;
+        self.message = Some(prose.to_string());
+        self.message.as_deref()
+    }
+}
+
+impl AgentAction {
+    /// Check that known action types carry the params the executor reads,
+    /// so a `tap` without coordinates is skipped instead of tapping (0,0).
+    /// Unknown types pass through (they go to the companion app).
+    pub fn validate(&self) -> Result<(), String> {
+        let p = &self.params;
+        let number = |k: &str| p.get(k).is_some_and(|v| v.is_number());
+        let string = |k: &str| p.get(k).and_then(|v| v.as_str()).is_some_and(|s| !s.is_empty());
+        let missing: Vec<&str> = match self.action_type.as_str() {
+            "tap" => ["x", "y"].into_iter().filter(|k| !number(k)).collect(),
+            // An element index (number or numeric string) or an x/y pair, like `PressTarget`
+            "long_press" | "open_context_menu" => {
+                let index = p.get("index").is_some_and(|v| {
+                    v.is_u64() || v.as_str().is_some_and(|s| s.trim().parse::<u64>().is_ok())
+                });
+                if index || (number("x") && number("y")) { Vec::new() } else { vec!["index or x, y"] }
+            }
+            "swipe" => ["x1", "y1", "x2", "y2"].into_iter().filter(|k| !number(k)).collect(),
+            "type_text" | "set_clipboard" => ["text"].into_iter().filter(|k| !string(k)).collect(),
+            "find_element" if !string("query") && !string("text") => vec!["query"],
+            "set_wifi" | "set_bluetooth" | "set_airplane" if !p.get("enabled").is_some_and(|v| v.is_boolean()) => {
+                vec!["enabled"]
+            }
+            "set_brightness" if !number("level") && p.get("auto").and_then(|v| v.as_bool()) != Some(true) => {
+                vec!["level or auto"]
+            }
+            "notification_action" if !string("action") && !string("label") => vec!["action"],
+            "notification_action" if !string("app") && !string("package") && !string("title") && self.app.is_none() => {
+                vec!["app or title"]
+            }
+            "wait_for_text" if !string("text") && self.text.as_deref().unwrap_or("").is_empty() => vec!["text"],
+            "launch_app" if !string("package") && !string("app") && self.app.as_deref().unwrap_or("").is_empty() => {
+                vec!["package or app"]
+            }
+            _ => Vec::new(),
+        };
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{} is missing {}", self.action_type, missing.join(", ")))
+        }
+    }
+
+    /// What the action acts on, for plan previews: coordinates, app, text or query
+    pub fn target(&self) -> Option<String> {
+        let p = &self.params;
+        let num = |k: &str| p.get(k).and_then(|v| v.as_i64());
+        let text = |k: &str| p.get(k).and_then(|v| v.as_str()).filter(|s| !s.is_empty()).map(str::to_string);
+        if let (Some(x1), Some(y1), Some(x2), Some(y2)) = (num("x1"), num("y1"), num("x2"), num("y2")) {
+            return Some(format!("({},{}) → ({},{})", x1, y1, x2, y2));
+        }
+        if let (Some(x), Some(y)) = (num("x").or(self.x.map(i64::from)), num("y").or(self.y.map(i64::from))) {
+            return Some(format!("({},{})", x, y));
+        }
+        ["package", "app", "text", "query"]
+            .into_iter()
+            .find_map(text)
+            .or_else(|| self.app.clone().filter(|s| !s.is_empty()))
+            .or_else(|| self.text.clone().filter(|s| !s.is_empty()))
+    }
+}
+
+/// The `{"type": "plan"}` event announced before a tick's actions run, with
+/// each action's class as `classify` (the executor's guardrails) sees it
+pub fn plan_event(actions: &[AgentAction], classify: impl Fn(&AgentAction) -> String) -> serde_json::Value {
+    let classes: Vec<String> = actions.iter().map(classify).collect();
+    let steps: Vec<serde_json::Value> = actions
+        .iter()
+        .zip(&classes)
+        .map(|(a, class)| serde_json::json!({
+            "type": a.action_type,
+            "target": a.target(),
+            "classification": class,
+            "reason": a.reason,
+        }))
+        .collect();
+    serde_json::json!({
+        "type": "plan",
+        "actions": steps,
+        "has_red": classes.iter().any(|c| c == "RED"),
+    })
+}
+
+/// `--dump-prompt FILE` / `--no-send`: write each action prompt to `target`
+/// ("-" = stdout) before the call, and with `no_send` stop there
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptDump {
+    pub target: String,
+    pub no_send: bool,
+}
+
+impl PromptDump {
+    pub fn from_args(target: Option<String>, no_send: bool) -> Option<Self> {
+        target.map(|target| Self { target, no_send })
+    }
+}
+
+/// Returned by `think_actions` instead of a reply under `--no-send`: the
+/// prompt was dumped and the model was never called
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptNotSent {
+    pub target: String,
+}
+
+impl std::fmt::Display for PromptNotSent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.target.as_str() {
+            "-" => f.write_str("prompt printed above, not sent to the model (--no-send)"),
+            path => write!(f, "prompt written to {}, not sent to the model (--no-send)", path),
+        }
+    }
+}
+
+impl std::error::Error for PromptNotSent {}
 
 /// Token cache duration — reload from disk every 7 minutes
 /// (Codex tokens refresh every ~8 minutes before expiry)
@@ -137,9 +302,43 @@ impl Brain {
                 .unwrap_or_default(),
             codex_token: Arc::new(RwLock::new(None)),
             fallback_mgr: Arc::new(RwLock::new(fallback_mgr)),
+            metrics: None,
+            safe_mode: false,
+            structured: false,
+            structured_unsupported: Arc::new(AtomicBool::new(false)),
+            stream_sink: None,
+            summary_cache: Arc::default(),
+            codex_expiry_warned: Arc::default(),
+            prompt_dump: None,
         }
     }
 
+    /// Brain for a `[[brain_profiles]]` entry (`None` / "default" = `[brain]`)
+    pub fn from_profile(config: &Config, name: Option<&str>) -> anyhow::Result<Self> {
+        Ok(Self::new(config.brain_profile(name)?))
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_safe_mode(mut self, safe_mode: bool) -> Self {
+        self.safe_mode = safe_mode;
+        self
+    }
+
+    pub fn with_prompt_dump(mut self, dump: Option<PromptDump>) -> Self {
+        self.prompt_dump = dump;
+        self
+    }
+
+    /// Forward reply text to `sink` while it streams in (see `DeltaSink`)
+    pub fn with_stream_sink(mut self, sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.stream_sink = Some(DeltaSink(Arc::new(sink)));
+        self
+    }
+
     pub fn model_name(&self) -> &str {
         &self.config.model
     }
@@ -148,10 +347,7 @@ impl Brain {
 
     /// Load the Codex access token from ~/.codex/auth.json (or custom path)
     fn load_codex_token_from_disk(custom_path: &Option<String>) -> Option<String> {
-        let path = custom_path.clone().unwrap_or_else(|| {
-            let home = std::env::var("HOME").unwrap_or_else(|_| "/root".to_string());
-            format!("{}/.codex/auth.json", home)
-        });
+        let path = codex_auth::auth_path(custom_path);
 
         let content = match std::fs::read_to_string(&path) {
             Ok(c) => c,
@@ -212,57 +408,35 @@ impl Brain {
                 )
             })?;
 
-        // Update cache
-        {
-            let mut cached = self.codex_token.write().await;
-            *cached = Some(CachedCodexToken {
-                access_token: token.clone(),
-                loaded_at: std::time::Instant::now(),
-            });
-        }
-
+        self.cache_codex_token(&token).await;
         info!("🔑 Codex OAuth: token refreshed from disk");
         Ok(token)
     }
 
+    async fn cache_codex_token(&self, token: &str) {
+        let mut cached = self.codex_token.write().await;
+        *cached = Some(CachedCodexToken {
+            access_token: token.to_string(),
+            loaded_at: std::time::Instant::now(),
+        });
+    }
+
     // ── Prompt builders ─────────────────────────────────────────────────
 
     /// Build the full system prompt from workspace bootstrap context
     pub fn build_system_prompt(&self, ctx: &BootstrapContext) -> String {
         let mut prompt = String::new();
 
-        if !ctx.soul.is_empty() {
-            prompt.push_str(&format!("--- SOUL.md ---\n{}\n\n", ctx.soul));
-        }
-        if !ctx.identity.is_empty() {
-            prompt.push_str(&format!("--- IDENTITY.md ---\n{}\n\n", ctx.identity));
-        }
-        if !ctx.agents.is_empty() {
-            prompt.push_str(&format!("--- AGENTS.md ---\n{}\n\n", ctx.agents));
-        }
-        if !ctx.tools.is_empty() {
-            prompt.push_str(&format!("--- TOOLS.md ---\n{}\n\n", ctx.tools));
-        }
-        if !ctx.user.is_empty() {
-            prompt.push_str(&format!("--- USER.md ---\n{}\n\n", ctx.user));
-        }
-        if !ctx.heartbeat.is_empty() {
-            prompt.push_str(&format!(
-                "--- HEARTBEAT.md ---\n{}\n\n",
-                ctx.heartbeat
-            ));
-        }
-        if let Some(bootstrap) = &ctx.bootstrap {
-            prompt.push_str(&format!(
-                "--- BOOTSTRAP.md (FIRST RUN) ---\n{}\n\n",
-                bootstrap
-            ));
-        }
-        for skill in &ctx.skills {
-            prompt.push_str(&format!(
-                "--- SKILL: {} ---\n{}\n\n",
-                skill.name, skill.content
-            ));
+        // Workspace files and skills, as laid out by `brain.prompt_sections`
+        let sections = match &self.config.prompt_sections {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => PROMPT_SECTIONS.to_vec(),
+        };
+        for name in sections {
+            let budget = self.config.prompt_max_chars.get(name).copied();
+            if let Some(section) = system_prompt_section(ctx, name, budget) {
+                prompt.push_str(&section);
+            }
         }
 
         // Vision instructions (when screenshots are enabled)
@@ -279,6 +453,19 @@ When a screenshot is attached to the screen state:
 7. Cross-reference the screenshot with the UI Tree for best accuracy
 8. For elements without UI Tree coordinates, estimate from their visual position in the screenshot
 
+"#,
+            );
+        }
+
+        if self.safe_mode {
+            prompt.push_str(
+                r#"--- SAFE MODE ---
+The agent is in SAFE MODE. Only read-only GREEN actions run: home, back, escape_to_home,
+scroll_up/scroll_down, open_notifications, wait, wait_for_text, find_element, where_am_i,
+read_full_screen, screenshot, capture and notify_user. Everything else (tap, type_text,
+launch_app, ...) and every YELLOW or RED action is refused with "BLOCKED (safe mode)" —
+do not plan them. If the task needs one, tell the user it can't be done in safe mode instead.
+
 "#,
             );
         }
@@ -292,6 +479,7 @@ When a screenshot is attached to the screen state:
         ctx: &BootstrapContext,
         notifications: &str,
         screen_state: &str,
+        recent_actions: &str,
         user_commands: &[String],
         now: &str,
     ) -> String {
@@ -316,6 +504,10 @@ When a screenshot is attached to the screen state:
         ));
         prompt.push_str(&format!("--- Screen State ---\n{}\n\n", screen_state));
 
+        if !recent_actions.is_empty() {
+            prompt.push_str(&format!("--- Recent Actions ---\n{}\n\n", recent_actions));
+        }
+
         if !user_commands.is_empty() {
             prompt.push_str("--- User Commands ---\n");
             for cmd in user_commands {
@@ -349,9 +541,88 @@ When a screenshot is attached to the screen state:
         user_prompt: &str,
         image_base64: Option<&str>,
     ) -> anyhow::Result<String> {
-        // Try primary model
+        self.think_with_overrides(system_prompt, user_prompt, image_base64, None, None)
+            .await
+    }
+
+    /// `think_with_overrides` for prompts answered with an AgentResponse (ticks,
+    /// `run` steps). With `brain.structured_output`, backends that support
+    /// structured outputs are held to `agent_response_schema()`.
+    pub async fn think_actions(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        image_base64: Option<&str>,
+        model: Option<&str>,
+        thinking_level: Option<&str>,
+    ) -> anyhow::Result<String> {
+        if let Some(dump) = &self.prompt_dump {
+            let model = model.unwrap_or(&self.config.model);
+            write_prompt_dump(&dump.target, &format_prompt_dump(model, system_prompt, user_prompt, image_base64))?;
+            if dump.no_send {
+                return Err(PromptNotSent { target: dump.target.clone() }.into());
+            }
+        }
+        if !self.config.structured_output {
+            return self
+                .think_with_overrides(system_prompt, user_prompt, image_base64, model, thinking_level)
+                .await;
+        }
+        let structured = Self { structured: true, ..self.clone() };
+        structured
+            .think_with_overrides(system_prompt, user_prompt, image_base64, model, thinking_level)
+            .await
+    }
+
+    /// Like `think`, but a session's `model_override` and `thinking_level`
+    /// (if set) take precedence over `brain.model` / `brain.thinking` from config.
+    /// The override model runs on the configured backend and endpoint.
+    pub async fn think_with_overrides(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        image_base64: Option<&str>,
+        model: Option<&str>,
+        thinking_level: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let overridden;
+        let brain = match model.filter(|m| !m.is_empty() && *m != self.config.model) {
+            Some(m) => {
+                debug!("Session model override: {}", m);
+                let mut b = self.clone();
+                b.config.model = m.to_string();
+                overridden = b;
+                &overridden
+            }
+            None => self,
+        };
+        let result = brain
+            .think_with_fallback(system_prompt, user_prompt, image_base64, thinking_level)
+            .await;
+        if let Some(m) = &self.metrics {
+            m.record_llm_call(result.is_ok());
+        }
+        result
+    }
+
+    async fn think_with_fallback(
+        &self,
+        system_prompt: &str,
+        user_prompt: &str,
+        image_base64: Option<&str>,
+        thinking_level: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let thinking = self.reasoning_effort(thinking_level);
+
+        // Try primary model (with retries for transient errors)
         match self
-            .call_backend(&self.config.backend, system_prompt, user_prompt, image_base64)
+            .call_backend_with_retry(
+                &self.config.backend,
+                system_prompt,
+                user_prompt,
+                image_base64,
+                thinking,
+            )
             .await
         {
             Ok(response) => {
@@ -375,6 +646,9 @@ When a screenshot is attached to the screen state:
                 };
 
                 if let Some(fb) = fallback_model {
+                    if let Some(m) = &self.metrics {
+                        m.record_fallback();
+                    }
                     warn!(
                         "Primary model failed ({}), trying fallback: {}/{}",
                         error_str, fb.backend, fb.model
@@ -384,6 +658,7 @@ When a screenshot is attached to the screen state:
                         system_prompt,
                         user_prompt,
                         image_base64,
+                        thinking,
                     )
                     .await
                 } else {
@@ -393,6 +668,38 @@ When a screenshot is attached to the screen state:
         }
     }
 
+    /// Call a backend, retrying transient failures with exponential backoff.
+    /// A 429 carrying Retry-After is not retried here — the fallback manager
+    /// handles it instead of hammering the same endpoint.
+    async fn call_backend_with_retry(
+        &self,
+        backend: &str,
+        system: &str,
+        user: &str,
+        image: Option<&str>,
+        thinking: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let mut attempt: u32 = 0;
+        loop {
+            match self.call_backend(backend, system, user, image, thinking).await {
+                Ok(response) => return Ok(response),
+                Err(e) if attempt < self.config.max_retries && is_retryable(&e.to_string()) => {
+                    let delay = self
+                        .config
+                        .retry_backoff_ms
+                        .saturating_mul(1u64 << attempt.min(16));
+                    attempt += 1;
+                    warn!(
+                        "LLM call failed ({}), retry {}/{} in {}ms",
+                        e, attempt, self.config.max_retries, delay
+                    );
+                    tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     /// Route to the correct backend by name
     fn call_backend<'a>(
         &'a self,
@@ -400,20 +707,37 @@ When a screenshot is attached to the screen state:
         system: &'a str,
         user: &'a str,
         image: Option<&'a str>,
+        thinking: Option<&'a str>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<String>> + Send + 'a>>
     {
         Box::pin(async move {
             match backend {
-                "ollama" => self.ollama(system, user, image).await,
-                "groq" | "openai_compatible" | "llamacpp" => {
-                    self.openai_compat(system, user, image).await
-                }
-                "codex" | "codex_oauth" => self.codex_oauth(system, user, image).await,
+                "ollama" => self.ollama(system, user, image).await.inspect(|t| self.emit(t)),
+                "groq" | "openai_compatible" | "llamacpp" => self
+                    .openai_compat(system, user, image, thinking)
+                    .await
+                    .inspect(|t| self.emit(t)),
+                // Streams its deltas to the sink itself
+                "codex" | "codex_oauth" => self.codex_oauth(system, user, image, thinking).await,
                 other => anyhow::bail!("Unknown backend: {}", other),
             }
         })
     }
 
+    /// Resolve the reasoning effort to request: session override first, then
+    /// `brain.thinking`. `None` means "off" — the field is omitted entirely.
+    fn reasoning_effort<'a>(&'a self, session_level: Option<&'a str>) -> Option<&'a str> {
+        let level = session_level.unwrap_or(&self.config.thinking);
+        match level {
+            "low" | "medium" | "high" => Some(level),
+            "off" | "" => None,
+            other => {
+                warn!("Unknown thinking level '{}', treating as off", other);
+                None
+            }
+        }
+    }
+
     /// Call a specific model config (used for fallback models)
     async fn call_with_model_config(
         &self,
@@ -421,6 +745,7 @@ When a screenshot is attached to the screen state:
         system: &str,
         user: &str,
         image: Option<&str>,
+        thinking: Option<&str>,
     ) -> anyhow::Result<String> {
         let url = format!("{}/chat/completions", model.endpoint);
 
@@ -428,7 +753,7 @@ When a screenshot is attached to the screen state:
             if model.vision_enabled {
                 serde_json::json!([
                     {"type": "text", "text": user},
-                    {"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", img)}}
+                    {"type": "image_url", "image_url": {"url": image_data_url(img)}}
                 ])
             } else {
                 // Fallback doesn't support vision — send text only
@@ -438,7 +763,7 @@ When a screenshot is attached to the screen state:
             serde_json::json!(user)
         };
 
-        let body = serde_json::json!({
+        let mut body = serde_json::json!({
             "model": model.model,
             "messages": [
                 {"role": "system", "content": system},
@@ -447,10 +772,13 @@ When a screenshot is attached to the screen state:
             "max_tokens": self.config.max_tokens,
             "temperature": self.config.temperature,
         });
+        if let Some(effort) = thinking.filter(|_| is_reasoning_model(&model.model)) {
+            body["reasoning_effort"] = serde_json::json!(effort);
+        }
 
         let mut req = self.client.post(&url).json(&body);
         if !model.api_key.is_empty() {
-            req = req.header("Authorization", format!("Bearer {}", model.api_key));
+            req = req.header("Authorization", format!("Bearer {}", model.api_key.expose()));
         }
 
         let resp = req.send().await?;
@@ -464,22 +792,53 @@ When a screenshot is attached to the screen state:
             );
         }
         let result: serde_json::Value = resp.json().await?;
-        Ok(result["choices"][0]["message"]["content"]
+        let text = result["choices"][0]["message"]["content"]
             .as_str()
             .unwrap_or("")
-            .to_string())
+            .to_string();
+        self.emit(&text);
+        Ok(text)
+    }
+
+    /// Hand text to the stream sink, if any
+    fn emit(&self, text: &str) {
+        if let Some(DeltaSink(sink)) = &self.stream_sink {
+            sink(text);
+        }
     }
 
+    /// Appended to the tick prompt for the one re-prompt after an unparseable reply
+    pub const JSON_RETRY_INSTRUCTION: &'static str =
+        "Your last reply had no JSON. Respond ONLY with the JSON action object — no prose, no markdown.";
+
     // ── Response parsing ────────────────────────────────────────────────
 
     /// Parse raw LLM text into structured AgentResponse
     pub fn parse_response(&self, raw: &str) -> AgentResponse {
+        if let Some(resp) = self.try_parse_response(raw) {
+            return resp;
+        }
+        let trimmed = raw.trim();
+        warn!(
+            "Could not parse any JSON from LLM response (len={})",
+            trimmed.len()
+        );
+        AgentResponse {
+            reflection: Some(trimmed.chars().take(500).collect()),
+            message: None,
+            ..Default::default()
+        }
+    }
+
+    /// Like `parse_response`, but `None` when the text holds neither
+    /// HEARTBEAT_OK nor any recoverable JSON (worth re-prompting)
+    pub fn try_parse_response(&self, raw: &str) -> Option<AgentResponse> {
         let trimmed = raw.trim();
         if trimmed.contains("HEARTBEAT_OK") {
-            return AgentResponse {
+            return Some(AgentResponse {
                 reflection: Some("HEARTBEAT_OK".into()),
                 ..Default::default()
-            };
+            });
         }
 
         let sanitized = sanitize_llm_json(trimmed);
@@ -487,7 +846,7 @@ When a screenshot is attached to the screen state:
         // Try normal parse
         if let Some(json_str) = extract_json(&sanitized) {
             if let Some(resp) = self.try_parse_json(&json_str) {
-                return resp;
+                return Some(resp);
             }
         }
 
@@ -496,7 +855,7 @@ When a screenshot is attached to the screen state:
         if let Some(json_str) = extract_json(&repaired) {
             if let Some(resp) = self.try_parse_json(&json_str) {
                 warn!("Recovered actions from truncated JSON response");
-                return resp;
+                return Some(resp);
             }
         }
 
@@ -504,23 +863,14 @@ When a screenshot is attached to the screen state:
         if let Some(actions) = extract_partial_actions(&sanitized) {
             if !actions.is_empty() {
                 warn!("Extracted {} action(s) from malformed JSON", actions.len());
-                return AgentResponse {
+                return Some(AgentResponse {
                     actions,
                     reflection: Some("(partial response recovered)".into()),
                     ..Default::default()
-                };
+                });
             }
         }
-
-        warn!(
-            "Could not parse any JSON from LLM response (len={})",
-            trimmed.len()
-        );
-        AgentResponse {
-            reflection: Some(trimmed.chars().take(500).collect()),
-            message: None,
-            ..Default::default()
-        }
+        None
     }
 
     fn try_parse_json(&self, json_str: &str) -> Option<AgentResponse> {
@@ -572,8 +922,20 @@ When a screenshot is attached to the screen state:
         if let Some(img) = image {
             body["images"] = serde_json::json!([img]);
         }
+        let structured = self.use_structured_output();
+        if structured {
+            body["format"] = agent_response_schema();
+        }
 
         let resp = self.client.post(&url).json(&body).send().await?;
+        if structured && resp.status() == reqwest::StatusCode::BAD_REQUEST {
+            let error = resp.text().await.unwrap_or_default();
+            if !rejects_structured_output(&error) {
+                anyhow::bail!("Ollama error {}: {}", reqwest::StatusCode::BAD_REQUEST, error);
+            }
+            self.structured_output_rejected(&error);
+            return Box::pin(self.ollama(system, user, image)).await;
+        }
         if !resp.status().is_success() {
             anyhow::bail!(
                 "Ollama error {}: {}",
@@ -590,18 +952,19 @@ When a screenshot is attached to the screen state:
         system: &str,
         user: &str,
         image: Option<&str>,
+        thinking: Option<&str>,
     ) -> anyhow::Result<String> {
         let url = format!("{}/chat/completions", self.config.endpoint);
         let user_content = if let Some(img) = image {
             serde_json::json!([
                 {"type": "text", "text": user},
-                {"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", img)}}
+                {"type": "image_url", "image_url": {"url": image_data_url(img)}}
             ])
         } else {
             serde_json::json!(user)
         };
 
-        let body = serde_json::json!({
+        let mut body = serde_json::json!({
             "model": self.config.model,
             "messages": [
                 {"role": "system", "content": system},
@@ -610,19 +973,40 @@ When a screenshot is attached to the screen state:
             "max_tokens": self.config.max_tokens,
             "temperature": self.config.temperature,
         });
+        // Non-reasoning models reject reasoning_effort with a 400
+        if let Some(effort) = thinking.filter(|_| is_reasoning_model(&self.config.model)) {
+            body["reasoning_effort"] = serde_json::json!(effort);
+        }
+        let structured = self.use_structured_output();
+        if structured {
+            body["response_format"] = serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {"name": "agent_response", "schema": agent_response_schema()},
+            });
+        }
 
         let mut req = self.client.post(&url).json(&body);
         if let Some(key) = &self.config.api_key {
             if !key.is_empty() {
-                req = req.header("Authorization", format!("Bearer {}", key));
+                req = req.header("Authorization", format!("Bearer {}", key.expose()));
             }
         }
 
         let resp = req.send().await?;
+        if structured && resp.status() == reqwest::StatusCode::BAD_REQUEST {
+            let error = resp.text().await.unwrap_or_default();
+            if !rejects_structured_output(&error) {
+                anyhow::bail!("LLM API error {}: {}", reqwest::StatusCode::BAD_REQUEST, error);
+            }
+            self.structured_output_rejected(&error);
+            return Box::pin(self.openai_compat(system, user, image, thinking)).await;
+        }
         if !resp.status().is_success() {
+            let retry_after = retry_after_hint(&resp);
             anyhow::bail!(
-                "LLM API error {}: {}",
+                "LLM API error {}{}: {}",
                 resp.status(),
+                retry_after,
                 resp.text().await.unwrap_or_default()
             );
         }
@@ -635,16 +1019,16 @@ When a screenshot is attached to the screen state:
 
     /// Codex OAuth backend — uses the Responses API at chatgpt.com/backend-api/codex/responses
     /// This endpoint REQUIRES stream:true and returns Server-Sent Events (SSE).
-    /// We collect the text deltas from the stream and return the full text.
+    /// We collect the text deltas from the stream (forwarding each to the
+    /// stream sink as it arrives) and return the full text.
     /// Reference: https://simonwillison.net/2025/Nov/9/gpt-5-codex-mini/
     async fn codex_oauth(
         &self,
         system: &str,
         user: &str,
         image: Option<&str>,
+        thinking: Option<&str>,
     ) -> anyhow::Result<String> {
-        let token = self.get_codex_token().await?;
-
         let url = "https://chatgpt.com/backend-api/codex/responses";
 
         // Build input array in OpenAI Responses API format
@@ -671,7 +1055,7 @@ When a screenshot is attached to the screen state:
                     },
                     {
                         "type": "input_image",
-                        "image_url": format!("data:image/png;base64,{}", img)
+                        "image_url": image_data_url(img)
                     }
                 ]
             }));
@@ -690,7 +1074,7 @@ When a screenshot is attached to the screen state:
 
         // Build the Responses API request body.
         // stream MUST be true — the Codex backend rejects stream:false.
-        let body = serde_json::json!({
+        let mut body = serde_json::json!({
             "model": self.config.model,
             "instructions": system,
             "input": input,
@@ -700,18 +1084,35 @@ When a screenshot is attached to the screen state:
             "store": false,
             "stream": true,
         });
+        if let Some(effort) = thinking {
+            body["reasoning"] = serde_json::json!({"effort": effort});
+        }
 
         debug!("Codex OAuth: POST {} model={}", url, self.config.model);
 
-        let resp = self
-            .client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", token))
-            .header("Content-Type", "application/json")
-            .header("Accept", "text/event-stream")
-            .json(&body)
-            .send()
-            .await?;
+        let mut refreshed = false;
+        let resp = loop {
+            let token = self.get_codex_token().await?;
+            let resp = self
+                .client
+                .post(url)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .header("Accept", "text/event-stream")
+                .json(&body)
+                .send()
+                .await?;
+            // One refresh-and-retry per call (`brain.codex_auto_refresh`)
+            if resp.status().as_u16() == 401 && self.config.codex_auto_refresh && !refreshed {
+                refreshed = true;
+                warn!("🔑 Codex OAuth: token rejected (401), refreshing it");
+                match self.refresh_codex_token().await {
+                    Ok(_) => continue,
+                    Err(e) => warn!("🔑 {}", e),
+                }
+            }
+            break resp;
+        };
 
         if resp.status().as_u16() == 401 || resp.status().as_u16() == 403 {
             warn!(
@@ -738,64 +1139,33 @@ When a screenshot is attached to the screen state:
             );
         }
 
-        // Parse the SSE stream to collect the full response text.
+        // Read the SSE stream line by line as chunks arrive.
         // The stream sends events like:
         //   data: {"type":"response.output_text.delta","delta":"Hello"}
         //   data: {"type":"response.output_text.delta","delta":" world"}
         //   data: {"type":"response.completed","response":{"output_text":"Hello world",...}}
         //   data: [DONE]
-        let full_body = resp.text().await?;
-        let mut collected_text = String::new();
-        let mut got_completed = false;
-
-        for line in full_body.lines() {
-            let line = line.trim();
-
-            // Skip empty lines and SSE comments
-            if line.is_empty() || line.starts_with(':') {
-                continue;
-            }
-
-            // Extract the data payload from "data: {...}"
-            if let Some(data) = line.strip_prefix("data: ") {
-                let data = data.trim();
-
-                // Stream terminator
-                if data == "[DONE]" {
+        let mut resp = resp;
+        let mut sse = CodexSse::default();
+        let mut full_body: Vec<u8> = Vec::new();
+        let mut pending: Vec<u8> = Vec::new();
+        while !sse.done {
+            let Some(chunk) = resp.chunk().await? else { break };
+            full_body.extend_from_slice(&chunk);
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                sse.feed_line(&String::from_utf8_lossy(&line), self.stream_sink.as_ref());
+                if sse.done {
                     break;
                 }
-
-                // Try to parse the JSON event
-                if let Ok(event) = serde_json::from_str::<serde_json::Value>(data) {
-                    let event_type = event["type"].as_str().unwrap_or("");
-
-                    match event_type {
-                        // Text delta — accumulate the output
-                        "response.output_text.delta" => {
-                            if let Some(delta) = event["delta"].as_str() {
-                                collected_text.push_str(delta);
-                            }
-                        }
-                        // Response completed — grab output_text from the full response
-                        "response.completed" => {
-                            got_completed = true;
-                            if let Some(output_text) =
-                                event["response"]["output_text"].as_str()
-                            {
-                                if !output_text.is_empty() {
-                                    // Use the final complete text instead of deltas
-                                    collected_text = output_text.to_string();
-                                }
-                            }
-                        }
-                        // Ignore other events (response.created, response.in_progress,
-                        // response.output_item.added, response.content_part.added,
-                        // response.content_part.done, response.output_item.done, etc.)
-                        _ => {}
-                    }
-                }
             }
         }
+        if !sse.done && !pending.is_empty() {
+            sse.feed_line(&String::from_utf8_lossy(&pending), self.stream_sink.as_ref());
+        }
+        let full_body = String::from_utf8_lossy(&full_body);
+        let CodexSse { text: collected_text, completed: got_completed, .. } = sse;
 
         if collected_text.is_empty() && !got_completed {
             warn!(
@@ -810,8 +1180,240 @@ When a screenshot is attached to the screen state:
         debug!("Codex OAuth: received {} chars", collected_text.len());
         Ok(collected_text)
     }
+
+    fn use_structured_output(&self) -> bool {
+        self.structured && !self.structured_unsupported.load(Ordering::Relaxed)
+    }
+
+    /// The endpoint refused the schema: parse free text from now on
+    fn structured_output_rejected(&self, error: &str) {
+        warn!(
+            "{} rejected structured output — falling back to free-text parsing: {}",
+            self.config.backend,
+            error.chars().take(200).collect::<String>()
+        );
+        self.structured_unsupported.store(true, Ordering::Relaxed);
+    }
 } // end impl Brain
 
+/// Whether a 400 is about the schema we sent (`format` for Ollama,
+/// `response_format`/`json_schema` for OpenAI-compatible APIs) rather than
+/// something else in the request, like an oversized prompt
+fn rejects_structured_output(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("format") || error.contains("json_schema")
+}
+
+/// Incremental state of a Codex Responses SSE stream
+#[derive(Debug, Default)]
+struct CodexSse {
+    text: String,
+    completed: bool,
+    /// Saw the `[DONE]` terminator
+    done: bool,
+}
+
+impl CodexSse {
+    /// Handle one SSE line; text deltas also go to `sink`
+    fn feed_line(&mut self, line: &str, sink: Option<&DeltaSink>) {
+        let line = line.trim();
+
+        // Skip empty lines and SSE comments; only "data: {...}" carries payload
+        let Some(data) = line.strip_prefix("data: ").map(str::trim) else {
+            return;
+        };
+
+        // Stream terminator
+        if data == "[DONE]" {
+            self.done = true;
+            return;
+        }
+
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else {
+            return;
+        };
+        match event["type"].as_str().unwrap_or("") {
+            // Text delta — accumulate the output
+            "response.output_text.delta" => {
+                if let Some(delta) = event["delta"].as_str() {
+                    self.text.push_str(delta);
+                    if let Some(DeltaSink(sink)) = sink {
+                        sink(delta);
+                    }
+                }
+            }
+            // Response completed — grab output_text from the full response
+            "response.completed" => {
+                self.completed = true;
+                if let Some(output_text) = event["response"]["output_text"].as_str() {
+                    if !output_text.is_empty() {
+                        // Use the final complete text instead of deltas
+                        self.text = output_text.to_string();
+                    }
+                }
+            }
+            // Ignore other events (response.created, response.in_progress,
+            // response.output_item.added, response.content_part.added,
+            // response.content_part.done, response.output_item.done, etc.)
+            _ => {}
+        }
+    }
+}
+
+/// The exact text of one model call, for `--dump-prompt`. The screenshot
+/// is summarized, not inlined.
+fn format_prompt_dump(model: &str, system_prompt: &str, user_prompt: &str, image_base64: Option<&str>) -> String {
+    let screenshot = match image_base64 {
+        Some(image) => format!("attached ({} KB base64)", image.len().div_ceil(1024)),
+        None => "none".to_string(),
+    };
+    format!(
+        "===== PROMPT {} (model: {}) =====\n\
+         ===== SYSTEM =====\n{}\n\
+         ===== USER =====\n{}\n\
+         ===== SCREENSHOT: {} =====\n\n",
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        model,
+        system_prompt,
+        user_prompt,
+        screenshot
+    )
+}
+
+/// Print the dump ("-") or append it to a file, one block per model call
+fn write_prompt_dump(target: &str, dump: &str) -> anyhow::Result<()> {
+    use std::io::Write;
+    if target == "-" {
+        print!("{}", dump);
+        return Ok(());
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(target)
+        .map_err(|e| anyhow::anyhow!("--dump-prompt {}: {}", target, e))?;
+    file.write_all(dump.as_bytes())?;
+    Ok(())
+}
+
+/// System-prompt sections in their default order (`brain.prompt_sections`).
+/// Vision and safe-mode instructions aren't listed: they follow whenever they apply.
+pub const PROMPT_SECTIONS: &[&str] = &[
+    "soul", "identity", "agents", "tools", "apps", "user", "heartbeat", "bootstrap", "skills",
+];
+
+/// One section of the system prompt, header included, cut to `max_chars`
+/// of content. None for an unknown name; empty when there is nothing to say.
+fn system_prompt_section(ctx: &BootstrapContext, name: &str, max_chars: Option<usize>) -> Option<String> {
+    let fit = |text: &str| match max_chars {
+        Some(max) => truncate_chars(text, max),
+        None => text.to_string(),
+    };
+    let file = |title: &str, body: &str| {
+        if body.is_empty() {
+            String::new()
+        } else {
+            format!("--- {} ---\n{}\n\n", title, fit(body))
+        }
+    };
+    Some(match name {
+        "soul" => file("SOUL.md", &ctx.soul),
+        "identity" => file("IDENTITY.md", &ctx.identity),
+        "agents" => file("AGENTS.md", &ctx.agents),
+        "tools" => file("TOOLS.md", &ctx.tools),
+        "apps" => file("INSTALLED APPS (launch_app targets)", &ctx.installed_apps),
+        "user" => file("USER.md", &ctx.user),
+        "heartbeat" => file("HEARTBEAT.md", &ctx.heartbeat),
+        "bootstrap" => ctx
+            .bootstrap
+            .as_deref()
+            .map(|b| file("BOOTSTRAP.md (FIRST RUN)", b))
+            .unwrap_or_default(),
+        "skills" => {
+            let all: String = ctx
+                .skills
+                .iter()
+                .map(|skill| format!("--- SKILL: {} ---\n{}\n\n", skill.name, skill.content))
+                .collect();
+            match max_chars {
+                Some(_) if !all.is_empty() => format!("{}\n\n", fit(all.trim_end())),
+                _ => all,
+            }
+        }
+        _ => return None,
+    })
+}
+
+/// The first `max` chars of `text`, marked when something was cut
+fn truncate_chars(text: &str, max: usize) -> String {
+    let total = text.chars().count();
+    if total <= max {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max).collect();
+    format!("{}\n<!-- truncated ({} chars total) -->", kept, total)
+}
+
+/// JSON schema of `AgentResponse`, sent with `brain.structured_output`.
+/// `params` stays an open object since every action type has its own.
+pub fn agent_response_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "actions": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "type": {"type": "string"},
+                        "params": {"type": "object"},
+                        "classification": {"type": "string", "enum": ["GREEN", "YELLOW", "RED"]},
+                        "reason": {"type": "string"},
+                        "confidence": {"type": "number"},
+                    },
+                    "required": ["type", "params", "classification", "reason"],
+                },
+            },
+            "reflection": {"type": "string"},
+            "message": {"type": "string"},
+            "memory_write": {"type": "string"},
+        },
+        "required": ["actions"],
+    })
+}
+
+// ── Free functions: retry helpers ───────────────────────────────────────────
+
+/// " (retry-after: N)" when the response carries a Retry-After header, else "".
+/// Embedded in the error message so `is_retryable` can see it.
+fn retry_after_hint(resp: &reqwest::Response) -> String {
+    resp.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| format!(" (retry-after: {})", v))
+        .unwrap_or_default()
+}
+
+/// Whether an OpenAI-compatible model accepts `reasoning_effort`
+fn is_reasoning_model(model: &str) -> bool {
+    let m = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    ["o1", "o3", "o4"].iter().any(|p| m == *p || m.starts_with(&format!("{}-", p)))
+        || m.starts_with("gpt-5")
+        || m.starts_with("gpt-oss")
+}
+
+/// Whether an LLM error should be retried against the same backend
+fn is_retryable(error: &str) -> bool {
+    ErrorClass::classify(error).is_transient() && !error.contains("retry-after")
+}
+
+/// Data URL for a base64 screenshot — JPEG when re-encoded
+/// (`perception.screenshot_max_width`), PNG otherwise
+fn image_data_url(b64: &str) -> String {
+    let mime = if b64.starts_with("/9j/") { "image/jpeg" } else { "image/png" };
+    format!("data:{};base64,{}", mime, b64)
+}
+
 // ── Free functions: JSON sanitization & extraction ──────────────────────────
 
 /// Sanitize common LLM JSON issues:
@@ -1022,4 +1624,258 @@ fn extract_json(text: &str) -> Option<String> {
     }
 
     None
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_codex_sse_forwards_deltas() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let sink = {
+            let seen = seen.clone();
+            DeltaSink(Arc::new(move |d: &str| seen.lock().unwrap().push(d.to_string())))
+        };
+        let mut sse = CodexSse::default();
+        for line in [
+            ": keep-alive",
+            r#"data: {"type":"response.created"}"#,
+            r#"data: {"type":"response.output_text.delta","delta":"{\"act"}"#,
+            r#"data: {"type":"response.output_text.delta","delta":"ions\": []}"}"#,
+            r#"data: {"type":"response.completed","response":{"output_text":"{\"actions\": []}"}}"#,
+            "data: [DONE]",
+        ] {
+            sse.feed_line(line, Some(&sink));
+        }
+        assert!(sse.done && sse.completed);
+        assert_eq!(sse.text, r#"{"actions": []}"#);
+        assert_eq!(*seen.lock().unwrap(), vec![r#"{"act"#, r#"ions": []}"#]);
+    }
+
+    fn test_config(endpoint: &str) -> BrainConfig {
+        toml::from_str(&format!(
+            r#"
+            backend = "openai_compatible"
+            model = "test-model"
+            endpoint = "{}"
+            max_retries = 2
+            retry_backoff_ms = 10
+            "#,
+            endpoint
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_no_send_dumps_prompt_without_calling_model() {
+        let path = std::env::temp_dir().join(format!("hermitdroid-prompt-{}.txt", uuid::Uuid::new_v4()));
+        let dump = PromptDump::from_args(Some(path.to_string_lossy().into_owned()), true);
+        let brain = Brain::new(&test_config("http://127.0.0.1:1")).with_prompt_dump(dump);
+
+        let err = brain.think_actions("SYS", "USER", Some("aGVsbG8="), None, None).await.unwrap_err();
+        assert!(err.is::<PromptNotSent>());
+        let dump = std::fs::read_to_string(&path).unwrap();
+        assert!(dump.contains("(model: test-model)"));
+        assert!(dump.contains("===== SYSTEM =====\nSYS\n===== USER =====\nUSER\n"));
+        assert!(dump.contains("===== SCREENSHOT: attached (1 KB base64) ====="));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_prose_reply_becomes_message() {
+        let brain = Brain::new(&test_config("http://localhost"));
+        let mut prose = brain.parse_response("I think you should open Settings and turn on Wi-Fi.");
+        assert_eq!(prose.prose_to_message(), Some("I think you should open Settings and turn on Wi-Fi."));
+        assert_eq!(prose.message.as_deref(), Some("I think you should open Settings and turn on Wi-Fi."));
+        assert!(prose.actions.is_empty());
+
+        let mut ok = brain.parse_response("HEARTBEAT_OK");
+        assert_eq!(ok.prose_to_message(), None);
+        let mut plan = brain.parse_response(r#"{"actions": [{"type": "back"}], "reflection": "going back"}"#);
+        assert_eq!(plan.prose_to_message(), None);
+        assert!(plan.message.is_none());
+    }
+
+    #[test]
+    fn test_prompt_sections_layout() {
+        let ctx = BootstrapContext {
+            soul: "Be kind.".into(),
+            identity: "I am Hermit.".into(),
+            agents: "A".repeat(500),
+            tools: "tap, swipe, type".into(),
+            user: String::new(),
+            heartbeat: "Check mail.".into(),
+            memory: String::new(),
+            goals: String::new(),
+            bootstrap: None,
+            skills: vec![crate::soul::SkillContext { name: "mail".into(), content: "Open Gmail.".into() }],
+            installed_apps: String::new(),
+        };
+        let default_prompt = Brain::new(&test_config("http://localhost")).build_system_prompt(&ctx);
+        assert!(default_prompt.starts_with("--- SOUL.md ---\nBe kind.\n\n--- IDENTITY.md ---"));
+        assert!(default_prompt.contains(&"A".repeat(500)));
+
+        let mut config = test_config("http://localhost");
+        config.prompt_sections = Some(vec!["tools".into(), "soul".into(), "skills".into()]);
+        config.prompt_max_chars.insert("tools".into(), 4);
+        let prompt = Brain::new(&config).build_system_prompt(&ctx);
+        assert!(prompt.starts_with(
+            "--- TOOLS.md ---\ntap,\n<!-- truncated (16 chars total) -->\n\n--- SOUL.md ---\nBe kind.\n\n--- SKILL: mail ---\nOpen Gmail.\n\n"
+        ));
+        assert!(!prompt.contains("AGENTS.md") && !prompt.contains("IDENTITY.md"));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable("LLM API error 503 Service Unavailable: busy"));
+        assert!(is_retryable("operation timed out"));
+        assert!(is_retryable("LLM API error 429 Too Many Requests: slow down"));
+        assert!(!is_retryable("LLM API error 429 Too Many Requests (retry-after: 30): slow down"));
+        assert!(!is_retryable("LLM API error 401 Unauthorized: bad key"));
+    }
+
+    #[test]
+    fn test_action_validation() {
+        let action = |v: serde_json::Value| serde_json::from_value::<AgentAction>(v).unwrap();
+        assert!(action(serde_json::json!({"type": "tap", "params": {"x": 10, "y": 20}})).validate().is_ok());
+        let err = action(serde_json::json!({"type": "tap", "params": {"x": 10}})).validate().unwrap_err();
+        assert_eq!(err, "tap is missing y");
+        assert!(action(serde_json::json!({"type": "swipe", "params": {"x1": 1, "y1": 2}})).validate().is_err());
+        assert!(action(serde_json::json!({"type": "type_text", "params": {}})).validate().is_err());
+        assert!(action(serde_json::json!({"type": "launch_app", "app": "youtube"})).validate().is_ok());
+        assert!(action(serde_json::json!({"type": "launch_app", "params": {}})).validate().is_err());
+        assert!(action(serde_json::json!({"type": "home"})).validate().is_ok());
+        assert!(action(serde_json::json!({"type": "long_press", "params": {"index": 5}})).validate().is_ok());
+        assert!(action(serde_json::json!({"type": "long_press", "params": {"index": "5"}})).validate().is_ok());
+        assert!(action(serde_json::json!({"type": "long_press", "params": {"x": 1, "y": 2}})).validate().is_ok());
+        let err = action(serde_json::json!({"type": "open_context_menu", "params": {}})).validate().unwrap_err();
+        assert_eq!(err, "open_context_menu is missing index or x, y");
+    }
+
+    #[test]
+    fn test_plan_event() {
+        let actions: Vec<AgentAction> = serde_json::from_value(serde_json::json!([
+            {"type": "launch_app", "app": "whatsapp"},
+            {"type": "tap", "params": {"x": 10, "y": 20}, "classification": "YELLOW"},
+            {"type": "type_text", "params": {"text": "hi"}, "classification": "red", "reason": "message Mom"},
+            {"type": "back"},
+        ])).unwrap();
+        let as_labelled = |a: &AgentAction| a.classification.to_uppercase();
+        let plan = plan_event(&actions, as_labelled);
+        assert_eq!(plan["type"], "plan");
+        assert_eq!(plan["has_red"], true);
+        let steps = plan["actions"].as_array().unwrap();
+        assert_eq!(steps[0]["target"], "whatsapp");
+        assert_eq!(steps[1]["target"], "(10,20)");
+        assert_eq!(steps[2]["classification"], "RED");
+        assert!(steps[3]["target"].is_null());
+        assert_eq!(plan_event(&actions[..2], as_labelled)["has_red"], false);
+        // The executor's class wins over the model's label
+        let restricted = |a: &AgentAction| if a.action_type == "launch_app" { "RED".into() } else { as_labelled(a) };
+        assert_eq!(plan_event(&actions[..2], restricted)["has_red"], true);
+    }
+
+    #[test]
+    fn test_try_parse_response() {
+        let brain = Brain::new(&test_config("http://localhost"));
+        assert!(brain.try_parse_response("I think we should open Settings next.").is_none());
+        assert!(brain.try_parse_response("").is_none());
+        assert_eq!(
+            brain.try_parse_response("HEARTBEAT_OK").unwrap().reflection.as_deref(),
+            Some("HEARTBEAT_OK")
+        );
+        let resp = brain
+            .try_parse_response(r#"Sure! {"actions":[{"type":"back","params":{},"classification":"GREEN","reason":"x"}]}"#)
+            .unwrap();
+        assert_eq!(resp.actions.len(), 1);
+        // Prose still becomes the reflection when nothing parses
+        assert_eq!(brain.parse_response("no json here").reflection.as_deref(), Some("no json here"));
+    }
+
+    #[test]
+    fn test_reasoning_effort_session_override() {
+        let brain = Brain::new(&test_config("http://localhost"));
+        assert_eq!(brain.reasoning_effort(None), Some("medium"));
+        assert_eq!(brain.reasoning_effort(Some("high")), Some("high"));
+        assert_eq!(brain.reasoning_effort(Some("off")), None);
+        assert!(is_reasoning_model("openai/gpt-oss-120b"));
+        assert!(is_reasoning_model("o3-mini"));
+        assert!(!is_reasoning_model("gpt-4o-mini"));
+    }
+
+    #[tokio::test]
+    async fn test_think_retries_transient_errors() {
+        use axum::{http::StatusCode, routing::post, Json, Router};
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/chat/completions",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                        Err(StatusCode::SERVICE_UNAVAILABLE)
+                    } else {
+                        Ok(Json(serde_json::json!({
+                            "choices": [{"message": {"content": "HEARTBEAT_OK"}}]
+                        })))
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let brain = Brain::new(&test_config(&format!("http://{}", addr)));
+        let raw = brain.think("system", "user", None).await.unwrap();
+        assert_eq!(raw, "HEARTBEAT_OK");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_structured_output_falls_back_when_rejected() {
+        use axum::{http::StatusCode, routing::post, Json, Router};
+
+        let schema_requests = Arc::new(AtomicU32::new(0));
+        let counter = schema_requests.clone();
+        let app = Router::new().route(
+            "/chat/completions",
+            post(move |Json(body): Json<serde_json::Value>| {
+                let counter = counter.clone();
+                async move {
+                    if body.get("response_format").is_some() {
+                        if body["messages"][1]["content"] == "too long" {
+                            return Err((StatusCode::BAD_REQUEST, "maximum context length exceeded"));
+                        }
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        return Err((StatusCode::BAD_REQUEST, "response_format json_schema is not supported"));
+                    }
+                    Ok(Json(serde_json::json!({
+                        "choices": [{"message": {"content": "HEARTBEAT_OK"}}]
+                    })))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = test_config(&format!("http://{}", addr));
+        config.structured_output = true;
+        let brain = Brain::new(&config);
+        // Other 400s are errors and keep the schema on
+        assert!(brain.think_actions("system", "too long", None, None, None).await.is_err());
+        assert!(!brain.structured_unsupported.load(Ordering::Relaxed));
+        for _ in 0..2 {
+            let raw = brain.think_actions("system", "user", None, None, None).await.unwrap();
+            assert_eq!(raw, "HEARTBEAT_OK");
+        }
+        // Rejected once, then no longer sent
+        assert_eq!(schema_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(agent_response_schema()["required"], serde_json::json!(["actions"]));
+    }
+}
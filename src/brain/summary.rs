@@ -0,0 +1,170 @@
+//! `brain.screen_summary`: a cheap extra call that turns the element list into
+//! one paragraph, for small text-only models that can't reason over 40 raw
+//! elements. Summaries are cached by screen text, so an unchanged screen
+//! costs nothing.
+
+use super::Brain;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use tracing::{info, warn};
+
+/// Where the summary goes in the prompt (`brain.screen_summary`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenSummaryMode {
+    Off,
+    /// Summary above the element list
+    Append,
+    /// Summary instead of the element list (only the header lines are kept)
+    Replace,
+}
+
+impl ScreenSummaryMode {
+    pub fn from_str(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "" | "off" => Self::Off,
+            "append" => Self::Append,
+            "replace" => Self::Replace,
+            other => {
+                warn!("Unknown brain.screen_summary '{}', defaulting to off", other);
+                Self::Off
+            }
+        }
+    }
+}
+
+/// Summaries kept before the cache is cleared
+const SUMMARY_CACHE_ENTRIES: usize = 32;
+
+/// Rough token count for the logs and metrics (no tokenizer at hand)
+fn estimate_tokens(text: &str) -> u64 {
+    text.chars().count().div_ceil(4) as u64
+}
+
+const SUMMARY_SYSTEM_PROMPT: &str = "You describe Android screens for another model that can't see them. \
+Reply with one plain paragraph of at most 80 words: which app and screen this is, what it shows, \
+and the main controls by their visible text and [index]. No JSON, no advice.";
+
+pub type SummaryCache = std::sync::Mutex<HashMap<u64, String>>;
+
+impl Brain {
+    /// The screen text for the prompt: unchanged when `brain.screen_summary`
+    /// is off, the screen has no elements or the summary call fails
+    pub async fn with_screen_summary(&self, screen_text: &str) -> String {
+        let mode = ScreenSummaryMode::from_str(&self.config.screen_summary);
+        let Some((header, elements)) = screen_text.split_once("\n\n") else {
+            return screen_text.to_string();
+        };
+        if mode == ScreenSummaryMode::Off || elements.trim().is_empty() || self.prompt_dump.as_ref().is_some_and(|d| d.no_send) {
+            return screen_text.to_string();
+        }
+        let Some(summary) = self.summarize_screen(screen_text).await else {
+            return screen_text.to_string();
+        };
+        let text = match mode {
+            ScreenSummaryMode::Replace => format!("{}\nSummary: {}\n", header, summary),
+            _ => format!("{}\nSummary: {}\n\n{}", header, summary, elements),
+        };
+        info!(
+            "📝 Screen summary ({:?}): ~{} prompt tokens instead of ~{}",
+            mode,
+            estimate_tokens(&text),
+            estimate_tokens(screen_text)
+        );
+        text
+    }
+
+    /// One paragraph describing `screen_text`, from the cache when this exact
+    /// screen was summarized before
+    async fn summarize_screen(&self, screen_text: &str) -> Option<String> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        screen_text.hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(summary) = self.summary_cache.lock().ok()?.get(&key).cloned() {
+            if let Some(m) = &self.metrics {
+                m.record_screen_summary(true, 0);
+            }
+            return Some(summary);
+        }
+
+        // A plain call: no action schema, nothing streamed to `run --verbose`
+        let plain = Self { structured: false, stream_sink: None, ..self.clone() };
+        let reply = match plain
+            .think_with_overrides(SUMMARY_SYSTEM_PROMPT, screen_text, None, None, Some("off"))
+            .await
+        {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Screen summary failed, sending the elements as they are: {}", e);
+                return None;
+            }
+        };
+        let summary = reply.split_whitespace().collect::<Vec<_>>().join(" ");
+        if summary.is_empty() {
+            return None;
+        }
+        if let Some(m) = &self.metrics {
+            let spent = estimate_tokens(SUMMARY_SYSTEM_PROMPT) + estimate_tokens(screen_text) + estimate_tokens(&summary);
+            m.record_screen_summary(false, spent);
+        }
+        let mut cache = self.summary_cache.lock().ok()?;
+        if cache.len() >= SUMMARY_CACHE_ENTRIES {
+            cache.clear();
+        }
+        cache.insert(key, summary.clone());
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_screen_summary_is_cached_per_screen() {
+        use axum::{routing::post, Json, Router};
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/chat/completions",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Json(serde_json::json!({
+                        "choices": [{"message": {"content": "WhatsApp chat list.\n Search at [1]."}}]
+                    }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config: crate::config::BrainConfig = toml::from_str(&format!(
+            "backend = \"openai_compatible\"\nmodel = \"m\"\nendpoint = \"http://{}\"\nscreen_summary = \"append\"",
+            addr
+        ))
+        .unwrap();
+        let screen = "App: com.whatsapp\nElements: 1 shown / 1 total (1 interactive)\n\n[1] ImageButton desc=\"Search\"\n";
+        let brain = Brain::new(&config);
+        let text = brain.with_screen_summary(screen).await;
+        assert_eq!(
+            text,
+            "App: com.whatsapp\nElements: 1 shown / 1 total (1 interactive)\nSummary: WhatsApp chat list. Search at [1].\n\n[1] ImageButton desc=\"Search\"\n"
+        );
+        assert_eq!(brain.with_screen_summary(screen).await, text);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        config.screen_summary = "replace".into();
+        let text = Brain::new(&config).with_screen_summary(screen).await;
+        assert!(text.ends_with("Summary: WhatsApp chat list. Search at [1].\n"), "{text}");
+        assert!(!text.contains("ImageButton"));
+
+        config.screen_summary = "off".into();
+        assert_eq!(Brain::new(&config).with_screen_summary(screen).await, screen);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::action::{ActionExecutor, CoordinateCalibration};
+use crate::config::Config;
+use crate::perception::{
+    load_app_aliases, load_app_calibrations, save_app_calibration, AppCalibration, Perception, UiElement,
+};
+use std::io::{self, BufRead, Write};
+use std::process::Command;
+
+// ── ANSI colors ────────────────────────────────────────────────────────────
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// How many on-screen targets to sample
+const MAX_TARGETS: usize = 3;
+
+// ── Public API ─────────────────────────────────────────────────────────────
+
+/// Interactive tap calibration.
+///
+/// Picks three labelled elements from the accessibility tree, turns on
+/// Android's pointer-location overlay, and asks the user to touch each one
+/// and type the coordinates the overlay reports. The difference between the
+/// UI-tree centre and the real touch point gives the correction.
+///
+/// Without a package the result is printed as the global
+/// `[action] coordinate_offset` / `coordinate_scale`. With a package the app
+/// is launched first and the per-axis transform is saved to
+/// `workspace/calibration.toml`.
+pub async fn run_calibrate(config: &Config, package: Option<&str>) -> anyhow::Result<()> {
+    let adb_device = config.perception.adb_device.clone();
+    let workspace_path = &config.agent.workspace_path;
+
+    println!("\n{CYAN}{BOLD}🎯 Hermitdroid — Tap Calibration{RESET}\n");
+
+    // Read the screen the way the agent would (existing profiles applied)
+    let perception = Perception::new(adb_device.clone(), vec![])
+        .with_app_calibrations(load_app_calibrations(workspace_path))
+        .with_app_aliases(load_app_aliases(workspace_path))
+        .with_ignore_packages(config.perception.ignore_packages.clone())
+        .with_max_elements(config.perception.max_elements);
+
+    // Accept friendly names too: `hermitdroid calibrate youtube`
+    let package = match package {
+        Some(name) => Some(
+            perception
+                .resolve_package(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown app: {}", name))?,
+        ),
+        None => None,
+    };
+    let package = package.as_deref();
+
+    if let Some(pkg) = package {
+        println!("  {DIM}Launching {}...{RESET}", pkg);
+        let mut cmd = Command::new("adb");
+        if let Some(dev) = &adb_device {
+            cmd.args(["-s", dev]);
+        }
+        cmd.args(["shell", "monkey", "-p", pkg, "-c", "android.intent.category.LAUNCHER", "1"]);
+        let _ = crate::adb::output(&mut cmd);
+        tokio::time::sleep(std::time::Duration::from_millis(2000)).await;
+    }
+
+    // Undo any existing profile so the new one is measured from raw UI-tree coordinates
+    perception.poll_screen_adb_full(false).await;
+    let existing = package.and_then(|pkg| perception.app_calibration(pkg));
+    let elements = perception
+        .get_screen_state()
+        .await
+        .map(|s| s.elements)
+        .unwrap_or_default();
+    let targets = pick_targets(&elements);
+    if targets.is_empty() {
+        anyhow::bail!("No labelled elements on screen — open an app with visible buttons and retry");
+    }
+
+    set_pointer_location(&adb_device, true);
+    println!("  Pointer location is now shown at the top of the phone screen.");
+    println!("  For each marker, touch its centre and enter the X,Y shown there.");
+    println!("  {DIM}(leave blank to skip a marker){RESET}\n");
+
+    let mut samples = Vec::new();
+    for (n, el) in targets.iter().enumerate() {
+        let label = if el.text.is_empty() { &el.desc } else { &el.text };
+        let (ux, uy) = match existing {
+            Some(cal) => cal.invert(el.center_x, el.center_y),
+            None => (el.center_x, el.center_y),
+        };
+        let answer = prompt(&format!(
+            "  [{}/{}] Touch \"{}\" (UI tree says {},{}) → actual X,Y:",
+            n + 1,
+            targets.len(),
+            label,
+            ux,
+            uy
+        ));
+        match parse_point(&answer) {
+            Some(touch) => samples.push(((ux as f64, uy as f64), touch)),
+            None if answer.is_empty() => {}
+            None => println!("  {YELLOW}⚠  Couldn't read \"{}\", skipping{RESET}", answer),
+        }
+    }
+    set_pointer_location(&adb_device, false);
+
+    if samples.is_empty() {
+        println!("\n  {YELLOW}No samples recorded — calibration unchanged.{RESET}\n");
+        return Ok(());
+    }
+    println!("\n  {GREEN}{BOLD}✅ Measured from {} sample(s){RESET}\n", samples.len());
+
+    let xs: Vec<(f64, f64)> = samples.iter().map(|(u, t)| (u.0, t.0)).collect();
+    let ys: Vec<(f64, f64)> = samples.iter().map(|(u, t)| (u.1, t.1)).collect();
+
+    if let Some(pkg) = package {
+        let (x_scale, x_offset) = fit_axis(&xs, fit_slope(&xs).unwrap_or(1.0));
+        let (y_scale, y_offset) = fit_axis(&ys, fit_slope(&ys).unwrap_or(1.0));
+        let profile = AppCalibration { x_offset, y_offset, x_scale, y_scale };
+        let path = save_app_calibration(workspace_path, pkg, profile)?;
+        println!(
+            "  Saved {} → offset ({}, {}), scale ({:.4}, {:.4})",
+            pkg, x_offset, y_offset, x_scale, y_scale
+        );
+        println!("  {DIM}{}{RESET}\n", path.display());
+        return Ok(());
+    }
+
+    let calibration = fit_calibration(&xs, &ys);
+    println!("  Add this to the [action] section of your config.toml:\n");
+    println!(
+        "    coordinate_offset = {{ x = {}, y = {} }}",
+        calibration.offset_x, calibration.offset_y
+    );
+    println!("    coordinate_scale = {:.4}\n", calibration.scale);
+
+    // Verify by tapping the first target with the new calibration
+    if prompt("  Tap the first target with this calibration to verify? [y/N]:")
+        .eq_ignore_ascii_case("y")
+    {
+        let (ui, _) = samples[0];
+        let executor = ActionExecutor::new(false, adb_device, vec![], None).with_calibration(calibration);
+        let action = serde_json::from_value(serde_json::json!({
+            "type": "tap",
+            "params": {"x": ui.0, "y": ui.1},
+            "reason": "calibration check",
+        }))?;
+        executor.execute(&action).await?;
+        println!("  {DIM}Tapped — check that the right element responded.{RESET}\n");
+    }
+
+    Ok(())
+}
+
+// ── Helpers ────────────────────────────────────────────────────────────────
+
+/// Labelled elements spread across the screen (top, middle, bottom)
+fn pick_targets(elements: &[UiElement]) -> Vec<UiElement> {
+    let mut labelled: Vec<&UiElement> = elements
+        .iter()
+        .filter(|e| !e.text.is_empty() || !e.desc.is_empty())
+        .collect();
+    labelled.sort_by_key(|e| e.center_y);
+    if labelled.len() <= MAX_TARGETS {
+        return labelled.into_iter().cloned().collect();
+    }
+    let last = labelled.len() - 1;
+    (0..MAX_TARGETS)
+        .map(|i| labelled[i * last / (MAX_TARGETS - 1)].clone())
+        .collect()
+}
+
+/// Fit the global `touch = ui * scale + offset`, sharing one scale across
+/// both axes. One sample → pure offset.
+fn fit_calibration(xs: &[(f64, f64)], ys: &[(f64, f64)]) -> CoordinateCalibration {
+    let scale = match (fit_slope(xs), fit_slope(ys)) {
+        (Some(a), Some(b)) => (a + b) / 2.0,
+        (Some(s), None) | (None, Some(s)) => s,
+        (None, None) => 1.0,
+    };
+    CoordinateCalibration {
+        offset_x: fit_axis(xs, scale).1,
+        offset_y: fit_axis(ys, scale).1,
+        scale,
+    }
+}
+
+/// Mean offset along one axis for a given scale → (scale, offset)
+fn fit_axis(pairs: &[(f64, f64)], scale: f64) -> (f64, i32) {
+    let sum: f64 = pairs.iter().map(|(u, t)| t - u * scale).sum();
+    (scale, (sum / pairs.len() as f64).round() as i32)
+}
+
+/// Least-squares slope of touch vs UI along one axis (None if the points don't spread)
+fn fit_slope(pairs: &[(f64, f64)]) -> Option<f64> {
+    let n = pairs.len() as f64;
+    let mean_u = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_t = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let var: f64 = pairs.iter().map(|p| (p.0 - mean_u).powi(2)).sum();
+    if var < 1.0 {
+        return None;
+    }
+    let cov: f64 = pairs.iter().map(|p| (p.0 - mean_u) * (p.1 - mean_t)).sum();
+    Some(cov / var)
+}
+
+/// Accept "123,456", "123 456" or "X: 123 Y: 456"-ish input
+fn parse_point(s: &str) -> Option<(f64, f64)> {
+    let nums: Vec<f64> = s
+        .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .filter_map(|t| t.parse().ok())
+        .collect();
+    match nums.as_slice() {
+        [x, y, ..] => Some((*x, *y)),
+        _ => None,
+    }
+}
+
+fn set_pointer_location(adb_device: &Option<String>, on: bool) {
+    let mut cmd = Command::new("adb");
+    if let Some(dev) = adb_device {
+        cmd.args(["-s", dev]);
+    }
+    cmd.args(["shell", "settings", "put", "system", "pointer_location", if on { "1" } else { "0" }]);
+    let _ = crate::adb::output(&mut cmd);
+}
+
+fn prompt(msg: &str) -> String {
+    print!("{BOLD}{msg}{RESET} ");
+    io::stdout().flush().ok();
+    let mut buf = String::new();
+    io::stdin().lock().read_line(&mut buf).ok();
+    buf.trim().to_string()
+}
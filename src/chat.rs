@@ -0,0 +1,139 @@
+use futures::StreamExt;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+// ── ANSI colors ────────────────────────────────────────────────────────────
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+const NOT_RUNNING: &str = "❌ Agent not running. Start it first with: hermitdroid";
+
+/// POST one message (or slash command) to a running agent's `/chat` and
+/// return its reply
+pub async fn send_message(client: &reqwest::Client, port: u16, msg: &str) -> anyhow::Result<String> {
+    let resp = client
+        .post(format!("http://127.0.0.1:{}/chat", port))
+        .json(&serde_json::json!({"message": msg}))
+        .timeout(std::time::Duration::from_secs(5))
+        .send()
+        .await?;
+    let data: serde_json::Value = resp.json().await?;
+    match data["data"].as_str() {
+        Some("queued") | None => Ok(format!("✅ Queued: {}", msg)),
+        Some(reply) => Ok(reply.to_string()),
+    }
+}
+
+/// `hermitdroid chat` without a message: read lines from stdin, send each to
+/// `/chat`, and print agent messages and actions from `/ws/user` as they
+/// arrive. Ends on EOF, `/exit` or `/quit`.
+pub async fn run_repl(port: u16) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let reachable = client
+        .get(format!("http://127.0.0.1:{}/status", port))
+        .timeout(std::time::Duration::from_secs(3))
+        .send()
+        .await
+        .is_ok();
+    if !reachable {
+        println!("{}", NOT_RUNNING);
+        return Ok(());
+    }
+
+    println!("\n{CYAN}{BOLD}💬 Hermitdroid chat{RESET} {DIM}— /help for commands, /exit to leave{RESET}\n");
+
+    match tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}/ws/user", port)).await {
+        Ok((ws, _)) => {
+            let (_, mut events) = ws.split();
+            tokio::spawn(async move {
+                while let Some(Ok(msg)) = events.next().await {
+                    if let Ok(text) = msg.to_text() {
+                        if let Some(line) = format_event(text) {
+                            println!("\r{}", line);
+                        }
+                    }
+                }
+                println!("\r{YELLOW}⚠  Live updates stopped (agent disconnected){RESET}");
+            });
+        }
+        Err(e) => println!("{YELLOW}⚠  No live updates ({}) — replies only{RESET}", e),
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    loop {
+        stdout.write_all(format!("{BOLD}you>{RESET} ").as_bytes()).await?;
+        stdout.flush().await?;
+        let Some(line) = lines.next_line().await? else {
+            println!();
+            break;
+        };
+        let msg = line.trim();
+        match msg {
+            "" => continue,
+            "/exit" | "/quit" => break,
+            _ => {}
+        }
+        match send_message(&client, port, msg).await {
+            Ok(reply) => println!("{DIM}{}{RESET}", reply),
+            Err(_) => println!("{}", NOT_RUNNING),
+        }
+    }
+    Ok(())
+}
+
+/// One printable line for a `/ws/user` event, or None for ones not worth
+/// showing in a terminal (echoed user commands, run progress)
+fn format_event(raw: &str) -> Option<String> {
+    let v: serde_json::Value = serde_json::from_str(raw).ok()?;
+    let field = |k: &str| v[k].as_str().unwrap_or("");
+    match (field("type"), field("event")) {
+        ("agent_message", _) => Some(format!("{GREEN}🤖 {}{RESET}", field("message"))),
+        ("action", _) => Some(format!(
+            "{DIM}  ▸ {} [{}] → {}{RESET}",
+            field("action"),
+            field("classification"),
+            field("result")
+        )),
+        ("plan", _) if v["has_red"].as_bool() == Some(true) => Some(format!(
+            "{YELLOW}⏸  Plan includes RED actions: {} — /stop to abort{RESET}",
+            v["actions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|a| a["type"].as_str().unwrap_or("?"))
+                .collect::<Vec<_>>()
+                .join(" → ")
+        )),
+        ("lagged", _) => Some(format!("{DIM}  … fell behind, {} events skipped{RESET}", v["skipped"])),
+        ("screenshot", _) => Some(format!("{DIM}  📸 Screenshot captured — open the dashboard to view it{RESET}")),
+        (_, "circuit_open") => Some(format!("{YELLOW}⚠  Agent paused after repeated errors — /start to resume{RESET}")),
+        (_, "tailscale_reconnected") => Some(format!("{GREEN}🌐 ADB reconnected via Tailscale ({}){RESET}", field("address"))),
+        (_, "kill") => Some(format!("{YELLOW}🛑 Kill switch activated{RESET}")),
+        (_, "confirmation_expired") => Some(format!("{DIM}  ⏱ Confirmation for {} expired{RESET}", field("action_type"))),
+        (_, "shutdown") => Some(format!("{YELLOW}🛑 Agent shutting down{RESET}")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_event() {
+        let msg = format_event(r#"{"type":"agent_message","message":"Done!"}"#).unwrap();
+        assert!(msg.contains("🤖 Done!"));
+        let action = format_event(r#"{"type":"action","action":"tap","classification":"GREEN","result":"ok"}"#).unwrap();
+        assert!(action.contains("tap [GREEN] → ok"));
+        let plan = format_event(r#"{"type":"plan","has_red":true,"actions":[{"type":"tap"},{"type":"type_text"}]}"#).unwrap();
+        assert!(plan.contains("tap → type_text"));
+        assert!(format_event(r#"{"type":"plan","has_red":false,"actions":[{"type":"tap"}]}"#).is_none());
+        assert!(format_event(r#"{"type":"user_command","text":"hi"}"#).is_none());
+        assert!(format_event(r#"{"event":"run_step","run_id":"ab12"}"#).is_none());
+        assert!(format_event("not json").is_none());
+    }
+}
@@ -1,13 +1,16 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use crate::tailscale::TailscaleConfig;
 use crate::stuck::StuckConfig;
 use crate::fallback::ModelConfig;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub agent: AgentConfig,
     pub brain: BrainConfig,
+    /// Named alternatives to `[brain]`
+    #[serde(default)]
+    pub brain_profiles: Vec<BrainProfile>,
     pub perception: PerceptionConfig,
     pub action: ActionConfig,
     pub server: ServerConfig,
@@ -21,7 +24,7 @@ pub struct Config {
     pub stuck: StuckConfig,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub name: String,
     pub heartbeat_interval_secs: u64,
@@ -31,24 +34,114 @@ pub struct AgentConfig {
     pub workspace_path: String,
     #[serde(default = "default_bootstrap_max_chars")]
     pub bootstrap_max_chars: usize,
+    /// If false, the heartbeat never ticks on its own — the agent only acts
+    /// on explicit user commands (chat, dashboard, flows)
+    #[serde(default = "default_true")]
+    pub autonomous: bool,
+    /// Pause the agent after this many failed ticks in a row (0 = never)
+    #[serde(default = "default_max_consecutive_errors")]
+    pub max_consecutive_errors: u32,
+    /// Action-log entries shown to the model each tick, failures first (0 = none)
+    #[serde(default = "default_recent_actions")]
+    pub recent_actions: usize,
+    /// Delete raw `memory/YYYY-MM-DD.md` logs older than this many days
+    /// on the gateway heartbeat (0 = keep forever)
+    #[serde(default)]
+    pub memory_retention_days: u32,
+    /// User commands containing any of these (case-insensitive substring)
+    /// trigger the kill switch
+    #[serde(default = "default_kill_phrases")]
+    pub kill_phrases: Vec<String>,
+    /// Also write logs to this file, relative to `workspace_path`, rotated
+    /// daily (`logs/hermitdroid.log` → `logs/hermitdroid.2025-06-15.log`)
+    #[serde(default)]
+    pub log_file: Option<String>,
+    /// "plain" or "json" lines in `log_file`
+    #[serde(default = "default_log_format")]
+    pub log_format: String,
+    /// IANA zone (e.g. "Asia/Jakarta") for the time shown to the model.
+    /// Unset or unknown = UTC. Daily memory files stay on UTC dates.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Local-time window ("08:00-23:00", may wrap past midnight) in which the
+    /// heartbeat acts on its own. Outside it only user commands get a tick.
+    #[serde(default)]
+    pub active_hours: Option<String>,
+    /// Let priority notifications wake the agent during quiet hours
+    #[serde(default)]
+    pub quiet_hours_allow_priority: bool,
+}
+
+impl AgentConfig {
+    /// Where `log_file` lives: always resolved under the workspace
+    pub fn log_path(&self) -> Option<std::path::PathBuf> {
+        let file = self.log_file.as_deref().filter(|f| !f.trim().is_empty())?;
+        let relative: std::path::PathBuf = Path::new(file)
+            .components()
+            .filter(|c| matches!(c, std::path::Component::Normal(_)))
+            .collect();
+        Some(Path::new(&self.workspace_path).join(relative))
+    }
+
+    /// `timezone` parsed, falling back to UTC
+    pub fn tz(&self) -> chrono_tz::Tz {
+        self.timezone
+            .as_deref()
+            .and_then(|name| name.trim().parse().ok())
+            .unwrap_or(chrono_tz::UTC)
+    }
+
+    /// Current time for prompts, e.g. `2025-06-15 09:00:00 Asia/Jakarta (+07:00)`
+    pub fn local_now(&self, fmt: &str) -> String {
+        let tz = self.tz();
+        let now = chrono::Utc::now().with_timezone(&tz);
+        format!("{} {} ({})", now.format(fmt), tz.name(), now.format("%:z"))
+    }
+
+    /// `active_hours` as (start, end), or `None` when unset or malformed
+    pub fn active_window(&self) -> Option<(chrono::NaiveTime, chrono::NaiveTime)> {
+        let (start, end) = self.active_hours.as_deref()?.split_once('-')?;
+        let parse = |t: &str| chrono::NaiveTime::parse_from_str(t.trim(), "%H:%M").ok();
+        Some((parse(start)?, parse(end)?))
+    }
+
+    /// Whether `now`, in `timezone`, is outside `active_hours`
+    pub fn in_quiet_hours(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let Some((start, end)) = self.active_window() else {
+            return false;
+        };
+        let time = now.with_timezone(&self.tz()).time();
+        let active = match start.cmp(&end) {
+            std::cmp::Ordering::Less => start <= time && time < end,
+            std::cmp::Ordering::Greater => time >= start || time < end,
+            std::cmp::Ordering::Equal => true,
+        };
+        !active
+    }
 }
 
 fn default_gateway_heartbeat() -> u64 { 1800 } // 30 min
 fn default_bootstrap_max_chars() -> usize { 20000 }
+fn default_max_consecutive_errors() -> u32 { 5 }
+fn default_recent_actions() -> usize { 8 }
+fn default_log_format() -> String { "plain".to_string() }
+fn default_kill_phrases() -> Vec<String> {
+    vec!["stop everything".into(), "emergency stop".into()]
+}
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrainConfig {
     /// "ollama", "openai_compatible", "llamacpp"
     pub backend: String,
     pub model: String,
     pub endpoint: String,
     #[serde(default)]
-    pub api_key: Option<String>,
+    pub api_key: Option<Secret>,
     #[serde(default)]
     pub vision_enabled: bool,
     #[serde(default = "default_max_tokens")]
     pub max_tokens: u32,
-    #[serde(default = "default_temperature")]
+    #[serde(default = "default_temperature", serialize_with = "serialize_f32")]
     pub temperature: f32,
     /// Thinking level: off, low, medium, high
     #[serde(default = "default_thinking")]
@@ -56,6 +149,10 @@ pub struct BrainConfig {
     /// Path to Codex OAuth auth.json (defaults to ~/.codex/auth.json)
     #[serde(default)]
     pub codex_auth_path: Option<String>,
+    /// Refresh an expiring or rejected Codex token with its refresh token
+    /// and write it back to auth.json
+    #[serde(default)]
+    pub codex_auto_refresh: bool,
     #[serde(default)]
     pub fallback_on_rate_limit: bool,
     #[serde(default)]
@@ -66,14 +163,56 @@ pub struct BrainConfig {
     pub fallback_cooldown_secs: u64,
     #[serde(default)]
     pub fallbacks: Vec<ModelConfig>,
+    /// Retries for transient errors (timeouts, 5xx, 429) before falling back
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Initial retry delay; doubles on each attempt
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// Send the action JSON schema to backends with structured outputs
+    /// (OpenAI-compatible `response_format`, Ollama `format`)
+    #[serde(default)]
+    pub structured_output: bool,
+    /// System-prompt sections to include, in order (unset = all of
+    /// `brain::PROMPT_SECTIONS`, in that order)
+    #[serde(default)]
+    pub prompt_sections: Option<Vec<String>>,
+    /// Per-section character budget for the system prompt ("skills" caps all skills together)
+    #[serde(default)]
+    pub prompt_max_chars: std::collections::HashMap<String, usize>,
+    /// Have the model summarize the element list first: "off" | "append" | "replace"
+    #[serde(default = "default_screen_summary")]
+    pub screen_summary: String,
+}
+
+/// The name that always refers to `[brain]`
+pub const DEFAULT_BRAIN_PROFILE: &str = "default";
+
+/// A `[[brain_profiles]]` entry: a complete `[brain]` section plus a name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrainProfile {
+    pub name: String,
+    #[serde(flatten)]
+    pub brain: BrainConfig,
 }
 
 fn default_max_tokens() -> u32 { 2048 }
+fn default_screen_summary() -> String { "off".into() }
 fn default_temperature() -> f32 { 0.7 }
+
+/// Allowed `--temperature` override (what OpenAI-compatible APIs accept)
+pub const TEMPERATURE_RANGE: std::ops::RangeInclusive<f32> = 0.0..=2.0;
 fn default_thinking() -> String { "medium".into() }
 fn default_cooldown() -> u64 { 60 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// Write an `f32` as the decimal it was read from (0.7, not 0.699999988079071)
+fn serialize_f32<S: serde::Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(value.to_string().parse().unwrap_or(f64::from(*value)))
+}
+fn default_max_retries() -> u32 { 2 }
+fn default_retry_backoff_ms() -> u64 { 500 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PerceptionConfig {
     /// "adb" or "websocket"
     pub bridge_mode: String,
@@ -81,6 +220,9 @@ pub struct PerceptionConfig {
     pub adb_device: Option<String>,
     #[serde(default = "default_ws_addr")]
     pub android_ws_address: String,
+    /// Close a companion WebSocket that sends nothing for this long (0 = never)
+    #[serde(default = "default_companion_timeout_secs")]
+    pub companion_timeout_secs: u64,
     #[serde(default)]
     pub screen_capture_interval_secs: u64,
     #[serde(default = "default_true")]
@@ -93,17 +235,73 @@ pub struct PerceptionConfig {
     /// Vision mode: "off" | "fallback" | "always" (default: "fallback")
     #[serde(default = "default_vision_mode")]
     pub vision_mode: String,
-    /// Max UI elements to send to LLM from accessibility tree (default: 50)
+    /// Max UI elements to send to LLM from accessibility tree (default: 40, 5..=200)
     #[serde(default = "default_max_elements")]
     pub max_elements: usize,
+    /// When to dump the UI tree: "always" | "on_demand" | "adaptive" (default: "always")
+    #[serde(default = "default_poll_strategy")]
+    pub poll_strategy: String,
+    /// UI nodes from these packages are dropped unless the package is in
+    /// the foreground (status bar, nav bar, launcher behind the app)
+    #[serde(default = "default_ignore_packages")]
+    pub ignore_packages: Vec<String>,
+    /// Downscale screenshots to this width and send them as JPEG (unset = original PNG)
+    #[serde(default)]
+    pub screenshot_max_width: Option<u32>,
+    /// JPEG quality 1-100 when `screenshot_max_width` is set (default: 80)
+    #[serde(default = "default_screenshot_jpeg_quality")]
+    pub screenshot_jpeg_quality: u8,
+    /// Heartbeat: don't re-send a screenshot while the screen stays the same (default: false)
+    #[serde(default)]
+    pub skip_static_screenshots: bool,
+    /// Kill any `adb` call that runs longer than this (default: 15)
+    #[serde(default = "default_adb_timeout_secs")]
+    pub adb_timeout_secs: u64,
+    /// Suppress an identical notification for this long after reporting it (default: 300)
+    #[serde(default = "default_notification_dedup_secs")]
+    pub notification_dedup_secs: u64,
+    /// Re-list installed apps once the cached list is this old (default: 600)
+    #[serde(default = "default_apps_refresh_secs")]
+    pub apps_refresh_secs: u64,
+    /// Put the launchable apps in the system prompt, for `launch_app` (default: false)
+    #[serde(default)]
+    pub apps_in_prompt: bool,
+}
+
+impl PerceptionConfig {
+    pub fn screenshot_encoding(&self) -> crate::sanitizer::ScreenshotEncoding {
+        crate::sanitizer::ScreenshotEncoding {
+            max_width: self.screenshot_max_width,
+            jpeg_quality: self.screenshot_jpeg_quality,
+        }
+    }
 }
 
 fn default_vision_mode() -> String { "fallback".to_string() }
-fn default_max_elements() -> usize { 50 }
+fn default_poll_strategy() -> String { "always".to_string() }
+fn default_text_input_method() -> String { "fast".to_string() }
+fn default_max_elements() -> usize { crate::perception::DEFAULT_MAX_ELEMENTS }
+fn default_apps_refresh_secs() -> u64 { crate::perception::DEFAULT_APPS_REFRESH_SECS }
+
+/// Allowed `perception.max_elements`: fewer starves the model, more floods its context
+pub const MAX_ELEMENTS_RANGE: std::ops::RangeInclusive<usize> = 5..=200;
+fn default_screenshot_jpeg_quality() -> u8 { 80 }
+fn default_adb_timeout_secs() -> u64 { crate::adb::DEFAULT_TIMEOUT_SECS }
+fn default_notification_dedup_secs() -> u64 { crate::perception::DEFAULT_NOTIFICATION_DEDUP_SECS }
+fn default_ignore_packages() -> Vec<String> {
+    vec![
+        "com.android.systemui".into(),
+        "com.android.launcher3".into(),
+        "com.google.android.apps.nexuslauncher".into(),
+        "com.sec.android.app.launcher".into(),
+        "com.miui.home".into(),
+    ]
+}
 fn default_ws_addr() -> String { "ws://192.168.1.100:9090".into() }
+fn default_companion_timeout_secs() -> u64 { 90 }
 fn default_true() -> bool { true }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActionConfig {
     pub dry_run: bool,
     #[serde(default = "default_timeout")]
@@ -111,24 +309,121 @@ pub struct ActionConfig {
     /// Apps that are always RED-classified regardless of action
     #[serde(default)]
     pub restricted_apps: Vec<String>,
+    /// Apps `launch_app` refuses outright, even with confirmation
+    #[serde(default)]
+    pub blocked_apps: Vec<String>,
+    /// Actions the model rates below this confidence are queued for confirmation
+    #[serde(default = "default_min_confidence", serialize_with = "serialize_f32")]
+    pub min_confidence: f32,
+    /// Pixel offset added to every tap/swipe coordinate (after scaling)
+    #[serde(default)]
+    pub coordinate_offset: CoordinateOffset,
+    /// Multiplier applied to every tap/swipe coordinate
+    #[serde(default = "default_coordinate_scale")]
+    pub coordinate_scale: f64,
+    /// Post `notify_user` messages as notifications on the phone, not just the log
+    #[serde(default)]
+    pub notify_on_device: bool,
+    /// Also ask on the phone for pending confirmations (auto-deny after `confirmation_timeout_secs`)
+    #[serde(default)]
+    pub confirm_via_device: bool,
+    /// Minimum gap between device actions in ms (0 = unlimited)
+    #[serde(default)]
+    pub min_interval_ms: u64,
+    /// After a tap on a clickable element, re-tap it once if the screen didn't change
+    #[serde(default)]
+    pub verify_taps: bool,
+    /// How `type_text` enters text: "fast" | "ime" | "clipboard" (default: "fast")
+    #[serde(default = "default_text_input_method")]
+    pub text_input_method: String,
+    /// Longest plan executed per heartbeat tick; extra actions are dropped (0 = unlimited)
+    #[serde(default)]
+    pub max_actions_per_tick: usize,
+    /// Pause after announcing a plan that contains a RED action, so it can be stopped (0 = no pause)
+    #[serde(default = "default_plan_preview_delay_ms")]
+    pub plan_preview_delay_ms: u64,
+    /// Refuse every action that isn't a read-only GREEN one (also `--safe`)
+    #[serde(default)]
+    pub safe_mode: bool,
+    /// What happens to GREEN, YELLOW and RED actions: run, confirm or refuse
+    #[serde(default)]
+    pub policy: ActionPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CoordinateOffset {
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+
+/// How `execute` treats one classification (`action.policy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyMode {
+    /// Execute right away
+    Auto,
+    /// Queue for the user's approval
+    Confirm,
+    /// Refuse without queueing
+    Block,
+}
+
+/// `[action.policy]`: a `PolicyMode` per classification. The default runs
+/// everything; `restricted_apps` still force confirmation of `auto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionPolicy {
+    #[serde(default = "default_policy_mode")]
+    pub green: PolicyMode,
+    #[serde(default = "default_policy_mode")]
+    pub yellow: PolicyMode,
+    #[serde(default = "default_policy_mode")]
+    pub red: PolicyMode,
 }
 
+impl Default for ActionPolicy {
+    fn default() -> Self {
+        Self { green: PolicyMode::Auto, yellow: PolicyMode::Auto, red: PolicyMode::Auto }
+    }
+}
+
+impl ActionPolicy {
+    /// The mode for an (upper-case) classification; None if it isn't one
+    pub fn mode(&self, classification: &str) -> Option<PolicyMode> {
+        match classification {
+            "GREEN" => Some(self.green),
+            "YELLOW" => Some(self.yellow),
+            "RED" => Some(self.red),
+            _ => None,
+        }
+    }
+}
+
+fn default_policy_mode() -> PolicyMode { PolicyMode::Auto }
 fn default_timeout() -> u64 { 60 }
+fn default_min_confidence() -> f32 { 0.5 }
+fn default_coordinate_scale() -> f64 { 1.0 }
+fn default_plan_preview_delay_ms() -> u64 { 3000 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     #[serde(default = "default_host")]
     pub host: String,
     #[serde(default = "default_port")]
     pub port: u16,
     #[serde(default)]
-    pub auth_token: Option<String>,
+    pub auth_token: Option<Secret>,
+    /// Events a dashboard / SSE client can fall behind before it skips ahead (default: 256)
+    #[serde(default = "default_event_buffer")]
+    pub event_buffer: usize,
 }
 
 fn default_host() -> String { "0.0.0.0".into() }
 fn default_port() -> u16 { 8420 }
+fn default_event_buffer() -> usize { 256 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CronJob {
     pub name: String,
     pub schedule: String, // cron expression
@@ -137,7 +432,7 @@ pub struct CronJob {
     pub enabled: bool,
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HooksConfig {
     #[serde(default)]
     pub on_boot: Option<String>,        // file to run on startup
@@ -147,10 +442,513 @@ pub struct HooksConfig {
     pub on_unlock: Option<String>,       // on device unlock
 }
 
+/// A credential from config.toml (`brain.api_key`, `server.auth_token`).
+/// `Debug` and `Serialize` print `REDACTED` instead of the value, so it can't
+/// leak into logs or `config show`; `expose` is for the request that needs it.
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+/// What a non-empty `Secret` prints as
+pub const REDACTED: &str = "<redacted>";
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn shown(&self) -> &str {
+        if self.0.is_empty() { "" } else { REDACTED }
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.shown())
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.shown())
+    }
+}
+
+/// Env var used for `brain.api_key` when the config doesn't set one
+pub const API_KEY_ENV: &str = "HERMITDROID_API_KEY";
+
 impl Config {
+    /// Parse config.toml. `${VAR}` in any string value is replaced with the
+    /// environment variable (an unset variable is a load error), `secret:<name>`
+    /// values are decrypted from secrets.json, and an unset `brain.api_key`
+    /// falls back to `HERMITDROID_API_KEY`.
     pub fn load(path: &Path) -> anyhow::Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
+        let mut table: toml::Value = toml::from_str(&content)?;
+        interpolate_env(&mut table, "", &|name| std::env::var(name).ok())?;
+        crate::secrets::resolve(&mut table, path)?;
+        let mut config: Config = table.try_into()?;
+        if config.brain.api_key.as_ref().is_none_or(Secret::is_empty) {
+            config.brain.api_key = std::env::var(API_KEY_ENV).ok().filter(|k| !k.is_empty()).map(Secret::new);
+        }
+        config.validate()?;
         Ok(config)
     }
+
+    /// `[brain]` for `None` / "default", otherwise the named profile
+    pub fn brain_profile(&self, name: Option<&str>) -> anyhow::Result<&BrainConfig> {
+        match name.map(str::trim).filter(|n| !n.is_empty() && *n != DEFAULT_BRAIN_PROFILE) {
+            None => Ok(&self.brain),
+            Some(name) => self
+                .brain_profiles
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| &p.brain)
+                .ok_or_else(|| anyhow::anyhow!(
+                    "Unknown brain profile '{}' (available: {})",
+                    name,
+                    self.brain_profile_names().join(", ")
+                )),
+        }
+    }
+
+    pub fn brain_profile_names(&self) -> Vec<&str> {
+        std::iter::once(DEFAULT_BRAIN_PROFILE)
+            .chain(self.brain_profiles.iter().map(|p| p.name.as_str()))
+            .collect()
+    }
+
+    /// This config with `[brain]` replaced by the named profile
+    pub fn with_brain_profile(&self, name: Option<&str>) -> anyhow::Result<Config> {
+        let brain = self.brain_profile(name)?.clone();
+        Ok(Config { brain, ..self.clone() })
+    }
+
+    /// This config with `--max-tokens` / `--temperature` applied to `[brain]`.
+    /// Only for the current invocation — config.toml is left as it is.
+    pub fn with_generation_overrides(mut self, max_tokens: Option<u32>, temperature: Option<f32>) -> anyhow::Result<Config> {
+        if let Some(max_tokens) = max_tokens {
+            if max_tokens == 0 {
+                anyhow::bail!("--max-tokens must be at least 1");
+            }
+            self.brain.max_tokens = max_tokens;
+        }
+        if let Some(temperature) = temperature {
+            if !TEMPERATURE_RANGE.contains(&temperature) {
+                anyhow::bail!(
+                    "--temperature {} is outside {}..={}",
+                    temperature,
+                    TEMPERATURE_RANGE.start(),
+                    TEMPERATURE_RANGE.end()
+                );
+            }
+            self.brain.temperature = temperature;
+        }
+        Ok(self)
+    }
+
+    /// This config as TOML with secrets redacted (`config show`). `file` is
+    /// config.toml as written; values it doesn't set are marked `# default`
+    /// when `effective`, and left out otherwise. Besides `Secret` fields, any
+    /// value written in the file as `secret:<name>` or with `${VAR}` is
+    /// redacted, and an API key taken from `HERMITDROID_API_KEY` is marked `# (env)`.
+    pub fn to_annotated_toml(&self, file: &toml::Value, effective: bool) -> anyhow::Result<String> {
+        let rendered = toml::to_string(self)?;
+        let mut out = String::new();
+        let mut arrays: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+        let mut section = Some(file);
+        let mut header: Option<&str> = None;
+        let mut section_name = "";
+        // Inside a multi-line string: whether its key was kept
+        let mut multiline: Option<bool> = None;
+        for line in rendered.lines() {
+            if let Some(kept) = multiline {
+                if kept {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                if line.contains("\"\"\"") || line.contains("'''") {
+                    multiline = None;
+                }
+                continue;
+            }
+            let trimmed = line.trim();
+            if let Some(name) = trimmed.strip_prefix("[[").and_then(|h| h.strip_suffix("]]")) {
+                *arrays.entry(name.to_string()).or_insert(0) += 1;
+                section = lookup_table(file, name, &arrays);
+                (header, section_name) = (Some(line), name);
+            } else if let Some(name) = trimmed.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+                section = lookup_table(file, name, &arrays);
+                (header, section_name) = (Some(line), name);
+            } else if let Some((key, value)) = line.split_once(" = ") {
+                let raw = section.and_then(|s| s.get(key.trim().trim_matches('"')));
+                let set = raw.is_some();
+                let from_env = section_name == "brain"
+                    && key.trim() == "api_key"
+                    && raw.and_then(|v| v.as_str()).is_none_or(str::is_empty);
+                let mut kept = set || effective;
+                if kept {
+                    if let Some(h) = header.take() {
+                        if !out.is_empty() {
+                            out.push('\n');
+                        }
+                        out.push_str(h);
+                        out.push('\n');
+                    }
+                    let sensitive = raw.is_some_and(is_sensitive_value);
+                    if sensitive {
+                        out.push_str(&format!("{} = \"{}\"", key, REDACTED));
+                    } else {
+                        out.push_str(line);
+                    }
+                    if from_env {
+                        out.push_str("  # (env)");
+                    } else if !set {
+                        out.push_str("  # default");
+                    }
+                    out.push('\n');
+                    // The redacted line stands for a whole multi-line string
+                    kept = !sensitive;
+                }
+                let opens = |q: &str| value.starts_with(q) && value.matches(q).count() == 1;
+                if opens("\"\"\"") || opens("'''") {
+                    multiline = Some(kept);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Reject values that parse but can't work
+    fn validate(&self) -> anyhow::Result<()> {
+        if !MAX_ELEMENTS_RANGE.contains(&self.perception.max_elements) {
+            anyhow::bail!(
+                "Config perception.max_elements: {} is outside {}..={}",
+                self.perception.max_elements,
+                MAX_ELEMENTS_RANGE.start(),
+                MAX_ELEMENTS_RANGE.end()
+            );
+        }
+        if self.perception.notification_dedup_secs == 0 {
+            anyhow::bail!("Config perception.notification_dedup_secs: must be at least 1");
+        }
+        if self.server.event_buffer == 0 {
+            anyhow::bail!("Config server.event_buffer: must be at least 1");
+        }
+        if crate::action::TextInputMethod::parse(&self.action.text_input_method).is_none() {
+            anyhow::bail!(
+                "Config action.text_input_method: \"{}\" is not fast, ime or clipboard",
+                self.action.text_input_method
+            );
+        }
+        let brains = std::iter::once(("brain", &self.brain))
+            .chain(self.brain_profiles.iter().map(|p| (p.name.as_str(), &p.brain)));
+        for (name, brain) in brains {
+            let listed = brain.prompt_sections.iter().flatten().chain(brain.prompt_max_chars.keys());
+            if let Some(unknown) = listed.into_iter().find(|s| !crate::brain::PROMPT_SECTIONS.contains(&s.as_str())) {
+                anyhow::bail!(
+                    "Config {}: unknown prompt section \"{}\" (known: {})",
+                    name,
+                    unknown,
+                    crate::brain::PROMPT_SECTIONS.join(", ")
+                );
+            }
+        }
+        let mut names = std::collections::HashSet::new();
+        for profile in &self.brain_profiles {
+            let name = profile.name.trim();
+            if name.is_empty() || name == DEFAULT_BRAIN_PROFILE {
+                anyhow::bail!("Config brain_profiles: name \"{}\" is reserved or empty", profile.name);
+            }
+            if !names.insert(name) {
+                anyhow::bail!("Config brain_profiles: duplicate name \"{}\"", name);
+            }
+        }
+        if self.agent.active_hours.is_some() && self.agent.active_window().is_none() {
+            anyhow::bail!(
+                "Config agent.active_hours: \"{}\" is not HH:MM-HH:MM",
+                self.agent.active_hours.as_deref().unwrap_or_default()
+            );
+        }
+        if let Some(name) = &self.agent.timezone {
+            if name.trim().parse::<chrono_tz::Tz>().is_err() {
+                tracing::warn!("Config agent.timezone: unknown zone \"{}\", using UTC", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A config.toml value that is, or holds, a `secret:` reference or a `${VAR}`
+/// — its resolved form must not be shown
+fn is_sensitive_value(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::String(s) => s.starts_with(crate::secrets::SECRET_PREFIX) || s.contains("${"),
+        toml::Value::Array(items) => items.iter().any(is_sensitive_value),
+        toml::Value::Table(table) => table.values().any(is_sensitive_value),
+        _ => false,
+    }
+}
+
+/// The table at a dotted TOML header path. `arrays` counts the `[[...]]`
+/// headers seen so far, so a path through an array picks the current entry.
+fn lookup_table<'a>(
+    file: &'a toml::Value,
+    name: &str,
+    arrays: &std::collections::HashMap<String, usize>,
+) -> Option<&'a toml::Value> {
+    let mut value = file;
+    let mut path = String::new();
+    for segment in name.split('.') {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(segment);
+        value = value.get(segment.trim_matches('"'))?;
+        if let (toml::Value::Array(items), Some(count)) = (value, arrays.get(&path)) {
+            value = items.get(count.checked_sub(1)?)?;
+        }
+    }
+    Some(value)
+}
+
+/// Replace `${VAR}` in every string under `value`; `key` is the dotted path
+/// for error messages
+fn interpolate_env(
+    value: &mut toml::Value,
+    key: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> anyhow::Result<()> {
+    match value {
+        toml::Value::String(s) if s.contains("${") => *s = expand_env(s, key, lookup)?,
+        toml::Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                interpolate_env(item, &format!("{}[{}]", key, i), lookup)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (k, v) in table.iter_mut() {
+                let path = if key.is_empty() { k.clone() } else { format!("{}.{}", key, k) };
+                interpolate_env(v, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand_env(s: &str, key: &str, lookup: &dyn Fn(&str) -> Option<String>) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            anyhow::bail!("Config {}: unterminated ${{ in \"{}\"", key, s);
+        };
+        let name = &after[..end];
+        match lookup(name) {
+            Some(v) => out.push_str(&v),
+            None => anyhow::bail!("Config {}: environment variable {} is not set", key, name),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_interpolation() {
+        let lookup = |name: &str| match name {
+            "GROQ_KEY" => Some("gsk_123".to_string()),
+            "HOST" => Some("pixel".to_string()),
+            _ => None,
+        };
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            [brain]
+            api_key = "${GROQ_KEY}"
+            endpoint = "http://${HOST}:11434/v1"
+            [perception]
+            priority_apps = ["whatsapp", "${HOST}"]
+            "#,
+        )
+        .unwrap();
+        interpolate_env(&mut value, "", &lookup).unwrap();
+        assert_eq!(value["brain"]["api_key"].as_str(), Some("gsk_123"));
+        assert_eq!(value["brain"]["endpoint"].as_str(), Some("http://pixel:11434/v1"));
+        assert_eq!(value["perception"]["priority_apps"][1].as_str(), Some("pixel"));
+
+        let mut missing: toml::Value = toml::from_str("[brain]\napi_key = \"${NOPE}\"").unwrap();
+        let err = interpolate_env(&mut missing, "", &lookup).unwrap_err().to_string();
+        assert!(err.contains("brain.api_key") && err.contains("NOPE"), "{}", err);
+        assert!(expand_env("${OPEN", "k", &lookup).is_err());
+    }
+
+    #[test]
+    fn test_log_path_stays_in_workspace() {
+        let agent: AgentConfig = toml::from_str(
+            "name = \"h\"\nheartbeat_interval_secs = 30\nworkspace_path = \"/ws\"\nlog_file = \"../../etc/hermit.log\"",
+        )
+        .unwrap();
+        assert_eq!(agent.log_path(), Some(std::path::PathBuf::from("/ws/etc/hermit.log")));
+        assert_eq!(agent.log_format, "plain");
+    }
+
+    #[test]
+    fn test_brain_profiles() {
+        let base = include_str!("../../config.default.toml");
+        let config: Config = toml::from_str(&format!(
+            "{}\n[[brain_profiles]]\nname = \"strong\"\nbackend = \"openai_compatible\"\nmodel = \"gpt-4o\"\nendpoint = \"https://api.openai.com/v1\"\n",
+            base
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.brain_profile(None).unwrap().model, config.brain.model);
+        assert_eq!(config.brain_profile(Some("default")).unwrap().model, config.brain.model);
+        let strong = config.with_brain_profile(Some("strong")).unwrap();
+        assert_eq!(strong.brain.model, "gpt-4o");
+        assert_eq!(strong.brain.max_retries, 2);
+        let err = config.brain_profile(Some("cheap")).unwrap_err().to_string();
+        assert!(err.contains("available: default, strong"), "{}", err);
+
+        let mut dup = config.clone();
+        dup.brain_profiles.push(dup.brain_profiles[0].clone());
+        assert!(dup.validate().unwrap_err().to_string().contains("duplicate"));
+    }
+
+    #[test]
+    fn test_generation_overrides() {
+        let config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        let tuned = config.clone().with_generation_overrides(Some(512), Some(0.0)).unwrap();
+        assert_eq!(tuned.brain.max_tokens, 512);
+        assert_eq!(tuned.brain.temperature, 0.0);
+        let kept = config.clone().with_generation_overrides(None, None).unwrap();
+        assert_eq!(kept.brain.max_tokens, config.brain.max_tokens);
+        assert!(config.clone().with_generation_overrides(None, Some(2.5)).is_err());
+        assert!(config.with_generation_overrides(Some(0), None).is_err());
+    }
+
+    #[test]
+    fn test_timezone_falls_back_to_utc() {
+        let mut agent: AgentConfig = toml::from_str(
+            "name = \"h\"\nheartbeat_interval_secs = 30\nworkspace_path = \"/ws\"\ntimezone = \"Asia/Jakarta\"",
+        )
+        .unwrap();
+        assert_eq!(agent.tz(), chrono_tz::Asia::Jakarta);
+        assert!(agent.local_now("%H:%M").ends_with("Asia/Jakarta (+07:00)"));
+        agent.timezone = Some("Mars/Olympus".into());
+        assert_eq!(agent.tz(), chrono_tz::UTC);
+        agent.timezone = None;
+        assert!(agent.local_now("%H:%M").ends_with("UTC (+00:00)"));
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let mut config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        let at = |t: &str| chrono::DateTime::parse_from_rfc3339(t).unwrap().with_timezone(&chrono::Utc);
+        assert!(!config.agent.in_quiet_hours(at("2025-06-15T03:00:00Z")));
+
+        config.agent.active_hours = Some("08:00-23:00".into());
+        assert!(config.agent.in_quiet_hours(at("2025-06-15T03:00:00Z")));
+        assert!(!config.agent.in_quiet_hours(at("2025-06-15T08:00:00Z")));
+        assert!(config.agent.in_quiet_hours(at("2025-06-15T23:00:00Z")));
+        // 03:00 UTC is 10:00 in Jakarta
+        config.agent.timezone = Some("Asia/Jakarta".into());
+        assert!(!config.agent.in_quiet_hours(at("2025-06-15T03:00:00Z")));
+
+        config.agent.timezone = None;
+        config.agent.active_hours = Some("22:00-06:00".into());
+        assert!(!config.agent.in_quiet_hours(at("2025-06-15T02:00:00Z")));
+        assert!(config.agent.in_quiet_hours(at("2025-06-15T12:00:00Z")));
+
+        config.agent.active_hours = Some("8am-11pm".into());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("agent.active_hours"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_max_elements() {
+        let mut config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        assert_eq!(config.perception.max_elements, 40);
+        assert!(config.validate().is_ok());
+        config.perception.max_elements = 3;
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("perception.max_elements"), "{}", err);
+    }
+
+    #[test]
+    fn test_show_redacts_secrets_and_marks_defaults() {
+        let file: toml::Value = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        let mut config: Config = file.clone().try_into().unwrap();
+        config.brain.api_key = Some(Secret::new("sk-live-123"));
+        config.server.auth_token = Some(Secret::new("tok-456"));
+        assert!(!format!("{:?}", config).contains("sk-live-123"));
+
+        let effective = config.to_annotated_toml(&file, true).unwrap();
+        assert!(!effective.contains("sk-live-123") && !effective.contains("tok-456"), "{}", effective);
+        assert!(effective.contains("api_key = \"<redacted>\""));
+        assert!(effective.contains("temperature = 0.7\n"));
+        assert!(effective.contains("\n[stuck]\nscreen_threshold = 3  # default\n"), "{}", effective);
+        assert!(effective.contains("fallback_cooldown_secs = 60  # default"));
+
+        let shown = config.to_annotated_toml(&file, false).unwrap();
+        assert!(!shown.contains("# default") && !shown.contains("[stuck]"), "{}", shown);
+        assert!(shown.contains("\n[server]\nhost = \"0.0.0.0\""));
+
+        // Values resolved from secrets.json or the environment are hidden by
+        // what the file says, not by the field's type
+        let file: toml::Value = toml::from_str(
+            "[brain]\nmodel = \"secret:brain.model\"\nendpoint = \"https://${HOST}/v1\"\n[agent]\nname = \"Hermit\"\n",
+        )
+        .unwrap();
+        let mut config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        config.brain.model = "decrypted-model".into();
+        config.brain.endpoint = "https://internal.example/v1".into();
+        config.brain.api_key = Some(Secret::new("sk-from-env"));
+        let effective = config.to_annotated_toml(&file, true).unwrap();
+        assert!(!effective.contains("decrypted-model") && !effective.contains("internal.example"), "{}", effective);
+        assert!(effective.contains("model = \"<redacted>\"\n"), "{}", effective);
+        assert!(effective.contains("api_key = \"<redacted>\"  # (env)\n"), "{}", effective);
+        assert!(effective.contains("name = \"Hermitdroid\"\n"));
+    }
+
+    #[test]
+    fn test_validate_event_buffer() {
+        let mut config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        assert_eq!(config.server.event_buffer, 256);
+        config.server.event_buffer = 0;
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("server.event_buffer"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_notification_dedup() {
+        let mut config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        config.perception.notification_dedup_secs = 0;
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("perception.notification_dedup_secs"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_text_input_method() {
+        let mut config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        config.action.text_input_method = "IME".into();
+        assert!(config.validate().is_ok());
+        config.action.text_input_method = "typewriter".into();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("action.text_input_method"), "{}", err);
+    }
 }
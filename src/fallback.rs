@@ -1,3 +1,4 @@
+use crate::config::Secret;
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 use tracing::{error, info, warn};
@@ -11,7 +12,7 @@ pub struct ModelConfig {
     pub model: String,
     pub endpoint: String,
     #[serde(default)]
-    pub api_key: String,
+    pub api_key: Secret,
     /// Optional: whether this model supports vision
     #[serde(default)]
     pub vision_enabled: bool,
@@ -168,6 +169,15 @@ impl ErrorClass {
         Self::Unknown
     }
 
+    /// Whether this error is worth retrying against the same model
+    /// (auth and client errors would fail the same way again)
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimit | Self::Timeout | Self::ServerError | Self::NetworkError
+        )
+    }
+
     /// Whether this error class should trigger a model fallback
     pub fn should_fallback(&self, config: &FallbackConfig) -> bool {
         match self {
@@ -380,7 +390,7 @@ mod tests {
             backend: "openai".to_string(),
             model: "gpt-4o".to_string(),
             endpoint: "https://api.openai.com/v1".to_string(),
-            api_key: "sk-test".to_string(),
+            api_key: Secret::new("sk-test"),
             vision_enabled: true,
         }
     }
@@ -391,14 +401,14 @@ mod tests {
                 backend: "groq".to_string(),
                 model: "llama-3.3-70b-versatile".to_string(),
                 endpoint: "https://api.groq.com/openai/v1".to_string(),
-                api_key: "gsk-test".to_string(),
+                api_key: Secret::new("gsk-test"),
                 vision_enabled: false,
             },
             ModelConfig {
                 backend: "ollama".to_string(),
                 model: "llama3.2".to_string(),
                 endpoint: "http://localhost:11434/v1".to_string(),
-                api_key: String::new(),
+                api_key: Secret::default(),
                 vision_enabled: false,
             },
         ]
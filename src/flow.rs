@@ -1,6 +1,8 @@
-use crate::action::ActionExecutor;
-use crate::config::Config;
-use crate::perception::Perception;
+use crate::action::{check_allowed, read_action_log, safe_mode_allows, ActionExecutor, APP_BLOCKED, ActionLogEntry, CoordinateCalibration, LoggedAction, ACTION_LOG_FILE, DEFAULT_WAIT_TIMEOUT_MS};
+use crate::adb;
+use crate::config::{ActionPolicy, Config, PolicyMode};
+use crate::perception::{ElementQuery, Perception};
+use crate::runs::RunProgress;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use tracing::error;
@@ -22,12 +24,17 @@ pub struct Flow {
     pub name: String,
 
     /// Optional: Android package name to launch first.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub app_id: Option<String>,
 
     /// Optional description.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+
+    /// Optional: the only action types this flow may use (e.g. `[tap, back, wait]`).
+    /// The flow is refused before it starts if any action falls outside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_actions: Option<Vec<String>>,
 }
 
 /// Individual action in a flow.
@@ -48,10 +55,162 @@ pub async fn run_flow(
     config: &Config,
     path: &str,
     dry_run: bool,
+    progress: Option<&RunProgress>,
 ) -> anyhow::Result<()> {
     let (flow, actions) = load_flow(path)?;
+    run_flow_actions(config, &flow, &actions, dry_run, progress).await
+}
+
+/// Turn a recorded session into a flow, then save it to
+/// `workspace/flows/<save_as>.yaml` or play it back. `source` is an action
+/// log file or a session id in `workspace/action_log.jsonl`; either way the
+/// latest session in the file is used unless one is named.
+pub async fn run_replay(
+    config: &Config,
+    source: Option<&str>,
+    save_as: Option<&str>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let (path, session) = match source {
+        Some(file) if Path::new(file).is_file() => (std::path::PathBuf::from(file), None),
+        other => (Path::new(&config.agent.workspace_path).join(ACTION_LOG_FILE), other),
+    };
+    let logged = read_action_log(&path)
+        .map_err(|e| anyhow::anyhow!("Cannot read action log '{}': {}", path.display(), e))?;
+    let entries = session_entries(logged, session).map_err(|sessions| match session {
+        Some(id) => anyhow::anyhow!(
+            "No session '{}' in '{}' (recent: {})",
+            id,
+            path.display(),
+            sessions.iter().rev().take(5).cloned().collect::<Vec<_>>().join(", ")
+        ),
+        None => anyhow::anyhow!("Action log '{}' is empty", path.display()),
+    })?;
+
+    let (actions, skipped) = actions_from_log(&entries);
+    for reason in &skipped {
+        println!("  {YELLOW}⚠  skipped {}{RESET}", reason);
+    }
+    if actions.is_empty() {
+        anyhow::bail!("Nothing replayable in '{}' ({} entries)", path.display(), entries.len());
+    }
+
+    let flow = Flow {
+        name: save_as.unwrap_or("Replay").to_string(),
+        app_id: None,
+        description: Some(format!("Recorded from {}", path.display())),
+        allowed_actions: None,
+    };
+    match save_as {
+        Some(name) => {
+            let saved = save_flow(&config.agent.workspace_path, name, &flow, &actions)?;
+            println!("\n  {GREEN}✅ Saved flow:{RESET} {} ({} actions)", saved.display(), actions.len());
+            println!("  {DIM}Re-run with: hermitdroid flow {}{RESET}\n", saved.display());
+            Ok(())
+        }
+        None => run_flow_actions(config, &flow, &actions, dry_run, None).await,
+    }
+}
+
+/// The entries of `session`, or of the latest session; `Err` lists the
+/// sessions there are when it isn't found
+fn session_entries(logged: Vec<LoggedAction>, session: Option<&str>) -> Result<Vec<ActionLogEntry>, Vec<String>> {
+    let Some(wanted) = session.map(str::to_string).or_else(|| logged.last().map(|l| l.session.clone())) else {
+        return Err(Vec::new());
+    };
+    let mut sessions: Vec<String> = Vec::new();
+    let mut entries = Vec::new();
+    for line in logged {
+        if sessions.last() != Some(&line.session) && !sessions.contains(&line.session) {
+            sessions.push(line.session.clone());
+        }
+        if line.session == wanted {
+            entries.push(line.entry);
+        }
+    }
+    if entries.is_empty() { Err(sessions) } else { Ok(entries) }
+}
+
+/// Convert successful GREEN/YELLOW log entries into flow actions. Entries
+/// with no deterministic equivalent (RED, index taps, companion actions)
+/// are returned as skip reasons instead.
+pub fn actions_from_log(entries: &[ActionLogEntry]) -> (Vec<FlowAction>, Vec<String>) {
+    let mut actions = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in entries {
+        if !matches!(entry.classification.as_str(), "GREEN" | "YELLOW") || entry.result == "DRY_RUN" || entry.failed() {
+            skipped.push(format!("{} ({})", entry.action_type, entry.classification));
+            continue;
+        }
+        match log_entry_to_flow(entry) {
+            Some(action) => actions.push(action),
+            None => skipped.push(format!("{} (no flow equivalent)", entry.action_type)),
+        }
+    }
+    (actions, skipped)
+}
+
+fn log_entry_to_flow(entry: &ActionLogEntry) -> Option<FlowAction> {
+    let p = &entry.params;
+    let num = |key: &str| p[key].as_f64().map(|v| v.round() as i64);
+    let keyed = |key: &str, value: serde_json::Value| {
+        let mut map = serde_json::Map::new();
+        map.insert(key.to_string(), value);
+        Some(FlowAction::Keyed(map))
+    };
+    match entry.action_type.as_str() {
+        "tap" => keyed("tap", serde_json::json!([num("x")?, num("y")?])),
+        "swipe" => {
+            let ms = p.get("ms").or(p.get("duration_ms")).and_then(|v| v.as_i64()).unwrap_or(300);
+            keyed("swipe", serde_json::json!([num("x1")?, num("y1")?, num("x2")?, num("y2")?, ms]))
+        }
+        "scroll_down" => keyed("swipe", serde_json::json!([540, 1500, 540, 500, 300])),
+        "scroll_up" => keyed("swipe", serde_json::json!([540, 500, 540, 1500, 300])),
+        "type_text" if p.get("index").is_none() => keyed("type", p["text"].as_str()?.into()),
+        "press_key" => keyed("key", p["key"].as_str()?.into()),
+        "launch_app" => keyed("launch", p["package"].as_str()?.into()),
+        "wait" => keyed("wait", (p["ms"].as_u64().unwrap_or(1000) as f64 / 1000.0).into()),
+        "wait_for_text" => keyed("wait_for_text", p["text"].as_str()?.into()),
+        "wait_for_element" => keyed("wait_for_element", p.clone()),
+        "home" | "go_home" => Some(FlowAction::Simple("home".into())),
+        "back" | "go_back" => Some(FlowAction::Simple("back".into())),
+        _ => None,
+    }
+}
+
+/// Write `flow` in the `load_flow` format to `workspace/flows/<name>.yaml`
+fn save_flow(workspace_path: &str, name: &str, flow: &Flow, actions: &[FlowAction]) -> anyhow::Result<std::path::PathBuf> {
+    let dir = Path::new(workspace_path).join("flows");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.yaml", crate::workflow::sanitize_filename(name)));
+    write_flow(&path, flow, actions)?;
+    Ok(path)
+}
+
+/// Write `flow` in the `load_flow` format (header, `---`, actions) to `path`
+pub fn write_flow(path: &Path, flow: &Flow, actions: &[FlowAction]) -> anyhow::Result<()> {
+    let content = format!("{}---\n{}", serde_yaml::to_string(flow)?, serde_yaml::to_string(actions)?);
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+async fn run_flow_actions(
+    config: &Config,
+    flow: &Flow,
+    actions: &[FlowAction],
+    dry_run: bool,
+    progress: Option<&RunProgress>,
+) -> anyhow::Result<()> {
+    check_flow_allowed(flow, actions)?;
+    if config.action.safe_mode {
+        check_flow_safe(flow, actions)?;
+    }
+    check_flow_policy(flow, actions, &config.action.policy, &config.action.restricted_apps)?;
     let total = actions.len();
     let dry_run = dry_run || config.action.dry_run;
+    if let Some(p) = progress {
+        p.emit("run_started", serde_json::json!({"kind": "flow", "name": flow.name, "total": total}));
+    }
 
     // Print header
     println!("\n{CYAN}{BOLD}⚡ Hermitdroid — Flow Mode (no AI){RESET}\n");
@@ -70,16 +229,36 @@ pub async fn run_flow(
 
     let start = std::time::Instant::now();
 
+    // Only fractional coordinates need it; skip the `wm size` round-trip otherwise
+    let resolution = if actions.iter().any(has_fractional_coords) {
+        crate::sanitizer::get_screen_resolution(&config.perception.adb_device).await
+    } else {
+        None
+    };
+
     // Initialize executor
     let adb_device = config.perception.adb_device.clone();
     let executor = ActionExecutor::new(
         dry_run,
         adb_device.clone(),
         config.action.restricted_apps.clone(),
+        Some(Perception::new(adb_device.clone(), vec![])),
+    )
+    .with_min_confidence(config.action.min_confidence)
+    .with_min_interval_ms(config.action.min_interval_ms)
+    .with_calibration(CoordinateCalibration::from_config(&config.action))
+    .with_safe_mode(config.action.safe_mode)
+    .with_blocked_apps(config.action.blocked_apps.clone())
+    .with_policy(config.action.policy)
+    .with_device_notifications(
+        config.action.notify_on_device.then(|| config.agent.name.clone()),
     );
 
     // Optional: launch app first
     if let Some(ref app_id) = flow.app_id {
+        if executor.is_blocked_app(app_id) {
+            anyhow::bail!("Flow '{}' refused: {} ({})", flow.name, APP_BLOCKED, app_id);
+        }
         let action_start = std::time::Instant::now();
         let _ = executor.execute_raw(&format!("launch {}", app_id), &config.perception.adb_device).await;
         let ms = action_start.elapsed().as_millis();
@@ -92,8 +271,18 @@ pub async fn run_flow(
         let step = i + 1;
         let action_start = std::time::Instant::now();
 
-        let (action_desc, result) = execute_flow_action(&executor, &adb_device, action, dry_run).await;
+        let (action_desc, result) = execute_flow_action(&executor, &adb_device, action, resolution).await;
         let ms = action_start.elapsed().as_millis();
+        if let Some(p) = progress {
+            p.emit("run_step", serde_json::json!({
+                "step": step,
+                "total": total,
+                "action": action_desc,
+                "ok": result.is_ok(),
+                "result": match &result { Ok(m) => m.clone(), Err(e) => e.to_string() },
+                "ms": ms,
+            }));
+        }
 
         match result {
             Ok(msg) => {
@@ -146,12 +335,140 @@ pub fn list_flows() -> Vec<(std::path::PathBuf, Flow)> {
 
 // ── Flow action execution ──────────────────────────────────────────────────
 
+/// The action type of a flow step, with aliases folded (`type_text` → `type`)
+fn flow_action_name(action: &FlowAction) -> String {
+    let name = match action {
+        FlowAction::Simple(cmd) => cmd.trim().to_lowercase(),
+        FlowAction::Keyed(map) => map.keys().next().map(|k| k.trim().to_lowercase()).unwrap_or_default(),
+    };
+    canonical_flow_action(&name).to_string()
+}
+
+fn canonical_flow_action(name: &str) -> &str {
+    match name {
+        "type_text" => "type",
+        "launch_app" | "launchapp" => "launch",
+        "keyevent" => "key",
+        "taptext" => "tap_text",
+        other => other,
+    }
+}
+
+/// Refuse a flow whose header `allowed_actions` doesn't cover every step
+/// (including the `app_id` launch) before anything runs
+fn check_flow_allowed(flow: &Flow, actions: &[FlowAction]) -> anyhow::Result<()> {
+    let Some(allowed) = &flow.allowed_actions else { return Ok(()) };
+    let allowed: Vec<String> = allowed
+        .iter()
+        .map(|a| canonical_flow_action(&a.trim().to_lowercase()).to_string())
+        .collect();
+    let launch = flow.app_id.as_ref().map(|_| "launch".to_string());
+    for (step, name) in launch.into_iter().chain(actions.iter().map(flow_action_name)).enumerate() {
+        check_allowed(Some(&allowed), &name).map_err(|e| {
+            anyhow::anyhow!("Flow '{}' refused at step {}: {}", flow.name, step + 1, e)
+        })?;
+    }
+    Ok(())
+}
+
+/// In safe mode (`--safe`), refuse a flow with any step besides the
+/// read-only ones (`action::SAFE_MODE_ACTIONS`) before anything runs
+fn check_flow_safe(flow: &Flow, actions: &[FlowAction]) -> anyhow::Result<()> {
+    let launch = flow.app_id.as_ref().map(|_| "launch".to_string());
+    for (step, name) in launch.into_iter().chain(actions.iter().map(flow_action_name)).enumerate() {
+        if name != "done" && !safe_mode_allows(&name) {
+            anyhow::bail!("Flow '{}' refused at step {}: {} is not allowed in safe mode", flow.name, step + 1, name);
+        }
+    }
+    Ok(())
+}
+
+/// Refuse a flow with a step `action.policy` blocks or wants confirmed
+/// (nobody is asked during a flow) before anything runs. Read-only steps
+/// are GREEN, the rest YELLOW, and a launch of a restricted app RED.
+fn check_flow_policy(
+    flow: &Flow,
+    actions: &[FlowAction],
+    policy: &ActionPolicy,
+    restricted_apps: &[String],
+) -> anyhow::Result<()> {
+    let restricted = |pkg: &str| restricted_apps.iter().any(|a| pkg.contains(a.as_str()));
+    let launch = flow.app_id.as_ref().map(|app| ("launch".to_string(), Some(app.clone())));
+    let steps = actions.iter().map(|a| {
+        let pkg = match a {
+            FlowAction::Keyed(map) => map.values().next().and_then(|v| v.as_str()).map(str::to_string),
+            FlowAction::Simple(_) => None,
+        };
+        (flow_action_name(a), pkg)
+    });
+    for (step, (name, pkg)) in launch.into_iter().chain(steps).enumerate() {
+        if name == "done" {
+            continue;
+        }
+        let class = if name == "launch" && pkg.as_deref().is_some_and(restricted) {
+            "RED"
+        } else if safe_mode_allows(&name) {
+            "GREEN"
+        } else {
+            "YELLOW"
+        };
+        let refusal = match policy.mode(class) {
+            Some(PolicyMode::Block) => "blocked",
+            Some(PolicyMode::Confirm) => "set to confirm, which a flow can't ask for",
+            _ => continue,
+        };
+        anyhow::bail!(
+            "Flow '{}' refused at step {}: {} ({}) is {} by action.policy",
+            flow.name, step + 1, name, class, refusal
+        );
+    }
+    Ok(())
+}
+
+/// A `tap` / `swipe` point in pixels. Floats in 0..=1 are fractions of the
+/// screen (`[0.5, 0.9]`), so a flow works across resolutions; integers and
+/// larger numbers are pixels.
+fn flow_point(
+    x: &serde_json::Value,
+    y: &serde_json::Value,
+    resolution: Option<(u32, u32)>,
+) -> anyhow::Result<(f64, f64)> {
+    let coord = |value: &serde_json::Value, size: Option<u32>| -> anyhow::Result<f64> {
+        if let Some(px) = value.as_i64() {
+            return Ok(px as f64);
+        }
+        let n = value
+            .as_f64()
+            .ok_or_else(|| anyhow::anyhow!("coordinate {} is not a number", value))?;
+        if !(0.0..=1.0).contains(&n) {
+            return Ok(n.round());
+        }
+        let size = size.ok_or_else(|| {
+            anyhow::anyhow!("coordinate {} is a fraction of the screen, but its resolution is unknown", n)
+        })?;
+        Ok((n * size as f64).round().min(size.saturating_sub(1) as f64))
+    };
+    Ok((coord(x, resolution.map(|r| r.0))?, coord(y, resolution.map(|r| r.1))?))
+}
+
+/// A `tap` or `swipe` step with a fractional coordinate (see `flow_point`)
+fn has_fractional_coords(action: &FlowAction) -> bool {
+    let FlowAction::Keyed(map) = action else {
+        return false;
+    };
+    map.iter()
+        .filter(|(key, _)| matches!(key.trim().to_lowercase().as_str(), "tap" | "swipe"))
+        .filter_map(|(_, value)| value.as_array())
+        .flatten()
+        .any(|v| v.as_i64().is_none() && v.as_f64().is_some_and(|n| (0.0..=1.0).contains(&n)))
+}
+
 /// Execute a single flow action and return (description, result).
 async fn execute_flow_action(
     executor: &ActionExecutor,
     adb_device: &Option<String>,
     action: &FlowAction,
-    _dry_run: bool,
+    resolution: Option<(u32, u32)>,
 ) -> (String, anyhow::Result<String>) {
     match action {
         FlowAction::Simple(cmd) => {
@@ -207,12 +524,15 @@ async fn execute_flow_action(
                         (format!("wait {}s", secs), Ok("ok".to_string()))
                     }
                     "tap" => {
-                        // tap: [x, y] — coordinate tap
+                        // tap: [x, y] — coordinate tap (pixels, or fractions of the screen)
                         if let Some(arr) = value.as_array() {
                             if arr.len() >= 2 {
-                                let x = arr[0].as_i64().unwrap_or(0);
-                                let y = arr[1].as_i64().unwrap_or(0);
-                                let result = execute_adb_tap(adb_device, x as i32, y as i32).await;
+                                let (x, y) = match flow_point(&arr[0], &arr[1], resolution) {
+                                    Ok(point) => point,
+                                    Err(e) => return ("tap".to_string(), Err(e)),
+                                };
+                                let (tx, ty) = executor.calibration().apply(x, y);
+                                let result = execute_adb_tap(adb_device, tx as i32, ty as i32).await;
                                 tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
                                 (format!("tap ({}, {})", x, y), result)
                             } else {
@@ -245,7 +565,8 @@ async fn execute_flow_action(
                                         let bounds = &elem.bounds;
                                         let cx = (bounds[0] + bounds[2]) / 2;  // (left + right) / 2
                                         let cy = (bounds[1] + bounds[3]) / 2;  // (top + bottom) / 2
-                                        let result = execute_adb_tap(adb_device, cx as i32, cy as i32).await;
+                                        let (tx, ty) = executor.calibration().apply(cx as f64, cy as f64);
+                                        let result = execute_adb_tap(adb_device, tx as i32, ty as i32).await;
                                         tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
                                         return (format!("tap_text \"{}\" → ({}, {})", text, cx, cy), result);
                                     }
@@ -257,14 +578,30 @@ async fn execute_flow_action(
                             Err(anyhow::anyhow!("Element with text '{}' not found on screen", text)),
                         )
                     }
+                    "wait_for_text" | "wait_for_element" => {
+                        // wait_for_text: "Wi-Fi"
+                        // wait_for_element: {resource_id: "search_bar", timeout_ms: 5000}
+                        let query = match value.as_str() {
+                            Some(text) => ElementQuery::text(text),
+                            None if key == "wait_for_text" => ElementQuery {
+                                text: value.get("text").and_then(|v| v.as_str()).map(str::to_string),
+                                ..Default::default()
+                            },
+                            None => ElementQuery::from_params(value),
+                        };
+                        let timeout_ms = value
+                            .get("timeout_ms")
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or(DEFAULT_WAIT_TIMEOUT_MS);
+                        (format!("{} {}", key, query), executor.wait_for(&query, timeout_ms).await)
+                    }
                     "type" | "type_text" => {
                         let text = value.as_str().unwrap_or("");
                         let escaped = text.replace(' ', "%s").replace('\n', "%n");
                         let device_arg = adb_device.as_ref().map(|d| format!("-s {} ", d)).unwrap_or_default();
-                        let output = tokio::process::Command::new("adb")
-                            .args(build_adb_args(adb_device, &["shell", "input", "text", &escaped]))
-                            .output()
-                            .await;
+                        let output = adb::output_async(
+                            tokio::process::Command::new("adb").args(build_adb_args(adb_device, &["shell", "input", "text", &escaped])),
+                        ).await;
                         match output {
                             Ok(o) if o.status.success() => {
                                 (format!("type \"{}\"", truncate(text, 30)), Ok("ok".to_string()))
@@ -280,21 +617,27 @@ async fn execute_flow_action(
                         // swipe: [x1, y1, x2, y2] with optional duration
                         if let Some(arr) = value.as_array() {
                             if arr.len() >= 4 {
-                                let x1 = arr[0].as_i64().unwrap_or(0).to_string();
-                                let y1 = arr[1].as_i64().unwrap_or(0).to_string();
-                                let x2 = arr[2].as_i64().unwrap_or(0).to_string();
-                                let y2 = arr[3].as_i64().unwrap_or(0).to_string();
+                                let points = flow_point(&arr[0], &arr[1], resolution)
+                                    .and_then(|from| Ok((from, flow_point(&arr[2], &arr[3], resolution)?)));
+                                let ((x1, y1), (x2, y2)) = match points {
+                                    Ok(points) => points,
+                                    Err(e) => return ("swipe".to_string(), Err(e)),
+                                };
+                                let calibration = executor.calibration();
+                                let (x1, y1) = calibration.apply(x1, y1);
+                                let (x2, y2) = calibration.apply(x2, y2);
+                                let (x1, y1, x2, y2) =
+                                    (x1.to_string(), y1.to_string(), x2.to_string(), y2.to_string());
                                 let dur = if arr.len() > 4 {
                                     arr[4].as_i64().unwrap_or(300).to_string()
                                 } else {
                                     "300".to_string()
                                 };
-                                let output = tokio::process::Command::new("adb")
-                                    .args(build_adb_args(adb_device, &[
+                                let output = adb::output_async(
+                                    tokio::process::Command::new("adb").args(build_adb_args(adb_device, &[
                                         "shell", "input", "swipe", &x1, &y1, &x2, &y2, &dur,
-                                    ]))
-                                    .output()
-                                    .await;
+                                    ])),
+                                ).await;
                                 tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
                                 match output {
                                     Ok(o) if o.status.success() => (
@@ -321,10 +664,9 @@ async fn execute_flow_action(
                         } else {
                             format!("KEYCODE_{}", keycode.to_uppercase())
                         };
-                        let output = tokio::process::Command::new("adb")
-                            .args(build_adb_args(adb_device, &["shell", "input", "keyevent", &full_key]))
-                            .output()
-                            .await;
+                        let output = adb::output_async(
+                            tokio::process::Command::new("adb").args(build_adb_args(adb_device, &["shell", "input", "keyevent", &full_key])),
+                        ).await;
                         tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
                         match output {
                             Ok(o) if o.status.success() => (
@@ -344,13 +686,15 @@ async fn execute_flow_action(
                     }
                     "launch" | "launch_app" => {
                         let pkg = value.as_str().unwrap_or("");
-                        let output = tokio::process::Command::new("adb")
-                            .args(build_adb_args(adb_device, &[
+                        if executor.is_blocked_app(pkg) {
+                            return (format!("launch {}", pkg), Err(anyhow::anyhow!("{} ({})", APP_BLOCKED, pkg)));
+                        }
+                        let output = adb::output_async(
+                            tokio::process::Command::new("adb").args(build_adb_args(adb_device, &[
                                 "shell", "monkey", "-p", pkg, "-c",
                                 "android.intent.category.LAUNCHER", "1",
-                            ]))
-                            .output()
-                            .await;
+                            ])),
+                        ).await;
                         tokio::time::sleep(tokio::time::Duration::from_millis(1500)).await;
                         match output {
                             Ok(o) if o.status.success() => (
@@ -385,12 +729,11 @@ async fn execute_adb_tap(
     x: i32,
     y: i32,
 ) -> anyhow::Result<String> {
-    let output = tokio::process::Command::new("adb")
-        .args(build_adb_args(adb_device, &[
+    let output = adb::output_async(
+        tokio::process::Command::new("adb").args(build_adb_args(adb_device, &[
             "shell", "input", "tap", &x.to_string(), &y.to_string(),
-        ]))
-        .output()
-        .await?;
+        ])),
+    ).await?;
 
     if output.status.success() {
         Ok("ok".to_string())
@@ -432,7 +775,7 @@ fn truncate(s: &str, max: usize) -> String {
 /// app_id: com.android.systemui
 /// ---
 /// - swipe: [540, 50, 540, 800]
-/// - wait: 1
+/// - wait_for_text: "Clear all"
 /// - tap_text: "Clear all"
 /// - done: "Cleared"
 /// ```
@@ -457,6 +800,7 @@ fn load_flow(path: &str) -> anyhow::Result<(Flow, Vec<FlowAction>)> {
                 name,
                 app_id: None,
                 description: None,
+                allowed_actions: None,
             },
             serde_yaml::from_str(&content)
                 .map_err(|e| anyhow::anyhow!("Invalid YAML in '{}': {}", path, e))?,
@@ -487,4 +831,150 @@ fn collect_flows(dir: &Path, results: &mut Vec<(std::path::PathBuf, Flow)>) {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(action_type: &str, classification: &str, params: serde_json::Value) -> ActionLogEntry {
+        ActionLogEntry {
+            timestamp: String::new(),
+            action_type: action_type.into(),
+            classification: classification.into(),
+            result: "ok".into(),
+            params,
+            preview: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocked_flow_launch() {
+        let executor = ActionExecutor::new(false, Some("hermitdroid-test-missing".into()), vec![], None)
+            .with_blocked_apps(vec!["banking".into()]);
+        let launch: FlowAction = serde_yaml::from_str("launch: com.example.banking").unwrap();
+        let (desc, result) = execute_flow_action(&executor, &None, &launch, None).await;
+        assert_eq!(desc, "launch com.example.banking");
+        assert!(result.unwrap_err().to_string().starts_with(APP_BLOCKED));
+        assert!(!executor.is_blocked_app("com.android.settings"));
+    }
+
+    #[test]
+    fn test_safe_mode_flow() {
+        let mut flow: Flow = serde_yaml::from_str("name: Look around\n").unwrap();
+        let reading: Vec<FlowAction> = serde_yaml::from_str("- home\n- wait_for_text: Wi-Fi\n- back\n- done: ok\n").unwrap();
+        assert!(check_flow_safe(&flow, &reading).is_ok());
+        let tapping: Vec<FlowAction> = serde_yaml::from_str("- wait: 1\n- tap: [100, 200]\n").unwrap();
+        let err = check_flow_safe(&flow, &tapping).unwrap_err().to_string();
+        assert!(err.contains("step 2: tap"), "{}", err);
+        flow.app_id = Some("com.android.settings".into());
+        assert!(check_flow_safe(&flow, &reading).is_err());
+    }
+
+    #[test]
+    fn test_policy_flow() {
+        let mut flow: Flow = serde_yaml::from_str("name: Pay\n").unwrap();
+        let actions: Vec<FlowAction> = serde_yaml::from_str("- home\n- launch: com.bank.app\n- tap: [1, 2]\n").unwrap();
+        let restricted = vec!["bank".to_string()];
+        let policy = |red| ActionPolicy { green: PolicyMode::Auto, yellow: PolicyMode::Auto, red };
+        assert!(check_flow_policy(&flow, &actions, &policy(PolicyMode::Auto), &restricted).is_ok());
+        let err = check_flow_policy(&flow, &actions, &policy(PolicyMode::Block), &restricted).unwrap_err().to_string();
+        assert!(err.contains("step 2: launch (RED)"), "{}", err);
+        let err = check_flow_policy(&flow, &actions, &policy(PolicyMode::Confirm), &restricted).unwrap_err().to_string();
+        assert!(err.contains("can't ask"), "{}", err);
+        // The header launch is step 1
+        flow.app_id = Some("com.bank.app".into());
+        let reading: Vec<FlowAction> = serde_yaml::from_str("- back\n").unwrap();
+        let err = check_flow_policy(&flow, &reading, &policy(PolicyMode::Block), &restricted).unwrap_err().to_string();
+        assert!(err.contains("step 1: launch (RED)"), "{}", err);
+    }
+
+    #[test]
+    fn test_allowed_actions() {
+        let header = "name: Read-only\napp_id: com.android.settings\nallowed_actions: [launch_app, tap_text, back, wait]\n";
+        let mut flow: Flow = serde_yaml::from_str(header).unwrap();
+        let actions: Vec<FlowAction> = serde_yaml::from_str("- tap_text: Wi-Fi\n- wait: 1\n- back\n- done: ok\n").unwrap();
+        assert!(check_flow_allowed(&flow, &actions).is_ok());
+
+        let typing: Vec<FlowAction> = serde_yaml::from_str("- tap_text: Search\n- type_text: hello\n").unwrap();
+        let err = check_flow_allowed(&flow, &typing).unwrap_err().to_string();
+        assert!(err.contains("step 3") && err.contains("'type'"), "{}", err);
+
+        flow.allowed_actions = None;
+        assert!(check_flow_allowed(&flow, &typing).is_ok());
+    }
+
+    #[test]
+    fn test_fractional_coordinates() {
+        let actions: Vec<FlowAction> =
+            serde_yaml::from_str("- tap: [0.5, 0.9]\n- swipe: [540, 2000, 540, 400]\n- tap: [1, 1.0]\n").unwrap();
+        assert!(has_fractional_coords(&actions[0]));
+        assert!(!has_fractional_coords(&actions[1]));
+
+        let point = |action: &FlowAction, resolution| {
+            let FlowAction::Keyed(map) = action else { unreachable!() };
+            let arr = map.values().next().unwrap().as_array().unwrap().clone();
+            flow_point(&arr[0], &arr[1], resolution)
+        };
+        assert_eq!(point(&actions[0], Some((1080, 2400))).unwrap(), (540.0, 2160.0));
+        assert_eq!(point(&actions[1], None).unwrap(), (540.0, 2000.0));
+        // Integer 1 is a pixel; 1.0 is the far edge
+        assert_eq!(point(&actions[2], Some((1080, 2400))).unwrap(), (1.0, 2399.0));
+        assert!(point(&actions[0], None).is_err());
+    }
+
+    #[test]
+    fn test_replay_log_to_flow() {
+        let entries = vec![
+            entry("launch_app", "GREEN", serde_json::json!({"app": "settings", "package": "com.android.settings"})),
+            entry("tap", "GREEN", serde_json::json!({"x": 540.4, "y": 1200})),
+            entry("type_text", "YELLOW", serde_json::json!({"text": "wifi"})),
+            entry("send_sms", "RED-AUTO", serde_json::json!({"to": "123"})),
+            entry("find_element", "GREEN", serde_json::json!({"text": "Wi-Fi"})),
+            entry("back", "GREEN", serde_json::Value::Null),
+        ];
+        let (actions, skipped) = actions_from_log(&entries);
+        assert_eq!(actions.len(), 4);
+        assert_eq!(skipped.len(), 2);
+
+        let dir = std::env::temp_dir().join(format!("hermitdroid-replay-{}", uuid::Uuid::new_v4()));
+        let flow = Flow { name: "Open wifi".into(), app_id: None, description: None, allowed_actions: None };
+        let path = save_flow(&dir.to_string_lossy(), "Open wifi", &flow, &actions).unwrap();
+        assert!(path.ends_with("flows/open-wifi.yaml"));
+
+        let (loaded, loaded_actions) = load_flow(&path.to_string_lossy()).unwrap();
+        assert_eq!(loaded.name, "Open wifi");
+        let yaml = serde_yaml::to_string(&loaded_actions).unwrap();
+        assert!(yaml.contains("launch: com.android.settings"));
+        assert!(yaml.contains("- 540"));
+        assert!(yaml.contains("type: wifi"));
+        assert!(yaml.contains("- back"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+    #[tokio::test]
+    async fn test_replay_by_session() {
+        let dir = std::env::temp_dir().join(format!("hermitdroid-sessions-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(ACTION_LOG_FILE);
+        let mut lines = String::new();
+        for (session, action) in [("s1", "home"), ("s1", "back"), ("s2", "home")] {
+            let line = LoggedAction { session: session.into(), entry: entry(action, "GREEN", serde_json::Value::Null) };
+            lines += &(serde_json::to_string(&line).unwrap() + "\n");
+        }
+        std::fs::write(&path, lines).unwrap();
+
+        let latest = session_entries(read_action_log(&path).unwrap(), None).unwrap();
+        assert_eq!(latest.len(), 1);
+        let named = session_entries(read_action_log(&path).unwrap(), Some("s1")).unwrap();
+        assert_eq!(named.iter().map(|e| e.action_type.as_str()).collect::<Vec<_>>(), ["home", "back"]);
+        assert_eq!(session_entries(read_action_log(&path).unwrap(), Some("s3")).unwrap_err(), ["s1", "s2"]);
+
+        // Executors append under their own session; the file is trimmed on open
+        let executor = ActionExecutor::new(true, None, vec![], None).with_log_file(path.clone());
+        let _ = executor.execute(&serde_json::from_str(r#"{"type": "home", "classification": "GREEN", "reason": "test"}"#).unwrap()).await;
+        let logged = read_action_log(&path).unwrap();
+        assert_eq!(logged.len(), 4);
+        assert!(!["s1", "s2"].contains(&logged[3].session.as_str()));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
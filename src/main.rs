@@ -1,5 +1,7 @@
 mod action;
+mod adb;
 mod brain;
+mod chat;
 mod config;
 mod onboarding;
 mod oneshot;
@@ -13,12 +15,19 @@ mod soul;
 mod tailscale;
 mod stuck;
 mod fallback;
-
-use crate::action::ActionExecutor;
-use crate::brain::Brain;
+mod calibrate;
+mod record;
+mod runs;
+mod scheduler;
+mod secrets;
+mod skills;
+mod metrics;
+
+use crate::action::{ActionExecutor, CoordinateCalibration};
+use crate::adb::AdbError;
+use crate::brain::{Brain, PromptDump, PromptNotSent};
 use crate::config::Config;
-use crate::perception::Perception;
-use crate::sanitizer::VisionMode;
+use crate::perception::{Perception, PerceptionProvider, PollStrategy};
 use crate::server::{build_router, AppState};
 use crate::session::SessionManager;
 use crate::soul::Workspace;
@@ -36,6 +45,8 @@ struct Cli {
     config: String,
     #[arg(long, help = "Log actions but don't execute")]
     dry_run: bool,
+    #[arg(long, help = "Only run read-only actions (home, back, scroll, wait, read the screen); the rest is refused (action.safe_mode)")]
+    safe: bool,
     #[command(subcommand)]
     command: Option<SubCommand>,
 }
@@ -43,8 +54,12 @@ struct Cli {
 #[derive(Parser)]
 enum SubCommand {
     /// Start the agent (default if no subcommand given)
-    Gateway,
-    /// Send a command to a running agent
+    Gateway {
+        /// Serve the dashboard/API but only act on explicit commands
+        #[arg(long)]
+        no_heartbeat: bool,
+    },
+    /// Send a command to a running agent (no message = interactive chat)
     Chat {
         /// The message or command to send
         message: Vec<String>,
@@ -54,7 +69,15 @@ enum SubCommand {
     /// Run the interactive setup wizard (AI, ADB, Tailscale)
     Onboard,
     /// Check workspace and config health
-    Doctor,
+    Doctor {
+        /// Repair what can be repaired: restore missing workspace files and
+        /// reconnect a network ADB device
+        #[arg(long)]
+        fix: bool,
+        /// Print the checks as JSON (ADB device states, workspace, server)
+        #[arg(long)]
+        json: bool,
+    },
     /// Run a one-shot goal (no daemon needed)
     Run {
         /// The goal in plain English (e.g. "open youtube and search lofi")
@@ -68,6 +91,27 @@ enum SubCommand {
         /// Save this goal as a reusable workflow
         #[arg(long)]
         save_as: Option<String>,
+        /// Use a saved PNG as the screen for the first step (offline prompt debugging)
+        #[arg(long)]
+        screenshot: Option<String>,
+        /// Continue the last unfinished run from workspace/last_run.json
+        #[arg(long)]
+        resume: bool,
+        /// Use this [[brain_profiles]] entry instead of [brain]
+        #[arg(long)]
+        profile: Option<String>,
+        /// Override brain.max_tokens for this run (not saved to config)
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Override brain.temperature for this run, 0.0–2.0 (not saved to config)
+        #[arg(long)]
+        temperature: Option<f32>,
+        /// Write each assembled prompt to this file ("-" = stdout) before calling the model
+        #[arg(long, value_name = "FILE")]
+        dump_prompt: Option<String>,
+        /// With --dump-prompt: stop after the first prompt instead of calling the model
+        #[arg(long, requires = "dump_prompt")]
+        no_send: bool,
     },
     /// Install/uninstall as a background service (systemd)
     Service {
@@ -83,20 +127,113 @@ enum SubCommand {
         /// Show LLM thinking in real-time
         #[arg(long)]
         verbose: bool,
+        /// Use this [[brain_profiles]] entry instead of [brain]
+        #[arg(long)]
+        profile: Option<String>,
+        /// Override brain.max_tokens for this workflow (not saved to config)
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// Override brain.temperature for this workflow, 0.0–2.0 (not saved to config)
+        #[arg(long)]
+        temperature: Option<f32>,
+        /// Write each assembled prompt to this file ("-" = stdout) before calling the model
+        #[arg(long, value_name = "FILE")]
+        dump_prompt: Option<String>,
+        /// With --dump-prompt: stop after the first prompt instead of calling the model
+        #[arg(long, requires = "dump_prompt")]
+        no_send: bool,
     },
     /// Run a deterministic flow (YAML, no AI, instant)
     Flow {
         /// Path to flow YAML file
         path: String,
     },
+    /// Turn a recorded session into a flow and play it back (no AI)
+    Replay {
+        /// Session id in workspace/action_log.jsonl, or an action log file (default: the latest session)
+        source: Option<String>,
+        /// Save as workspace/flows/<name>.yaml instead of running it
+        #[arg(long)]
+        save_as: Option<String>,
+    },
+    /// Record taps, swipes and typing on the phone into a flow (Ctrl+C to stop)
+    Record {
+        /// Flow YAML to write, e.g. workspace/flows/morning.yaml
+        output: String,
+    },
     /// List available workflows and flows
-    Workflows,
+    Workflows {
+        /// Print a JSON array instead of the human-readable list
+        #[arg(long)]
+        json: bool,
+    },
+    /// Measure and fix systematic tap offsets on this device
+    Calibrate {
+        /// Save a per-app profile to workspace/calibration.toml instead
+        package: Option<String>,
+    },
+    /// Run exactly one heartbeat tick with debug logging, then exit
+    Tick {
+        /// Write each assembled prompt to this file ("-" = stdout) before calling the model
+        #[arg(long, value_name = "FILE")]
+        dump_prompt: Option<String>,
+        /// With --dump-prompt: stop after the first prompt instead of calling the model
+        #[arg(long, requires = "dump_prompt")]
+        no_send: bool,
+    },
+    /// Manage workspace skills
+    Skill {
+        #[command(subcommand)]
+        action: SkillAction,
+    },
+    /// Store config values encrypted instead of in plaintext
+    Secrets {
+        #[command(subcommand)]
+        action: SecretsAction,
+    },
+    /// Inspect the loaded configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
     /// Stop a running background agent
     Stop,
     /// Restart the background agent
     Restart,
 }
 
+#[derive(Parser)]
+enum SkillAction {
+    /// Install a skill from a git URL or local folder (must contain SKILL.md)
+    Add {
+        source: String,
+    },
+    /// List installed skills
+    List,
+    /// Remove an installed skill
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Parser)]
+enum SecretsAction {
+    /// Prompt for a value (e.g. brain.api_key), encrypt it and reference it from config.toml
+    Set {
+        name: String,
+    },
+}
+
+#[derive(Parser)]
+enum ConfigAction {
+    /// Print config.toml as loaded (env vars and secrets resolved, secrets redacted)
+    Show {
+        /// Print every setting, marking the ones config.toml doesn't set with `# default`
+        #[arg(long)]
+        effective: bool,
+    },
+}
+
 #[derive(Parser)]
 enum ServiceAction {
     /// Install systemd service for current user
@@ -131,6 +268,44 @@ fn default_config_path() -> String {
     }
 }
 
+/// What woke the heartbeat loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TickTrigger {
+    /// Periodic heartbeat interval elapsed
+    Timer,
+    /// A notification or device event arrived
+    Event,
+    /// A notification from one of `perception.priority_apps`
+    PriorityNotification,
+    /// The user sent a command (chat, dashboard, WebSocket)
+    UserCommand,
+}
+
+/// Whether a tick should run. Without autonomy only user commands wake the agent.
+fn should_tick(autonomous: bool, trigger: TickTrigger) -> bool {
+    autonomous || trigger == TickTrigger::UserCommand
+}
+
+/// Whether quiet hours (`agent.active_hours`) hold a tick back. User commands
+/// always get through; priority notifications only when allowed.
+fn quiet_blocks(trigger: TickTrigger, allow_priority: bool) -> bool {
+    match trigger {
+        TickTrigger::UserCommand => false,
+        TickTrigger::PriorityNotification => !allow_priority,
+        TickTrigger::Timer | TickTrigger::Event => true,
+    }
+}
+
+/// How often the on_unlock watcher checks the screen and keyguard
+const UNLOCK_POLL_SECS: u64 = 3;
+
+/// Await `fut` and report how long it took
+async fn timed<F: std::future::Future>(fut: F) -> (F::Output, std::time::Duration) {
+    let started = std::time::Instant::now();
+    let out = fut.await;
+    (out, started.elapsed())
+}
+
 /// Fast hash for screen change detection (not cryptographic, just for comparison)
 fn simple_hash(text: &str) -> u64 {
     use std::hash::{Hash, Hasher};
@@ -141,19 +316,29 @@ fn simple_hash(text: &str) -> u64 {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "hermitdroid=info".into()),
-        )
-        .init();
-
     let cli = Cli::parse();
 
+    // `tick` is for debugging — show the prompt and raw response by default
+    let default_filter = if matches!(cli.command, Some(SubCommand::Tick { .. })) {
+        "hermitdroid=debug"
+    } else {
+        "hermitdroid=info"
+    };
+    // Peek at the config for `agent.log_file`; a missing or broken config is
+    // reported properly further down
+    let log_config = Path::new(&cli.config).exists()
+        .then(|| Config::load(Path::new(&cli.config)).ok())
+        .flatten();
+    let _log_guard = init_logging(default_filter, log_config.as_ref().map(|c| &c.agent))?;
+
     // Commands that don't need full config
     match &cli.command {
         Some(SubCommand::Service { action }) => return handle_service(action),
         Some(SubCommand::Logs) => return run_logs(),
+        // Runs before Config::load so a secret that no longer decrypts can be replaced
+        Some(SubCommand::Secrets { action: SecretsAction::Set { name } }) => {
+            return secrets::run_set(Path::new(&cli.config), name);
+        }
         _ => {}
     }
 
@@ -177,7 +362,13 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    let config = Config::load(Path::new(&cli.config))?;
+    let mut config = Config::load(Path::new(&cli.config))?;
+    config.action.safe_mode |= cli.safe;
+    let tick_prompt_dump = match &cli.command {
+        Some(SubCommand::Tick { dump_prompt, no_send }) => PromptDump::from_args(dump_prompt.clone(), *no_send),
+        _ => None,
+    };
+    adb::set_timeout(config.perception.adb_timeout_secs);
 
     // This is placed early because `run` should be lightweight and fast.
     // No need to check for a running instance or start a server.
@@ -186,10 +377,20 @@ async fn main() -> anyhow::Result<()> {
         max_steps,
         verbose,
         save_as,
+        screenshot,
+        resume,
+        profile,
+        max_tokens,
+        temperature,
+        dump_prompt,
+        no_send,
     }) = &cli.command
     {
+        let config = config
+            .with_brain_profile(profile.as_deref())?
+            .with_generation_overrides(*max_tokens, *temperature)?;
         let goal_text = goal.join(" ");
-        if goal_text.is_empty() {
+        if goal_text.is_empty() && !resume {
             println!("Usage: hermitdroid run \"your goal here\"");
             println!();
             println!("Examples:");
@@ -198,6 +399,8 @@ async fn main() -> anyhow::Result<()> {
             println!("  hermitdroid run --max-steps 10 \"turn on wifi\"");
             println!("  hermitdroid run --dry-run \"send hi to Mom on whatsapp\"");
             println!("  hermitdroid run \"open settings\" --save-as check-settings");
+            println!("  hermitdroid run --screenshot screen.png \"tap the search bar\"");
+            println!("  hermitdroid run --resume");
             return Ok(());
         }
 
@@ -210,7 +413,13 @@ async fn main() -> anyhow::Result<()> {
                 None, // no specific app
             )?;
         }
-        return oneshot::run_oneshot(&config, &goal_text, *max_steps, *verbose, cli.dry_run).await;
+        let opts = oneshot::RunOptions {
+            verbose: *verbose,
+            dry_run: cli.dry_run,
+            prompt_dump: PromptDump::from_args(dump_prompt.clone(), *no_send),
+            allowed_actions: None,
+        };
+        return oneshot::run_oneshot(&config, &goal_text, *max_steps, screenshot.as_deref(), *resume, &opts).await;
     }
 
     match cli.command {
@@ -221,12 +430,23 @@ async fn main() -> anyhow::Result<()> {
                 Ok(resp) => {
                     let data: serde_json::Value = resp.json().await?;
                     let running = data["data"]["running"].as_bool().unwrap_or(false);
+                    let quiet = data["data"]["quiet_hours"].as_bool().unwrap_or(false);
                     let app = data["data"]["current_app"].as_str().unwrap_or("unknown");
                     let pending = data["data"]["pending_confirmations"].as_u64().unwrap_or(0);
+                    let elements = data["data"]["element_count"].as_u64();
                     println!("🤖 Hermitdroid v{}", env!("CARGO_PKG_VERSION"));
-                    println!("   Status:  {}", if running { "🟢 Running" } else { "🔴 Paused" });
+                    println!("   Status:  {}", match (running, quiet) {
+                        (false, _) => "🔴 Paused",
+                        (true, true) => "🌙 Quiet hours",
+                        (true, false) => "🟢 Running",
+                    });
                     println!("   Model:   {} via {}", config.brain.model, config.brain.backend);
                     println!("   App:     {}", app);
+                    match elements {
+                        Some(0) => println!("   Screen:  ⚠️  0 elements (accessibility tree empty — vision only)"),
+                        Some(n) => println!("   Screen:  {} elements", n),
+                        None => println!("   Screen:  not polled yet"),
+                    }
                     if pending > 0 {
                         println!("   Pending: {} action(s) awaiting confirmation", pending);
                     }
@@ -248,42 +468,98 @@ async fn main() -> anyhow::Result<()> {
             }
             return Ok(());
         }
-        Some(SubCommand::Doctor) => {
+        Some(SubCommand::Doctor { fix, json }) => {
+            if fix {
+                run_doctor_fix(&config)?;
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&doctor_report(&config))?);
+                return Ok(());
+            }
             return run_doctor(&config);
         }
+        Some(SubCommand::Config { action: ConfigAction::Show { effective } }) => {
+            let file: toml::Value = toml::from_str(&std::fs::read_to_string(config_path)?)?;
+            print!("{}", config.to_annotated_toml(&file, effective)?);
+            return Ok(());
+        }
         Some(SubCommand::Chat { message }) => {
             let msg = message.join(" ");
             if msg.is_empty() {
-                println!("Usage: hermitdroid chat <message>");
-                return Ok(());
+                return chat::run_repl(config.server.port).await;
             }
-            let url = format!("http://127.0.0.1:{}/chat", config.server.port);
-            match reqwest::Client::new().post(&url)
-                .json(&serde_json::json!({"message": msg}))
-                .timeout(std::time::Duration::from_secs(5))
-                .send().await
-            {
-                Ok(resp) => {
-                    let data: serde_json::Value = resp.json().await?;
-                    if let Some(d) = data["data"].as_str() {
-                        println!("{}", d);
-                    } else {
-                        println!("✅ Queued: {}", msg);
+            match chat::send_message(&reqwest::Client::new(), config.server.port, &msg).await {
+                Ok(reply) => println!("{}", reply),
+                Err(_) => println!("❌ Agent not running. Start it first with: hermitdroid"),
+            }
+            return Ok(());
+        }
+        Some(SubCommand::Workflow { path, verbose, profile, max_tokens, temperature, dump_prompt, no_send }) => {
+            let config = config
+                .with_brain_profile(profile.as_deref())?
+                .with_generation_overrides(max_tokens, temperature)?;
+            let dump = PromptDump::from_args(dump_prompt, no_send);
+            return workflow::run_workflow(&config, &path, verbose, cli.dry_run, dump, None).await;
+        }
+        Some(SubCommand::Flow { path }) => {
+            return flow::run_flow(&config, &path, cli.dry_run, None).await;
+        }
+        Some(SubCommand::Replay { source, save_as }) => {
+            return flow::run_replay(&config, source.as_deref(), save_as.as_deref(), cli.dry_run).await;
+        }
+        Some(SubCommand::Record { output }) => {
+            return record::run_record(&config, &output).await;
+        }
+        Some(SubCommand::Calibrate { package }) => {
+            return calibrate::run_calibrate(&config, package.as_deref()).await;
+        }
+        Some(SubCommand::Skill { action }) => {
+            let ws = &config.agent.workspace_path;
+            match action {
+                SkillAction::Add { source } => {
+                    let name = skills::add_skill(ws, &source)?;
+                    println!("✅ Installed skill '{}' — it loads on the next tick", name);
+                }
+                SkillAction::List => {
+                    let installed = skills::list_skills(ws);
+                    if installed.is_empty() {
+                        println!("No skills installed. Add one with: hermitdroid skill add <git-url-or-path>");
+                    }
+                    for (name, description) in installed {
+                        println!("  📦 {:<28} {}", name, description);
                     }
                 }
-                Err(_) => {
-                    println!("❌ Agent not running. Start it first with: hermitdroid");
+                SkillAction::Remove { name } => {
+                    skills::remove_skill(ws, &name)?;
+                    println!("🗑  Removed skill '{}'", name);
                 }
             }
             return Ok(());
         }
-        Some(SubCommand::Workflow { path, verbose }) => {
-            return workflow::run_workflow(&config, &path, verbose, cli.dry_run).await;
-        }
-        Some(SubCommand::Flow { path }) => {
-            return flow::run_flow(&config, &path, cli.dry_run).await;
+        Some(SubCommand::Workflows { json: true }) => {
+            let workflows = workflow::list_workflows(&config.agent.workspace_path);
+            let flows = flow::list_flows();
+            let entries: Vec<serde_json::Value> = workflows
+                .iter()
+                .map(|(path, w)| serde_json::json!({
+                    "path": path.display().to_string(),
+                    "name": w.name,
+                    "description": w.description,
+                    "type": "workflow",
+                    "steps": w.steps.len(),
+                }))
+                .chain(flows.iter().map(|(path, f)| serde_json::json!({
+                    "path": path.display().to_string(),
+                    "name": f.name,
+                    "description": f.description,
+                    "type": "flow",
+                    "steps": null,
+                })))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
         }
-        Some(SubCommand::Workflows) => {
+        Some(SubCommand::Workflows { json: false }) => {
             println!("\n\x1b[1m📋 Available Workflows (AI-powered)\x1b[0m\n");
             let workflows = workflow::list_workflows(&config.agent.workspace_path);
             if workflows.is_empty() {
@@ -347,8 +623,11 @@ async fn main() -> anyhow::Result<()> {
     info!("🤖 Hermitdroid v{}", env!("CARGO_PKG_VERSION"));
     info!("Agent: {} | Model: {} | Backend: {}", config.agent.name, config.brain.model, config.brain.backend);
 
+    let (event_tx, _) = broadcast::channel::<String>(config.server.event_buffer);
     let tailscale_manager = Arc::new(Mutex::new(TailscaleManager::new(config.tailscale.clone())));
     let effective_adb_device: String;
+    // Flipped to true on SIGINT/SIGTERM; observed by the health loop, server and heartbeat
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
 
     if config.tailscale.enabled {
         info!("🌐 Tailscale enabled — connecting to {} ...", config.tailscale.phone_hostname);
@@ -372,19 +651,28 @@ async fn main() -> anyhow::Result<()> {
 
         // Spawn background health-check loop
         let ts_clone = tailscale_manager.clone();
-        let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let health_rx = shutdown_rx.clone();
         let health_interval = config.tailscale.health_check_interval_secs;
+        let health_tx = event_tx.clone();
         tokio::spawn(async move {
-            tailscale::tailscale_health_loop(ts_clone, health_interval, shutdown_rx).await;
+            tailscale::tailscale_health_loop(ts_clone, health_interval, health_tx, health_rx).await;
         });
-        // shutdown_tx will be dropped on process exit, stopping the loop
     } else {
         effective_adb_device = config.perception.adb_device.clone().unwrap_or_default();
     }
     // ── END Tailscale init ──────────────────────────────────────────────
 
     let workspace = Arc::new(Workspace::new(&config.agent.workspace_path, config.agent.bootstrap_max_chars));
-    let brain = Arc::new(Brain::new(&config.brain));
+    let metrics = Arc::new(metrics::Metrics::default());
+    let mut brain = Arc::new(
+        Brain::new(&config.brain)
+            .with_metrics(metrics.clone())
+            .with_safe_mode(config.action.safe_mode)
+            .with_prompt_dump(tick_prompt_dump.clone()),
+    );
+    // Switched with `/profile`; the heartbeat rebuilds `brain` when it changes
+    let brain_profile: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let mut active_profile: Option<String> = None;
 
     let perception_adb: Option<String> = if effective_adb_device.is_empty() {
         config.perception.adb_device.clone()
@@ -395,51 +683,136 @@ async fn main() -> anyhow::Result<()> {
     let perception = Arc::new(Perception::new(
         perception_adb.clone(),
         config.perception.priority_apps.clone(),
-    ));
+    )
+    .with_app_calibrations(perception::load_app_calibrations(&config.agent.workspace_path))
+    .with_app_aliases(perception::load_app_aliases(&config.agent.workspace_path))
+    .with_ignore_packages(config.perception.ignore_packages.clone())
+    .with_max_elements(config.perception.max_elements)
+    .with_skip_static_screenshots(config.perception.skip_static_screenshots)
+    .with_notification_dedup_secs(config.perception.notification_dedup_secs)
+    .with_apps_refresh_secs(config.perception.apps_refresh_secs)
+    .with_screenshot_encoding(config.perception.screenshot_encoding()));
+    // Where ticks read the device from (`perception.bridge_mode`)
+    let provider = perception::for_bridge(&perception, &config.perception);
     let dry_run = cli.dry_run || config.action.dry_run;
+    let text_input = action::TextInputMethod::from_str(&config.action.text_input_method);
     let executor = Arc::new(ActionExecutor::new(
         dry_run,
         perception_adb.clone(),
         config.action.restricted_apps.clone(),
-    ));
+        Some(Perception::clone(&perception)),
+    )
+    .with_min_confidence(config.action.min_confidence)
+    .with_policy(config.action.policy)
+    .with_min_interval_ms(config.action.min_interval_ms)
+    .with_verify_taps(config.action.verify_taps)
+    .with_text_input_method(text_input)
+    .with_calibration(CoordinateCalibration::from_config(&config.action))
+    .with_app_resolver(perception.app_resolver())
+    .with_perception_provider(provider.clone())
+    .with_device_notifications(
+        config.action.notify_on_device.then(|| config.agent.name.clone()),
+    )
+    .with_device_confirmation(
+        config.action.confirm_via_device.then_some(config.action.confirmation_timeout_secs),
+    )
+    .with_log_file(Path::new(&config.agent.workspace_path).join(action::ACTION_LOG_FILE))
+    .with_blocked_apps(config.action.blocked_apps.clone())
+    .with_safe_mode(config.action.safe_mode)
+    .with_metrics(metrics.clone()));
     let sessions = Arc::new(SessionManager::new());
     let running = Arc::new(Mutex::new(true));
-    let (event_tx, _) = broadcast::channel::<String>(256);
+    let circuit_open = Arc::new(Mutex::new(false));
+    let last_tick = Arc::new(Mutex::new(None));
+    let ready = Arc::new(std::sync::atomic::AtomicBool::new(false));
 
     if dry_run { warn!("⚠️  DRY RUN mode — actions logged but not executed"); }
+    if config.action.safe_mode { warn!("🛡  SAFE MODE — only read-only actions run"); }
+    if text_input == action::TextInputMethod::Ime && !dry_run {
+        // Warns now rather than at the first type_text
+        executor.adb_keyboard_installed();
+    }
 
     // ---- Bridge mode info ----
-    info!("📡 Bridge mode: {}", config.perception.bridge_mode);
-    if config.perception.bridge_mode == "adb" {
+    info!("📡 Bridge mode: {}", provider.name());
+    if provider.name() == "adb" {
         if config.tailscale.enabled {
             info!("📡 ADB target (via Tailscale): {}", perception_adb.as_deref().unwrap_or("(unresolved)"));
         } else {
-            match std::process::Command::new("adb").args(["devices"]).output() {
-                Ok(out) => {
-                    let devices = String::from_utf8_lossy(&out.stdout);
-                    let connected = devices.lines().filter(|l| l.contains("\tdevice")).count();
+            match adb::list_devices() {
+                Ok(devices) => {
+                    let connected = devices.iter().filter(|(_, state)| state.is_ready()).count();
                     if connected > 0 {
                         info!("✅ ADB: {} device(s) connected", connected);
-                    } else {
+                    } else if devices.is_empty() {
                         warn!("⚠️  ADB: no devices found. Run `adb devices` to check.");
                     }
+                    for (serial, state) in &devices {
+                        if let Some(hint) = state.hint() {
+                            warn!("⚠️  ADB: {} is {} — {}", serial, state, hint);
+                        }
+                    }
                 }
-                Err(_) => warn!("⚠️  ADB binary not found. Install Android SDK platform-tools."),
+                Err(AdbError::NotFound) => warn!("⚠️  ADB binary not found. Install Android SDK platform-tools."),
+                Err(e) => warn!("⚠️  {} — {}", e, e.hint()),
             }
         }
+        if !dry_run {
+            executor.probe_capabilities();
+        }
     }
 
     sessions.main_session().await;
 
+    // ---- Single tick (no server, no loop) ----
+    if matches!(cli.command, Some(SubCommand::Tick { .. })) {
+        info!("🔂 Running a single heartbeat tick");
+        // Tick 0 never takes the idle shortcut, so the LLM is always called
+        let ctx = TickContext {
+            config: &config,
+            workspace: &workspace,
+            brain: &brain,
+            perception: &perception,
+            executor: &executor,
+            sessions: &sessions,
+            event_tx: &event_tx,
+            running: &running,
+            provider: provider.as_ref(),
+        };
+        let result = heartbeat_tick(ctx, 0).await;
+        for entry in executor.action_log().lock().await.iter() {
+            info!("📋 {} [{}] → {}", entry.action_type, entry.classification, entry.result);
+        }
+        if let Some(not_sent) = result.as_ref().err().and_then(|e| e.downcast_ref::<PromptNotSent>()) {
+            info!("📝 {}", not_sent);
+            return Ok(());
+        }
+        return result;
+    }
+
     // ---- Start HTTP/WS server ----
+    // Flows/workflows started from the dashboard use the resolved ADB target
+    let mut run_config = config.clone();
+    run_config.perception.adb_device = perception_adb.clone();
+    run_config.action.dry_run = dry_run;
+    let active_run: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
     let state = AppState {
         perception: perception.clone(),
         executor: executor.clone(),
         workspace: workspace.clone(),
         sessions: sessions.clone(),
         running: running.clone(),
+        circuit_open: circuit_open.clone(),
+        last_tick: last_tick.clone(),
+        ready: ready.clone(),
+        metrics: metrics.clone(),
+        config: Arc::new(run_config),
+        active_run: active_run.clone(),
+        device_lock: executor.device_lock(),
+        brain_profile: brain_profile.clone(),
         event_tx: event_tx.clone(),
         tailscale: tailscale_manager.clone(),
+        shutdown: shutdown_rx.clone(),
     };
 
     let addr = format!("{}:{}", config.server.host, config.server.port);
@@ -454,21 +827,113 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    tokio::spawn(async move {
-        if let Err(e) = axum::serve(listener, router).await {
+    let mut server_shutdown = shutdown_rx.clone();
+    let server = tokio::spawn(async move {
+        let graceful = async move {
+            let _ = server_shutdown.wait_for(|stop| *stop).await;
+        };
+        if let Err(e) = axum::serve(listener, router).with_graceful_shutdown(graceful).await {
             error!("Server error: {}", e);
         }
     });
 
+    {
+        let running = running.clone();
+        let workspace = workspace.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            info!("🛑 Shutdown signal received — stopping agent");
+            *running.lock().await = false;
+            workspace.append_daily_memory("Agent stopping").ok();
+            let _ = shutdown_tx.send(true);
+            // Wake the heartbeat if it is waiting for a trigger
+            let _ = event_tx.send(serde_json::json!({"event": "shutdown"}).to_string());
+        });
+    }
+
+    // ---- Cron jobs ----
+    let cron_jobs = scheduler::spawn_cron_jobs(&config.cron, config.agent.tz(), perception.clone(), event_tx.clone());
+    if cron_jobs > 0 {
+        info!("⏰ {} cron job(s) scheduled", cron_jobs);
+    }
+
+    // ---- Expire unanswered confirmations ----
+    if config.action.confirmation_timeout_secs > 0 {
+        let executor = executor.clone();
+        let event_tx = event_tx.clone();
+        let timeout = std::time::Duration::from_secs(config.action.confirmation_timeout_secs);
+        tokio::spawn(async move {
+            let mut sweep = tokio::time::interval(std::time::Duration::from_secs(5));
+            loop {
+                sweep.tick().await;
+                for p in executor.expire_stale(timeout).await {
+                    let _ = event_tx.send(serde_json::json!({
+                        "event": "confirmation_expired",
+                        "action_id": p.action_id,
+                        "action_type": p.action.action_type,
+                    }).to_string());
+                }
+            }
+        });
+    }
+
+    // ---- Report companion actions that never got an action_result ----
+    {
+        let executor = executor.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            let timeout = std::time::Duration::from_secs(action::COMPANION_ACK_TIMEOUT_SECS);
+            let mut sweep = tokio::time::interval(std::time::Duration::from_secs(5));
+            loop {
+                sweep.tick().await;
+                for (action_id, action_type) in executor.expire_unacknowledged(timeout).await {
+                    let _ = event_tx.send(serde_json::json!({
+                        "event": "companion_action_timeout",
+                        "action_id": action_id,
+                        "action_type": action_type,
+                    }).to_string());
+                }
+            }
+        });
+    }
+
     // ---- Run on_boot hook ----
     if let Some(boot_file) = &config.hooks.on_boot {
         info!("Running on_boot hook: {}", boot_file);
-        let content = workspace.read_file(boot_file);
-        if !content.is_empty() {
-            perception.push_user_command(format!("[BOOT] {}", content)).await;
+        if let Some(cmd) = workspace.hook_command(boot_file, "BOOT") {
+            perception.push_user_command(cmd).await;
         }
     }
 
+    // ---- Watch for device unlock (on_unlock hook) ----
+    if let Some(unlock_file) = config.hooks.on_unlock.clone() {
+        let perception = perception.clone();
+        let workspace = workspace.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(std::time::Duration::from_secs(UNLOCK_POLL_SECS));
+            let mut was_unlocked = None;
+            loop {
+                poll.tick().await;
+                let p = perception.clone();
+                let Ok(unlocked) = tokio::task::spawn_blocking(move || p.is_unlocked()).await else {
+                    continue;
+                };
+                if was_unlocked == Some(false) && unlocked {
+                    info!("🔓 Device unlocked — running on_unlock hook: {}", unlock_file);
+                    if let Some(cmd) = workspace.hook_command(&unlock_file, "UNLOCK") {
+                        perception.push_user_command(cmd.clone()).await;
+                        let _ = event_tx.send(
+                            serde_json::json!({"type": "user_command", "text": cmd, "source": "on_unlock"}).to_string(),
+                        );
+                    }
+                }
+                was_unlocked = Some(unlocked);
+            }
+        });
+    }
+
     if workspace.needs_bootstrap() {
         info!("📋 BOOTSTRAP.md detected — first-run ritual active");
     }
@@ -484,12 +949,32 @@ async fn main() -> anyhow::Result<()> {
     let gateway_heartbeat = config.agent.gateway_heartbeat_interval_secs;
     info!("💓 Heartbeat: {}s tick, {}s gateway", heartbeat_interval, gateway_heartbeat);
 
+    let no_heartbeat = matches!(cli.command, Some(SubCommand::Gateway { no_heartbeat: true }));
+    let autonomous = config.agent.autonomous && !no_heartbeat;
+    if !autonomous {
+        info!("🙋 Autonomous heartbeat disabled — acting only on explicit commands");
+    }
+
     let mut event_rx = event_tx.subscribe();
     let mut last_gateway_heartbeat = std::time::Instant::now();
     let mut tick_count: u64 = 0;
+    let mut trigger = TickTrigger::Timer;
+    let max_errors = config.agent.max_consecutive_errors;
+    let mut consecutive_errors: u32 = 0;
+    let mut was_quiet = false;
 
     loop {
+        if *shutdown_rx.borrow() {
+            break;
+        }
         if !*running.lock().await {
+            // A manual restart gets a fresh error budget
+            consecutive_errors = 0;
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            continue;
+        }
+        if active_run.lock().await.is_some() {
+            // A dashboard-started flow/workflow owns the device until it finishes
             tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
             continue;
         }
@@ -500,95 +985,325 @@ async fn main() -> anyhow::Result<()> {
             info!("🔄 Gateway heartbeat (memory flush)");
             workspace.append_daily_memory("--- gateway heartbeat ---").ok();
             last_gateway_heartbeat = std::time::Instant::now();
+
+            if let Err(e) = workspace
+                .summarize_daily_memory(&brain, config.agent.memory_retention_days)
+                .await
+            {
+                warn!("Memory summarization failed: {}", e);
+            }
+
+            let overdue = workspace.overdue_goals(chrono::Utc::now());
+            if !overdue.is_empty() {
+                info!("⏰ {} overdue goal(s)", overdue.len());
+                let list: Vec<String> = overdue
+                    .iter()
+                    .map(|(id, desc, due)| format!("- {} (due: {}, id: {})", desc, due, id))
+                    .collect();
+                perception
+                    .push_user_command(format!(
+                        "[REMINDER] These goals are overdue — work on them or tell the user:\n{}",
+                        list.join("\n")
+                    ))
+                    .await;
+            }
+        }
+
+        if !should_tick(autonomous, trigger) {
+            trigger = wait_for_trigger(&mut event_rx, heartbeat_interval, &running, &workspace, &config.agent.kill_phrases).await;
+            continue;
+        }
+
+        let quiet = config.agent.in_quiet_hours(chrono::Utc::now());
+        if quiet != was_quiet {
+            if quiet {
+                info!(
+                    "🌙 Quiet hours (active {}) — acting only on explicit commands",
+                    config.agent.active_hours.as_deref().unwrap_or_default()
+                );
+            } else {
+                info!("☀️ Active hours — heartbeat resumed");
+            }
+            was_quiet = quiet;
+        }
+        if quiet && quiet_blocks(trigger, config.agent.quiet_hours_allow_priority) {
+            // Over ADB nothing else polls notifications while ticks are skipped
+            // (the companion pushes priority_notification events on its own)
+            if config.agent.quiet_hours_allow_priority && matches!(provider.poll_notifications().await, Ok(true)) {
+                trigger = TickTrigger::PriorityNotification;
+                continue;
+            }
+            trigger = wait_for_trigger(&mut event_rx, heartbeat_interval, &running, &workspace, &config.agent.kill_phrases).await;
+            continue;
         }
 
-        // let vision_mode = VisionMode::from_str(&config.perception.vision_mode);
-        // let perception_result = sanitizer::perceive_screen(
-        //     &config.perception.adb_device,
-        //     vision_mode,
-        //     config.perception.max_elements,
-        // ).await;
-
-        // if let Err(e) = perception_result {
-        //     error!("Tick error: {}", e);
-        //     workspace.append_daily_memory(&format!("ERROR: {}", e)).ok();
-        // }
-
-        if let Err(e) = heartbeat_tick(
-            &config,
-            &workspace,
-            &brain,
-            &perception,
-            &executor,
-            &sessions,
-            &event_tx,
-            tick_count,
-            &config.perception.bridge_mode,
-        ).await {
+        let wanted_profile = brain_profile.lock().await.clone();
+        if wanted_profile != active_profile {
+            match Brain::from_profile(&config, wanted_profile.as_deref()) {
+                Ok(b) => {
+                    info!("🧠 Brain profile: {}", wanted_profile.as_deref().unwrap_or(config::DEFAULT_BRAIN_PROFILE));
+                    brain = Arc::new(
+                        b.with_metrics(metrics.clone())
+                            .with_safe_mode(config.action.safe_mode)
+                            .with_prompt_dump(tick_prompt_dump.clone()),
+                    );
+                }
+                Err(e) => warn!("Keeping the current brain: {}", e),
+            }
+            active_profile = wanted_profile;
+        }
+
+        brain.check_codex_token().await;
+        let ctx = TickContext {
+            config: &config,
+            workspace: &workspace,
+            brain: &brain,
+            perception: &perception,
+            executor: &executor,
+            sessions: &sessions,
+            event_tx: &event_tx,
+            running: &running,
+            provider: provider.as_ref(),
+        };
+        let tick_result = heartbeat_tick(ctx, tick_count).await;
+        *last_tick.lock().await = Some(std::time::Instant::now());
+        metrics.record_tick(tick_result.is_ok());
+        if tick_result.is_ok() {
+            ready.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        if let Err(e) = tick_result {
             error!("Tick error: {}", e);
             workspace.append_daily_memory(&format!("ERROR: {}", e)).ok();
+            consecutive_errors += 1;
+            let adb_error = e.downcast_ref::<AdbError>();
+            if let Some(adb) = adb_error {
+                warn!("📱 {} — {}", adb, adb.hint());
+                if adb.is_fatal() {
+                    // Retrying can't fix a missing adb binary
+                    consecutive_errors = consecutive_errors.max(max_errors);
+                }
+            }
+            if max_errors > 0 && consecutive_errors >= max_errors {
+                *running.lock().await = false;
+                *circuit_open.lock().await = true;
+                error!(
+                    "🔌 Circuit open: {} consecutive tick errors — pausing agent (POST /start to resume)",
+                    consecutive_errors
+                );
+                workspace
+                    .append_daily_memory(&format!(
+                        "PAUSED: {} consecutive tick errors, {}. Last error: {}",
+                        consecutive_errors,
+                        adb_error.map_or("device likely unreachable", AdbError::hint),
+                        e
+                    ))
+                    .ok();
+                let _ = event_tx.send(
+                    serde_json::json!({
+                        "event": "circuit_open",
+                        "errors": consecutive_errors,
+                        "last_error": e.to_string(),
+                    })
+                    .to_string(),
+                );
+                continue;
+            }
+        } else {
+            consecutive_errors = 0;
         }
         
+        trigger = wait_for_trigger(&mut event_rx, heartbeat_interval, &running, &workspace, &config.agent.kill_phrases).await;
+    }
+
+    // Let axum finish in-flight requests before the process exits
+    server.await.ok();
+    info!("👋 Agent stopped");
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on unix (systemd, docker stop)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Cannot listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                error!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Sleep until the next heartbeat interval or an incoming event, whichever comes first
+async fn wait_for_trigger(
+    event_rx: &mut broadcast::Receiver<String>,
+    heartbeat_interval: u64,
+    running: &Mutex<bool>,
+    workspace: &Workspace,
+    kill_phrases: &[String],
+) -> TickTrigger {
+    let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(heartbeat_interval);
+    loop {
         tokio::select! {
-            _ = tokio::time::sleep(tokio::time::Duration::from_secs(heartbeat_interval)) => {}
+            _ = tokio::time::sleep_until(deadline) => return TickTrigger::Timer,
             event = event_rx.recv() => {
-                if let Ok(ev) = event {
-                    if ev.contains("priority_notification") || ev.contains("user_command") {
-                        info!("⚡ Event interrupt — immediate tick");
-                    }
-                    if ev.contains("stop everything") || ev.contains("\"event\":\"kill\"") {
-                        *running.lock().await = false;
-                        warn!("🛑 KILL SWITCH activated");
-                        workspace.append_daily_memory("KILL SWITCH activated").ok();
-                    }
+                let Ok(ev) = event else { return TickTrigger::Event };
+                if runs::is_run_event(&ev) {
+                    continue;
+                }
+                if ev.contains("priority_notification") || ev.contains("user_command") {
+                    info!("⚡ Event interrupt — immediate tick");
                 }
+                if is_kill_event(&ev, kill_phrases) {
+                    *running.lock().await = false;
+                    warn!("🛑 KILL SWITCH activated");
+                    workspace.append_daily_memory("KILL SWITCH activated").ok();
+                }
+                return if ev.contains("user_command") {
+                    TickTrigger::UserCommand
+                } else if ev.contains("priority_notification") {
+                    TickTrigger::PriorityNotification
+                } else {
+                    TickTrigger::Event
+                };
             }
         }
     }
 }
 
+/// Truncate an oversized plan to `max` actions (0 = unlimited)
+fn cap_actions(actions: &[brain::AgentAction], max: usize) -> &[brain::AgentAction] {
+    if max == 0 || actions.len() <= max {
+        return actions;
+    }
+    warn!(
+        "⏱ Plan has {} actions — executing only the first {} (action.max_actions_per_tick)",
+        actions.len(), max
+    );
+    &actions[..max]
+}
+
+/// A `{"event":"kill"}` broadcast, or a user command containing one of the
+/// configured kill phrases (case-insensitive substring match)
+fn is_kill_event(ev: &str, kill_phrases: &[String]) -> bool {
+    let Ok(v) = serde_json::from_str::<serde_json::Value>(ev) else {
+        return false;
+    };
+    if v["event"] == "kill" {
+        return true;
+    }
+    if v["type"] != "user_command" {
+        return false;
+    }
+    let text = v["text"].as_str().unwrap_or("").to_lowercase();
+    kill_phrases
+        .iter()
+        .map(|p| p.trim().to_lowercase())
+        .any(|p| !p.is_empty() && text.contains(&p))
+}
+
+/// What a heartbeat tick works with; the same for every tick
+#[derive(Clone, Copy)]
+struct TickContext<'a> {
+    config: &'a Config,
+    workspace: &'a Workspace,
+    brain: &'a Brain,
+    perception: &'a Perception,
+    executor: &'a ActionExecutor,
+    sessions: &'a SessionManager,
+    event_tx: &'a broadcast::Sender<String>,
+    running: &'a Mutex<bool>,
+    provider: &'a dyn PerceptionProvider,
+}
+
 /// Single heartbeat tick — the core agent loop
-async fn heartbeat_tick(
-    config: &Config,
-    workspace: &Workspace,
-    brain: &Brain,
-    perception: &Perception,
-    executor: &ActionExecutor,
-    sessions: &SessionManager,
-    event_tx: &broadcast::Sender<String>,
-    tick: u64,
-    bridge_mode: &str,
-) -> anyhow::Result<()> {
-    // 0. ADB polling
-    if bridge_mode == "adb" {
-        let has_priority = perception.poll_notifications_adb().await;
-        if has_priority {
-            info!("⚡ Priority notification detected");
-        }
-        let commands_pending = !perception.peek_user_commands().await;
-        let use_screenshot = has_priority || commands_pending;
-        perception.poll_screen_adb_full(use_screenshot).await;
+async fn heartbeat_tick(ctx: TickContext<'_>, tick: u64) -> anyhow::Result<()> {
+    let TickContext { config, workspace, brain, perception, executor, sessions, event_tx, running, provider } = ctx;
+    // 0. Device polling (ADB UI dumps are cached per tick). The device lock
+    // is held until the screen is read, not through the LLM call.
+    let device_lock = executor.device_lock();
+    let device = device_lock.lock().await;
+    perception.next_tick();
+    let poll_strategy = PollStrategy::from_str(&config.perception.poll_strategy);
+    let commands_pending = !perception.peek_user_commands().await;
+    let has_event = commands_pending || perception.has_pending_events().await;
+    let (has_priority, dump_screen);
+    if poll_strategy.should_poll_screen(tick, has_event) {
+        // The screen is dumped whatever the notifications say — read both at once
+        let started = std::time::Instant::now();
+        let ((priority, notif_time), (_, screen_time)) = tokio::join!(
+            timed(provider.poll_notifications()),
+            timed(provider.refresh_screen(commands_pending)),
+        );
+        has_priority = priority?;
+        dump_screen = true;
+        tracing::debug!(
+            "Tick {}: {} poll took {}ms (notifications {}ms + screen {}ms, concurrent)",
+            tick,
+            provider.name(),
+            started.elapsed().as_millis(),
+            notif_time.as_millis(),
+            screen_time.as_millis()
+        );
+        if has_priority && !commands_pending {
+            // Priority notifications get a screenshot; the UI dump is cached
+            provider.refresh_screen(true).await;
+        }
+    } else {
+        has_priority = provider.poll_notifications().await?;
+        dump_screen = has_priority && poll_strategy.should_poll_screen(tick, true);
+        if dump_screen {
+            provider.refresh_screen(true).await;
+        } else {
+            tracing::debug!("Tick {}: no events, skipping screen dump ({:?})", tick, poll_strategy);
+        }
+    }
+    if has_priority {
+        info!("⚡ Priority notification detected");
     }
 
     // 1. Gather context
-    let ctx = workspace.assemble_bootstrap();
+    let mut ctx = workspace.assemble_bootstrap();
+    if config.perception.apps_in_prompt {
+        // `pm list` runs on a cache miss; keep it off the runtime
+        let p = perception.clone();
+        ctx.installed_apps = tokio::task::spawn_blocking(move || p.format_installed_apps()).await.unwrap_or_default();
+    }
     let notifications = perception.drain_notifications().await;
-    // let screen = perception.get_screen_state().await;
-    let vision_mode = VisionMode::from_str(&config.perception.vision_mode);
-    let screen = Some(sanitizer::perceive_screen(
-        &config.perception.adb_device,
-        vision_mode,
-        config.perception.max_elements,
-    ).await);
+    let screen = if dump_screen {
+        Some(provider.poll_screen(true).await)
+    } else {
+        None
+    };
+    drop(device);
     let commands = perception.drain_user_commands().await;
     let events = perception.drain_device_events().await;
-    let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
+    let now = config.agent.local_now("%Y-%m-%d %H:%M:%S");
 
     let notif_text = Perception::format_notifications(&notifications);
     let screen_text = screen
         .as_ref()
-        .map(|s| s.formatted_text.clone())
+        .map(|s| s.prompt_text())
         .unwrap_or_else(|| "[No screen data available]".to_string());
+    // Compared against re-polls after UI actions, which never skip the screenshot
+    let screen_hash = simple_hash(screen.as_ref().map_or("", |s| s.formatted_text.as_str()));
 
     if notifications.is_empty() && commands.is_empty() && events.is_empty() && tick % 4 != 0 {
         tracing::debug!("Tick {}: idle (skipping LLM)", tick);
@@ -596,15 +1311,71 @@ async fn heartbeat_tick(
     }
 
     // 2. Build prompts
+    let screen_text = brain.with_screen_summary(&screen_text).await;
     let system_prompt = brain.build_system_prompt(&ctx);
-    let user_prompt = brain.build_tick_prompt(&ctx, &notif_text, &screen_text, &commands, &now);
+    let recent_actions = action::format_recent_actions(&executor.action_log().lock().await, config.agent.recent_actions);
+    let user_prompt = brain.build_tick_prompt(&ctx, &notif_text, &screen_text, &recent_actions, &commands, &now);
+    tracing::debug!("Tick {} system prompt:\n{}", tick, system_prompt);
+    tracing::debug!("Tick {} user prompt:\n{}", tick, user_prompt);
 
     // 3. Call LLM
     let screenshot = screen.as_ref().and_then(|s| s.screenshot_base64.as_deref());
-    let raw = brain.think(&system_prompt, &user_prompt, screenshot).await?;
+    let session = sessions.main_session().await;
+    let raw = brain
+        .think_actions(
+            &system_prompt,
+            &user_prompt,
+            screenshot,
+            session.model_override.as_deref(),
+            session.thinking_level.as_deref(),
+        )
+        .await?;
+    tracing::debug!("Tick {} raw response:\n{}", tick, raw);
+
+    // 4. Parse — one re-prompt if the reply held no usable JSON (common with small local models)
+    let mut prose_only = false;
+    let mut response = match brain.try_parse_response(&raw) {
+        Some(response) => response,
+        None => {
+            warn!("Tick {}: no JSON in LLM response — re-prompting once", tick);
+            let retry_prompt = format!("{}\n\n{}", user_prompt, Brain::JSON_RETRY_INSTRUCTION);
+            let retry = brain
+                .think_actions(
+                    &system_prompt,
+                    &retry_prompt,
+                    screenshot,
+                    session.model_override.as_deref(),
+                    session.thinking_level.as_deref(),
+                )
+                .await;
+            match retry.as_deref().map(|r| brain.try_parse_response(r)) {
+                Ok(Some(response)) => {
+                    info!("Tick {}: re-prompt recovered a valid plan ({} actions)", tick, response.actions.len());
+                    response
+                }
+                Ok(None) => {
+                    prose_only = true;
+                    brain.parse_response(&raw)
+                }
+                Err(e) => {
+                    warn!("Tick {}: re-prompt failed: {}", tick, e);
+                    prose_only = true;
+                    brain.parse_response(&raw)
+                }
+            }
+        }
+    };
 
-    // 4. Parse
-    let response = brain.parse_response(&raw);
+    // Prose instead of a plan: nothing will run, so at least pass on what the
+    // model said rather than look frozen
+    if prose_only {
+        if let Some(prose) = response.prose_to_message() {
+            warn!("Tick {}: model replied with prose and no actions — forwarding it to the user", tick);
+            workspace
+                .append_daily_memory(&format!("Model replied without a plan: {}", prose))
+                .ok();
+        }
+    }
 
     // 5. HEARTBEAT_OK
     if response.reflection.as_deref() == Some("HEARTBEAT_OK") {
@@ -616,6 +1387,9 @@ async fn heartbeat_tick(
     if let Some(ref r) = response.reflection {
         if !r.is_empty() && r != "HEARTBEAT_OK" {
             info!("💭 {}", r);
+            if let Err(e) = workspace.append_reflection(r) {
+                warn!("Could not record reflection: {}", e);
+            }
         }
     }
 
@@ -647,17 +1421,41 @@ async fn heartbeat_tick(
         tracing::debug!("Tick {}: no actions", tick);
     } else {
         info!("Tick {}: {} action(s)", tick, response.actions.len());
+        let mut actions = cap_actions(&response.actions, config.action.max_actions_per_tick);
+
+        // Announce the whole plan first; give RED plans a moment to be stopped
+        let plan = brain::plan_event(actions, |a| executor.effective_classification(a));
+        let _ = event_tx.send(plan.to_string());
+        let delay = config.action.plan_preview_delay_ms;
+        if delay > 0 && plan["has_red"] == true {
+            info!("  ⏸ Plan has RED action(s) — executing in {}ms unless stopped", delay);
+            tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+            if !*running.lock().await {
+                warn!("  🛑 Agent stopped during plan preview — dropping {} action(s)", actions.len());
+                workspace.append_daily_memory("ABORTED plan: agent stopped during preview").ok();
+                actions = &[];
+            }
+        }
 
         // Categorize actions by how much they change the UI
         let heavy_ui = ["launch_app", "back", "home"];      // App transitions, ~800ms settle
-        let light_ui = ["tap", "long_press", "swipe"];       // In-app interaction, ~300ms settle
+        let light_ui = ["tap", "long_press", "open_context_menu", "swipe"]; // In-app interaction, ~300ms settle
 
         let mut consecutive_ui_actions = 0;
-        let mut last_screen_hash: u64 = simple_hash(&screen_text);
+        let mut last_screen_hash: u64 = screen_hash;
 
-        for (i, action) in response.actions.iter().enumerate() {
+        for (i, action) in actions.iter().enumerate() {
+            if let Err(e) = action.validate() {
+                warn!("  ⚠ Skipping invalid action: {}", e);
+                workspace.append_daily_memory(&format!("SKIPPED invalid action: {}", e)).ok();
+                continue;
+            }
             match executor.execute(action).await {
                 Ok(result) => {
+                    if let Some(data) = action::screenshot_data(&result) {
+                        let _ = event_tx.send(serde_json::json!({"type": "screenshot", "data": data}).to_string());
+                    }
+                    let result = action::summarize_result(&result);
                     info!("  ✅ {} → {}", action.action_type, result);
                     workspace.append_daily_memory(&format!(
                         "Action: {} ({}) → {}", action.action_type, action.reason, result
@@ -674,43 +1472,41 @@ async fn heartbeat_tick(
 
                     if is_heavy || is_light {
                         consecutive_ui_actions += 1;
+                        perception.invalidate_screen_cache();
 
                         // Adaptive settle: wait just long enough for the UI to update
                         let settle_ms = if is_heavy { 800 } else { 300 };
                         tokio::time::sleep(tokio::time::Duration::from_millis(settle_ms)).await;
 
                         // After 2+ UI actions with more remaining, check if screen changed
-                        if consecutive_ui_actions >= 2 && i + 1 < response.actions.len() && bridge_mode == "adb" {
+                        if consecutive_ui_actions >= 2 && i + 1 < actions.len() {
                             // Quick screen poll
-                            perception.poll_screen_adb_full(true).await;
-                            // let new_screen = perception.get_screen_state().await;
-                            let vision_mode = VisionMode::from_str(&config.perception.vision_mode);
-                            let new_screen = Some(sanitizer::perceive_screen(
-                                &config.perception.adb_device,
-                                vision_mode,
-                                config.perception.max_elements,
-                            ).await);
-                            let new_screen_text = new_screen
-                                .as_ref()
-                                .map(|s| s.formatted_text.clone())
-                                .unwrap_or_else(|| "[No screen data available]".to_string());
+                            let new_screen_text = {
+                                let _device = device_lock.lock().await;
+                                provider.refresh_screen(true).await;
+                                provider.poll_screen(false).await.formatted_text
+                            };
                             let new_hash = simple_hash(&new_screen_text);
 
                             if new_hash != last_screen_hash {
                                 // Screen changed → break for LLM re-plan with fresh screen data
-                                let remaining = response.actions.len() - i - 1;
+                                let remaining = actions.len() - i - 1;
                                 info!("  🔄 Screen changed after {} actions — re-planning {} remaining",
                                     consecutive_ui_actions, remaining);
 
-                                let remaining_descriptions: Vec<String> = response.actions[i+1..]
+                                let remaining_descriptions: Vec<String> = actions[i+1..]
                                     .iter()
                                     .map(|a| format!("{}: {}", a.action_type, a.reason))
                                     .collect();
-                                let continuation = format!(
+                                let mut continuation = format!(
                                     "[CONTINUE] Screen updated after actions. Remaining goals: {}. \
                                      Check current screen and adjust coordinates/approach if needed.",
                                     remaining_descriptions.join("; ")
                                 );
+                                // Say what changed so the model needn't re-read everything
+                                if let Some(diff) = perception.screen_diff().await.filter(|d| !d.is_empty()) {
+                                    continuation.push_str(&format!("\nWhat changed:\n{}", diff.summary()));
+                                }
                                 perception.push_user_command(continuation).await;
 
                                 // Wake up the heartbeat loop IMMEDIATELY (not after 30s)
@@ -740,9 +1536,13 @@ async fn heartbeat_tick(
                     workspace.append_daily_memory(&format!(
                         "FAILED: {} → {}", action.action_type, e
                     )).ok();
+                    // A lost device fails the tick so the circuit breaker sees it
+                    if e.downcast_ref::<AdbError>().is_some_and(AdbError::is_device_error) {
+                        return Err(e);
+                    }
                     // Don't continue blindly after a failure
-                    if i + 1 < response.actions.len() {
-                        warn!("  Aborting remaining {} actions after failure", response.actions.len() - i - 1);
+                    if i + 1 < actions.len() {
+                        warn!("  Aborting remaining {} actions after failure", actions.len() - i - 1);
                         break;
                     }
                 }
@@ -751,7 +1551,7 @@ async fn heartbeat_tick(
     }
 
     // 10. Track in session
-    if !commands.is_empty() || !response.actions.is_empty() {
+    if !commands.is_empty() || !response.actions.is_empty() || prose_only {
         for cmd in &commands {
             sessions.append_message("main", "user", cmd).await;
         }
@@ -847,6 +1647,47 @@ WantedBy=default.target
     Ok(())
 }
 
+/// Stdout logging plus, when `agent.log_file` is set, a daily-rotated file
+/// layer. Both share the same `RUST_LOG` filter. The returned guard flushes
+/// the file writer and must live until exit.
+fn init_logging(
+    default_filter: &str,
+    agent: Option<&config::AgentConfig>,
+) -> anyhow::Result<Option<tracing_appender::non_blocking::WorkerGuard>> {
+    use tracing_subscriber::prelude::*;
+
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| default_filter.into());
+    let mut guard = None;
+    let file_layer = match agent.and_then(|a| a.log_path().map(|p| (a, p))) {
+        Some((agent, path)) => {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            std::fs::create_dir_all(dir)?;
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("hermitdroid");
+            let mut builder = tracing_appender::rolling::Builder::new()
+                .rotation(tracing_appender::rolling::Rotation::DAILY)
+                .filename_prefix(stem);
+            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+                builder = builder.filename_suffix(ext);
+            }
+            let appender = builder.build(dir)
+                .map_err(|e| anyhow::anyhow!("Cannot open log file {}: {}", path.display(), e))?;
+            let (writer, g) = tracing_appender::non_blocking(appender);
+            guard = Some(g);
+            let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false);
+            Some(if agent.log_format == "json" { layer.json().boxed() } else { layer.boxed() })
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
+        .init();
+    Ok(guard)
+}
+
 fn run_logs() -> anyhow::Result<()> {
     let _ = std::process::Command::new("journalctl")
         .args(["--user", "-u", "hermitdroid", "-f", "--no-pager"])
@@ -854,6 +1695,98 @@ fn run_logs() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// `doctor --fix`: repair the workspace and try to reach a network ADB device
+/// before the regular health report runs.
+fn run_doctor_fix(config: &Config) -> anyhow::Result<()> {
+    println!("🔧 Hermitdroid Doctor — fixing\n");
+
+    let ws = Workspace::new(&config.agent.workspace_path, config.agent.bootstrap_max_chars);
+    let repairs = ws.repair()?;
+    if repairs.is_empty() {
+        println!("  ✅ Workspace: nothing to repair");
+    }
+    for repair in &repairs {
+        println!("  🔧 {}", repair);
+    }
+
+    let connected = adb::list_devices()
+        .map(|devices| devices.iter().any(|(_, state)| state.is_ready()))
+        .unwrap_or(false);
+    if !connected {
+        let target = if config.tailscale.enabled {
+            TailscaleManager::new(config.tailscale.clone()).resolve_phone_ip().ok()
+                .map(|ip| format!("{}:{}", ip, config.tailscale.adb_port))
+        } else {
+            config.perception.adb_device.clone().filter(|d| d.contains(':'))
+        };
+        if let Some(addr) = target {
+            match adb::output(std::process::Command::new("adb").args(["connect", &addr])) {
+                Ok(out) => {
+                    let msg = String::from_utf8_lossy(&out.stdout).trim().to_string();
+                    let ok = msg.contains("connected to");
+                    println!("  {} adb connect {}: {}", if ok { "🔧" } else { "❌" }, addr, msg);
+                }
+                Err(e) => println!("  ❌ adb connect {}: {}", addr, e),
+            }
+        }
+    }
+    println!();
+    Ok(())
+}
+
+/// `doctor --json`: the checks scripts care about, with each ADB device's state
+fn doctor_report(config: &Config) -> serde_json::Value {
+    let ws_path = Path::new(&config.agent.workspace_path);
+    let missing: Vec<&str> = soul::CORE_FILES
+        .iter()
+        .map(|(file, _)| *file)
+        .filter(|file| !ws_path.join(file).exists())
+        .collect();
+    let adb = match adb::list_devices() {
+        Ok(devices) => serde_json::json!({
+            "ready": devices.iter().any(|(_, state)| state.is_ready()),
+            "devices": devices.iter().map(|(serial, state)| serde_json::json!({
+                "serial": serial,
+                "state": state.as_str(),
+                "hint": state.hint(),
+            })).collect::<Vec<_>>(),
+        }),
+        Err(e) => serde_json::json!({
+            "ready": false,
+            "devices": [],
+            "error": e.to_string(),
+            "hint": e.hint(),
+        }),
+    };
+    let capabilities = match adb::Capabilities::probe(&config.perception.adb_device) {
+        Ok(caps) => serde_json::to_value(caps).unwrap_or_default(),
+        Err(e) => serde_json::json!({"error": e.to_string()}),
+    };
+    let port = config.server.port;
+    let listening = std::net::TcpStream::connect_timeout(
+        &format!("127.0.0.1:{}", port).parse().unwrap(),
+        std::time::Duration::from_secs(1),
+    )
+    .is_ok();
+    serde_json::json!({
+        "workspace": {
+            "path": config.agent.workspace_path,
+            "exists": ws_path.exists(),
+            "missing_files": missing,
+        },
+        "adb": adb,
+        "capabilities": capabilities,
+        "tailscale": if config.tailscale.enabled {
+            serde_json::to_value(TailscaleManager::new(config.tailscale.clone()).diagnose()).unwrap_or_default()
+        } else {
+            serde_json::json!({"enabled": false})
+        },
+        "server": {"port": port, "listening": listening},
+        "brain": {"backend": config.brain.backend, "model": config.brain.model},
+        "dry_run": config.action.dry_run,
+    })
+}
+
 fn run_doctor(config: &Config) -> anyhow::Result<()> {
     println!("🩺 Hermitdroid Doctor\n");
 
@@ -864,7 +1797,7 @@ fn run_doctor(config: &Config) -> anyhow::Result<()> {
         println!("❌ Workspace missing: {}", config.agent.workspace_path);
     }
 
-    for file in &["SOUL.md", "AGENTS.md", "TOOLS.md", "IDENTITY.md", "USER.md", "HEARTBEAT.md", "MEMORY.md", "GOALS.md"] {
+    for (file, _) in soul::CORE_FILES {
         let p = ws_path.join(file);
         if p.exists() {
             let size = std::fs::metadata(&p).map(|m| m.len()).unwrap_or(0);
@@ -891,17 +1824,37 @@ fn run_doctor(config: &Config) -> anyhow::Result<()> {
 
     // ADB check
     println!();
-    match std::process::Command::new("adb").args(["devices"]).output() {
-        Ok(out) => {
-            let devices = String::from_utf8_lossy(&out.stdout);
-            let connected = devices.lines().filter(|l| l.contains("\tdevice")).count();
+    match adb::list_devices() {
+        Ok(devices) => {
+            let connected = devices.iter().filter(|(_, state)| state.is_ready()).count();
             if connected > 0 {
                 println!("✅ ADB: {} device(s) connected", connected);
+            } else if devices.is_empty() {
+                println!("❌ ADB: no devices connected — {}", AdbError::NoDevice.hint());
             } else {
-                println!("❌ ADB: no devices connected");
+                println!("❌ ADB: no usable device");
+            }
+            for (serial, state) in &devices {
+                match state.hint() {
+                    Some(hint) => println!("  ⚠️  {} is {} — {}", serial, state, hint),
+                    None => println!("  ✅ {}", serial),
+                }
+            }
+        }
+        Err(e) => println!("❌ {} — {}", e, e.hint()),
+    }
+    match adb::Capabilities::probe(&config.perception.adb_device) {
+        Ok(caps) => {
+            println!("🔧 Device commands:");
+            for (name, ok) in caps.list() {
+                if ok {
+                    println!("  ✅ {}", name);
+                } else {
+                    println!("  ⚠️  {} unsupported — a fallback is used", name);
+                }
             }
         }
-        Err(_) => println!("❌ ADB: not found in PATH"),
+        Err(e) => println!("⚫ Device commands: not probed ({})", e),
     }
 
     if config.tailscale.enabled {
@@ -917,11 +1870,10 @@ fn run_doctor(config: &Config) -> anyhow::Result<()> {
             println!("  Phone: {} (port {})", config.tailscale.phone_hostname, config.tailscale.adb_port);
 
             // Try to resolve and ping
-            let mut mgr = TailscaleManager::new(config.tailscale.clone());
-            match mgr.resolve_phone_ip() {
-                Ok(ip) => {
+            let status = TailscaleManager::new(config.tailscale.clone()).diagnose();
+            match (&status.resolved_ip, &status.adb_address) {
+                (Some(ip), Some(addr)) => {
                     println!("  ✅ Resolved → {}", ip);
-                    let addr = format!("{}:{}", ip, config.tailscale.adb_port);
                     match std::net::TcpStream::connect_timeout(
                         &addr.parse().unwrap(),
                         std::time::Duration::from_secs(5),
@@ -929,11 +1881,16 @@ fn run_doctor(config: &Config) -> anyhow::Result<()> {
                         Ok(_) => println!("  ✅ TCP to {} reachable", addr),
                         Err(e) => println!("  ❌ TCP to {} failed: {}", addr, e),
                     }
-                    if let Some(ms) = mgr.ping_phone() {
+                    if let Some(ms) = status.last_ping_ms {
                         println!("  ✅ Ping: {}ms", ms);
                     }
+                    if status.adb_connected {
+                        println!("  ✅ ADB connected to {}", addr);
+                    } else {
+                        println!("  ⚠️  ADB not connected to {} — run `hermitdroid doctor --fix`", addr);
+                    }
                 }
-                Err(e) => println!("  ❌ Resolution failed: {}", e),
+                _ => println!("  ❌ Resolution failed: {}", status.state.trim_start_matches("failed: ")),
             }
         } else {
             println!("  ❌ tailscale CLI not found");
@@ -967,4 +1924,38 @@ fn run_doctor(config: &Config) -> anyhow::Result<()> {
 
     println!("\n✨ Doctor complete.");
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_tick_when_not_autonomous() {
+        assert!(!should_tick(false, TickTrigger::Timer));
+        assert!(!should_tick(false, TickTrigger::Event));
+        assert!(should_tick(false, TickTrigger::UserCommand));
+        assert!(should_tick(true, TickTrigger::Timer));
+    }
+
+    #[test]
+    fn test_cap_actions() {
+        let plan: Vec<brain::AgentAction> = (0..5)
+            .map(|_| serde_json::from_value(serde_json::json!({"type": "tap", "reason": "x"})).unwrap())
+            .collect();
+        assert_eq!(cap_actions(&plan, 0).len(), 5);
+        assert_eq!(cap_actions(&plan, 10).len(), 5);
+        assert_eq!(cap_actions(&plan, 3).len(), 3);
+    }
+
+    #[test]
+    fn test_kill_phrases_match_case_insensitively() {
+        let phrases = vec!["stop everything".to_string(), "Berhenti Semua".to_string()];
+        let cmd = |t: &str| serde_json::json!({"type": "user_command", "text": t}).to_string();
+        assert!(is_kill_event(&cmd("please STOP EVERYTHING now"), &phrases));
+        assert!(is_kill_event(&cmd("berhenti semua!"), &phrases));
+        assert!(!is_kill_event(&cmd("stop the music"), &phrases));
+        assert!(is_kill_event(r#"{"event":"kill"}"#, &phrases));
+        // Only user commands are matched, not e.g. notification payloads
+        assert!(!is_kill_event(r#"{"type":"device_event","event":"stop everything"}"#, &phrases));
+    }
+}
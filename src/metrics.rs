@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Process-wide counters for `GET /metrics` (Prometheus text format).
+/// Shared as `Arc<Metrics>` between the heartbeat, brain, executor and server.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    ticks: AtomicU64,
+    tick_errors: AtomicU64,
+    llm_calls: AtomicU64,
+    llm_failures: AtomicU64,
+    fallback_activations: AtomicU64,
+    screen_summaries: AtomicU64,
+    screen_summary_cache_hits: AtomicU64,
+    /// Estimated (chars / 4) tokens sent and received by screen summary calls
+    screen_summary_tokens: AtomicU64,
+    /// Executed actions by classification label (GREEN, YELLOW, RED-AUTO, ...)
+    actions: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn record_tick(&self, ok: bool) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.tick_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_llm_call(&self, ok: bool) {
+        self.llm_calls.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.llm_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_fallback(&self) {
+        self.fallback_activations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A `brain.screen_summary` lookup; `tokens` spent when it wasn't `cached`
+    pub fn record_screen_summary(&self, cached: bool, tokens: u64) {
+        if cached {
+            self.screen_summary_cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.screen_summaries.fetch_add(1, Ordering::Relaxed);
+            self.screen_summary_tokens.fetch_add(tokens, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_action(&self, classification: &str) {
+        if let Ok(mut actions) = self.actions.lock() {
+            *actions.entry(classification.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    /// Text exposition format. `pending` is sampled by the caller.
+    pub fn render(&self, pending: usize) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP hermitdroid_{name} {help}");
+            let _ = writeln!(out, "# TYPE hermitdroid_{name} {kind}");
+            let _ = writeln!(out, "hermitdroid_{name} {value}");
+        };
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        metric("ticks_total", "counter", "Heartbeat ticks run.", get(&self.ticks));
+        metric("tick_errors_total", "counter", "Heartbeat ticks that failed.", get(&self.tick_errors));
+        metric("llm_calls_total", "counter", "Prompts sent to the model, screen summaries included; retries and fallback models count once.", get(&self.llm_calls));
+        metric("llm_failures_total", "counter", "LLM requests that failed after retries and fallback.", get(&self.llm_failures));
+        metric("fallback_activations_total", "counter", "Times a fallback model was used.", get(&self.fallback_activations));
+        metric("screen_summaries_total", "counter", "Screen summaries requested from the model.", get(&self.screen_summaries));
+        metric("screen_summary_cache_hits_total", "counter", "Screen summaries reused for an unchanged screen.", get(&self.screen_summary_cache_hits));
+        metric("screen_summary_tokens_total", "counter", "Estimated tokens (chars / 4) spent on screen summary calls.", get(&self.screen_summary_tokens));
+        metric("pending_confirmations", "gauge", "Actions waiting for user confirmation.", pending as u64);
+
+        out.push_str("# HELP hermitdroid_actions_total Executed actions by classification.\n");
+        out.push_str("# TYPE hermitdroid_actions_total counter\n");
+        if let Ok(actions) = self.actions.lock() {
+            for (class, count) in actions.iter() {
+                let _ = writeln!(out, "hermitdroid_actions_total{{classification=\"{}\"}} {}", class, count);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_exposition_format() {
+        let m = Metrics::default();
+        m.record_tick(true);
+        m.record_tick(false);
+        m.record_llm_call(false);
+        m.record_action("GREEN");
+        m.record_action("GREEN");
+        m.record_action("RED-AUTO");
+
+        let out = m.render(3);
+        assert!(out.contains("hermitdroid_ticks_total 2\n"));
+        assert!(out.contains("hermitdroid_tick_errors_total 1\n"));
+        assert!(out.contains("hermitdroid_llm_failures_total 1\n"));
+        assert!(out.contains("hermitdroid_pending_confirmations 3\n"));
+        assert!(out.contains("hermitdroid_actions_total{classification=\"GREEN\"} 2\n"));
+        assert!(out.contains("# TYPE hermitdroid_actions_total counter\n"));
+    }
+}
@@ -365,7 +365,7 @@ fn step_adb() -> Option<AdbResult> {
     println!("\n{CYAN}━━━ Step 3/4: ADB Connection ━━━{RESET}\n");
 
     // Check adb binary
-    let adb_ok = Command::new("adb").arg("version").output()
+    let adb_ok = crate::adb::output(Command::new("adb").arg("version"))
         .map(|o| o.status.success()).unwrap_or(false);
 
     if !adb_ok {
@@ -395,7 +395,7 @@ fn step_adb() -> Option<AdbResult> {
             println!("    3. RSA key prompt accepted on phone\n");
 
             // Show connected devices
-            if let Ok(out) = Command::new("adb").arg("devices").output() {
+            if let Ok(out) = crate::adb::output(Command::new("adb").arg("devices")) {
                 let text = String::from_utf8_lossy(&out.stdout);
                 let devs: Vec<_> = text.lines()
                     .filter(|l| l.contains("\tdevice") || l.contains("\tunauthorized"))
@@ -425,7 +425,7 @@ fn step_adb() -> Option<AdbResult> {
             } else {
                 let a = if ip.contains(':') { ip } else { format!("{ip}:5555") };
                 println!("  Connecting to {a}...");
-                if let Ok(out) = Command::new("adb").args(["connect", &a]).output() {
+                if let Ok(out) = crate::adb::output(Command::new("adb").args(["connect", &a])) {
                     let s = String::from_utf8_lossy(&out.stdout);
                     if s.contains("connected") {
                         println!("  {GREEN}✓  Connected!{RESET}");
@@ -565,7 +565,7 @@ fn step_tailscale(adb_configured: bool) -> Option<TailscaleResult> {
             Ok(_) => {
                 println!("  {GREEN}✓  TCP OK!{RESET}");
                 // Try adb connect
-                if let Ok(out) = Command::new("adb").args(["connect", &test_addr]).output() {
+                if let Ok(out) = crate::adb::output(Command::new("adb").args(["connect", &test_addr])) {
                     let s = String::from_utf8_lossy(&out.stdout);
                     if s.contains("connected") {
                         println!("  {GREEN}✓  ADB connected via Tailscale!{RESET} 🎉");
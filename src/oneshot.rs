@@ -1,10 +1,12 @@
-use crate::action::ActionExecutor;
-use crate::brain::Brain;
+use crate::action::{check_allowed, ActionExecutor, CoordinateCalibration, ACTION_LOG_FILE, DEFAULT_ESCAPE_MAX_BACKS, PENDING_PREFIX};
+use crate::brain::{AgentAction, Brain, PromptDump, PromptNotSent};
 use crate::config::Config;
 use crate::perception::Perception;
 use crate::sanitizer;
 use crate::soul::Workspace;
 use crate::stuck::{StuckDetector, StuckStatus, RecoveryAction, action_target_key};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::time::{Instant, Duration};
 use tracing::{error, info};
 
@@ -39,33 +41,252 @@ fn simple_hash(text: &str) -> u64 {
     hasher.finish()
 }
 
+// ── Offline screenshots ─────────────────────────────────────────────────────
+
+/// Upper bound for `run --screenshot` images (raw PNG bytes)
+const MAX_SCREENSHOT_BYTES: u64 = 8 * 1024 * 1024;
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Read a saved PNG for `run --screenshot` and return it base64-encoded
+fn load_screenshot(path: &str) -> anyhow::Result<String> {
+    use base64::Engine;
+
+    let size = std::fs::metadata(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read screenshot {}: {}", path, e))?
+        .len();
+    if size > MAX_SCREENSHOT_BYTES {
+        anyhow::bail!(
+            "Screenshot {} is {} bytes (limit {})",
+            path, size, MAX_SCREENSHOT_BYTES
+        );
+    }
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(PNG_MAGIC) {
+        anyhow::bail!("Screenshot {} is not a PNG file", path);
+    }
+    Ok(base64::engine::general_purpose::STANDARD.encode(&bytes))
+}
+
+/// Stand-in perception result for an offline screenshot: image only,
+/// with a placeholder where the accessibility tree would be.
+fn offline_perception(image_base64: String) -> sanitizer::PerceptionResult {
+    sanitizer::PerceptionResult {
+        screen: sanitizer::SanitizedScreen {
+            elements: Vec::new(),
+            total_found: 0,
+            foreground_package: None,
+            needs_vision_fallback: true,
+            raw_count: 0,
+            interactive_count: 0,
+        },
+        screenshot_base64: Some(image_base64),
+        resolution: None,
+        formatted_text: "[Offline screenshot — no accessibility tree available. \
+                         Use the attached image to locate elements.]"
+            .to_string(),
+        used_vision: true,
+        screen_unchanged: false,
+        screenshot_scale: None,
+    }
+}
+
+// ── Resumable runs ──────────────────────────────────────────────────────────
+
+/// Where `hermitdroid run` keeps its progress, relative to the workspace
+const LAST_RUN_FILE: &str = "last_run.json";
+
+/// How much of the earlier attempt a resumed run repeats in each step prompt
+const RESUME_MAX_STEPS: usize = 20;
+const RESUME_MAX_SCREEN_CHARS: usize = 2000;
+
+/// Progress of the last `hermitdroid run`, saved after every step so
+/// `run --resume` can pick up an interrupted goal. Removed once the goal is done.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LastRun {
+    pub goal: String,
+    /// One line per executed action, e.g. `tap @(540,1200) open search`
+    #[serde(default)]
+    pub completed_steps: Vec<String>,
+    /// The model's latest reflection — its own account of what's left
+    #[serde(default)]
+    pub remaining: Option<String>,
+    /// The screen as last seen, in the prompt's element-list format
+    #[serde(default)]
+    pub last_screen: String,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+impl LastRun {
+    pub fn new(goal: &str) -> Self {
+        Self { goal: goal.to_string(), ..Default::default() }
+    }
+
+    fn path(workspace_path: &str) -> PathBuf {
+        Path::new(workspace_path).join(LAST_RUN_FILE)
+    }
+
+    pub fn load(workspace_path: &str) -> anyhow::Result<Option<Self>> {
+        let path = Self::path(workspace_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    fn save(&mut self, workspace_path: &str) -> anyhow::Result<()> {
+        self.updated_at = chrono::Utc::now().to_rfc3339();
+        std::fs::write(Self::path(workspace_path), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn clear(workspace_path: &str) {
+        std::fs::remove_file(Self::path(workspace_path)).ok();
+    }
+
+    /// Prompt section telling the model what the interrupted attempt already did
+    fn resume_context(&self) -> String {
+        let skipped = self.completed_steps.len().saturating_sub(RESUME_MAX_STEPS);
+        let mut out = String::from("This goal was interrupted earlier. Do not redo finished steps.\n");
+        if skipped > 0 {
+            out.push_str(&format!("({} earlier actions omitted)\n", skipped));
+        }
+        if self.completed_steps.is_empty() {
+            out.push_str("No actions were completed.\n");
+        }
+        for step in &self.completed_steps[skipped..] {
+            out.push_str(&format!("- {}\n", step));
+        }
+        if let Some(remaining) = self.remaining.as_deref().filter(|r| !r.is_empty()) {
+            out.push_str(&format!("Your last note: {}\n", remaining));
+        }
+        if !self.last_screen.is_empty() {
+            let screen: String = self.last_screen.chars().take(RESUME_MAX_SCREEN_CHARS).collect();
+            out.push_str(&format!("Screen when it stopped:\n{}\n", screen));
+        }
+        out
+    }
+}
+
 // ── Public entry point ──────────────────────────────────────────────────────
 
+/// What a `run_goal` call gets besides the config: command-line flags, and
+/// the restrictions a workflow puts on its steps
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub verbose: bool,
+    pub dry_run: bool,
+    pub prompt_dump: Option<PromptDump>,
+    /// A workflow's `allowed_actions` header
+    pub allowed_actions: Option<Vec<String>>,
+}
+
+/// `hermitdroid run`. Progress goes to `workspace/last_run.json` (except on dry
+/// runs); with `resume` the goal and history come from there instead (`goal`
+/// may then be empty).
 pub async fn run_oneshot(
     config: &Config,
     goal: &str,
     max_steps: u32,
-    verbose: bool,
-    dry_run: bool,
+    screenshot_path: Option<&str>,
+    resume: bool,
+    opts: &RunOptions,
 ) -> anyhow::Result<()> {
+    let journal = if resume {
+        let Some(last) = LastRun::load(&config.agent.workspace_path)? else {
+            anyhow::bail!("No interrupted run to resume ({} not found)", LAST_RUN_FILE);
+        };
+        if !goal.is_empty() && goal != last.goal {
+            anyhow::bail!(
+                "The last run was for \"{}\" — drop the goal text to resume it",
+                last.goal
+            );
+        }
+        last
+    } else {
+        LastRun::new(goal)
+    };
+    let goal = journal.goal.clone();
+    let completed = match run_goal(config, &goal, max_steps, screenshot_path, Some(journal), opts).await {
+        Err(e) if e.is::<PromptNotSent>() => {
+            println!("  {DIM}📝 {e}{RESET}\n");
+            return Ok(());
+        }
+        result => result?,
+    };
+    if !completed && !opts.dry_run && !config.action.dry_run {
+        println!("  {DIM}Continue where this left off with `hermitdroid run --resume`{RESET}\n");
+    }
+    Ok(())
+}
+
+/// Run the one-shot loop and report whether the model declared the goal done
+/// (`false` when it gave up or ran out of steps). With a `journal`, progress
+/// is saved to `last_run.json` after each step, and any steps it already
+/// holds are shown to the model as an earlier attempt. Dry runs read the
+/// journal but never write or clear it.
+pub async fn run_goal(
+    config: &Config,
+    goal: &str,
+    max_steps: u32,
+    screenshot_path: Option<&str>,
+    mut journal: Option<LastRun>,
+    opts: &RunOptions,
+) -> anyhow::Result<bool> {
     let max_steps = if max_steps == 0 { DEFAULT_MAX_STEPS } else { max_steps };
-    let dry_run = dry_run || config.action.dry_run;
+    let verbose = opts.verbose;
+    let dry_run = opts.dry_run || config.action.dry_run;
 
     // ── Initialize components (lightweight — no server, no sessions) ────
     let workspace = Workspace::new(&config.agent.workspace_path, config.agent.bootstrap_max_chars);
-    let brain = Brain::new(&config.brain);
+    let mut brain = Brain::new(&config.brain)
+        .with_safe_mode(config.action.safe_mode)
+        .with_prompt_dump(opts.prompt_dump.clone());
+    if verbose {
+        // Show the reply live; SSE backends (Codex) stream it token by token
+        brain = brain.with_stream_sink(|delta| {
+            use std::io::Write;
+            print!("{DIM}{delta}{RESET}");
+            let _ = std::io::stdout().flush();
+        });
+    }
 
     // Resolve ADB device (Tailscale handled at higher level if needed)
     let adb_device = config.perception.adb_device.clone();
-    let _perception = Perception::new(
+    let perception = Perception::new(
         adb_device.clone(),
         config.perception.priority_apps.clone(),
-    );
+    )
+    .with_app_calibrations(crate::perception::load_app_calibrations(&config.agent.workspace_path))
+    .with_app_aliases(crate::perception::load_app_aliases(&config.agent.workspace_path))
+    .with_ignore_packages(config.perception.ignore_packages.clone())
+    .with_max_elements(config.perception.max_elements)
+    .with_notification_dedup_secs(config.perception.notification_dedup_secs)
+    .with_apps_refresh_secs(config.perception.apps_refresh_secs)
+    .with_screenshot_encoding(config.perception.screenshot_encoding());
     let executor = ActionExecutor::new(
         dry_run,
         adb_device,
         config.action.restricted_apps.clone(),
-    );
+        Some(perception.clone()),
+    )
+    .with_min_confidence(config.action.min_confidence)
+    .with_policy(config.action.policy)
+    .with_min_interval_ms(config.action.min_interval_ms)
+    .with_verify_taps(config.action.verify_taps)
+    .with_text_input_method(crate::action::TextInputMethod::from_str(&config.action.text_input_method))
+    .with_calibration(CoordinateCalibration::from_config(&config.action))
+    .with_app_resolver(perception.app_resolver())
+    .with_device_notifications(
+        config.action.notify_on_device.then(|| config.agent.name.clone()),
+    )
+    .with_log_file(Path::new(&config.agent.workspace_path).join(ACTION_LOG_FILE))
+    .with_blocked_apps(config.action.blocked_apps.clone())
+    .with_safe_mode(config.action.safe_mode);
 
     // ── Print header ────────────────────────────────────────────────────
     println!("\n{CYAN}{BOLD}🤖 Hermitdroid — One-Shot Mode{RESET}\n");
@@ -77,6 +298,9 @@ pub async fn run_oneshot(
     if dry_run {
         println!("  {YELLOW}⚠  DRY RUN — actions logged but not executed{RESET}");
     }
+    if config.action.safe_mode {
+        println!("  {YELLOW}🛡  SAFE MODE — only read-only actions run{RESET}");
+    }
     println!(
         "  {DIM}Max steps: {} | Vision: {}{RESET}\n",
         max_steps,
@@ -86,7 +310,11 @@ pub async fn run_oneshot(
     // ── Assemble system prompt with workspace context ───────────────────
     // The one-shot system prompt includes SOUL/TOOLS/AGENTS context but
     // frames the task as a single goal to complete, not an ongoing daemon.
-    let workspace_ctx = workspace.assemble_bootstrap();
+    let mut workspace_ctx = workspace.assemble_bootstrap();
+    if config.perception.apps_in_prompt {
+        let p = perception.clone();
+        workspace_ctx.installed_apps = tokio::task::spawn_blocking(move || p.format_installed_apps()).await.unwrap_or_default();
+    }
     let system_prompt = build_oneshot_system_prompt(&brain, &workspace_ctx, goal);
 
     // ── State tracking ──────────────────────────────────────────────────
@@ -94,6 +322,27 @@ pub async fn run_oneshot(
     let mut stuck = StuckDetector::new(config.stuck.clone());
     let mut total_actions: u32 = 0;
     let mut user_prompt_suffix: Option<String> = None;
+    // Told to the model once, on the step after an action was refused
+    let mut refusal: Option<String> = None;
+    let mut completed = false;
+    let resume_context = journal
+        .as_ref()
+        .filter(|j| !j.completed_steps.is_empty() || !j.last_screen.is_empty())
+        .map(LastRun::resume_context);
+    if let Some(j) = journal.as_ref().filter(|_| resume_context.is_some()) {
+        println!("  {CYAN}↻ Resuming after {} completed action(s){RESET}\n", j.completed_steps.len());
+    }
+
+    // Offline screenshot replaces the live screen for the first step
+    let mut offline_screenshot = match screenshot_path {
+        Some(path) => {
+            if !config.brain.vision_enabled {
+                println!("  {YELLOW}⚠  brain.vision_enabled is off — the model may ignore the image{RESET}");
+            }
+            Some(load_screenshot(path)?)
+        }
+        None => None,
+    };
 
     // ── Main loop ───────────────────────────────────────────────────────
     for step in 1..=max_steps {
@@ -103,14 +352,29 @@ pub async fn run_oneshot(
         } else {
             crate::sanitizer::VisionMode::Off
         };
-        let perception_result = Some(sanitizer::perceive_screen(
-            &config.perception.adb_device,
-            vision_mode,
-            config.perception.max_elements,
-        ).await);
+        let perception_result = match offline_screenshot.take() {
+            Some(image) => offline_perception(image),
+            None => {
+                perception.next_tick();
+                let mut result = sanitizer::perceive_screen(
+                    &config.perception.adb_device,
+                    perception.ui_tree_dump().await,
+                    vision_mode,
+                    config.perception.max_elements,
+                    &config.perception.ignore_packages,
+                    config.perception.screenshot_encoding(),
+                    // Each step's screenshot is the model's only view
+                    // of what its last action did: never skip one
+                    None,
+                ).await;
+                perception.calibrate_result(&mut result);
+                result
+            }
+        };
+        let perception_result = Some(perception_result);
         let screen_text = perception_result
             .as_ref()
-            .map(|s| s.formatted_text.clone())
+            .map(|s| s.prompt_text())
             .unwrap_or_else(|| "[No screen data available]".to_string());
 
         // For verbose output, show perception mode:
@@ -126,7 +390,7 @@ pub async fn run_oneshot(
         }
 
         // 2. Stuck detection (replaces old hash comparison)
-        let screen_hash = simple_hash(&screen_text);
+        let screen_hash = simple_hash(perception_result.as_ref().map_or("", |s| s.formatted_text.as_str()));
         match stuck.check_screen(screen_hash) {
             StuckStatus::Ok => {}
             StuckStatus::Hint(hint) => {
@@ -146,6 +410,11 @@ pub async fn run_oneshot(
                         let _ = executor.execute_raw("back", &config.perception.adb_device).await;
                         tokio::time::sleep(Duration::from_millis(800)).await;
                     }
+                    RecoveryAction::EscapeToHome => {
+                        if let Ok(backs) = executor.escape_to_home(DEFAULT_ESCAPE_MAX_BACKS).await {
+                            println!("  {DIM}🏠 Home after {backs} back(s){RESET}");
+                        }
+                    }
                     RecoveryAction::HomeAndRelaunch { .. } => {
                         let _ = executor.execute_raw("home", &config.perception.adb_device).await;
                         tokio::time::sleep(Duration::from_millis(1000)).await;
@@ -166,9 +435,10 @@ pub async fn run_oneshot(
         }
 
         // 3. Build step prompt
-        let now = chrono::Utc::now().format("%H:%M:%S UTC").to_string();
+        let now = config.agent.local_now("%H:%M:%S");
+        let prompt_screen = brain.with_screen_summary(&screen_text).await;
         let user_prompt = build_oneshot_step_prompt(
-            &screen_text, goal, step, max_steps, &now,
+            &prompt_screen, goal, step, max_steps, &now, resume_context.as_deref(),
         );
 
         // 4. Call LLM
@@ -176,14 +446,25 @@ pub async fn run_oneshot(
             .as_ref()
             .and_then(|s| s.screenshot_base64.as_deref());
 
-        let final_user_prompt = if let Some(ref suffix) = user_prompt_suffix {
+        let mut final_user_prompt = if let Some(ref suffix) = user_prompt_suffix {
             format!("{}\n{}", user_prompt, suffix)
         } else {
             user_prompt.clone()
         };
+        if let Some(note) = refusal.take() {
+            final_user_prompt = format!("{}\n{}", final_user_prompt, note);
+        }
 
-        let raw = match brain.think(&system_prompt, &final_user_prompt, screenshot).await {
+        if verbose {
+            print!("  {DIM}[{step}/{max_steps}] ✎ {RESET}");
+        }
+        let raw = brain.think_actions(&system_prompt, &final_user_prompt, screenshot, None, None).await;
+        if verbose {
+            println!();
+        }
+        let raw = match raw {
             Ok(r) => r,
+            Err(e) if e.is::<PromptNotSent>() => return Err(e),
             Err(e) => {
                 println!("  {RED}[{step}/{max_steps}] ❌ LLM error: {e}{RESET}");
                 error!("LLM error at step {}: {}", step, e);
@@ -211,9 +492,20 @@ pub async fn run_oneshot(
             workspace
                 .append_daily_memory(&format!("[run] Goal completed: {}", goal))
                 .ok();
+            if journal.is_some() && !dry_run {
+                LastRun::clear(&config.agent.workspace_path);
+            }
+            completed = true;
             break;
         }
 
+        if let Some(j) = journal.as_mut() {
+            j.last_screen = screen_text.clone();
+            if let Some(r) = response.reflection.as_ref().filter(|r| r.as_str() != "HEARTBEAT_OK") {
+                j.remaining = Some(r.clone());
+            }
+        }
+
         // 6. Check HEARTBEAT_OK (nothing to do)
         if response.reflection.as_deref() == Some("HEARTBEAT_OK") {
             if verbose {
@@ -245,14 +537,55 @@ pub async fn run_oneshot(
             continue;
         }
 
+        // Show multi-action plans up front; RED ones wait so Ctrl+C can abort
+        let planned: Vec<&AgentAction> = response.actions.iter().filter(|a| a.action_type != "done").collect();
+        let red: Vec<bool> = planned.iter().map(|a| executor.effective_classification(a) == "RED").collect();
+        let has_red = red.contains(&true);
+        if planned.len() > 1 || has_red {
+            println!("  {DIM}[{step}/{max_steps}] Plan:{RESET}");
+            for (i, action) in planned.iter().enumerate() {
+                let mark = if red[i] { format!("{RED}🔴{RESET}") } else { " ".to_string() };
+                println!("  {DIM}  {}. {}{RESET} {}", i + 1, format_action_desc(action), mark);
+            }
+        }
+        let delay = config.action.plan_preview_delay_ms;
+        if has_red && delay > 0 && !dry_run {
+            println!("  {YELLOW}⏸  RED action planned — executing in {:.1}s (Ctrl+C to abort){RESET}", delay as f64 / 1000.0);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+
         for action in &response.actions {
             // Skip "done" actions (already handled above)
             if action.action_type == "done" {
                 continue;
             }
+            if let Err(e) = action.validate() {
+                println!("  {YELLOW}⚠  Skipping invalid action: {}{RESET}", e);
+                continue;
+            }
+            if let Err(e) = check_allowed(opts.allowed_actions.as_deref(), &action.action_type) {
+                println!("  {RED}[{step}/{max_steps}] ✖ {e} — aborting{RESET}");
+                return Err(e.into());
+            }
 
             let action_start = Instant::now();
             match executor.execute(action).await {
+                // `action.policy` (or low confidence) wants a confirmation,
+                // but nobody can give one here: refuse it and let the model re-plan
+                Ok(result) if result.starts_with(PENDING_PREFIX) => {
+                    let id = &result[PENDING_PREFIX.len()..];
+                    let _ = executor.confirm(id, false).await;
+                    println!(
+                        "  {YELLOW}[{step}/{max_steps}] ⏸  {} needs confirmation, which `run` can't ask for — refused{RESET}",
+                        format_action_desc(action)
+                    );
+                    refusal = Some(format!(
+                        "Your action `{}` was refused: it needs the user's confirmation, which this run can't get. \
+                         Reach the goal another way, or finish with done and explain what the user must do.",
+                        action.action_type
+                    ));
+                    break;
+                }
                 Ok(result) => {
                     let ms = action_start.elapsed().as_millis();
                     total_actions += 1;
@@ -275,11 +608,17 @@ pub async fn run_oneshot(
                     println!(
                         "  {prefix}{class_icon} ▸ {action_desc} {DIM}({ms}ms){RESET}"
                     );
+                    if dry_run {
+                        println!("         {DIM}{result}{RESET}");
+                    }
 
                     info!(
                         "Step {}: {} ({}) → {} [{}ms]",
-                        step, action.action_type, action.reason, result, ms
+                        step, action.action_type, action.reason, crate::action::summarize_result(&result), ms
                     );
+                    if let Some(j) = journal.as_mut() {
+                        j.completed_steps.push(action_desc);
+                    }
                 }
                 Err(e) => {
                     let ms = action_start.elapsed().as_millis();
@@ -325,6 +664,12 @@ pub async fn run_oneshot(
                 StuckStatus::Ok => {}
             }
         }
+
+        if let Some(j) = journal.as_mut().filter(|_| !dry_run) {
+            if let Err(e) = j.save(&config.agent.workspace_path) {
+                error!("Could not save {}: {}", LAST_RUN_FILE, e);
+            }
+        }
     }
 
     // ── Summary ─────────────────────────────────────────────────────────
@@ -342,7 +687,7 @@ pub async fn run_oneshot(
         ))
         .ok();
 
-    Ok(())
+    Ok(completed)
 }
 
 // ── Prompt builders ─────────────────────────────────────────────────────────
@@ -381,7 +726,11 @@ fn build_oneshot_step_prompt(
     step: u32,
     max_steps: u32,
     time: &str,
+    resume_context: Option<&str>,
 ) -> String {
+    let resumed = resume_context
+        .map(|ctx| format!("\n=== EARLIER ATTEMPT ===\n{ctx}"))
+        .unwrap_or_default();
     let urgency = if step > max_steps * 3 / 4 {
         "\n⚠️ Running low on steps! Prioritize completing the goal quickly."
     } else {
@@ -392,7 +741,7 @@ fn build_oneshot_step_prompt(
         r#"Step {step}/{max_steps} | {time}
 Goal: "{goal}"
 {urgency}
-
+{resumed}
 === CURRENT SCREEN ===
 {screen_text}
 
@@ -458,11 +807,14 @@ fn format_action_desc(action: &crate::brain::AgentAction) -> String {
         "swipe" => format!("swipe {}", truncate(&action.reason, 50)),
         "back" => "back".to_string(),
         "home" => "home".to_string(),
-        "long_press" => {
-            if let (Some(x), Some(y)) = (action.x, action.y) {
-                format!("long_press @({},{}) {}", x, y, truncate(&action.reason, 40))
+        "long_press" | "open_context_menu" => {
+            let kind = action.action_type.as_str();
+            if let Some(index) = action.params["index"].as_u64() {
+                format!("{} [{}] {}", kind, index, truncate(&action.reason, 40))
+            } else if let (Some(x), Some(y)) = (action.x, action.y) {
+                format!("{} @({},{}) {}", kind, x, y, truncate(&action.reason, 40))
             } else {
-                format!("long_press {}", truncate(&action.reason, 50))
+                format!("{} {}", kind, truncate(&action.reason, 50))
             }
         }
         "key" => {
@@ -488,4 +840,38 @@ fn truncate(s: &str, max: usize) -> String {
 /// Build ADB device args for raw commands
 fn adb_device_args(config: &Config) -> Option<String> {
     config.perception.adb_device.clone()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_run_roundtrip_and_context() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let ws = root.to_str().unwrap();
+        assert!(LastRun::load(ws).unwrap().is_none());
+
+        let mut run = LastRun::new("send hi to Mom on whatsapp");
+        run.completed_steps = vec!["launch whatsapp".into(), "tap @(540,300) open Mom's chat".into()];
+        run.remaining = Some("Type the message and send it".into());
+        run.last_screen = "[1] EditText \"Message\" @(500,2200)".into();
+        run.save(ws).unwrap();
+
+        let loaded = LastRun::load(ws).unwrap().unwrap();
+        assert_eq!(loaded.goal, run.goal);
+        let ctx = loaded.resume_context();
+        assert!(ctx.contains("- launch whatsapp\n- tap @(540,300)"), "{}", ctx);
+        assert!(ctx.contains("Your last note: Type the message"));
+        assert!(ctx.contains("EditText \"Message\""));
+
+        let prompt = build_oneshot_step_prompt("screen", &run.goal, 1, 30, "10:00 UTC", Some(&ctx));
+        assert!(prompt.contains("=== EARLIER ATTEMPT ==="));
+        assert!(!build_oneshot_step_prompt("screen", &run.goal, 1, 30, "10:00 UTC", None).contains("EARLIER"));
+
+        LastRun::clear(ws);
+        assert!(LastRun::load(ws).unwrap().is_none());
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
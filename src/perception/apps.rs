@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Bundled defaults; `workspace/app_aliases.toml` entries override these.
+const DEFAULT_ALIASES: &str = include_str!("../../workspace.default/app_aliases.toml");
+
+/// How long the installed-package list is trusted before `pm` is asked again
+pub const DEFAULT_APPS_REFRESH_SECS: u64 = 600;
+
+/// A name that didn't resolve isn't looked up again for this long, so one
+/// `launch_app` (guardrail checks, preview, log, launch) lists packages once
+const MISS_TTL: Duration = Duration::from_secs(30);
+
+/// Package segments that say nothing about the app ("com.google.android.…")
+const GENERIC_SEGMENTS: &[&str] = &["android", "google", "apps", "app", "mobile"];
+
+/// Resolves friendly app names ("youtube") to package names.
+///
+/// Order: exact installed package → alias map → installed package whose
+/// name segments match. The installed list comes from `pm list packages -f`
+/// and is cached for `refresh_interval`; a miss refreshes it once (the app
+/// may be newly installed) and is then remembered for `MISS_TTL`.
+#[derive(Debug, Clone)]
+pub struct AppResolver {
+    adb_device: Option<String>,
+    aliases: Arc<HashMap<String, String>>,
+    packages: Arc<Mutex<Option<PackageCache>>>,
+    /// Names that recently failed to resolve, with when
+    misses: Arc<Mutex<HashMap<String, Instant>>>,
+    refresh_interval: Duration,
+}
+
+#[derive(Debug)]
+struct PackageCache {
+    fetched: Instant,
+    packages: Vec<String>,
+    /// Packages with a launcher icon, fetched on first `installed_apps`
+    launchable: Option<Vec<String>>,
+}
+
+impl AppResolver {
+    pub fn new(adb_device: Option<String>, aliases: HashMap<String, String>) -> Self {
+        Self {
+            adb_device,
+            aliases: Arc::new(aliases),
+            packages: Arc::new(Mutex::new(None)),
+            misses: Arc::new(Mutex::new(HashMap::new())),
+            refresh_interval: Duration::from_secs(DEFAULT_APPS_REFRESH_SECS),
+        }
+    }
+
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+
+    /// Launchable apps as (package, label), sorted by label. `pm` doesn't
+    /// expose real app labels, so the label is an alias name or the
+    /// package's most telling segment ("com.spotify.music" → "Spotify").
+    pub fn installed_apps(&self) -> Vec<(String, String)> {
+        let mut cache = match self.packages.lock() {
+            Ok(c) => c,
+            Err(_) => return Vec::new(),
+        };
+        let cache = self.fresh_cache(&mut cache, false);
+        let launchable = cache.launchable.get_or_insert_with(|| self.list_launchable());
+        let mut apps: Vec<(String, String)> = cache
+            .packages
+            .iter()
+            // Without a launcher list (old Android), keep everything
+            .filter(|p| launchable.is_empty() || launchable.contains(p))
+            .map(|p| (p.clone(), app_label(p, &self.aliases)))
+            .collect();
+        apps.sort_by(|a, b| a.1.to_lowercase().cmp(&b.1.to_lowercase()).then_with(|| a.0.cmp(&b.0)));
+        apps
+    }
+
+    pub fn resolve_package(&self, name: &str) -> Option<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        if self.recent_miss(name) {
+            debug!("App '{}' didn't resolve a moment ago, not listing packages again", name);
+            return None;
+        }
+        let looks_like_package = name.contains('.') && !name.contains(' ');
+        // A wrong package guess ("com.youtube") still carries the app name
+        let key = if looks_like_package {
+            normalize(name.rsplit('.').next().unwrap_or(name))
+        } else {
+            normalize(name)
+        };
+
+        for refresh in [false, true] {
+            let packages = self.installed_packages(refresh);
+            if looks_like_package && (packages.is_empty() || packages.iter().any(|p| p == name)) {
+                return Some(name.to_string());
+            }
+            if let Some(pkg) = self.aliases.get(&key) {
+                return Some(pkg.clone());
+            }
+            if let Some(pkg) = match_installed(&packages, &key) {
+                debug!("Resolved app '{}' → {}", name, pkg);
+                return Some(pkg);
+            }
+        }
+        warn!("Could not resolve app '{}' to a package", name);
+        if let Ok(mut misses) = self.misses.lock() {
+            misses.retain(|_, at| at.elapsed() < MISS_TTL);
+            misses.insert(name.to_string(), Instant::now());
+        }
+        None
+    }
+
+    fn recent_miss(&self, name: &str) -> bool {
+        self.misses
+            .lock()
+            .is_ok_and(|misses| misses.get(name).is_some_and(|at| at.elapsed() < MISS_TTL))
+    }
+
+    fn installed_packages(&self, refresh: bool) -> Vec<String> {
+        let mut cache = match self.packages.lock() {
+            Ok(c) => c,
+            Err(_) => return Vec::new(),
+        };
+        self.fresh_cache(&mut cache, refresh).packages.clone()
+    }
+
+    /// The cache, re-listed if asked to or once it is older than `refresh_interval`
+    fn fresh_cache<'a>(&self, cache: &'a mut Option<PackageCache>, refresh: bool) -> &'a mut PackageCache {
+        if refresh || cache.as_ref().is_some_and(|c| c.fetched.elapsed() >= self.refresh_interval) {
+            *cache = None;
+        }
+        cache.get_or_insert_with(|| PackageCache {
+            fetched: Instant::now(),
+            packages: self.list_packages(),
+            launchable: None,
+        })
+    }
+
+    fn list_packages(&self) -> Vec<String> {
+        match self.adb_shell(&["pm", "list", "packages", "-f"]) {
+            Some(raw) => parse_package_list(&raw),
+            None => {
+                debug!("pm list packages failed");
+                Vec::new()
+            }
+        }
+    }
+
+    fn list_launchable(&self) -> Vec<String> {
+        let query = [
+            "cmd", "package", "query-activities", "--brief",
+            "-a", "android.intent.action.MAIN", "-c", "android.intent.category.LAUNCHER",
+        ];
+        match self.adb_shell(&query) {
+            Some(raw) => parse_launcher_activities(&raw),
+            None => {
+                debug!("cmd package query-activities failed");
+                Vec::new()
+            }
+        }
+    }
+
+    fn adb_shell(&self, args: &[&str]) -> Option<String> {
+        let mut cmd = Command::new("adb");
+        if let Some(dev) = &self.adb_device {
+            cmd.args(["-s", dev]);
+        }
+        cmd.arg("shell").args(args);
+        match crate::adb::output(&mut cmd) {
+            Ok(out) if out.status.success() => Some(String::from_utf8_lossy(&out.stdout).into_owned()),
+            _ => None,
+        }
+    }
+}
+
+pub fn bundled_aliases() -> HashMap<String, String> {
+    parse_aliases(DEFAULT_ALIASES)
+}
+
+/// Bundled aliases merged with `workspace/app_aliases.toml`
+pub fn load_app_aliases(workspace_path: &str) -> HashMap<String, String> {
+    let mut aliases = bundled_aliases();
+    let path = Path::new(workspace_path).join("app_aliases.toml");
+    if let Ok(content) = std::fs::read_to_string(&path) {
+        let custom = parse_aliases(&content);
+        info!("📱 Loaded {} app alias(es) from {}", custom.len(), path.display());
+        aliases.extend(custom);
+    }
+    aliases
+}
+
+fn parse_aliases(content: &str) -> HashMap<String, String> {
+    match toml::from_str::<HashMap<String, String>>(content) {
+        Ok(map) => map.into_iter().map(|(k, v)| (normalize(&k), v)).collect(),
+        Err(e) => {
+            warn!("⚠️  Ignoring malformed app alias file: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// "package:/data/app/.../base.apk=com.foo.bar" → "com.foo.bar"
+fn parse_package_list(raw: &str) -> Vec<String> {
+    raw.lines()
+        .filter_map(|l| l.trim().strip_prefix("package:"))
+        .map(|l| l.rsplit('=').next().unwrap_or(l).to_string())
+        .collect()
+}
+
+/// `query-activities --brief` lists one "com.foo/.MainActivity" per match,
+/// after a "priority=… preferredOrder=…" line
+fn parse_launcher_activities(raw: &str) -> Vec<String> {
+    let mut packages: Vec<String> = Vec::new();
+    for line in raw.lines().map(str::trim) {
+        let Some((pkg, _activity)) = line.split_once('/') else { continue };
+        if !line.contains(' ') && !line.contains('=') && !packages.iter().any(|p| p == pkg) {
+            packages.push(pkg.to_string());
+        }
+    }
+    packages
+}
+
+/// A readable name for a package: an alias pointing at it (one that is also
+/// a package segment first, then the longest), else its first non-generic
+/// segment after the TLD
+fn app_label(package: &str, aliases: &HashMap<String, String>) -> String {
+    let segments: Vec<&str> = package.split('.').collect();
+    let alias = aliases
+        .iter()
+        .filter(|(_, pkg)| pkg.as_str() == package)
+        .map(|(name, _)| name.as_str())
+        .max_by_key(|name| (segments.contains(name), name.len(), std::cmp::Reverse(*name)));
+    let name = alias.unwrap_or_else(|| {
+        package
+            .split('.')
+            .skip(1)
+            .find(|s| !GENERIC_SEGMENTS.contains(s))
+            .or_else(|| package.rsplit('.').next())
+            .unwrap_or(package)
+    });
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Best installed package for a normalized name: a segment equal to the
+/// name wins over a segment that merely contains it; shorter packages first.
+fn match_installed(packages: &[String], key: &str) -> Option<String> {
+    let segments = |p: &str| p.split('.').map(str::to_lowercase).collect::<Vec<_>>();
+    let mut best: Option<(u8, &String)> = None;
+    for pkg in packages {
+        let segs = segments(pkg);
+        let rank = if segs.iter().any(|s| s == key) {
+            0
+        } else if key.len() >= 4 && segs.iter().skip(1).any(|s| s.contains(key)) {
+            1
+        } else {
+            continue;
+        };
+        let better = match best {
+            None => true,
+            Some((r, b)) => rank < r || (rank == r && pkg.len() < b.len()),
+        };
+        if better {
+            best = Some((rank, pkg));
+        }
+    }
+    best.map(|(_, p)| p.clone())
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_from_installed_and_aliases() {
+        let packages = parse_package_list(
+            "package:/data/app/base.apk=com.google.android.youtube\n\
+             package:/system/app/Settings.apk=com.android.settings\n\
+             package:/data/app/x/base.apk=com.spotify.music\n",
+        );
+        assert_eq!(packages.len(), 3);
+        assert_eq!(match_installed(&packages, "spotify").as_deref(), Some("com.spotify.music"));
+        assert_eq!(match_installed(&packages, "settings").as_deref(), Some("com.android.settings"));
+        assert_eq!(match_installed(&packages, "nothing"), None);
+
+        let aliases = bundled_aliases();
+        assert_eq!(aliases["youtube"], "com.google.android.youtube");
+        assert_eq!(aliases[&normalize("Play Store")], "com.android.vending");
+    }
+
+    #[test]
+    fn test_miss_is_remembered() {
+        let resolver = AppResolver::new(Some("hermitdroid-test-missing".into()), HashMap::new());
+        assert_eq!(resolver.resolve_package("no such app"), None);
+        assert!(resolver.recent_miss("no such app"));
+        assert_eq!(resolver.resolve_package(" no such app "), None);
+        assert!(!resolver.recent_miss("youtube"));
+    }
+
+    #[test]
+    fn test_launchable_apps_and_labels() {
+        let launchable = parse_launcher_activities(
+            "3 activities found:\n\
+             priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=false\n\
+             \x20 com.spotify.music/.MainActivity\n\
+             priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=false\n\
+             \x20 com.google.android.apps.maps/com.google.android.maps.MapsActivity\n\
+             \x20 com.spotify.music/.AliasActivity\n",
+        );
+        assert_eq!(launchable, vec!["com.spotify.music", "com.google.android.apps.maps"]);
+
+        let aliases = bundled_aliases();
+        assert_eq!(app_label("com.spotify.music", &aliases), "Spotify");
+        assert_eq!(app_label("com.google.android.apps.maps", &aliases), "Maps");
+        assert_eq!(app_label("com.google.android.gm", &aliases), "Gmail");
+        assert_eq!(app_label("com.google.android.apps.messaging", &aliases), "Messages");
+        assert_eq!(app_label("org.telegram.messenger", &HashMap::new()), "Telegram");
+        assert_eq!(app_label("com.android.settings", &HashMap::new()), "Settings");
+    }
+}
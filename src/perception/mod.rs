@@ -1,9 +1,18 @@
+mod apps;
+mod provider;
+
+pub use apps::{load_app_aliases, AppResolver, DEFAULT_APPS_REFRESH_SECS};
+pub use provider::{for_bridge, PerceptionProvider};
+use crate::adb::{self, AdbError};
+use crate::sanitizer::{encode_screenshot, ScreenshotEncoding, StaticScreenTracker};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 // ================================================================
 // Data types
@@ -16,6 +25,9 @@ pub struct Notification {
     pub title: String,
     pub text: String,
     pub timestamp: String,
+    /// Action buttons the notification offers ("Reply", "Mark as read"), if any
+    #[serde(default)]
+    pub actions: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +82,308 @@ pub struct UiElement {
     pub score: f32,
 }
 
+impl UiElement {
+    /// Visible text, or the content description when there is none
+    pub fn label(&self) -> &str {
+        if self.text.is_empty() { &self.desc } else { &self.text }
+    }
+}
+
+/// What changed between two screen states. Elements are matched by
+/// resource id and its occurrence on screen (the 2nd `item_title` pairs
+/// with the 2nd `item_title`); elements without an id by class and label.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScreenDiff {
+    /// `(before, after)` when the foreground app or activity changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_changed: Option<(String, String)>,
+    pub added: Vec<UiElement>,
+    pub removed: Vec<UiElement>,
+    /// `(before, after)` for matched elements whose label or state differs
+    pub changed: Vec<(UiElement, UiElement)>,
+}
+
+/// `ScreenDiff::summary` lists at most this many elements per kind
+const DIFF_SUMMARY_MAX: usize = 5;
+
+impl ScreenDiff {
+    pub fn between(before: &ScreenState, after: &ScreenState) -> Self {
+        let app_changed = (before.current_app != after.current_app || before.activity != after.activity)
+            .then(|| (
+                format!("{}/{}", before.current_app, before.activity),
+                format!("{}/{}", after.current_app, after.activity),
+            ));
+        let old = diff_keys(&before.elements);
+        let new = diff_keys(&after.elements);
+        let old_map: HashMap<&str, &UiElement> = old.iter().map(|(k, e)| (k.as_str(), *e)).collect();
+        let new_map: HashMap<&str, &UiElement> = new.iter().map(|(k, e)| (k.as_str(), *e)).collect();
+
+        let mut diff = ScreenDiff { app_changed, ..Default::default() };
+        for (key, e) in &new {
+            match old_map.get(key.as_str()) {
+                None => diff.added.push((*e).clone()),
+                Some(prev) if element_changed(prev, e) => diff.changed.push(((*prev).clone(), (*e).clone())),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .iter()
+            .filter(|(key, _)| !new_map.contains_key(key.as_str()))
+            .map(|(_, e)| (*e).clone())
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.app_changed.is_none() && self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Short text for the prompt, e.g. `+2 added: Button "OK", TextView "Delete?"`
+    pub fn summary(&self) -> String {
+        let list = |elements: &mut dyn Iterator<Item = &UiElement>| {
+            elements
+                .take(DIFF_SUMMARY_MAX)
+                .map(|e| format!("{} \"{}\"", e.class, e.label()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut lines = Vec::new();
+        if let Some((from, to)) = &self.app_changed {
+            lines.push(format!("app: {} → {}", from, to));
+        }
+        if !self.added.is_empty() {
+            lines.push(format!("+{} added: {}", self.added.len(), list(&mut self.added.iter())));
+        }
+        if !self.removed.is_empty() {
+            lines.push(format!("-{} removed: {}", self.removed.len(), list(&mut self.removed.iter())));
+        }
+        if !self.changed.is_empty() {
+            let changes: Vec<String> = self.changed
+                .iter()
+                .take(DIFF_SUMMARY_MAX)
+                .map(|(a, b)| format!("{} \"{}\" → \"{}\"", b.class, a.label(), b.label()))
+                .collect();
+            lines.push(format!("~{} changed: {}", self.changed.len(), changes.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+/// `resource_id#n` (or `class|label#n`) for each element, n counting repeats
+fn diff_keys(elements: &[UiElement]) -> Vec<(String, &UiElement)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    elements
+        .iter()
+        .map(|e| {
+            let base = if e.resource_id.is_empty() {
+                format!("{}|{}", e.class, e.label())
+            } else {
+                e.resource_id.clone()
+            };
+            let n = seen.entry(base.clone()).or_default();
+            *n += 1;
+            (format!("{}#{}", base, n), e)
+        })
+        .collect()
+}
+
+fn element_changed(a: &UiElement, b: &UiElement) -> bool {
+    a.text != b.text
+        || a.desc != b.desc
+        || a.checked != b.checked
+        || a.enabled != b.enabled
+        || a.focused != b.focused
+        || a.bounds != b.bounds
+}
+
+/// Criteria for `wait_for_text` / `wait_for_element`. Every field that is
+/// set must match; text comparisons are case-insensitive substrings.
+#[derive(Debug, Clone, Default)]
+pub struct ElementQuery {
+    /// Matches visible text or the content description
+    pub text: Option<String>,
+    pub desc: Option<String>,
+    /// Short resource id ("search_bar"), exact
+    pub resource_id: Option<String>,
+    /// Short class name ("EditText"), exact
+    pub class: Option<String>,
+}
+
+impl ElementQuery {
+    pub fn text(text: &str) -> Self {
+        Self { text: Some(text.to_string()), ..Default::default() }
+    }
+
+    /// `{"text": ..., "desc": ..., "resource_id": ..., "class": ...}`
+    pub fn from_params(p: &serde_json::Value) -> Self {
+        let field = |k: &str| p.get(k).and_then(|v| v.as_str()).map(str::to_string);
+        Self {
+            text: field("text"),
+            desc: field("desc").or_else(|| field("content_desc")),
+            resource_id: field("resource_id").or_else(|| field("id")),
+            class: field("class"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_none() && self.desc.is_none() && self.resource_id.is_none() && self.class.is_none()
+    }
+
+    pub fn matches(&self, el: &UiElement) -> bool {
+        let contains = |hay: &str, needle: &str| hay.to_lowercase().contains(&needle.to_lowercase());
+        self.text.as_deref().is_none_or(|t| contains(&el.text, t) || contains(&el.desc, t))
+            && self.desc.as_deref().is_none_or(|d| contains(&el.desc, d))
+            && self.resource_id.as_deref().is_none_or(|id| el.resource_id == id)
+            && self.class.as_deref().is_none_or(|c| el.class == c)
+    }
+}
+
+impl std::fmt::Display for ElementQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = [
+            ("text", &self.text),
+            ("desc", &self.desc),
+            ("id", &self.resource_id),
+            ("class", &self.class),
+        ]
+        .iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| format!("{}=\"{}\"", k, v)))
+        .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Highest-scoring element for `find_element`, if any field matched at all
+fn best_match(elements: Vec<UiElement>, query: &str, want_editable: bool) -> Option<UiElement> {
+    elements
+        .into_iter()
+        .map(|el| (find_score(&el, query, want_editable), el))
+        .filter(|(score, _)| *score > 0.0)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, el)| el)
+}
+
+/// How well `el` matches a free-text `query`: exact > substring > shared words,
+/// over text, desc and resource id ("search_bar" reads as "search bar",
+/// "wifi" matches "Wi-Fi").
+/// Zero means no match.
+fn find_score(el: &UiElement, query: &str, want_editable: bool) -> f32 {
+    let normalize = |s: &str| -> String {
+        s.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let q = normalize(query);
+    if q.is_empty() {
+        return 0.0;
+    }
+    let q_words: Vec<&str> = q.split(' ').collect();
+    let field_score = |field: &str| -> f32 {
+        let f = normalize(field);
+        if f.is_empty() {
+            0.0
+        } else if f == q {
+            10.0
+        } else if f.contains(&q) || f.replace(' ', "").contains(&q.replace(' ', "")) {
+            6.0
+        } else {
+            let shared = q_words.iter().filter(|w| f.split(' ').any(|fw| fw.starts_with(*w))).count();
+            4.0 * shared as f32 / q_words.len() as f32
+        }
+    };
+    let best = field_score(&el.text)
+        .max(field_score(&el.desc) * 0.9)
+        .max(field_score(&el.resource_id) * 0.8);
+    if best == 0.0 {
+        return 0.0;
+    }
+    let mut score = best;
+    if want_editable && el.editable {
+        score += 5.0;
+    }
+    if el.clickable || el.editable {
+        score += 0.5;
+    }
+    score
+}
+
+/// One dump taken by `capture_scrolling`, `offset` px below the first
+struct ScrollPage {
+    offset: i32,
+    elements: Vec<UiElement>,
+}
+
+/// Pause after each scroll swipe before re-dumping
+const SCROLL_SETTLE_MS: u64 = 600;
+
+/// Identity of an element across scroll pages. Unlabelled elements (layout
+/// containers) have none and are only kept from the first page.
+fn scroll_key(el: &UiElement) -> Option<String> {
+    if el.text.is_empty() && el.desc.is_empty() {
+        return None;
+    }
+    Some(format!("{}|{}|{}", el.resource_id, el.text, el.desc))
+}
+
+fn page_signature(elements: &[UiElement]) -> Vec<Option<String>> {
+    elements.iter().map(scroll_key).collect()
+}
+
+/// Hash of what a UI dump shows: equal hashes mean the screen didn't react
+pub fn screen_signature(elements: &[UiElement]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for el in elements {
+        (&el.class, &el.text, &el.desc, &el.resource_id, el.bounds).hash(&mut hasher);
+        (el.checked, el.focused, el.enabled).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// How far content moved between two dumps, from an element visible in both
+fn scroll_step(prev: &[UiElement], next: &[UiElement]) -> Option<i32> {
+    next.iter().find_map(|n| {
+        let key = scroll_key(n)?;
+        let p = prev.iter().find(|p| scroll_key(p).as_ref() == Some(&key))?;
+        Some(p.center_y - n.center_y).filter(|dy| *dy > 0)
+    })
+}
+
+/// Merge pages into one renumbered element list plus a tree listing with
+/// a marker before each page after the first
+fn merge_scroll_pages(pages: Vec<ScrollPage>) -> (Vec<UiElement>, String) {
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    let mut lines = Vec::new();
+    for (i, page) in pages.into_iter().enumerate() {
+        let fresh: Vec<UiElement> = page
+            .elements
+            .into_iter()
+            .filter(|el| match scroll_key(el) {
+                Some(key) => seen.insert(key),
+                None => i == 0,
+            })
+            .collect();
+        if i > 0 {
+            if fresh.is_empty() {
+                continue;
+            }
+            lines.push(format!("── page {} (scrolled ~{}px) ──", i + 1, page.offset));
+        }
+        for mut el in fresh {
+            el.index = merged.len() + 1;
+            lines.push(format_single_element(&el));
+            merged.push(el);
+        }
+    }
+    (merged, lines.join("\n"))
+}
+
+/// How often `wait_for_element` re-dumps the UI tree
+const WAIT_POLL_INTERVAL_MS: u64 = 250;
+
 /// Messages from the Android companion app (WebSocket mode)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -96,9 +410,193 @@ pub enum AndroidMessage {
 // Config
 // ================================================================
 
-/// Maximum UI elements sent to the LLM per step.
+/// Controls when the heartbeat dumps the UI tree (`[perception] poll_strategy`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PollStrategy {
+    /// Dump every tick
+    Always,
+    /// Dump only when a command, notification or device event is pending
+    OnDemand,
+    /// Dump every `ADAPTIVE_POLL_EVERY` ticks, plus immediately on events
+    Adaptive,
+}
+
+/// Idle-tick cadence for `PollStrategy::Adaptive`
+const ADAPTIVE_POLL_EVERY: u64 = 4;
+
+impl PollStrategy {
+    pub fn from_str(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "always" => PollStrategy::Always,
+            "on_demand" | "on-demand" | "ondemand" => PollStrategy::OnDemand,
+            "adaptive" => PollStrategy::Adaptive,
+            _ => {
+                warn!("Unknown poll_strategy '{}', defaulting to 'always'", s);
+                PollStrategy::Always
+            }
+        }
+    }
+
+    /// Whether this tick should dump the screen.
+    /// `has_event` is true when a command, notification or device event is pending.
+    pub fn should_poll_screen(&self, tick: u64, has_event: bool) -> bool {
+        match self {
+            PollStrategy::Always => true,
+            PollStrategy::OnDemand => has_event,
+            PollStrategy::Adaptive => has_event || tick.is_multiple_of(ADAPTIVE_POLL_EVERY),
+        }
+    }
+}
+
+/// Where `uiautomator dump` writes on the device
+const UI_DUMP_PATH: &str = "/sdcard/hermitdroid_ui_dump.xml";
+
+/// Whether `uiautomator dump` succeeded; unexpected output is only logged
+fn check_ui_dump(out: &Result<String, AdbError>) -> bool {
+    match out {
+        Ok(out) => {
+            if !out.contains("dumped to") && !out.contains("hierchary") {
+                debug!("uiautomator dump unexpected output: {}", out);
+            }
+            true
+        }
+        Err(e) => {
+            debug!("uiautomator dump failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Default number of UI elements sent to the LLM per step (`perception.max_elements`).
 /// Elements are scored and ranked; only the top N are included.
-const MAX_ELEMENTS: usize = 40;
+pub const DEFAULT_MAX_ELEMENTS: usize = 40;
+
+// ================================================================
+// Per-app coordinate calibration
+// ================================================================
+
+/// Per-app transform from `workspace/calibration.toml`, keyed by package:
+///
+/// ```toml
+/// ["com.example.app"]
+/// x_offset = 0
+/// y_offset = 24
+/// x_scale = 1.0
+/// y_scale = 1.0
+/// ```
+///
+/// Applied to element centers (`touch = ui * scale + offset`) before they are
+/// shown to the LLM, so the coordinates it taps are already corrected.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AppCalibration {
+    #[serde(default)]
+    pub x_offset: i32,
+    #[serde(default)]
+    pub y_offset: i32,
+    #[serde(default = "default_scale")]
+    pub x_scale: f64,
+    #[serde(default = "default_scale")]
+    pub y_scale: f64,
+}
+
+fn default_scale() -> f64 { 1.0 }
+
+impl AppCalibration {
+    /// UI-tree coordinates → touch coordinates
+    pub fn apply(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            (x as f64 * self.x_scale).round() as i32 + self.x_offset,
+            (y as f64 * self.y_scale).round() as i32 + self.y_offset,
+        )
+    }
+
+    /// Touch coordinates → UI-tree coordinates
+    pub fn invert(&self, x: i32, y: i32) -> (i32, i32) {
+        let inv = |v: i32, offset: i32, scale: f64| {
+            if scale.abs() < f64::EPSILON { v } else { ((v - offset) as f64 / scale).round() as i32 }
+        };
+        (inv(x, self.x_offset, self.x_scale), inv(y, self.y_offset, self.y_scale))
+    }
+}
+
+pub fn calibration_path(workspace_path: &str) -> PathBuf {
+    Path::new(workspace_path).join("calibration.toml")
+}
+
+/// Load `workspace/calibration.toml`. Missing or malformed → no profiles.
+pub fn load_app_calibrations(workspace_path: &str) -> HashMap<String, AppCalibration> {
+    let path = calibration_path(workspace_path);
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return HashMap::new();
+    };
+    match toml::from_str::<HashMap<String, AppCalibration>>(&content) {
+        Ok(profiles) => {
+            if !profiles.is_empty() {
+                info!("🎯 Loaded {} app calibration profile(s)", profiles.len());
+            }
+            profiles
+        }
+        Err(e) => {
+            warn!("⚠️  Ignoring malformed {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Insert or replace one package's profile in `workspace/calibration.toml`
+pub fn save_app_calibration(
+    workspace_path: &str,
+    package: &str,
+    calibration: AppCalibration,
+) -> anyhow::Result<PathBuf> {
+    let mut profiles = load_app_calibrations(workspace_path);
+    profiles.insert(package.to_string(), calibration);
+    let sorted: std::collections::BTreeMap<_, _> = profiles.into_iter().collect();
+    let path = calibration_path(workspace_path);
+    std::fs::write(&path, toml::to_string(&sorted)?)?;
+    Ok(path)
+}
+
+/// Default for `perception.notification_dedup_secs`
+pub const DEFAULT_NOTIFICATION_DEDUP_SECS: u64 = 300;
+
+/// Notification keys with the time they were last seen. A key is suppressed
+/// while it keeps showing up, and reported again once it has been gone for
+/// the window, so a notification left in the shade isn't re-reported every
+/// window, and a repeat "1 new message" later on isn't swallowed forever.
+#[derive(Debug)]
+struct NotificationDedup {
+    window: std::time::Duration,
+    reported: HashMap<String, std::time::Instant>,
+}
+
+impl NotificationDedup {
+    /// Past this many keys, `trim` drops the oldest down to half
+    const MAX_KEYS: usize = 1000;
+
+    fn new(window: std::time::Duration) -> Self {
+        Self { window, reported: HashMap::new() }
+    }
+
+    /// `true` unless `key` was seen within the window; every sighting
+    /// restarts it
+    fn is_new(&mut self, key: String, now: std::time::Instant) -> bool {
+        let last = self.reported.insert(key, now);
+        !last.is_some_and(|at| now.saturating_duration_since(at) < self.window)
+    }
+
+    /// Keep only the most recently seen keys once the map overflows
+    fn trim(&mut self) {
+        if self.reported.len() <= Self::MAX_KEYS {
+            return;
+        }
+        let mut by_age: Vec<_> = self.reported.iter().map(|(k, at)| (*at, k.clone())).collect();
+        by_age.sort_unstable_by_key(|(at, _)| std::cmp::Reverse(*at));
+        for (_, key) in by_age.into_iter().skip(Self::MAX_KEYS / 2) {
+            self.reported.remove(&key);
+        }
+    }
+}
 
 // ================================================================
 // Perception engine
@@ -109,26 +607,73 @@ pub struct Perception {
     adb_device: Option<String>,
     notifications: Arc<Mutex<Vec<Notification>>>,
     current_screen: Arc<Mutex<Option<ScreenState>>>,
+    /// The state `current_screen` replaced, for `screen_diff`
+    previous_screen: Arc<Mutex<Option<ScreenState>>>,
     user_commands: Arc<Mutex<Vec<String>>>,
     device_events: Arc<Mutex<Vec<String>>>,
-    /// Notification keys we already reported — only report new ones
-    seen_keys: Arc<Mutex<HashSet<String>>>,
+    /// Notification keys we already reported, with when — see `NotificationDedup`
+    seen_keys: Arc<Mutex<NotificationDedup>>,
     priority_apps: Vec<String>,
     /// Detected screen resolution (width x height)
     screen_resolution: Arc<Mutex<Option<(u32, u32)>>>,
+    /// Monotonic tick id — UI dumps are cached per tick
+    tick_id: Arc<AtomicU64>,
+    /// Last parsed UI dump, reused until the tick advances or it is invalidated
+    ui_cache: Arc<std::sync::Mutex<Option<UiDumpCache>>>,
+    /// Number of uiautomator dumps avoided by the cache
+    saved_dumps: Arc<AtomicU64>,
+    /// Per-app coordinate transforms (package → calibration)
+    app_calibrations: Arc<HashMap<String, AppCalibration>>,
+    /// The profile applied to the screen the agent last saw (None = none)
+    screen_calibration: Arc<std::sync::Mutex<Option<AppCalibration>>>,
+    /// Friendly app name → package lookup
+    app_resolver: AppResolver,
+    /// UI nodes from these packages are dropped unless they're in the foreground
+    ignore_packages: Arc<Vec<String>>,
+    /// Downscale / JPEG settings for captured screenshots
+    screenshot_encoding: ScreenshotEncoding,
+    /// Top-scored elements kept per UI dump
+    max_elements: usize,
+    /// Set when `perception.skip_static_screenshots` is on
+    static_screen: Option<Arc<StaticScreenTracker>>,
+}
+
+/// A parsed UI dump tagged with the tick it was taken in
+#[derive(Debug, Clone)]
+struct UiDumpCache {
+    tick_id: u64,
+    /// The raw uiautomator XML, for `sanitizer::perceive_screen`
+    xml: Option<String>,
+    /// `(package, activity)` from `dumpsys activity`, taken with the dump
+    foreground: Option<(String, String)>,
+    ui_tree: Option<String>,
+    elements: Vec<UiElement>,
 }
 
 impl Perception {
     pub fn new(adb_device: Option<String>, priority_apps: Vec<String>) -> Self {
         let p = Self {
-            adb_device,
+            adb_device: adb_device.clone(),
             notifications: Arc::new(Mutex::new(Vec::new())),
             current_screen: Arc::new(Mutex::new(None)),
+            previous_screen: Arc::new(Mutex::new(None)),
             user_commands: Arc::new(Mutex::new(Vec::new())),
             device_events: Arc::new(Mutex::new(Vec::new())),
-            seen_keys: Arc::new(Mutex::new(HashSet::new())),
+            seen_keys: Arc::new(Mutex::new(NotificationDedup::new(
+                std::time::Duration::from_secs(DEFAULT_NOTIFICATION_DEDUP_SECS),
+            ))),
             priority_apps,
             screen_resolution: Arc::new(Mutex::new(None)),
+            tick_id: Arc::new(AtomicU64::new(0)),
+            ui_cache: Arc::new(std::sync::Mutex::new(None)),
+            saved_dumps: Arc::new(AtomicU64::new(0)),
+            app_calibrations: Arc::new(HashMap::new()),
+            screen_calibration: Arc::new(std::sync::Mutex::new(None)),
+            app_resolver: AppResolver::new(adb_device.clone(), apps::bundled_aliases()),
+            ignore_packages: Arc::new(Vec::new()),
+            screenshot_encoding: ScreenshotEncoding::default(),
+            max_elements: DEFAULT_MAX_ELEMENTS,
+            static_screen: None,
         };
         // Detect resolution on init
         if let Ok(raw) = p.adb(&["shell", "wm", "size"]) {
@@ -149,6 +694,129 @@ impl Perception {
         p
     }
 
+    pub fn with_app_calibrations(mut self, profiles: HashMap<String, AppCalibration>) -> Self {
+        self.app_calibrations = Arc::new(profiles);
+        self
+    }
+
+    pub fn with_app_aliases(mut self, aliases: HashMap<String, String>) -> Self {
+        let interval = self.app_resolver.refresh_interval();
+        self.app_resolver = AppResolver::new(self.adb_device.clone(), aliases).with_refresh_interval(interval);
+        self
+    }
+
+    /// Re-list installed packages once the cached list is this old
+    pub fn with_apps_refresh_secs(mut self, secs: u64) -> Self {
+        self.app_resolver = self.app_resolver.with_refresh_interval(std::time::Duration::from_secs(secs));
+        self
+    }
+
+    pub fn with_ignore_packages(mut self, packages: Vec<String>) -> Self {
+        self.ignore_packages = Arc::new(packages);
+        self
+    }
+
+    pub fn with_screenshot_encoding(mut self, encoding: ScreenshotEncoding) -> Self {
+        self.screenshot_encoding = encoding;
+        self
+    }
+
+    pub fn with_max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = max_elements;
+        self
+    }
+
+    /// Re-report an identical notification once this many seconds have passed
+    pub fn with_notification_dedup_secs(mut self, secs: u64) -> Self {
+        self.seen_keys = Arc::new(Mutex::new(NotificationDedup::new(
+            std::time::Duration::from_secs(secs),
+        )));
+        self
+    }
+
+    /// Let `perceive_screen` drop screenshots of a screen that hasn't changed
+    pub fn with_skip_static_screenshots(mut self, enabled: bool) -> Self {
+        self.static_screen = enabled.then(|| Arc::new(StaticScreenTracker::default()));
+        self
+    }
+
+    /// Pass to `sanitizer::perceive_screen` from the heartbeat / one-shot loop
+    pub fn static_screen(&self) -> Option<&StaticScreenTracker> {
+        self.static_screen.as_deref()
+    }
+
+    /// Resolve "youtube" → "com.google.android.youtube" (see `AppResolver`)
+    pub fn resolve_package(&self, name: &str) -> Option<String> {
+        self.app_resolver.resolve_package(name)
+    }
+
+    /// Launchable apps as (package, label), from the cached `pm` listing
+    pub fn list_installed_apps(&self) -> Vec<(String, String)> {
+        self.app_resolver.installed_apps()
+    }
+
+    /// One "Label: package" line per launchable app, for the system prompt
+    pub fn format_installed_apps(&self) -> String {
+        self.list_installed_apps()
+            .iter()
+            .map(|(package, label)| format!("{}: {}", label, package))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Shared handle for the executor (same package cache)
+    pub fn app_resolver(&self) -> AppResolver {
+        self.app_resolver.clone()
+    }
+
+    pub fn app_calibration(&self, package: &str) -> Option<AppCalibration> {
+        self.app_calibrations.get(package).copied()
+    }
+
+    /// The per-app profile the coordinates of the last formatted screen went
+    /// through. While there is one, it stands in for the global tap calibration.
+    pub fn screen_calibration(&self) -> Option<AppCalibration> {
+        self.screen_calibration.lock().ok().and_then(|cal| *cal)
+    }
+
+    fn set_screen_calibration(&self, cal: Option<AppCalibration>) {
+        if let Ok(mut current) = self.screen_calibration.lock() {
+            *current = cal;
+        }
+    }
+
+    /// Apply the foreground app's calibration to a sanitizer perception
+    /// result and re-format the LLM text so it shows corrected coordinates.
+    pub fn calibrate_result(&self, result: &mut crate::sanitizer::PerceptionResult) {
+        let cal = result
+            .screen
+            .foreground_package
+            .as_deref()
+            .and_then(|pkg| self.app_calibration(pkg));
+        self.set_screen_calibration(cal);
+        let Some(cal) = cal else {
+            return;
+        };
+        for el in &mut result.screen.elements {
+            el.center = cal.apply(el.center.0, el.center.1);
+        }
+        result.formatted_text = crate::sanitizer::format_for_llm(&result.screen, result.resolution);
+    }
+
+    /// Start a new logical step. UI dumps cached in the previous tick are
+    /// no longer reused. Returns the new tick id.
+    pub fn next_tick(&self) -> u64 {
+        self.tick_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Drop the cached UI dump. Call right after executing a UI action so the
+    /// next poll sees the updated screen.
+    pub fn invalidate_screen_cache(&self) {
+        if let Ok(mut cache) = self.ui_cache.lock() {
+            *cache = None;
+        }
+    }
+
     /// Get the detected screen resolution
     pub async fn get_resolution(&self) -> Option<(u32, u32)> {
         *self.screen_resolution.lock().await
@@ -159,27 +827,29 @@ impl Perception {
     // ================================================================
 
     /// Poll notifications via `adb shell dumpsys notification --noredact`.
-    /// Diffs against previously seen notifications. Pushes only new ones.
-    /// Returns `true` if any new notification is from a priority app.
-    pub async fn poll_notifications_adb(&self) -> bool {
-        let raw = match self.adb(&["shell", "dumpsys", "notification", "--noredact"]) {
+    /// Diffs against recently seen notifications. Pushes only new ones.
+    /// Returns `true` if any new notification is from a priority app, or the
+    /// error when the device itself is unreachable (a failed dumpsys is only logged).
+    pub async fn poll_notifications_adb(&self) -> Result<bool, AdbError> {
+        let raw = match self.adb_async(&["shell", "dumpsys", "notification", "--noredact"]).await {
             Ok(out) => out,
+            Err(e) if e.is_device_error() => return Err(e),
             Err(e) => {
                 debug!("ADB notification poll failed: {}", e);
-                return false;
+                return Ok(false);
             }
         };
 
         let parsed = parse_dumpsys_notifications(&raw);
         let mut seen = self.seen_keys.lock().await;
         let mut has_priority = false;
+        let now = std::time::Instant::now();
 
         for notif in parsed {
             let key = format!("{}|{}|{}", notif.app, notif.title, notif.text);
-            if seen.contains(&key) {
+            if !seen.is_new(key, now) {
                 continue;
             }
-            seen.insert(key);
 
             let is_prio = self.priority_apps.iter().any(|a| notif.app.contains(a));
             if is_prio {
@@ -189,28 +859,37 @@ impl Perception {
             self.notifications.lock().await.push(notif);
         }
 
-        if seen.len() > 1000 {
-            let drain: Vec<String> = seen.iter().take(seen.len() - 500).cloned().collect();
-            for k in drain {
-                seen.remove(&k);
-            }
-        }
+        seen.trim();
+
+        Ok(has_priority)
+    }
 
-        has_priority
+    /// Every notification currently posted, without the "already seen" filter
+    /// `poll_notifications_adb` applies
+    pub async fn active_notifications(&self) -> Result<Vec<Notification>, AdbError> {
+        let raw = self.adb_async(&["shell", "dumpsys", "notification", "--noredact"]).await?;
+        Ok(parse_dumpsys_notifications(&raw))
     }
 
     /// Poll current foreground app + UI tree via ADB.
     /// If `with_screenshot` is true, also captures a screenshot.
     /// If the UI tree is empty (WebView/Flutter/game), auto-enables screenshot as vision fallback.
+    /// Uses async `adb` calls so it can run alongside `poll_notifications_adb`.
     pub async fn poll_screen_adb_full(&self, with_screenshot: bool) {
-        // 1. Current activity
-        let (app, activity) = self
-            .adb(&["shell", "dumpsys", "activity", "activities"])
-            .map(|raw| parse_foreground_activity(&raw))
-            .unwrap_or(("unknown".into(), "unknown".into()));
-
-        // 2. UI tree → structured elements
-        let (ui_tree_str, elements) = self.dump_and_parse_ui_tree();
+        // 1-2. Current activity and UI tree → structured elements
+        let dump = self.ui_dump_async().await;
+        let (app, activity) = dump.foreground.unwrap_or(("unknown".into(), "unknown".into()));
+        let (mut ui_tree_str, mut elements) = (dump.ui_tree, dump.elements);
+        let cal = self.app_calibration(&app);
+        self.set_screen_calibration(cal);
+        if let Some(cal) = cal {
+            for el in &mut elements {
+                (el.center_x, el.center_y) = cal.apply(el.center_x, el.center_y);
+            }
+            if !elements.is_empty() {
+                ui_tree_str = Some(format_elements_for_tree(&elements));
+            }
+        }
 
         // 3. Vision fallback: auto-screenshot when tree is empty
         let tree_is_empty = elements.is_empty();
@@ -220,7 +899,7 @@ impl Perception {
             if tree_is_empty && !with_screenshot {
                 debug!("📸 UI tree empty — vision fallback (auto-screenshot)");
             }
-            self.capture_screenshot_adb()
+            self.encode_capture(self.adb_bytes_async(&["exec-out", "screencap", "-p"]).await)
         } else {
             None
         };
@@ -234,34 +913,183 @@ impl Perception {
             timestamp: chrono::Utc::now().to_rfc3339(),
         };
 
-        *self.current_screen.lock().await = Some(state);
+        self.set_screen(state).await;
     }
 
-    /// Simple poll without screenshot (backward compatible)
-    pub async fn poll_screen_adb(&self) {
-        self.poll_screen_adb_full(false).await;
+    /// Poll the UI tree every ~250ms until an element matches `query`.
+    /// Bypasses the per-tick dump cache; returns None on timeout.
+    pub async fn wait_for_element(&self, query: &ElementQuery, timeout_ms: u64) -> Option<UiElement> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
+        loop {
+            let (_, elements) = self.dump_and_parse_ui_tree_uncached();
+            if let Some(el) = elements.into_iter().find(|e| query.matches(e)) {
+                return Some(el);
+            }
+            if std::time::Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(WAIT_POLL_INTERVAL_MS)).await;
+        }
+    }
+
+    /// A fresh UI dump, bypassing the per-tick cache
+    pub fn dump_elements(&self) -> Vec<UiElement> {
+        self.dump_and_parse_ui_tree_uncached().1
+    }
+
+    /// `dump_elements` without blocking the runtime on adb
+    pub async fn dump_elements_async(&self) -> Vec<UiElement> {
+        if !check_ui_dump(&self.adb_async(&["shell", "uiautomator", "dump", UI_DUMP_PATH]).await) {
+            return Vec::new();
+        }
+        self.parse_ui_dump(self.adb_async(&["shell", "cat", UI_DUMP_PATH]).await, None).1
+    }
+
+    /// Every element on screen, without the `max_elements` cap the prompt
+    /// needs — for lookups that must see the whole screen
+    pub fn dump_all_elements(&self) -> Vec<UiElement> {
+        Self { max_elements: usize::MAX, ..self.clone() }.dump_elements()
     }
 
-    /// Dump UI tree and parse into structured, scored, numbered elements.
-    fn dump_and_parse_ui_tree(&self) -> (Option<String>, Vec<UiElement>) {
-        let dump_path = "/sdcard/hermitdroid_ui_dump.xml";
+    /// Best fuzzy match for `query` on the current screen (see `find_score`).
+    /// With `want_editable`, text fields outrank labels that merely mention the query.
+    pub fn find_element(&self, query: &str, want_editable: bool) -> Option<UiElement> {
+        let (_, elements) = self.dump_and_parse_ui_tree_uncached();
+        best_match(elements, query, want_editable)
+    }
+
+    /// Read a screen taller than the display: dump, scroll the largest scrollable
+    /// element by ~half its height, dump again, up to `max_pages` dumps or until
+    /// the content stops changing. Elements are de-duplicated (resource id + text)
+    /// and `ui_tree` marks where each page starts with its approximate scroll
+    /// offset. Coordinates are as seen on the element's own page; the screen is
+    /// scrolled back to the top afterwards.
+    pub async fn capture_scrolling(&self, max_pages: usize) -> ScreenState {
+        let (app, activity) = self
+            .adb(&["shell", "dumpsys", "activity", "activities"])
+            .map(|raw| parse_foreground_activity(&raw))
+            .unwrap_or(("unknown".into(), "unknown".into()));
 
-        match self.adb(&["shell", "uiautomator", "dump", dump_path]) {
-            Ok(out) => {
-                if !out.contains("dumped to") && !out.contains("hierchary") {
-                    debug!("uiautomator dump unexpected output: {}", out);
+        let (_, first) = self.dump_and_parse_ui_tree_uncached();
+        let scroller = first
+            .iter()
+            .filter(|el| el.scrollable)
+            .max_by_key(|el| (el.bounds[2] - el.bounds[0]) * (el.bounds[3] - el.bounds[1]))
+            .map(|el| el.bounds);
+        let mut pages = vec![ScrollPage { offset: 0, elements: first }];
+
+        if let Some([left, top, right, bottom]) = scroller {
+            let x = ((left + right) / 2).to_string();
+            let (from, to) = (top + (bottom - top) * 3 / 4, top + (bottom - top) / 4);
+            let (from_s, to_s) = (from.to_string(), to.to_string());
+            let mut last_sig = page_signature(&pages[0].elements);
+            while pages.len() < max_pages.max(1) {
+                if self.adb(&["shell", "input", "swipe", &x, &from_s, &x, &to_s, "400"]).is_err() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(SCROLL_SETTLE_MS)).await;
+                let (_, elements) = self.dump_and_parse_ui_tree_uncached();
+                let sig = page_signature(&elements);
+                if sig == last_sig {
+                    break; // reached the end
                 }
+                last_sig = sig;
+                let prev = pages.last().expect("first page always present");
+                let step = scroll_step(&prev.elements, &elements).unwrap_or(from - to);
+                pages.push(ScrollPage { offset: prev.offset + step, elements });
             }
-            Err(e) => {
-                debug!("uiautomator dump failed: {}", e);
-                return (None, Vec::new());
+            // Back to the top so first-page coordinates are valid again
+            for _ in 1..pages.len() {
+                self.adb(&["shell", "input", "swipe", &x, &to_s, &x, &from_s, "300"]).ok();
             }
+            self.invalidate_screen_cache();
+        }
+
+        let page_count = pages.len();
+        let (elements, ui_tree) = merge_scroll_pages(pages);
+        info!("📜 Captured {} elements over {} page(s)", elements.len(), page_count);
+        ScreenState {
+            current_app: app,
+            activity,
+            ui_tree: Some(ui_tree),
+            elements,
+            screenshot_base64: None,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Simple poll without screenshot (backward compatible)
+    pub async fn poll_screen_adb(&self) {
+        self.poll_screen_adb_full(false).await;
+    }
+
+    /// The raw uiautomator dump of the current screen with the foreground app
+    /// and resolution, from the same per-tick cache as `poll_screen_adb_full`,
+    /// so a tick dumps the tree only once
+    pub async fn ui_tree_dump(&self) -> Option<crate::sanitizer::TreeDump> {
+        let dump = self.ui_dump_async().await;
+        let resolution = self.get_resolution().await;
+        dump.xml.map(|xml| crate::sanitizer::TreeDump {
+            xml,
+            resolution,
+            foreground: dump.foreground.map(|(package, _)| package),
+        })
+    }
+
+    /// Dump the UI tree and parse it into structured, scored, numbered
+    /// elements. Reuses the previous dump if it was taken in the current
+    /// tick and hasn't been invalidated since.
+    async fn ui_dump_async(&self) -> UiDumpCache {
+        if let Some(hit) = self.cached_dump() {
+            return hit;
+        }
+        // Which app is in front decides which `ignore_packages` nodes stay
+        let foreground = self
+            .adb_async(&["shell", "dumpsys", "activity", "activities"])
+            .await
+            .ok()
+            .map(|raw| parse_foreground_activity(&raw))
+            .filter(|(package, _)| package != "unknown");
+        let dumped = self.adb_async(&["shell", "uiautomator", "dump", UI_DUMP_PATH]).await;
+        let (xml, (ui_tree, elements)) = if check_ui_dump(&dumped) {
+            let cat = self.adb_async(&["shell", "cat", UI_DUMP_PATH]).await;
+            let package = foreground.as_ref().map(|(package, _)| package.as_str());
+            (cat.as_ref().ok().cloned(), self.parse_ui_dump(cat, package))
+        } else {
+            (None, (None, Vec::new()))
+        };
+        let dump = UiDumpCache { tick_id: self.tick_id.load(Ordering::SeqCst), xml, foreground, ui_tree, elements };
+        if let Ok(mut cache) = self.ui_cache.lock() {
+            *cache = Some(dump.clone());
+        }
+        dump
+    }
+
+    fn cached_dump(&self) -> Option<UiDumpCache> {
+        let tick = self.tick_id.load(Ordering::SeqCst);
+        let cache = self.ui_cache.lock().ok()?;
+        let c = cache.as_ref().filter(|c| c.tick_id == tick)?;
+        let saved = self.saved_dumps.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("UI dump cache hit (tick {}, {} dumps saved so far)", tick, saved);
+        Some(c.clone())
+    }
+
+    fn dump_and_parse_ui_tree_uncached(&self) -> (Option<String>, Vec<UiElement>) {
+        if !check_ui_dump(&self.adb(&["shell", "uiautomator", "dump", UI_DUMP_PATH])) {
+            return (None, Vec::new());
         }
+        self.parse_ui_dump(self.adb(&["shell", "cat", UI_DUMP_PATH]), None)
+    }
 
-        match self.adb(&["shell", "cat", dump_path]) {
+    /// Elements from the `cat` of a uiautomator dump. Without the
+    /// `foreground` package, it's guessed from the nodes (see `parse_ui_elements`).
+    fn parse_ui_dump(&self, xml: Result<String, AdbError>, foreground: Option<&str>) -> (Option<String>, Vec<UiElement>) {
+        match xml {
             Ok(xml) => {
                 if xml.contains("<hierarchy") && xml.contains("<node") {
-                    let elements = parse_ui_elements(&xml);
+                    // Set once at startup, so the lock is never held for long
+                    let resolution = self.screen_resolution.try_lock().ok().and_then(|r| *r);
+                    let elements = parse_ui_elements(&xml, &self.ignore_packages, self.max_elements, resolution, foreground);
                     if elements.is_empty() {
                         debug!("UI tree parsed to 0 elements");
                         return (None, Vec::new());
@@ -282,14 +1110,15 @@ impl Perception {
 
     /// Take a screenshot, return base64-encoded PNG.
     pub fn capture_screenshot_adb(&self) -> Option<String> {
-        let bytes = self.adb_bytes(&["exec-out", "screencap", "-p"]).ok()?;
+        self.encode_capture(self.adb_bytes(&["exec-out", "screencap", "-p"]))
+    }
+
+    fn encode_capture(&self, bytes: Result<Vec<u8>, AdbError>) -> Option<String> {
+        let bytes = bytes.ok()?;
         if bytes.len() < 100 {
             return None;
         }
-        Some(base64::Engine::encode(
-            &base64::engine::general_purpose::STANDARD,
-            &bytes,
-        ))
+        Some(encode_screenshot(&bytes, self.screenshot_encoding))
     }
 
     pub fn is_screen_on(&self) -> bool {
@@ -298,6 +1127,16 @@ impl Perception {
             .unwrap_or(false)
     }
 
+    /// Screen on and keyguard dismissed. A missing keyguard flag counts as unlocked.
+    pub fn is_unlocked(&self) -> bool {
+        self.is_screen_on()
+            && !self
+                .adb(&["shell", "dumpsys", "window"])
+                .ok()
+                .and_then(|raw| parse_keyguard_showing(&raw))
+                .unwrap_or(false)
+    }
+
     // ================================================================
     // Push interface (WebSocket companion app path)
     // ================================================================
@@ -311,7 +1150,7 @@ impl Perception {
 
     pub async fn update_screen(&self, state: ScreenState) {
         debug!("[SCREEN] {} / {}", state.current_app, state.activity);
-        *self.current_screen.lock().await = Some(state);
+        self.set_screen(state).await;
     }
 
     pub async fn push_user_command(&self, text: String) {
@@ -340,6 +1179,11 @@ impl Perception {
         self.user_commands.lock().await.is_empty()
     }
 
+    /// Whether any notification or device event is waiting to be drained
+    pub async fn has_pending_events(&self) -> bool {
+        !self.notifications.lock().await.is_empty() || !self.device_events.lock().await.is_empty()
+    }
+
     pub async fn drain_device_events(&self) -> Vec<String> {
         self.device_events.lock().await.drain(..).collect()
     }
@@ -348,6 +1192,20 @@ impl Perception {
         self.current_screen.lock().await.clone()
     }
 
+    /// What changed between the last two screen states (None until there are two)
+    pub async fn screen_diff(&self) -> Option<ScreenDiff> {
+        let previous = self.previous_screen.lock().await.clone()?;
+        let current = self.current_screen.lock().await.clone()?;
+        Some(ScreenDiff::between(&previous, &current))
+    }
+
+    async fn set_screen(&self, state: ScreenState) {
+        let replaced = self.current_screen.lock().await.replace(state);
+        if replaced.is_some() {
+            *self.previous_screen.lock().await = replaced;
+        }
+    }
+
     // ================================================================
     // Formatting for LLM context
     // ================================================================
@@ -358,7 +1216,14 @@ impl Perception {
         }
         notifs
             .iter()
-            .map(|n| format!("[{}] {}: {}", n.app, n.title, n.text))
+            .map(|n| {
+                let line = format!("[{}] {}: {}", n.app, n.title, n.text);
+                if n.actions.is_empty() {
+                    line
+                } else {
+                    format!("{} (actions: {})", line, n.actions.join(", "))
+                }
+            })
             .collect::<Vec<_>>()
             .join("\n")
     }
@@ -469,28 +1334,46 @@ impl Perception {
     // ADB helpers
     // ================================================================
 
-    fn adb(&self, args: &[&str]) -> anyhow::Result<String> {
+    fn adb(&self, args: &[&str]) -> Result<String, AdbError> {
         let mut cmd = Command::new("adb");
         if let Some(dev) = &self.adb_device {
             cmd.args(["-s", dev]);
         }
         cmd.args(args);
-        let out = cmd.output()?;
+        let out = adb::output(&mut cmd)?;
         if !out.status.success() {
-            anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim());
+            return Err(AdbError::from_output(out.status.code(), &String::from_utf8_lossy(&out.stderr)));
         }
         Ok(String::from_utf8_lossy(&out.stdout).to_string())
     }
 
-    fn adb_bytes(&self, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    /// `adb` without blocking the runtime, for the per-tick polls
+    async fn adb_async(&self, args: &[&str]) -> Result<String, AdbError> {
+        self.adb_bytes_async(args).await.map(|out| String::from_utf8_lossy(&out).to_string())
+    }
+
+    async fn adb_bytes_async(&self, args: &[&str]) -> Result<Vec<u8>, AdbError> {
+        let mut cmd = tokio::process::Command::new("adb");
+        if let Some(dev) = &self.adb_device {
+            cmd.args(["-s", dev]);
+        }
+        cmd.args(args);
+        let out = adb::output_async(&mut cmd).await?;
+        if !out.status.success() {
+            return Err(AdbError::from_output(out.status.code(), &String::from_utf8_lossy(&out.stderr)));
+        }
+        Ok(out.stdout)
+    }
+
+    fn adb_bytes(&self, args: &[&str]) -> Result<Vec<u8>, AdbError> {
         let mut cmd = Command::new("adb");
         if let Some(dev) = &self.adb_device {
             cmd.args(["-s", dev]);
         }
         cmd.args(args);
-        let out = cmd.output()?;
+        let out = adb::output(&mut cmd)?;
         if !out.status.success() {
-            anyhow::bail!("adb error");
+            return Err(AdbError::from_output(out.status.code(), &String::from_utf8_lossy(&out.stderr)));
         }
         Ok(out.stdout)
     }
@@ -504,9 +1387,19 @@ impl Perception {
 // 3. Rank and take only top MAX_ELEMENTS (keeps prompt small & fast)
 // 4. Sort by screen position (top-to-bottom) for natural reading order
 // 5. Assign 1-based index for LLM targeting ("tap element 5 @(540,150)")
+//
+// Centers are taken from the on-screen part of the bounds: uiautomator
+// reports partially visible items with off-screen (even negative) bounds,
+// and a tap there lands on the system edge gestures.
 // ════════════════════════════════════════════════════════════════════
 
-fn parse_ui_elements(xml: &str) -> Vec<UiElement> {
+fn parse_ui_elements(
+    xml: &str,
+    ignore_packages: &[String],
+    max_elements: usize,
+    resolution: Option<(u32, u32)>,
+    foreground: Option<&str>,
+) -> Vec<UiElement> {
     let xml = if let Some(idx) = xml.find("<?xml") {
         &xml[idx..]
     } else if let Some(idx) = xml.find("<hierarchy") {
@@ -514,8 +1407,10 @@ fn parse_ui_elements(xml: &str) -> Vec<UiElement> {
     } else {
         xml
     };
+    let resolution = oriented_resolution(xml, resolution);
 
-    let mut all_elements: Vec<UiElement> = Vec::new();
+    let mut all_elements: Vec<(String, UiElement)> = Vec::new();
+    let mut package_counts: HashMap<String, usize> = HashMap::new();
 
     for chunk in xml.split("<node ") {
         if chunk.is_empty()
@@ -525,6 +1420,8 @@ fn parse_ui_elements(xml: &str) -> Vec<UiElement> {
             continue;
         }
 
+        let package = xml_attr(chunk, "package").unwrap_or_default();
+        *package_counts.entry(package.clone()).or_insert(0) += 1;
         let text = xml_attr(chunk, "text").unwrap_or_default();
         let desc = xml_attr(chunk, "content-desc").unwrap_or_default();
         let resource_id = xml_attr(chunk, "resource-id")
@@ -568,15 +1465,17 @@ fn parse_ui_elements(xml: &str) -> Vec<UiElement> {
             || focused
             || scrollable;
 
-        // Filter: skip zero-area elements (invisible)
-        let has_area = bounds_arr[2] > bounds_arr[0] && bounds_arr[3] > bounds_arr[1];
-
-        if !has_info || !has_area {
+        if !has_info {
             continue;
         }
 
-        let center_x = (bounds_arr[0] + bounds_arr[2]) / 2;
-        let center_y = (bounds_arr[1] + bounds_arr[3]) / 2;
+        // Filter: skip zero-area and entirely off-screen elements (invisible)
+        let Some(visible) = visible_bounds(&bounds_arr, resolution) else {
+            continue;
+        };
+
+        let center_x = (visible[0] + visible[2]) / 2;
+        let center_y = (visible[1] + visible[3]) / 2;
 
         let score = score_element(
             &text, &desc, &resource_id, &class_short,
@@ -584,7 +1483,7 @@ fn parse_ui_elements(xml: &str) -> Vec<UiElement> {
             &bounds_arr,
         );
 
-        all_elements.push(UiElement {
+        all_elements.push((package, UiElement {
             index: 0,
             class: class_short,
             text: text.chars().take(100).collect(),
@@ -600,16 +1499,26 @@ fn parse_ui_elements(xml: &str) -> Vec<UiElement> {
             checked,
             enabled,
             score,
-        });
+        }));
     }
 
-    // Sort by score desc → take top MAX_ELEMENTS
+    // Drop status bar / launcher nodes so they don't take the element budget
+    let foreground = foreground
+        .map(str::to_string)
+        .or_else(|| crate::sanitizer::foreground_package(&package_counts, ignore_packages));
+    let mut all_elements: Vec<UiElement> = all_elements
+        .into_iter()
+        .filter(|(pkg, _)| crate::sanitizer::keep_package(pkg, ignore_packages, foreground.as_deref()))
+        .map(|(_, el)| el)
+        .collect();
+
+    // Sort by score desc → take top max_elements
     all_elements.sort_by(|a, b| {
         b.score
             .partial_cmp(&a.score)
             .unwrap_or(std::cmp::Ordering::Equal)
     });
-    all_elements.truncate(MAX_ELEMENTS);
+    all_elements.truncate(max_elements);
 
     // Re-sort by position (top-to-bottom, left-to-right)
     all_elements
@@ -717,6 +1626,24 @@ fn xml_attr(s: &str, attr: &str) -> Option<String> {
     }
 }
 
+/// `resolution` (`wm size`, the portrait size) turned to match the dump,
+/// whose bounds follow the `<hierarchy rotation>`
+pub(crate) fn oriented_resolution(xml: &str, resolution: Option<(u32, u32)>) -> Option<(u32, u32)> {
+    let rotated = xml
+        .find("<hierarchy")
+        .and_then(|i| xml_attr(&xml[i..], "rotation"))
+        .is_some_and(|r| r == "1" || r == "3");
+    resolution.map(|(w, h)| if rotated { (h, w) } else { (w, h) })
+}
+
+/// The on-screen part of `[left, top, right, bottom]`, or `None` when none
+/// of it is visible. Without a resolution only the top and left edges clip.
+pub(crate) fn visible_bounds(bounds: &[i32; 4], resolution: Option<(u32, u32)>) -> Option<[i32; 4]> {
+    let (w, h) = resolution.map_or((i32::MAX, i32::MAX), |(w, h)| (w as i32, h as i32));
+    let clipped = [bounds[0].max(0), bounds[1].max(0), bounds[2].min(w), bounds[3].min(h)];
+    (clipped[2] > clipped[0] && clipped[3] > clipped[1]).then_some(clipped)
+}
+
 fn parse_bounds(bounds: &str) -> [i32; 4] {
     let nums: Vec<i32> = bounds
         .replace('[', "")
@@ -759,13 +1686,17 @@ fn parse_dumpsys_notifications(raw: &str) -> Vec<Notification> {
     let mut title: Option<String> = None;
     let mut text: Option<String> = None;
     let mut big_text: Option<String> = None;
+    let mut actions: Vec<String> = Vec::new();
+    // Inside an `actions={ ... }` block
+    let mut in_actions = false;
 
     let flush = |results: &mut Vec<Notification>,
                  pkg: &mut Option<String>,
                  key: &mut Option<String>,
                  title: &mut Option<String>,
                  text: &mut Option<String>,
-                 big_text: &mut Option<String>| {
+                 big_text: &mut Option<String>,
+                 actions: &mut Vec<String>| {
         if let (Some(p), Some(k)) = (pkg.take(), key.take()) {
             let t = title.take().unwrap_or_default();
             let tx = big_text.take().or_else(|| text.take()).unwrap_or_default();
@@ -776,12 +1707,14 @@ fn parse_dumpsys_notifications(raw: &str) -> Vec<Notification> {
                     title: t,
                     text: tx,
                     timestamp: chrono::Utc::now().to_rfc3339(),
+                    actions: std::mem::take(actions),
                 });
             }
         }
         *title = None;
         *text = None;
         *big_text = None;
+        actions.clear();
     };
 
     for line in raw.lines() {
@@ -789,8 +1722,9 @@ fn parse_dumpsys_notifications(raw: &str) -> Vec<Notification> {
 
         if s.starts_with("NotificationRecord(") || s.starts_with("NotificationRecord{") {
             flush(
-                &mut results, &mut pkg, &mut key, &mut title, &mut text, &mut big_text,
+                &mut results, &mut pkg, &mut key, &mut title, &mut text, &mut big_text, &mut actions,
             );
+            in_actions = false;
             pkg = extract_field(s, "pkg=");
             key = extract_field(s, "0x")
                 .or_else(|| extract_field(s, "id="))
@@ -802,6 +1736,24 @@ fn parse_dumpsys_notifications(raw: &str) -> Vec<Notification> {
             continue;
         }
 
+        // actions={
+        //   [0] "Reply" -> PendingIntent{...}
+        // }
+        if s.starts_with("actions=") {
+            in_actions = true;
+            continue;
+        }
+        if in_actions {
+            if let Some(label) = s.strip_prefix('[').and_then(parse_action_label) {
+                actions.push(label);
+                continue;
+            }
+            in_actions = false;
+            if s == "}" {
+                continue;
+            }
+        }
+
         if s.starts_with("android.title=") {
             title = Some(s["android.title=".len()..].to_string());
         } else if s.starts_with("android.text=") {
@@ -820,12 +1772,19 @@ fn parse_dumpsys_notifications(raw: &str) -> Vec<Notification> {
     }
 
     flush(
-        &mut results, &mut pkg, &mut key, &mut title, &mut text, &mut big_text,
+        &mut results, &mut pkg, &mut key, &mut title, &mut text, &mut big_text, &mut actions,
     );
 
     results
 }
 
+/// `0] "Mark as read" -> PendingIntent{...}` → `Mark as read`
+fn parse_action_label(entry: &str) -> Option<String> {
+    let (_, rest) = entry.split_once("] ")?;
+    let label = rest.strip_prefix('"')?.split_once('"')?.0.trim();
+    (!label.is_empty()).then(|| label.to_string())
+}
+
 fn extract_field(line: &str, prefix: &str) -> Option<String> {
     let start = line.find(prefix)? + prefix.len();
     let rest = &line[start..];
@@ -844,7 +1803,20 @@ fn extract_field(line: &str, prefix: &str) -> Option<String> {
 // dumpsys activity parser
 // ================================================================
 
-fn parse_foreground_activity(raw: &str) -> (String, String) {
+/// Whether `dumpsys window` reports the lock screen as showing, if it says at all
+pub fn parse_keyguard_showing(raw: &str) -> Option<bool> {
+    let mut found = None;
+    for key in ["mDreamingLockscreen=", "mShowingLockscreen="] {
+        for value in raw.split_whitespace().filter_map(|w| w.strip_prefix(key)) {
+            let showing = value == "true";
+            found = Some(found.unwrap_or(false) || showing);
+        }
+    }
+    found
+}
+
+/// `(package, activity)` of the resumed activity in `dumpsys activity activities` output
+pub fn parse_foreground_activity(raw: &str) -> (String, String) {
     for needle in &["mResumedActivity:", "topResumedActivity:"] {
         for line in raw.lines() {
             if !line.contains(needle) {
@@ -894,6 +1866,49 @@ fn find_component_in_line(line: &str) -> Option<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_notification_dedup_window() {
+        let window = std::time::Duration::from_secs(300);
+        let mut dedup = NotificationDedup::new(window);
+        let t0 = std::time::Instant::now();
+        let key = || "com.whatsapp|Mom|1 new message".to_string();
+
+        let secs = std::time::Duration::from_secs;
+        assert!(dedup.is_new(key(), t0));
+        assert!(!dedup.is_new(key(), t0 + secs(60)));
+        // Still in the shade: each sighting restarts the window
+        assert!(!dedup.is_new(key(), t0 + window));
+        assert!(!dedup.is_new(key(), t0 + window + secs(200)));
+        // Gone for a whole window, then back
+        assert!(dedup.is_new(key(), t0 + window * 2 + secs(200)));
+        assert!(!dedup.is_new(key(), t0 + window * 2 + secs(201)));
+
+        for i in 0..=NotificationDedup::MAX_KEYS {
+            dedup.is_new(format!("k{i}"), t0 + std::time::Duration::from_millis(i as u64));
+        }
+        dedup.trim();
+        assert_eq!(dedup.reported.len(), NotificationDedup::MAX_KEYS / 2);
+        assert!(dedup.reported.contains_key(&key()));
+        assert!(!dedup.reported.contains_key("k0"));
+    }
+
+    #[test]
+    fn test_app_calibration_roundtrip() {
+        let profiles: HashMap<String, AppCalibration> = toml::from_str(
+            r#"
+            ["com.example.app"]
+            x_offset = 10
+            y_offset = -24
+            y_scale = 1.5
+            "#,
+        )
+        .unwrap();
+        let cal = profiles["com.example.app"];
+        assert_eq!(cal.x_scale, 1.0);
+        assert_eq!(cal.apply(100, 200), (110, 276));
+        assert_eq!(cal.invert(110, 276), (100, 200));
+    }
+
     #[test]
     fn test_bounds_center() {
         assert_eq!(bounds_center("[0,0][1080,200]"), Some((540, 100)));
@@ -923,7 +1938,7 @@ mod tests {
     fn test_parse_ui_elements() {
         let xml = r#"<?xml version="1.0" ?><hierarchy rotation="0"><node text="Search" resource-id="com.whatsapp:id/search_bar" class="android.widget.EditText" clickable="true" bounds="[0,100][1080,200]" content-desc="" focused="false" enabled="true" scrollable="false" /><node text="Chats" resource-id="com.whatsapp:id/tab_chats" class="android.widget.TextView" clickable="true" bounds="[0,200][360,300]" content-desc="" focused="false" enabled="true" scrollable="false" /><node text="" resource-id="" class="android.widget.FrameLayout" clickable="false" bounds="[0,0][0,0]" content-desc="" focused="false" enabled="true" scrollable="false" /></hierarchy>"#;
 
-        let elements = parse_ui_elements(xml);
+        let elements = parse_ui_elements(xml, &[], DEFAULT_MAX_ELEMENTS, None, None);
 
         // FrameLayout has zero area → filtered out
         assert_eq!(elements.len(), 2);
@@ -941,6 +1956,92 @@ mod tests {
         assert_eq!(elements[1].index, 2);
     }
 
+    #[test]
+    fn test_element_centers_clamped_to_screen() {
+        let node = |text: &str, bounds: &str| format!(
+            r#"<node text="{text}" resource-id="" class="android.widget.TextView" clickable="true" bounds="{bounds}" content-desc="" focused="false" enabled="true" scrollable="false" />"#
+        );
+        let xml = |rotation: u8| format!(
+            r#"<?xml version="1.0" ?><hierarchy rotation="{rotation}">{}{}{}{}</hierarchy>"#,
+            node("Gone", "[-100,-100][0,0]"),
+            node("Left", "[-200,100][100,200]"),
+            node("Corner", "[1000,2300][1200,2400]"),
+            node("Far", "[1500,500][1700,600]"),
+        );
+        let elements = parse_ui_elements(&xml(0), &[], DEFAULT_MAX_ELEMENTS, Some((1080, 2340)), None);
+        let centers: Vec<_> = elements.iter().map(|e| (e.text.as_str(), e.center_x, e.center_y)).collect();
+        assert_eq!(centers, vec![("Left", 50, 150), ("Corner", 1040, 2320)]);
+
+        // Landscape: the width is 2340, so "Far" is on screen
+        let elements = parse_ui_elements(&xml(1), &[], DEFAULT_MAX_ELEMENTS, Some((1080, 2340)), None);
+        let texts: Vec<_> = elements.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, vec!["Left", "Far"]);
+    }
+
+    #[test]
+    fn test_element_query_matches() {
+        let xml = r#"<?xml version="1.0" ?><hierarchy rotation="0"><node text="Search" resource-id="com.whatsapp:id/search_bar" class="android.widget.EditText" clickable="true" bounds="[0,100][1080,200]" content-desc="" focused="false" enabled="true" scrollable="false" /></hierarchy>"#;
+        let el = &parse_ui_elements(xml, &[], DEFAULT_MAX_ELEMENTS, None, None)[0];
+
+        assert!(ElementQuery::text("search").matches(el));
+        assert!(!ElementQuery::text("Chats").matches(el));
+        let by_id = ElementQuery::from_params(&serde_json::json!({"resource_id": "search_bar"}));
+        assert!(by_id.matches(el));
+        let mismatch = ElementQuery::from_params(&serde_json::json!({"id": "search_bar", "class": "Button"}));
+        assert!(!mismatch.matches(el));
+        assert!(ElementQuery::from_params(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_find_element_prefers_editable_match() {
+        let xml = r#"<?xml version="1.0" ?><hierarchy rotation="0">
+<node text="Search settings" resource-id="com.android.settings:id/title" class="android.widget.TextView" clickable="false" bounds="[0,300][1080,400]" content-desc="" focused="false" enabled="true" scrollable="false" />
+<node text="" resource-id="com.android.settings:id/search_src_text" class="android.widget.EditText" clickable="true" bounds="[0,100][1080,200]" content-desc="" focused="false" enabled="true" scrollable="false" />
+<node text="Wi-Fi" resource-id="com.android.settings:id/title" class="android.widget.TextView" clickable="true" bounds="[0,500][1080,600]" content-desc="" focused="false" enabled="true" scrollable="false" />
+</hierarchy>"#;
+        let elements = parse_ui_elements(xml, &[], DEFAULT_MAX_ELEMENTS, None, None);
+
+        let wifi = best_match(elements.clone(), "wifi", false).unwrap();
+        assert_eq!(wifi.text, "Wi-Fi");
+
+        let label = best_match(elements.clone(), "search", false).unwrap();
+        assert_eq!(label.class, "TextView");
+        let field = best_match(elements.clone(), "search", true).unwrap();
+        assert_eq!(field.class, "EditText");
+
+        assert!(best_match(elements, "bluetooth", false).is_none());
+    }
+
+    #[test]
+    fn test_merge_scroll_pages() {
+        let node = |text: &str, y: i32| format!(
+            r#"<node text="{text}" resource-id="com.app:id/msg" class="android.widget.TextView" clickable="true" bounds="[0,{}][1080,{}]" content-desc="" focused="false" enabled="true" scrollable="false" />"#,
+            y - 50, y + 50
+        );
+        let page = |nodes: &[String]| parse_ui_elements(
+            &format!(r#"<?xml version="1.0" ?><hierarchy rotation="0">{}</hierarchy>"#, nodes.concat()),
+            &[],
+            DEFAULT_MAX_ELEMENTS,
+            None,
+            None,
+        );
+        let first = page(&[node("Alice: hi", 500), node("Bob: hey", 1500)]);
+        let second = page(&[node("Bob: hey", 700), node("Alice: lunch?", 1700)]);
+
+        let step = scroll_step(&first, &second).unwrap();
+        assert_eq!(step, 800);
+        assert_ne!(page_signature(&first), page_signature(&second));
+
+        let (merged, tree) = merge_scroll_pages(vec![
+            ScrollPage { offset: 0, elements: first },
+            ScrollPage { offset: step, elements: second },
+        ]);
+        let texts: Vec<&str> = merged.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["Alice: hi", "Bob: hey", "Alice: lunch?"]);
+        assert_eq!(merged.iter().map(|e| e.index).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(tree.contains("page 2 (scrolled ~800px)"));
+    }
+
     #[test]
     fn test_element_scoring() {
         let edit_score = score_element(
@@ -986,6 +2087,60 @@ mod tests {
         assert!(out.contains("*click*"));
     }
 
+    #[test]
+    fn test_screen_diff() {
+        let el = |index: usize, class: &str, text: &str, id: &str| UiElement {
+            index,
+            class: class.into(),
+            text: text.into(),
+            desc: String::new(),
+            resource_id: id.into(),
+            center_x: 0,
+            center_y: 0,
+            bounds: [0, 0, 0, 0],
+            clickable: true,
+            editable: false,
+            focused: false,
+            scrollable: false,
+            checked: None,
+            enabled: true,
+            score: 1.0,
+        };
+        let screen = |elements: Vec<UiElement>| ScreenState {
+            current_app: "com.example".into(),
+            activity: ".Main".into(),
+            ui_tree: None,
+            elements,
+            screenshot_base64: None,
+            timestamp: String::new(),
+        };
+        let before = screen(vec![
+            el(1, "TextView", "Inbox (3)", "title"),
+            el(2, "TextView", "Alice", "sender"),
+            el(3, "TextView", "Bob", "sender"),
+            el(4, "Button", "Compose", ""),
+        ]);
+        // A dialog shifts every index; matching by id/occurrence ignores that
+        let after = screen(vec![
+            el(1, "TextView", "Delete message?", "alertTitle"),
+            el(2, "Button", "Delete", "button1"),
+            el(3, "TextView", "Inbox (2)", "title"),
+            el(4, "TextView", "Alice", "sender"),
+            el(5, "TextView", "Bob", "sender"),
+        ]);
+
+        let diff = ScreenDiff::between(&before, &after);
+        assert!(diff.app_changed.is_none());
+        assert_eq!(diff.added.len(), 2);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].text, "Compose");
+        assert_eq!(diff.changed.len(), 1);
+        let summary = diff.summary();
+        assert!(summary.contains("+2 added: TextView \"Delete message?\", Button \"Delete\""), "{}", summary);
+        assert!(summary.contains("\"Inbox (3)\" → \"Inbox (2)\""), "{}", summary);
+        assert!(ScreenDiff::between(&after, &after).is_empty());
+    }
+
     #[test]
     fn test_max_elements_cap() {
         let mut xml = String::from("<?xml version=\"1.0\" ?><hierarchy rotation=\"0\">");
@@ -998,9 +2153,9 @@ mod tests {
         }
         xml.push_str("</hierarchy>");
 
-        let elements = parse_ui_elements(&xml);
-        assert!(elements.len() <= MAX_ELEMENTS,
-            "Got {} elements, expected <= {}", elements.len(), MAX_ELEMENTS);
+        let elements = parse_ui_elements(&xml, &[], DEFAULT_MAX_ELEMENTS, None, None);
+        assert_eq!(elements.len(), DEFAULT_MAX_ELEMENTS);
+        assert_eq!(parse_ui_elements(&xml, &[], 10, None, None).len(), 10);
     }
 
     #[test]
@@ -1024,6 +2179,30 @@ mod tests {
         assert_eq!(notifs[0].text, "Hey! Are you coming to dinner tonight?");
     }
 
+    #[test]
+    fn test_parse_notification_actions() {
+        let raw = r#"
+  NotificationRecord(0xabc: pkg=com.whatsapp user=UserHandle{0} id=1)
+    android.title=Mom
+    android.text=dinner at 7?
+    actions={
+      [0] "Reply" -> PendingIntent{4b1c2d3: PendingIntentRecord{b2 com.whatsapp broadcastIntent}}
+      [1] "Mark as read" -> PendingIntent{8e0f1a2: PendingIntentRecord{c3 com.whatsapp broadcastIntent}}
+    }
+  NotificationRecord(0xdef: pkg=com.google.android.gm user=UserHandle{0} id=2)
+    android.title=boss@work.com
+    android.text=Q3 Review
+        "#;
+
+        let notifs = parse_dumpsys_notifications(raw);
+        assert_eq!(notifs[0].actions, vec!["Reply", "Mark as read"]);
+        assert!(notifs[1].actions.is_empty());
+
+        let prompt = Perception::format_notifications(&notifs);
+        assert!(prompt.contains("[com.whatsapp] Mom: dinner at 7? (actions: Reply, Mark as read)"));
+        assert!(prompt.ends_with("boss@work.com: Q3 Review"));
+    }
+
     #[test]
     fn test_parse_foreground() {
         let raw = r#"
@@ -1034,6 +2213,28 @@ mod tests {
         assert_eq!(act, ".HomeActivity");
     }
 
+    #[test]
+    fn test_parse_keyguard_showing() {
+        let locked = "  mShowingLockscreen=true mShowingDream=false mDreamingLockscreen=false";
+        assert_eq!(parse_keyguard_showing(locked), Some(true));
+        let unlocked = "  mShowingLockscreen=false mShowingDream=false mDreamingLockscreen=false";
+        assert_eq!(parse_keyguard_showing(unlocked), Some(false));
+        assert_eq!(parse_keyguard_showing("mCurrentFocus=null"), None);
+    }
+
+    #[test]
+    fn test_poll_strategy_on_demand_skips_idle_tick() {
+        let strategy = PollStrategy::from_str("on_demand");
+        assert_eq!(strategy, PollStrategy::OnDemand);
+        assert!(!strategy.should_poll_screen(1, false));
+        assert!(!strategy.should_poll_screen(4, false));
+        assert!(strategy.should_poll_screen(1, true));
+
+        assert!(PollStrategy::Always.should_poll_screen(1, false));
+        assert!(PollStrategy::Adaptive.should_poll_screen(4, false));
+        assert!(!PollStrategy::Adaptive.should_poll_screen(3, false));
+    }
+
     #[test]
     fn test_vision_fallback_format() {
         let screen = Some(ScreenState {
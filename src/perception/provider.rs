@@ -0,0 +1,214 @@
+//! Where a tick's screen and notifications come from (`perception.bridge_mode`).
+//! The heartbeat only talks to `PerceptionProvider`; a new bridge (scrcpy,
+//! Waydroid) is one more implementation plus a `for_bridge` arm.
+
+use super::Perception;
+use crate::adb::AdbError;
+use crate::config::PerceptionConfig;
+use crate::sanitizer::{self, PerceptionResult, SanitizedScreen, VisionMode};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use tracing::warn;
+
+/// A source of device state. Implementations fill the shared `Perception`
+/// buffers (notifications, screen state) that the tick drains.
+pub trait PerceptionProvider: Send + Sync + std::fmt::Debug {
+    /// The `perception.bridge_mode` this provider serves
+    fn name(&self) -> &'static str;
+
+    /// Pull new notifications into `Perception`; `true` if one is from a
+    /// priority app. Errors only when the device itself is unreachable.
+    fn poll_notifications(&self) -> BoxFuture<'_, Result<bool, AdbError>>;
+
+    /// Refresh `Perception`'s screen state (foreground app, UI elements),
+    /// with a screenshot if asked
+    fn refresh_screen(&self, with_screenshot: bool) -> BoxFuture<'_, ()>;
+
+    /// The current screen formatted for the LLM. With `skip_static`, an idle
+    /// screen may drop its screenshot (`perception.skip_static_screenshots`).
+    fn poll_screen(&self, skip_static: bool) -> BoxFuture<'_, PerceptionResult>;
+
+    /// The current screen as a base64 image, for the `capture` action
+    fn capture_screenshot(&self) -> BoxFuture<'_, Option<String>>;
+}
+
+/// The provider for `perception.bridge_mode`; unknown modes fall back to ADB
+pub fn for_bridge(perception: &Perception, config: &PerceptionConfig) -> Arc<dyn PerceptionProvider> {
+    let vision_mode = VisionMode::from_str(&config.vision_mode);
+    let perception = perception.clone();
+    match config.bridge_mode.as_str() {
+        "websocket" => Arc::new(WebSocketProvider { perception, vision_mode }),
+        other => {
+            if other != "adb" {
+                warn!("Unknown perception.bridge_mode '{}', using adb", other);
+            }
+            Arc::new(AdbProvider { perception, vision_mode })
+        }
+    }
+}
+
+/// Polls the phone with `adb` commands — no companion app needed
+#[derive(Debug)]
+pub struct AdbProvider {
+    perception: Perception,
+    vision_mode: VisionMode,
+}
+
+impl PerceptionProvider for AdbProvider {
+    fn name(&self) -> &'static str {
+        "adb"
+    }
+
+    fn poll_notifications(&self) -> BoxFuture<'_, Result<bool, AdbError>> {
+        Box::pin(self.perception.poll_notifications_adb())
+    }
+
+    fn refresh_screen(&self, with_screenshot: bool) -> BoxFuture<'_, ()> {
+        Box::pin(self.perception.poll_screen_adb_full(with_screenshot))
+    }
+
+    fn poll_screen(&self, skip_static: bool) -> BoxFuture<'_, PerceptionResult> {
+        Box::pin(async move {
+            let p = &self.perception;
+            let mut result = sanitizer::perceive_screen(
+                &p.adb_device,
+                p.ui_tree_dump().await,
+                self.vision_mode,
+                p.max_elements,
+                &p.ignore_packages,
+                p.screenshot_encoding,
+                p.static_screen().filter(|_| skip_static),
+            )
+            .await;
+            p.calibrate_result(&mut result);
+            result
+        })
+    }
+
+    fn capture_screenshot(&self) -> BoxFuture<'_, Option<String>> {
+        Box::pin(async move { self.perception.capture_screenshot_adb() })
+    }
+}
+
+/// Reads what the companion app pushed over `/ws/android`. Polling is a
+/// no-op: the gateway stores notifications and screens as they arrive and
+/// wakes the heartbeat for priority ones itself.
+#[derive(Debug)]
+pub struct WebSocketProvider {
+    perception: Perception,
+    vision_mode: VisionMode,
+}
+
+impl PerceptionProvider for WebSocketProvider {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn poll_notifications(&self) -> BoxFuture<'_, Result<bool, AdbError>> {
+        Box::pin(async { Ok(false) })
+    }
+
+    fn refresh_screen(&self, _with_screenshot: bool) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn poll_screen(&self, _skip_static: bool) -> BoxFuture<'_, PerceptionResult> {
+        Box::pin(async move {
+            let state = self.perception.get_screen_state().await;
+            let resolution = *self.perception.screen_resolution.lock().await;
+            let formatted_text = Perception::format_screen_with_resolution(&state, resolution);
+            let elements = state.as_ref().map(|s| s.elements.as_slice()).unwrap_or_default();
+            let interactive_count = elements.iter().filter(|e| e.clickable || e.editable).count();
+            let needs_vision_fallback = elements.is_empty();
+            let screenshot_base64 = match self.vision_mode {
+                VisionMode::Off => None,
+                VisionMode::Always => state.as_ref().and_then(|s| s.screenshot_base64.clone()),
+                VisionMode::Fallback => state
+                    .as_ref()
+                    .filter(|_| needs_vision_fallback)
+                    .and_then(|s| s.screenshot_base64.clone()),
+            };
+            let screenshot_scale = screenshot_base64.as_deref().and_then(|shot| sanitizer::screenshot_scale(shot, resolution));
+            PerceptionResult {
+                // The elements themselves live in `Perception`'s screen state;
+                // the tick only reads `formatted_text`
+                screen: SanitizedScreen {
+                    elements: Vec::new(),
+                    total_found: elements.len(),
+                    foreground_package: state.as_ref().map(|s| s.current_app.clone()),
+                    needs_vision_fallback,
+                    raw_count: elements.len(),
+                    interactive_count,
+                },
+                used_vision: screenshot_base64.is_some(),
+                screenshot_base64,
+                resolution,
+                formatted_text,
+                screen_unchanged: false,
+                screenshot_scale,
+            }
+        })
+    }
+
+    fn capture_screenshot(&self) -> BoxFuture<'_, Option<String>> {
+        Box::pin(async move {
+            self.perception
+                .get_screen_state()
+                .await
+                .and_then(|s| s.screenshot_base64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perception::ScreenState;
+
+    #[tokio::test]
+    async fn test_websocket_provider_reads_pushed_screen() {
+        let perception = Perception::new(None, vec![]);
+        let mut config: crate::config::Config =
+            toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        config.perception.bridge_mode = "websocket".into();
+        config.perception.vision_mode = "always".into();
+        let provider = for_bridge(&perception, &config.perception);
+        assert_eq!(provider.name(), "websocket");
+        assert!(!provider.poll_notifications().await.unwrap());
+
+        perception
+            .update_screen(ScreenState {
+                current_app: "com.whatsapp".into(),
+                activity: ".HomeActivity".into(),
+                ui_tree: None,
+                elements: Vec::new(),
+                screenshot_base64: Some("iVBORw0KGgo".into()),
+                timestamp: String::new(),
+            })
+            .await;
+        let result = provider.poll_screen(true).await;
+        assert!(result.formatted_text.starts_with("App: com.whatsapp | Activity: .HomeActivity"));
+        assert_eq!(result.screenshot_base64.as_deref(), Some("iVBORw0KGgo"));
+        assert_eq!(provider.capture_screenshot().await.as_deref(), Some("iVBORw0KGgo"));
+    }
+
+    #[tokio::test]
+    async fn test_adb_provider_dumps_ui_once_per_tick() {
+        // No device here, so every dump fails, but failed dumps are cached too
+        let perception = Perception::new(Some("hermitdroid-test-missing".into()), vec![]);
+        let config: crate::config::Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        let provider = for_bridge(&perception, &config.perception);
+        assert_eq!(provider.name(), "adb");
+
+        perception.next_tick();
+        provider.refresh_screen(false).await;
+        provider.poll_screen(false).await;
+        assert_eq!(perception.saved_dumps.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // A new tick dumps again; within it, the re-poll reuses that dump
+        perception.next_tick();
+        provider.poll_screen(false).await;
+        provider.refresh_screen(false).await;
+        assert_eq!(perception.saved_dumps.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+}
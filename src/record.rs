@@ -0,0 +1,508 @@
+use crate::action::CoordinateCalibration;
+use crate::adb;
+use crate::config::Config;
+use crate::flow::{self, Flow, FlowAction};
+use crate::perception::{Perception, UiElement};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+// ── ANSI colors ────────────────────────────────────────────────────────────
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// A touch that moves less than this (screen pixels) is a tap, not a swipe
+const TAP_SLOP_PX: i64 = 30;
+/// A stationary touch held this long is a long press
+const LONG_PRESS_MS: u64 = 500;
+/// Re-read the UI tree once the screen has been left alone this long
+const SETTLE_MS: u64 = 800;
+/// Pauses longer than this between gestures are kept as `wait` steps (capped)
+const MIN_RECORDED_WAIT_MS: u64 = 1500;
+const MAX_RECORDED_WAIT_MS: u64 = 5000;
+
+// ── Public API ─────────────────────────────────────────────────────────────
+
+/// `hermitdroid record <output.yaml>`: follow the touchscreen through
+/// `adb shell getevent` and write what the user does on the phone as a flow.
+///
+/// Taps become `tap_text` when the UI tree has a label that `tap_text` would
+/// find again, coordinates otherwise. Keyboard taps aren't recorded; instead
+/// the text that appears in the focused field is written as one `type` step.
+/// Recording stops on Ctrl+C.
+pub async fn run_record(config: &Config, output: &str) -> anyhow::Result<()> {
+    let adb_device = config.perception.adb_device.clone();
+    let calibration = CoordinateCalibration::from_config(&config.action);
+
+    let devices = adb_shell(&adb_device, &["getevent", "-pl"])?;
+    let touch = parse_touchscreen(&devices)
+        .ok_or_else(|| anyhow::anyhow!("No touchscreen found in `adb shell getevent -pl`"))?;
+    let (width, height) = parse_wm_size(&adb_shell(&adb_device, &["wm", "size"])?)
+        .ok_or_else(|| anyhow::anyhow!("Could not read the screen size from `adb shell wm size`"))?;
+
+    println!("\n{CYAN}{BOLD}⏺  Hermitdroid — Recording{RESET}\n");
+    println!("  {BOLD}Touchscreen:{RESET} {} ({}x{})", touch.path, width, height);
+    println!("  {BOLD}Output:{RESET} {}", output);
+    println!("  {DIM}Use the phone normally. Pause a moment after typing. Ctrl+C to finish.{RESET}\n");
+
+    let perception = Perception::new(adb_device.clone(), vec![]);
+    let mut screen = snapshot(&perception).await;
+    let start_app = perception.get_screen_state().await.map(|s| s.current_app).unwrap_or_default();
+
+    let mut child = tokio::process::Command::new("adb")
+        .args(device_args(&adb_device))
+        .args(["shell", "getevent", "-l"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(adb::AdbError::from_io)?;
+    let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("getevent has no output"))?;
+    let mut lines = BufReader::new(stdout).lines();
+
+    let mut tracker = GestureTracker::default();
+    let mut actions: Vec<FlowAction> = Vec::new();
+    let mut last_gesture: Option<Instant> = None;
+    let mut resnap_at: Option<tokio::time::Instant> = None;
+
+    loop {
+        let resnap = async {
+            match resnap_at {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    println!("  {YELLOW}⚠  getevent stopped (device disconnected?) — saving what was recorded{RESET}");
+                    break;
+                };
+                let Some(event) = parse_getevent_line(&line, &touch.path) else { continue };
+                let Some(gesture) = tracker.feed(event) else { continue };
+                let gesture = gesture.scaled(&touch, width, height, &calibration);
+                resnap_at = Some(tokio::time::Instant::now() + Duration::from_millis(SETTLE_MS));
+
+                if let Gesture::Tap { x, y } = gesture {
+                    if is_keyboard_tap(&screen, x, y) {
+                        continue;
+                    }
+                }
+                let now = Instant::now();
+                if let Some(wait) = last_gesture.and_then(|t| recorded_wait(now - t)) {
+                    actions.push(keyed("wait", wait.into()));
+                }
+                last_gesture = Some(now);
+                let action = gesture.to_flow_action(&screen);
+                println!("  {GREEN}●{RESET} {}", describe(&action));
+                actions.push(action);
+            }
+            _ = resnap => {
+                resnap_at = None;
+                let next = snapshot(&perception).await;
+                if let Some(text) = typed_text(&screen, &next) {
+                    let action = keyed("type", text.into());
+                    println!("  {GREEN}●{RESET} {}", describe(&action));
+                    actions.push(action);
+                }
+                screen = next;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!();
+                break;
+            }
+        }
+    }
+    drop(child);
+
+    // Text typed right before Ctrl+C
+    let last = snapshot(&perception).await;
+    if let Some(text) = typed_text(&screen, &last) {
+        actions.push(keyed("type", text.into()));
+    }
+    if actions.is_empty() {
+        println!("  {YELLOW}Nothing recorded — no flow written.{RESET}\n");
+        return Ok(());
+    }
+
+    let name = Path::new(output)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Recorded flow".into());
+    let description = if start_app.is_empty() {
+        format!("Recorded {}", chrono::Local::now().format("%Y-%m-%d %H:%M"))
+    } else {
+        format!("Recorded {} starting in {}", chrono::Local::now().format("%Y-%m-%d %H:%M"), start_app)
+    };
+    let flow = Flow { name, app_id: None, description: Some(description), allowed_actions: None };
+    flow::write_flow(Path::new(output), &flow, &actions)?;
+    println!("  {GREEN}✅ Saved flow:{RESET} {} ({} actions)", output, actions.len());
+    println!("  {DIM}Play it back with: hermitdroid flow {}{RESET}\n", output);
+    Ok(())
+}
+
+// ── Input events ───────────────────────────────────────────────────────────
+
+/// The touchscreen and its raw axis ranges, from `getevent -pl`
+#[derive(Debug, Clone, PartialEq)]
+struct Touchscreen {
+    path: String,
+    max_x: i64,
+    max_y: i64,
+}
+
+/// First device in `getevent -pl` output that reports multitouch positions
+fn parse_touchscreen(raw: &str) -> Option<Touchscreen> {
+    let mut path: Option<String> = None;
+    let (mut max_x, mut max_y) = (None, None);
+    for line in raw.lines() {
+        if let Some(rest) = line.strip_prefix("add device") {
+            if let (Some(p), Some(x), Some(y)) = (&path, max_x, max_y) {
+                return Some(Touchscreen { path: p.clone(), max_x: x, max_y: y });
+            }
+            path = rest.split_once(':').map(|(_, p)| p.trim().to_string());
+            (max_x, max_y) = (None, None);
+        } else if line.contains("ABS_MT_POSITION_X") {
+            max_x = axis_max(line);
+        } else if line.contains("ABS_MT_POSITION_Y") {
+            max_y = axis_max(line);
+        }
+    }
+    match (path, max_x, max_y) {
+        (Some(path), Some(max_x), Some(max_y)) => Some(Touchscreen { path, max_x, max_y }),
+        _ => None,
+    }
+}
+
+/// `max` from `ABS_MT_POSITION_X : value 0, min 0, max 1079, fuzz 0, ...`
+fn axis_max(line: &str) -> Option<i64> {
+    line.split(',')
+        .find_map(|part| part.trim().strip_prefix("max "))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Logical screen size from `wm size` (an override wins over the physical size)
+fn parse_wm_size(raw: &str) -> Option<(i64, i64)> {
+    let size = |prefix: &str| {
+        raw.lines()
+            .find_map(|l| l.trim().strip_prefix(prefix))
+            .and_then(|s| s.trim().split_once('x'))
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+    };
+    size("Override size:").or_else(|| size("Physical size:"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputEvent {
+    X(i64),
+    Y(i64),
+    Down,
+    Up,
+    /// End of one input frame (`SYN_REPORT`)
+    Sync,
+    Back,
+    Home,
+}
+
+/// One `getevent -l` line, e.g.
+/// `/dev/input/event2: EV_ABS       ABS_MT_POSITION_X    000001f4`.
+/// Position events only count from the touchscreen; keys from any device.
+fn parse_getevent_line(line: &str, touch_path: &str) -> Option<InputEvent> {
+    let (device, rest) = line.split_once(": ").unwrap_or(("", line));
+    let mut parts = rest.split_whitespace();
+    let (kind, code, value) = (parts.next()?, parts.next()?, parts.next()?);
+    let from_touch = device.is_empty() || device.trim() == touch_path;
+    let hex = || i64::from_str_radix(value, 16).ok();
+    match (kind, code) {
+        ("EV_ABS", "ABS_MT_POSITION_X") if from_touch => hex().map(InputEvent::X),
+        ("EV_ABS", "ABS_MT_POSITION_Y") if from_touch => hex().map(InputEvent::Y),
+        ("EV_ABS", "ABS_MT_TRACKING_ID") if from_touch => {
+            Some(if value == "ffffffff" { InputEvent::Up } else { InputEvent::Down })
+        }
+        ("EV_KEY", "BTN_TOUCH") if from_touch => match value {
+            "DOWN" => Some(InputEvent::Down),
+            "UP" => Some(InputEvent::Up),
+            _ => None,
+        },
+        ("EV_SYN", "SYN_REPORT") if from_touch => Some(InputEvent::Sync),
+        ("EV_KEY", "KEY_BACK") if value == "UP" => Some(InputEvent::Back),
+        ("EV_KEY", "KEY_HOMEPAGE" | "KEY_HOME") if value == "UP" => Some(InputEvent::Home),
+        _ => None,
+    }
+}
+
+/// A finished gesture, in raw touchscreen units until `scaled`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Gesture {
+    Tap { x: i64, y: i64 },
+    Swipe { x1: i64, y1: i64, x2: i64, y2: i64, ms: u64 },
+    Back,
+    Home,
+}
+
+/// Turns the event stream into gestures (single touch; extra fingers are ignored)
+#[derive(Debug, Default)]
+struct GestureTracker {
+    down: Option<(Instant, Option<(i64, i64)>)>,
+    x: Option<i64>,
+    y: Option<i64>,
+}
+
+impl GestureTracker {
+    fn feed(&mut self, event: InputEvent) -> Option<Gesture> {
+        match event {
+            InputEvent::X(x) => self.x = Some(x),
+            InputEvent::Y(y) => self.y = Some(y),
+            InputEvent::Down => {
+                if self.down.is_none() {
+                    self.down = Some((Instant::now(), None));
+                }
+            }
+            InputEvent::Up => {
+                let (started, start) = self.down.take()?;
+                let (x2, y2) = (self.x?, self.y?);
+                let (x1, y1) = start.unwrap_or((x2, y2));
+                let ms = started.elapsed().as_millis() as u64;
+                return Some(Gesture::Swipe { x1, y1, x2, y2, ms });
+            }
+            // The position at the end of the touch-down frame is where the gesture starts
+            InputEvent::Sync => {
+                if let (Some((_, start @ None)), Some(x), Some(y)) = (self.down.as_mut(), self.x, self.y) {
+                    *start = Some((x, y));
+                }
+            }
+            InputEvent::Back => return Some(Gesture::Back),
+            InputEvent::Home => return Some(Gesture::Home),
+        }
+        None
+    }
+}
+
+impl Gesture {
+    /// Raw touch units → UI-tree pixels; short, stationary touches become taps
+    fn scaled(self, touch: &Touchscreen, width: i64, height: i64, calibration: &CoordinateCalibration) -> Gesture {
+        let point = |x: i64, y: i64| {
+            let sx = x as f64 * width as f64 / (touch.max_x + 1).max(1) as f64;
+            let sy = y as f64 * height as f64 / (touch.max_y + 1).max(1) as f64;
+            calibration.invert(sx, sy)
+        };
+        match self {
+            Gesture::Swipe { x1, y1, x2, y2, ms } => {
+                let (x1, y1) = point(x1, y1);
+                let (x2, y2) = point(x2, y2);
+                let moved = (x2 - x1).abs().max((y2 - y1).abs());
+                if moved < TAP_SLOP_PX && ms < LONG_PRESS_MS {
+                    Gesture::Tap { x: x1, y: y1 }
+                } else if moved < TAP_SLOP_PX {
+                    // Long press: a swipe that stays put
+                    Gesture::Swipe { x1, y1, x2: x1, y2: y1, ms }
+                } else {
+                    Gesture::Swipe { x1, y1, x2, y2, ms }
+                }
+            }
+            other => other,
+        }
+    }
+
+    fn to_flow_action(self, screen: &[UiElement]) -> FlowAction {
+        match self {
+            Gesture::Tap { x, y } => match label_for_tap(screen, x, y) {
+                Some(label) => keyed("tap_text", label.into()),
+                None => keyed("tap", serde_json::json!([x, y])),
+            },
+            Gesture::Swipe { x1, y1, x2, y2, ms } => keyed("swipe", serde_json::json!([x1, y1, x2, y2, ms])),
+            Gesture::Back => FlowAction::Simple("back".into()),
+            Gesture::Home => FlowAction::Simple("home".into()),
+        }
+    }
+}
+
+// ── Mapping onto the UI tree ───────────────────────────────────────────────
+
+fn contains(e: &UiElement, x: i64, y: i64) -> bool {
+    let [l, t, r, b] = e.bounds.map(i64::from);
+    x >= l && x <= r && y >= t && y <= b
+}
+
+/// A label `tap_text` would resolve back to the tapped element: the
+/// smallest labelled element under the point whose label isn't matched
+/// first by some element elsewhere on screen
+fn label_for_tap(screen: &[UiElement], x: i64, y: i64) -> Option<String> {
+    let mut under: Vec<&UiElement> = screen.iter().filter(|e| contains(e, x, y)).collect();
+    under.sort_by_key(|e| {
+        let [l, t, r, b] = e.bounds.map(i64::from);
+        (r - l) * (b - t)
+    });
+    under.into_iter().find_map(|e| {
+        let label = [e.text.trim(), e.desc.trim()].into_iter().find(|s| !s.is_empty())?;
+        // Replay taps the first element whose text or description contains the label
+        let first = screen.iter().find(|o| o.text.contains(label) || o.desc.contains(label))?;
+        contains(first, x, y).then(|| label.to_string())
+    })
+}
+
+/// A tap outside every element while a text field has focus landed on the
+/// keyboard, which isn't part of the app's UI tree
+fn is_keyboard_tap(screen: &[UiElement], x: i64, y: i64) -> bool {
+    screen.iter().any(|e| e.editable && e.focused) && !screen.iter().any(|e| contains(e, x, y))
+}
+
+/// Text that appeared in an editable field between two snapshots. Returns
+/// only the new part when the field was extended, the whole text otherwise.
+fn typed_text(before: &[UiElement], after: &[UiElement]) -> Option<String> {
+    let field = after
+        .iter()
+        .filter(|e| e.editable)
+        .max_by_key(|e| e.focused)?;
+    let old = before
+        .iter()
+        .find(|e| e.editable && !field.resource_id.is_empty() && e.resource_id == field.resource_id)
+        .or_else(|| before.iter().find(|e| e.editable && e.bounds == field.bounds))
+        .map(|e| e.text.as_str())
+        .unwrap_or("");
+    let new = field.text.as_str();
+    if new.is_empty() || new == old {
+        return None;
+    }
+    Some(new.strip_prefix(old).filter(|s| !old.is_empty() && !s.is_empty()).unwrap_or(new).to_string())
+}
+
+// ── Helpers ────────────────────────────────────────────────────────────────
+
+async fn snapshot(perception: &Perception) -> Vec<UiElement> {
+    perception.poll_screen_adb_full(false).await;
+    perception.get_screen_state().await.map(|s| s.elements).unwrap_or_default()
+}
+
+/// A pause worth replaying, in seconds (rounded to half a second)
+fn recorded_wait(gap: Duration) -> Option<f64> {
+    let ms = gap.as_millis() as u64;
+    (ms >= MIN_RECORDED_WAIT_MS).then(|| (ms.min(MAX_RECORDED_WAIT_MS) as f64 / 500.0).round() / 2.0)
+}
+
+fn keyed(key: &str, value: serde_json::Value) -> FlowAction {
+    let mut map = serde_json::Map::new();
+    map.insert(key.to_string(), value);
+    FlowAction::Keyed(map)
+}
+
+fn describe(action: &FlowAction) -> String {
+    match action {
+        FlowAction::Simple(cmd) => cmd.clone(),
+        FlowAction::Keyed(map) => map.iter().map(|(k, v)| format!("{}: {}", k, v)).collect(),
+    }
+}
+
+fn device_args(adb_device: &Option<String>) -> Vec<String> {
+    adb_device.iter().flat_map(|d| ["-s".to_string(), d.clone()]).collect()
+}
+
+fn adb_shell(adb_device: &Option<String>, args: &[&str]) -> anyhow::Result<String> {
+    let out = adb::output(Command::new("adb").args(device_args(adb_device)).arg("shell").args(args))?;
+    if !out.status.success() {
+        return Err(adb::AdbError::from_output(out.status.code(), &String::from_utf8_lossy(&out.stderr)).into());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(text: &str, bounds: [i32; 4]) -> UiElement {
+        UiElement {
+            index: 1,
+            class: "TextView".into(),
+            text: text.into(),
+            desc: String::new(),
+            resource_id: String::new(),
+            center_x: (bounds[0] + bounds[2]) / 2,
+            center_y: (bounds[1] + bounds[3]) / 2,
+            bounds,
+            clickable: true,
+            editable: false,
+            focused: false,
+            scrollable: false,
+            checked: None,
+            enabled: true,
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_device_info() {
+        let devices = "add device 1: /dev/input/event0\n  name:     \"gpio-keys\"\n  events:\n    KEY (0001): KEY_VOLUMEDOWN\n\
+                       add device 2: /dev/input/event2\n  name:     \"touchscreen\"\n  events:\n    \
+                       ABS (0003): ABS_MT_SLOT           : value 0, min 0, max 9, fuzz 0, flat 0, resolution 0\n                \
+                       ABS_MT_POSITION_X     : value 0, min 0, max 4095, fuzz 0, flat 0, resolution 0\n                \
+                       ABS_MT_POSITION_Y     : value 0, min 0, max 4095, fuzz 0, flat 0, resolution 0\n";
+        assert_eq!(
+            parse_touchscreen(devices),
+            Some(Touchscreen { path: "/dev/input/event2".into(), max_x: 4095, max_y: 4095 })
+        );
+        assert_eq!(parse_wm_size("Physical size: 1080x2400\n"), Some((1080, 2400)));
+        assert_eq!(parse_wm_size("Physical size: 1080x2400\nOverride size: 720x1600\n"), Some((720, 1600)));
+
+        let touch = "/dev/input/event2";
+        assert_eq!(parse_getevent_line("/dev/input/event2: EV_ABS       ABS_MT_POSITION_X    000001f4", touch), Some(InputEvent::X(500)));
+        assert_eq!(parse_getevent_line("/dev/input/event2: EV_ABS       ABS_MT_TRACKING_ID   ffffffff", touch), Some(InputEvent::Up));
+        assert_eq!(parse_getevent_line("/dev/input/event0: EV_KEY       KEY_BACK             UP", touch), Some(InputEvent::Back));
+        assert_eq!(parse_getevent_line("/dev/input/event5: EV_ABS       ABS_MT_POSITION_X    000001f4", touch), None);
+        assert_eq!(parse_getevent_line("/dev/input/event2: EV_SYN       SYN_REPORT           00000000", touch), Some(InputEvent::Sync));
+        assert_eq!(parse_getevent_line("/dev/input/event2: EV_SYN       SYN_MT_REPORT        00000000", touch), None);
+    }
+
+    #[test]
+    fn test_gestures_to_flow_actions() {
+        let touch = Touchscreen { path: String::new(), max_x: 1079, max_y: 2399 };
+        let calibration = CoordinateCalibration::default();
+        let mut tracker = GestureTracker::default();
+        use InputEvent::*;
+        let events = [Down, X(540), Y(1200), Sync, Y(1205), Sync, Up, Sync];
+        let gesture = events.into_iter().find_map(|e| tracker.feed(e)).unwrap();
+        assert_eq!(gesture.scaled(&touch, 1080, 2400, &calibration), Gesture::Tap { x: 540, y: 1200 });
+
+        // Only changed axes are reported, so Y is stale until its own event arrives
+        let events = [Down, X(500), Y(1800), Sync, Y(1200), Sync, Y(600), Sync, Up, Sync];
+        let swipe = events.into_iter().find_map(|e| tracker.feed(e)).unwrap().scaled(&touch, 1080, 2400, &calibration);
+        assert!(matches!(swipe, Gesture::Swipe { x1: 500, y1: 1800, x2: 500, y2: 600, .. }));
+
+        let screen = vec![
+            element("Settings", [0, 0, 1080, 200]),
+            element("Wi-Fi calling", [0, 300, 1080, 400]),
+            element("Wi-Fi", [0, 500, 1080, 600]),
+        ];
+        let tap = |x, y| Gesture::Tap { x, y }.to_flow_action(&screen);
+        assert_eq!(describe(&tap(540, 350)), "tap_text: \"Wi-Fi calling\"");
+        // "Wi-Fi" would hit the row above, so this one stays a coordinate tap
+        assert_eq!(describe(&tap(540, 550)), "tap: [540,550]");
+        assert_eq!(describe(&tap(540, 1500)), "tap: [540,1500]");
+    }
+
+    #[test]
+    fn test_typed_text_and_keyboard_taps() {
+        let mut field = element("Search", [0, 100, 1080, 200]);
+        field.editable = true;
+        field.focused = true;
+        field.resource_id = "search".into();
+        let before = vec![field.clone()];
+        assert!(is_keyboard_tap(&before, 540, 2000));
+        assert!(!is_keyboard_tap(&before, 540, 150));
+
+        let mut typed = field.clone();
+        typed.text = "lofi".into();
+        assert_eq!(typed_text(&before, &[typed.clone()]), Some("lofi".into()));
+        let mut more = typed.clone();
+        more.text = "lofi beats".into();
+        assert_eq!(typed_text(&[typed.clone()], &[more]), Some(" beats".into()));
+        assert_eq!(typed_text(&[typed.clone()], &[typed]), None);
+        assert_eq!(recorded_wait(Duration::from_millis(900)), None);
+        assert_eq!(recorded_wait(Duration::from_millis(2300)), Some(2.5));
+        assert_eq!(recorded_wait(Duration::from_secs(30)), Some(5.0));
+    }
+}
@@ -0,0 +1,58 @@
+use tokio::sync::broadcast;
+
+/// Progress reporting for flows/workflows started over the HTTP API.
+/// Each update is broadcast on `event_tx` as
+/// `{"event": "run_step", "run_id": "...", ...}` so `/ws/user` and `/events`
+/// clients can follow along. CLI runs pass `None` and only print.
+#[derive(Debug, Clone)]
+pub struct RunProgress {
+    run_id: String,
+    event_tx: broadcast::Sender<String>,
+}
+
+impl RunProgress {
+    pub fn new(event_tx: broadcast::Sender<String>) -> Self {
+        let run_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+        Self { run_id, event_tx }
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Broadcast `event` with the fields of `data` (a JSON object) merged in
+    pub fn emit(&self, event: &str, data: serde_json::Value) {
+        let mut msg = serde_json::json!({"event": event, "run_id": self.run_id});
+        if let (Some(obj), serde_json::Value::Object(fields)) = (msg.as_object_mut(), data) {
+            obj.extend(fields);
+        }
+        let _ = self.event_tx.send(msg.to_string());
+    }
+}
+
+/// Progress events from a run — they shouldn't wake the heartbeat
+pub fn is_run_event(ev: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(ev)
+        .map(|v| v.get("run_id").is_some())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_merges_fields() {
+        let (tx, mut rx) = broadcast::channel(4);
+        let progress = RunProgress::new(tx);
+        progress.emit("run_step", serde_json::json!({"step": 2, "ok": true}));
+
+        let ev = rx.try_recv().unwrap();
+        assert!(is_run_event(&ev));
+        let v: serde_json::Value = serde_json::from_str(&ev).unwrap();
+        assert_eq!(v["event"], "run_step");
+        assert_eq!(v["run_id"], progress.run_id());
+        assert_eq!(v["step"], 2);
+        assert!(!is_run_event(r#"{"type":"user_command","text":"hi"}"#));
+    }
+}
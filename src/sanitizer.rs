@@ -1,3 +1,4 @@
+use crate::perception::{oriented_resolution, visible_bounds, DEFAULT_MAX_ELEMENTS};
 use std::collections::HashMap;
 use tracing::{debug, trace, warn};
 
@@ -98,10 +99,32 @@ impl VisionMode {
     }
 }
 
-// ── Constants ────────────────────────────────────────────────────────────────
+/// A uiautomator dump with the `wm size` of the screen it came from and the
+/// foreground package from `dumpsys activity` (see `parse_accessibility_xml`)
+#[derive(Debug, Clone)]
+pub struct TreeDump {
+    pub xml: String,
+    pub resolution: Option<(u32, u32)>,
+    pub foreground: Option<String>,
+}
+
+/// How captured screenshots are encoded before being sent to the model.
+/// `max_width: None` passes the device PNG through untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenshotEncoding {
+    /// Downscale to this width (aspect ratio preserved) and re-encode as JPEG
+    pub max_width: Option<u32>,
+    /// JPEG quality 1-100
+    pub jpeg_quality: u8,
+}
 
-/// Default max UI elements to send to the LLM
-pub const DEFAULT_MAX_ELEMENTS: usize = 50;
+impl Default for ScreenshotEncoding {
+    fn default() -> Self {
+        Self { max_width: None, jpeg_quality: 80 }
+    }
+}
+
+// ── Constants ────────────────────────────────────────────────────────────────
 
 /// Minimum interactive elements before triggering vision fallback
 /// (WebViews, Flutter, games often have 0-3 interactive elements)
@@ -126,7 +149,20 @@ const VISION_FALLBACK_THRESHOLD: usize = 5;
 ///   </node>
 /// </hierarchy>
 /// ```
-pub fn parse_accessibility_xml(xml: &str, max_elements: usize) -> SanitizedScreen {
+///
+/// Nodes from `ignore_packages` (status bar, launcher, ...) are dropped unless
+/// that package is the `foreground` app; without it, the foreground app is
+/// guessed as the most common package among the nodes. With the screen `resolution`, nodes
+/// entirely off-screen are dropped too, and centers are taken from the
+/// on-screen part of the bounds so a tap doesn't land past the edge.
+pub fn parse_accessibility_xml(
+    xml: &str,
+    max_elements: usize,
+    ignore_packages: &[String],
+    resolution: Option<(u32, u32)>,
+    foreground: Option<&str>,
+) -> SanitizedScreen {
+    let resolution = oriented_resolution(xml, resolution);
     let mut elements: Vec<UiElement> = Vec::new();
     let mut package_counts: HashMap<String, usize> = HashMap::new();
     let mut index: usize = 0;
@@ -150,7 +186,7 @@ pub fn parse_accessibility_xml(xml: &str, max_elements: usize) -> SanitizedScree
                 let tag = &xml[start..=tag_end];
 
                 // Parse attributes from this node tag
-                if let Some(elem) = parse_node_tag(tag, index) {
+                if let Some(elem) = parse_node_tag(tag, index, resolution) {
                     // Track package counts for foreground detection
                     if !elem.package.is_empty() {
                         *package_counts.entry(elem.package.clone()).or_insert(0) += 1;
@@ -169,6 +205,17 @@ pub fn parse_accessibility_xml(xml: &str, max_elements: usize) -> SanitizedScree
         }
     }
 
+    // Foreground = the resumed activity's app, else the most common non-ignored
+    // package (the launcher counts if it's all there is)
+    let foreground_package = foreground
+        .map(str::to_string)
+        .or_else(|| foreground_package(&package_counts, ignore_packages));
+    let before = elements.len();
+    elements.retain(|e| keep_package(&e.package, ignore_packages, foreground_package.as_deref()));
+    if elements.len() < before {
+        trace!("Dropped {} element(s) from ignored packages", before - elements.len());
+    }
+
     let raw_count = elements.len();
 
     // Count interactive elements before scoring
@@ -200,13 +247,6 @@ pub fn parse_accessibility_xml(xml: &str, max_elements: usize) -> SanitizedScree
         elem.index = i + 1; // 1-based indexing for LLM
     }
 
-    // Determine foreground package (most common package in the tree)
-    let foreground_package = package_counts
-        .into_iter()
-        .max_by_key(|(_, count)| *count)
-        .map(|(pkg, _)| pkg)
-        .filter(|pkg| !pkg.is_empty());
-
     // Determine if vision fallback is needed
     let needs_vision_fallback = interactive_count < VISION_FALLBACK_THRESHOLD;
 
@@ -227,6 +267,25 @@ pub fn parse_accessibility_xml(xml: &str, max_elements: usize) -> SanitizedScree
     }
 }
 
+/// Most common package among nodes, preferring packages not in `ignore_packages`
+pub fn foreground_package(counts: &HashMap<String, usize>, ignore_packages: &[String]) -> Option<String> {
+    let top = |skip_ignored: bool| {
+        counts
+            .iter()
+            .filter(|(pkg, _)| !pkg.is_empty())
+            .filter(|(pkg, _)| !skip_ignored || !ignore_packages.contains(pkg))
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(pkg, _)| pkg.clone())
+    };
+    top(true).or_else(|| top(false))
+}
+
+/// Whether a node from `package` survives the `ignore_packages` filter.
+/// The foreground app is always kept, even if it is listed (e.g. the launcher).
+pub fn keep_package(package: &str, ignore_packages: &[String], foreground: Option<&str>) -> bool {
+    Some(package) == foreground || !ignore_packages.iter().any(|p| p == package)
+}
+
 // ── Formatting ───────────────────────────────────────────────────────────────
 
 /// Format the sanitized screen as a text representation for the LLM.
@@ -466,8 +525,9 @@ fn is_useful_element(elem: &UiElement) -> bool {
 
 // ── XML Attribute Parsing ────────────────────────────────────────────────────
 
-/// Parse a single <node ...> tag into a UiElement.
-fn parse_node_tag(tag: &str, index: usize) -> Option<UiElement> {
+/// Parse a single <node ...> tag into a UiElement; `None` for one entirely
+/// off-screen (see `perception::visible_bounds`).
+fn parse_node_tag(tag: &str, index: usize, resolution: Option<(u32, u32)>) -> Option<UiElement> {
     let text = get_attr(tag, "text").unwrap_or_default();
     let content_desc = get_attr(tag, "content-desc").unwrap_or_default();
     let resource_id = get_attr(tag, "resource-id").unwrap_or_default();
@@ -478,9 +538,10 @@ fn parse_node_tag(tag: &str, index: usize) -> Option<UiElement> {
     // Parse bounds "[left,top][right,bottom]"
     let bounds = parse_bounds(&bounds_str)?;
 
-    // Compute center
-    let cx = (bounds[0] + bounds[2]) / 2;
-    let cy = (bounds[1] + bounds[3]) / 2;
+    // Compute center of the visible part
+    let visible = visible_bounds(&bounds, resolution)?;
+    let cx = (visible[0] + visible[2]) / 2;
+    let cy = (visible[1] + visible[3]) / 2;
 
     // Parse boolean attributes
     let clickable = get_bool_attr(tag, "clickable");
@@ -605,95 +666,92 @@ fn find_substr(haystack: &str, from: usize, needle: &str) -> Option<usize> {
 
 // ── ADB Integration ──────────────────────────────────────────────────────────
 
-/// Dump the accessibility tree via ADB.
+/// Take a screenshot via ADB and return it base64-encoded — PNG, or JPEG
+/// when `encoding.max_width` is set.
 ///
-/// Runs: `adb shell uiautomator dump /dev/tty`
-/// Returns the raw XML string, or None if the command fails.
-pub async fn dump_accessibility_tree(adb_device: &Option<String>) -> Option<String> {
+/// Runs: `adb exec-out screencap -p` → [`encode_screenshot`]
+pub async fn take_screenshot_base64(adb_device: &Option<String>, encoding: ScreenshotEncoding) -> Option<String> {
     let mut cmd = tokio::process::Command::new("adb");
 
     if let Some(ref device) = adb_device {
         cmd.args(["-s", device]);
     }
 
-    // Dump to /dev/tty prints to stdout instead of a file
-    cmd.args(["shell", "uiautomator", "dump", "/dev/tty"]);
+    cmd.args(["exec-out", "screencap", "-p"]);
 
     let start = std::time::Instant::now();
-    match cmd.output().await {
+    match crate::adb::output_async(&mut cmd).await {
         Ok(output) => {
             let elapsed = start.elapsed().as_millis();
-            if output.status.success() {
-                let raw = String::from_utf8_lossy(&output.stdout).to_string();
-                debug!(
-                    "Accessibility tree dump: {} bytes in {}ms",
-                    raw.len(),
-                    elapsed
-                );
-
-                // The output sometimes has a prefix like "UI hierchary dumped to: /dev/tty\n"
-                // followed by the actual XML. Find the XML start.
-                if let Some(xml_start) = raw.find("<?xml") {
-                    Some(raw[xml_start..].to_string())
-                } else if let Some(xml_start) = raw.find("<hierarchy") {
-                    Some(raw[xml_start..].to_string())
-                } else if raw.contains("<node") {
-                    // Sometimes the XML doesn't have a proper header
-                    Some(raw)
-                } else {
-                    debug!("No XML content in uiautomator dump output");
-                    None
-                }
+            if output.status.success() && !output.stdout.is_empty() {
+                debug!("Screenshot captured: {} bytes in {}ms", output.stdout.len(), elapsed);
+                Some(encode_screenshot(&output.stdout, encoding))
             } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                debug!("uiautomator dump failed ({}ms): {}", elapsed, stderr.trim());
+                debug!("Screenshot capture failed ({}ms)", elapsed);
                 None
             }
         }
         Err(e) => {
-            warn!("Failed to run uiautomator dump: {}", e);
+            warn!("Failed to take screenshot: {}", e);
             None
         }
     }
 }
 
-/// Take a screenshot via ADB and return base64-encoded PNG.
-///
-/// Runs: `adb exec-out screencap -p` → pipe to base64
-pub async fn take_screenshot_base64(adb_device: &Option<String>) -> Option<String> {
+/// Base64-encode a captured PNG, downscaling and re-encoding it as JPEG when
+/// `encoding.max_width` is set. Falls back to the original PNG if the image
+/// can't be decoded or re-encoded.
+pub fn encode_screenshot(png: &[u8], encoding: ScreenshotEncoding) -> String {
     use base64::Engine;
 
-    let mut cmd = tokio::process::Command::new("adb");
-
-    if let Some(ref device) = adb_device {
-        cmd.args(["-s", device]);
-    }
-
-    cmd.args(["exec-out", "screencap", "-p"]);
-
-    let start = std::time::Instant::now();
-    match cmd.output().await {
-        Ok(output) => {
-            let elapsed = start.elapsed().as_millis();
-            if output.status.success() && !output.stdout.is_empty() {
-                let encoded = base64::engine::general_purpose::STANDARD.encode(&output.stdout);
+    let bytes = match encoding.max_width {
+        Some(max_width) => match downscale_to_jpeg(png, max_width, encoding.jpeg_quality) {
+            Ok(jpeg) => {
                 debug!(
-                    "Screenshot captured: {} bytes → {} base64 chars in {}ms",
-                    output.stdout.len(),
-                    encoded.len(),
-                    elapsed
+                    "Screenshot re-encoded: {} KB PNG → {} KB JPEG (max width {}, quality {})",
+                    png.len() / 1024,
+                    jpeg.len() / 1024,
+                    max_width,
+                    encoding.jpeg_quality
                 );
-                Some(encoded)
-            } else {
-                debug!("Screenshot capture failed ({}ms)", elapsed);
-                None
+                jpeg
             }
-        }
-        Err(e) => {
-            warn!("Failed to take screenshot: {}", e);
-            None
-        }
+            Err(e) => {
+                warn!("Screenshot re-encode failed, sending PNG: {}", e);
+                png.to_vec()
+            }
+        },
+        None => png.to_vec(),
+    };
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// How many screen pixels one pixel of a base64 screenshot spans when it
+/// was downscaled (`screenshot_max_width`); `None` at full size. Compares
+/// the long sides, so it holds in landscape too.
+pub fn screenshot_scale(screenshot_b64: &str, resolution: Option<(u32, u32)>) -> Option<f64> {
+    use base64::Engine;
+    let (w, h) = resolution?;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(screenshot_b64).ok()?;
+    let (iw, ih) = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    let scale = w.max(h) as f64 / iw.max(ih).max(1) as f64;
+    (scale > 1.01).then_some(scale)
+}
+
+fn downscale_to_jpeg(png: &[u8], max_width: u32, quality: u8) -> anyhow::Result<Vec<u8>> {
+    let mut img = image::load_from_memory_with_format(png, image::ImageFormat::Png)?;
+    if max_width > 0 && img.width() > max_width {
+        let height = (img.height() as u64 * max_width as u64 / img.width() as u64).max(1) as u32;
+        img = img.resize_exact(max_width, height, image::imageops::FilterType::Triangle);
     }
+    let mut out = Vec::new();
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100));
+    img.to_rgb8().write_with_encoder(encoder)?;
+    Ok(out)
 }
 
 /// Get device screen resolution via ADB.
@@ -708,7 +766,7 @@ pub async fn get_screen_resolution(adb_device: &Option<String>) -> Option<(u32,
 
     cmd.args(["shell", "wm", "size"]);
 
-    match cmd.output().await {
+    match crate::adb::output_async(&mut cmd).await {
         Ok(output) if output.status.success() => {
             let text = String::from_utf8_lossy(&output.stdout);
             // Parse "Physical size: 1080x2400" or "Override size: 1080x2400"
@@ -730,24 +788,32 @@ pub async fn get_screen_resolution(adb_device: &Option<String>) -> Option<(u32,
 
 // ── High-level perception function ───────────────────────────────────────────
 
-/// Complete perception step: dump accessibility tree, optionally take screenshot.
+/// Complete perception step: parse the accessibility tree, optionally take a
+/// screenshot. `tree` is the uiautomator dump (`Perception::ui_tree_dump`, cached
+/// per tick), so this never dumps the tree itself.
 ///
 /// This is the main entry point for the perception system.
 /// It implements the vision fallback strategy:
 ///   - `VisionMode::Off`:      tree only, never screenshot
 ///   - `VisionMode::Fallback`: tree first, screenshot only if tree is sparse
 ///   - `VisionMode::Always`:   tree + screenshot every step
+///
+/// With a `static_screen` tracker, a screenshot identical to the last one sent
+/// (same elements, same average hash) is dropped and `screen_unchanged` set.
 pub async fn perceive_screen(
     adb_device: &Option<String>,
+    tree: Option<TreeDump>,
     vision_mode: VisionMode,
     max_elements: usize,
+    ignore_packages: &[String],
+    screenshot_encoding: ScreenshotEncoding,
+    static_screen: Option<&StaticScreenTracker>,
 ) -> PerceptionResult {
-    // Step 1: Always dump the accessibility tree (fast, ~100-300ms)
-    let tree_xml = dump_accessibility_tree(adb_device).await;
-
-    // Step 2: Parse it
-    let screen = match tree_xml {
-        Some(ref xml) => parse_accessibility_xml(xml, max_elements),
+    // Step 1-2: Parse the accessibility tree
+    let screen = match tree {
+        Some(ref tree) => {
+            parse_accessibility_xml(&tree.xml, max_elements, ignore_packages, tree.resolution, tree.foreground.as_deref())
+        }
         None => {
             debug!("No accessibility tree available");
             SanitizedScreen {
@@ -768,8 +834,8 @@ pub async fn perceive_screen(
         VisionMode::Fallback => screen.needs_vision_fallback,
     };
 
-    let screenshot_b64 = if need_screenshot {
-        take_screenshot_base64(adb_device).await
+    let mut screenshot_b64 = if need_screenshot {
+        take_screenshot_base64(adb_device, screenshot_encoding).await
     } else {
         None
     };
@@ -779,16 +845,94 @@ pub async fn perceive_screen(
 
     // Step 5: Format for LLM
     let formatted_text = format_for_llm(&screen, resolution);
+    let mut screen_unchanged = false;
+
+    // Step 6: Don't re-upload an idle screen
+    if let (Some(tracker), Some(shot)) = (static_screen, screenshot_b64.as_deref()) {
+        if tracker.should_skip(&formatted_text, shot) {
+            debug!("Screen static — screenshot skipped");
+            screenshot_b64 = None;
+            screen_unchanged = true;
+        }
+    }
+
+    let screenshot_scale = screenshot_b64.as_deref().and_then(|shot| screenshot_scale(shot, resolution));
 
     PerceptionResult {
         screen,
+        used_vision: screenshot_b64.is_some(),
         screenshot_base64: screenshot_b64,
         resolution,
         formatted_text,
-        used_vision: need_screenshot,
+        screen_unchanged,
+        screenshot_scale,
+    }
+}
+
+/// A static screen is still re-sent after this many skipped steps at most;
+/// the gap doubles from 2 up to this cap while nothing changes
+pub const MAX_STATIC_SKIP_STEPS: u32 = 32;
+
+/// Remembers the last screen sent with a screenshot so `perceive_screen` can
+/// skip identical ones while the phone sits idle. Any change resets it.
+#[derive(Debug, Default)]
+pub struct StaticScreenTracker {
+    state: std::sync::Mutex<StaticScreenState>,
+}
+
+#[derive(Debug, Default)]
+struct StaticScreenState {
+    /// (elements hash, screenshot average hash) of the last screen sent
+    last: Option<(u64, u64)>,
+    /// Static steps skipped since the image was last sent
+    skipped: u32,
+    /// Send again once `skipped` reaches this
+    resend_after: u32,
+}
+
+impl StaticScreenTracker {
+    /// `true` when this screen matches the last one sent and the image can be
+    /// dropped. Records the screen as sent otherwise.
+    pub fn should_skip(&self, elements_text: &str, screenshot_b64: &str) -> bool {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        elements_text.hash(&mut hasher);
+        let current = (hasher.finish(), screenshot_average_hash(screenshot_b64));
+
+        let mut state = self.state.lock().unwrap();
+        if state.last != Some(current) {
+            *state = StaticScreenState { last: Some(current), skipped: 0, resend_after: 2 };
+            return false;
+        }
+        state.skipped += 1;
+        if state.skipped < state.resend_after {
+            return true;
+        }
+        state.skipped = 0;
+        state.resend_after = (state.resend_after * 2).min(MAX_STATIC_SKIP_STEPS);
+        false
     }
 }
 
+/// 64-bit average hash of an 8×8 grayscale thumbnail: ignores compression
+/// noise and tiny changes like the status-bar clock. 0 if undecodable.
+fn screenshot_average_hash(screenshot_b64: &str) -> u64 {
+    use base64::Engine;
+    let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(screenshot_b64) else {
+        return 0;
+    };
+    let Ok(img) = image::load_from_memory(&bytes) else {
+        return 0;
+    };
+    let thumb = img.resize_exact(8, 8, image::imageops::FilterType::Triangle).to_luma8();
+    let mean = thumb.pixels().map(|p| p.0[0] as u32).sum::<u32>() / 64;
+    thumb
+        .pixels()
+        .enumerate()
+        .filter(|(_, p)| p.0[0] as u32 > mean)
+        .fold(0u64, |hash, (i, _)| hash | (1 << i))
+}
+
 /// Complete result from a perception step.
 #[derive(Debug)]
 pub struct PerceptionResult {
@@ -802,6 +946,29 @@ pub struct PerceptionResult {
     pub formatted_text: String,
     /// Whether vision (screenshot) was used this step
     pub used_vision: bool,
+    /// The screenshot was dropped because nothing changed (`StaticScreenTracker`).
+    /// Only `prompt_text` says so; `formatted_text` stays comparable.
+    pub screen_unchanged: bool,
+    /// The attached screenshot is downscaled by this factor (see `screenshot_scale`)
+    pub screenshot_scale: Option<f64>,
+}
+
+impl PerceptionResult {
+    /// `formatted_text` for the prompt, noting a skipped screenshot, or how
+    /// to turn a downscaled screenshot's pixels into screen coordinates
+    pub fn prompt_text(&self) -> String {
+        let mut text = self.formatted_text.clone();
+        if self.screen_unchanged {
+            text.push_str("\n[screen unchanged]");
+        }
+        if let Some(scale) = self.screenshot_scale {
+            text.push_str(&format!(
+                "\nThe screenshot is scaled down: multiply coordinates read off it by {:.3} to get screen pixels (element coordinates above are already in screen pixels)",
+                scale
+            ));
+        }
+        text
+    }
 }
 
 // ── Tests ────────────────────────────────────────────────────────────────────
@@ -826,7 +993,7 @@ mod tests {
 
     #[test]
     fn test_parse_accessibility_xml() {
-        let result = parse_accessibility_xml(SAMPLE_XML, 50);
+        let result = parse_accessibility_xml(SAMPLE_XML, 50, &[], None, None);
         assert_eq!(result.raw_count, 4); // 4 useful elements (FrameLayout filtered)
         assert!(result.foreground_package.as_deref() == Some("com.whatsapp"));
         assert!(!result.needs_vision_fallback); // Has enough interactive elements
@@ -920,8 +1087,156 @@ mod tests {
 
     #[test]
     fn test_empty_tree_triggers_fallback() {
-        let result = parse_accessibility_xml("", 50);
+        let result = parse_accessibility_xml("", 50, &[], None, None);
         assert!(result.needs_vision_fallback);
         assert_eq!(result.interactive_count, 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_elements_clipped_to_screen() {
+        let node = |text: &str, bounds: &str| {
+            format!(
+                r#"<node index="0" text="{text}" resource-id="" class="android.widget.Button" package="com.example" content-desc="" clickable="true" enabled="true" focusable="true" focused="false" scrollable="false" long-clickable="false" password="false" selected="false" bounds="{bounds}" />"#
+            )
+        };
+        let xml = format!(
+            r#"<hierarchy rotation="0">{}{}{}</hierarchy>"#,
+            node("Above", "[0,-300][1080,-100]"),
+            node("Peeking", "[0,2200][1080,2600]"),
+            node("Right", "[1200,500][1400,600]"),
+        );
+        let screen = parse_accessibility_xml(&xml, 50, &[], Some((1080, 2340)), None);
+        let centers: Vec<_> = screen.elements.iter().map(|e| (e.text.as_str(), e.center)).collect();
+        assert_eq!(centers, vec![("Peeking", (540, 2270))]);
+
+        // Unknown resolution: only the top and left edges clip
+        let screen = parse_accessibility_xml(&xml, 50, &[], None, None);
+        assert_eq!(screen.elements.len(), 2);
+    }
+
+    #[test]
+    fn test_ignore_packages_drops_overlay_nodes() {
+        let node = |pkg: &str, text: &str, y: u32| {
+            format!(
+                r#"<node index="0" text="{text}" resource-id="" class="android.widget.TextView" package="{pkg}" content-desc="" clickable="true" enabled="true" focusable="true" focused="false" scrollable="false" long-clickable="false" password="false" selected="false" bounds="[0,{y}][500,{}]" />"#,
+                y + 60
+            )
+        };
+        let ignore = vec!["com.android.systemui".to_string(), "com.android.launcher3".to_string()];
+
+        let mixed = format!(
+            "<hierarchy>{}{}{}{}</hierarchy>",
+            node("com.android.systemui", "12:00", 0),
+            node("com.android.systemui", "Back", 2300),
+            node("com.whatsapp", "Chats", 200),
+            node("com.whatsapp", "Calls", 300),
+        );
+        let screen = parse_accessibility_xml(&mixed, 50, &ignore, None, None);
+        assert_eq!(screen.foreground_package.as_deref(), Some("com.whatsapp"));
+        assert_eq!(screen.elements.len(), 2);
+        assert!(screen.elements.iter().all(|e| e.package == "com.whatsapp"));
+
+        // On the home screen the launcher is the foreground app and stays
+        let home = format!(
+            "<hierarchy>{}{}{}</hierarchy>",
+            node("com.android.systemui", "12:00", 0),
+            node("com.android.launcher3", "YouTube", 800),
+            node("com.android.launcher3", "Maps", 900),
+        );
+        let screen = parse_accessibility_xml(&home, 50, &ignore, None, None);
+        assert_eq!(screen.foreground_package.as_deref(), Some("com.android.launcher3"));
+        assert_eq!(screen.elements.len(), 2);
+
+        // `dumpsys activity` knows better than the node count: on the home
+        // screen under a picture-in-picture video, the launcher still stays
+        let pip = format!(
+            "<hierarchy>{}{}{}</hierarchy>",
+            node("com.android.launcher3", "Maps", 900),
+            node("com.google.android.youtube", "Pause", 1200),
+            node("com.google.android.youtube", "Close", 1300),
+        );
+        let screen = parse_accessibility_xml(&pip, 50, &ignore, None, None);
+        assert_eq!(screen.elements.len(), 2);
+        let screen = parse_accessibility_xml(&pip, 50, &ignore, None, Some("com.android.launcher3"));
+        assert_eq!(screen.foreground_package.as_deref(), Some("com.android.launcher3"));
+        assert_eq!(screen.elements.len(), 3);
+    }
+
+    #[test]
+    fn test_encode_screenshot_downscales_to_jpeg() {
+        use base64::Engine;
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(400, 800, image::Rgb([30, 120, 200]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let decode = |b64: String| base64::engine::general_purpose::STANDARD.decode(b64).unwrap();
+
+        // Unset → untouched PNG
+        assert_eq!(decode(encode_screenshot(&png, ScreenshotEncoding::default())), png);
+
+        let encoding = ScreenshotEncoding { max_width: Some(200), jpeg_quality: 70 };
+        let jpeg = decode(encode_screenshot(&png, encoding));
+        assert_eq!(image::guess_format(&jpeg).unwrap(), image::ImageFormat::Jpeg);
+        let img = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((img.width(), img.height()), (200, 400));
+
+        // Garbage input falls back to passthrough
+        assert_eq!(decode(encode_screenshot(b"not a png", encoding)), b"not a png");
+    }
+
+    #[test]
+    fn test_static_screenshots_skipped_with_backoff() {
+        let shot = |split: u32| {
+            let mut png = Vec::new();
+            image::RgbImage::from_fn(64, 128, |_, y| if y < split { image::Rgb([250, 250, 250]) } else { image::Rgb([10, 10, 10]) })
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            encode_screenshot(&png, ScreenshotEncoding::default())
+        };
+        let (idle, dialog) = (shot(64), shot(16));
+        let tracker = StaticScreenTracker::default();
+
+        // First sight is sent, then skipped 1 step, re-sent, skipped 3, re-sent …
+        let sent: Vec<bool> = (0..8).map(|_| !tracker.should_skip("[1] Button", &idle)).collect();
+        assert_eq!(sent, [true, false, true, false, false, false, true, false]);
+
+        // Any change is sent right away and resets the backoff
+        assert!(!tracker.should_skip("[1] Button", &dialog));
+        assert!(!tracker.should_skip("[1] Button \"OK\"", &dialog));
+        assert!(tracker.should_skip("[1] Button \"OK\"", &dialog));
+        assert!(!tracker.should_skip("[1] Button \"OK\"", &dialog));
+    }
+
+    #[test]
+    fn test_screenshot_scale_for_downscaled_shots() {
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(1080, 2340, image::Rgb([0, 0, 0]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let full = encode_screenshot(&png, ScreenshotEncoding::default());
+        let small = encode_screenshot(&png, ScreenshotEncoding { max_width: Some(540), jpeg_quality: 50 });
+
+        assert_eq!(screenshot_scale(&full, Some((1080, 2340))), None);
+        assert_eq!(screenshot_scale(&small, Some((1080, 2340))), Some(2.0));
+        // Landscape dump against the portrait `wm size`
+        assert_eq!(screenshot_scale(&small, Some((2340, 1080))), Some(2.0));
+        assert_eq!(screenshot_scale(&small, None), None);
+    }
+
+    #[test]
+    fn test_screen_unchanged_kept_out_of_formatted_text() {
+        let mut result = PerceptionResult {
+            screen: parse_accessibility_xml("", 50, &[], None, None),
+            screenshot_base64: None,
+            resolution: None,
+            formatted_text: "[1] Button".into(),
+            used_vision: false,
+            screen_unchanged: false,
+            screenshot_scale: None,
+        };
+        assert_eq!(result.prompt_text(), "[1] Button");
+        result.screen_unchanged = true;
+        assert_eq!(result.prompt_text(), "[1] Button\n[screen unchanged]");
+        assert_eq!(result.formatted_text, "[1] Button");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::config::CronJob;
+use crate::perception::Perception;
+use chrono::Utc;
+use chrono_tz::Tz;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Parse a `[[cron]]` schedule (its times are in `agent.timezone`). Standard 5-field expressions
+/// (`0 8 * * *`) are accepted as well as the `cron` crate's 6/7-field form
+/// with a leading seconds field.
+pub fn parse_schedule(expr: &str) -> anyhow::Result<cron::Schedule> {
+    let expr = expr.trim();
+    let full = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    cron::Schedule::from_str(&full).map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Spawn one task per enabled job, firing on `tz` wall-clock time. At each
+/// fire time the job's message is queued as a user command and a
+/// `user_command` event wakes the heartbeat. Returns how many jobs were scheduled.
+pub fn spawn_cron_jobs(
+    jobs: &[CronJob],
+    tz: Tz,
+    perception: Arc<Perception>,
+    event_tx: broadcast::Sender<String>,
+) -> usize {
+    let mut scheduled = 0;
+    for job in jobs.iter().filter(|j| j.enabled) {
+        let schedule = match parse_schedule(&job.schedule) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("⏰ Cron job '{}' skipped — bad schedule \"{}\": {}", job.name, job.schedule, e);
+                continue;
+            }
+        };
+        let Some(next) = schedule.upcoming(tz).next() else {
+            warn!("⏰ Cron job '{}' never fires (\"{}\")", job.name, job.schedule);
+            continue;
+        };
+        info!("⏰ Cron job '{}' next fires at {}", job.name, next.format("%Y-%m-%d %H:%M %Z"));
+
+        let job = job.clone();
+        let perception = perception.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            while let Some(next) = schedule.upcoming(tz).next() {
+                let wait = (next.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+                info!("⏰ Cron job '{}' fired", job.name);
+                let text = format!("[CRON {}] {}", job.name, job.message);
+                perception.push_user_command(text.clone()).await;
+                let _ = event_tx.send(
+                    serde_json::json!({"type": "user_command", "text": text, "source": "cron"}).to_string(),
+                );
+            }
+        });
+        scheduled += 1;
+    }
+    scheduled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedule() {
+        let daily = parse_schedule("0 8 * * *").unwrap();
+        let next = daily.upcoming(chrono_tz::Asia::Jakarta).next().unwrap();
+        assert_eq!(next.format("%H:%M:%S").to_string(), "08:00:00");
+        assert_eq!(next.with_timezone(&Utc).format("%H:%M").to_string(), "01:00");
+        assert!(parse_schedule("30 0 22 * * *").is_ok());
+        assert!(parse_schedule("every morning").is_err());
+    }
+}
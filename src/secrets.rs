@@ -0,0 +1,298 @@
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hkdf;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Config values of the form `secret:<name>` are looked up in the store
+pub const SECRET_PREFIX: &str = "secret:";
+/// Lives next to config.toml
+pub const SECRETS_FILE: &str = "secrets.json";
+
+const HKDF_INFO: &[u8] = b"hermitdroid secrets v1";
+
+/// Encrypted values for `secret:` references in config.toml. The key is
+/// derived from this machine's id plus a random salt kept in the file, so a
+/// copied config + secrets.json is useless on another machine. The machine id
+/// is readable by anyone on the host, so this only keeps values out of casual
+/// reads of the config directory — it is not protection from local users.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretStore {
+    salt: String,
+    #[serde(default)]
+    secrets: BTreeMap<String, String>,
+}
+
+impl SecretStore {
+    pub fn path(config_path: &Path) -> PathBuf {
+        config_path.parent().unwrap_or(Path::new(".")).join(SECRETS_FILE)
+    }
+
+    /// The store for this config, or an empty one with a fresh salt
+    pub fn open(config_path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(Self::path(config_path)) {
+            Ok(raw) => Ok(serde_json::from_str(&raw)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut salt = [0u8; 32];
+                SystemRandom::new().fill(&mut salt).map_err(|_| anyhow::anyhow!("no system RNG"))?;
+                Ok(Self { salt: b64().encode(salt), secrets: BTreeMap::new() })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, config_path: &Path) -> anyhow::Result<()> {
+        let path = Self::path(config_path);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, machine_id: &str, name: &str, value: &str) -> anyhow::Result<()> {
+        let key = self.key(machine_id)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| anyhow::anyhow!("no system RNG"))?;
+        let mut sealed = value.as_bytes().to_vec();
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(name), &mut sealed)
+            .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+        let blob: Vec<u8> = nonce.iter().copied().chain(sealed).collect();
+        self.secrets.insert(name.to_string(), b64().encode(blob));
+        Ok(())
+    }
+
+    pub fn get(&self, machine_id: &str, name: &str) -> anyhow::Result<String> {
+        let Some(encoded) = self.secrets.get(name) else {
+            anyhow::bail!("secret '{}' is not in {}", name, SECRETS_FILE);
+        };
+        let blob = b64().decode(encoded)?;
+        if blob.len() < NONCE_LEN {
+            anyhow::bail!("secret '{}' is corrupt", name);
+        }
+        let (nonce, sealed) = blob.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow::anyhow!("bad nonce"))?;
+        let mut sealed = sealed.to_vec();
+        let plain = self
+            .key(machine_id)?
+            .open_in_place(nonce, Aad::from(name), &mut sealed)
+            .map_err(|_| anyhow::anyhow!(
+                "secret '{}' can't be decrypted on this machine — run `hermitdroid secrets set {}` again",
+                name, name
+            ))?;
+        Ok(String::from_utf8(plain.to_vec())?)
+    }
+
+    fn key(&self, machine_id: &str) -> anyhow::Result<LessSafeKey> {
+        let salt = b64().decode(&self.salt)?;
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &salt).extract(machine_id.trim().as_bytes());
+        let mut key = [0u8; 32];
+        prk.expand(&[HKDF_INFO], &CHACHA20_POLY1305)
+            .and_then(|okm| okm.fill(&mut key))
+            .map_err(|_| anyhow::anyhow!("key derivation failed"))?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| anyhow::anyhow!("bad key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+fn b64() -> base64::engine::GeneralPurpose {
+    base64::engine::general_purpose::STANDARD
+}
+
+/// A stable per-machine id: systemd/dbus machine-id on Linux, the hardware
+/// UUID on macOS. None means there's nothing to bind secrets to.
+pub fn machine_id() -> Option<String> {
+    for path in ["/etc/machine-id", "/var/lib/dbus/machine-id"] {
+        if let Ok(id) = std::fs::read_to_string(path) {
+            if !id.trim().is_empty() {
+                return Some(id.trim().to_string());
+            }
+        }
+    }
+    let out = std::process::Command::new("ioreg")
+        .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .find(|l| l.contains("IOPlatformUUID"))
+        .and_then(|l| l.rsplit('"').nth(1))
+        .map(str::to_string)
+}
+
+/// Replace every `secret:<name>` string in the parsed config with its
+/// decrypted value. Nothing is read unless a reference is present.
+pub fn resolve(value: &mut toml::Value, config_path: &Path) -> anyhow::Result<()> {
+    if !has_references(value) {
+        return Ok(());
+    }
+    let id = machine_id().ok_or_else(|| anyhow::anyhow!("Config references secrets but this machine has no machine id"))?;
+    resolve_with(value, &SecretStore::open(config_path)?, &id)
+}
+
+fn has_references(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::String(s) => s.starts_with(SECRET_PREFIX),
+        toml::Value::Array(items) => items.iter().any(has_references),
+        toml::Value::Table(table) => table.values().any(has_references),
+        _ => false,
+    }
+}
+
+fn resolve_with(value: &mut toml::Value, store: &SecretStore, machine_id: &str) -> anyhow::Result<()> {
+    match value {
+        toml::Value::String(s) => {
+            if let Some(name) = s.strip_prefix(SECRET_PREFIX) {
+                *s = store.get(machine_id, name)?;
+            }
+        }
+        toml::Value::Array(items) => {
+            for item in items {
+                resolve_with(item, store, machine_id)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, v) in table.iter_mut() {
+                resolve_with(v, store, machine_id)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `hermitdroid secrets set <section.key>`: prompt for the value, encrypt it
+/// into secrets.json and point config.toml at it. Without a machine id the
+/// value goes into config.toml as plaintext.
+pub fn run_set(config_path: &Path, name: &str) -> anyhow::Result<()> {
+    let Some((section, key)) = name.split_once('.').filter(|(s, k)| !s.is_empty() && !k.is_empty()) else {
+        anyhow::bail!("Secret name must be <section>.<key>, e.g. brain.api_key");
+    };
+    let content = std::fs::read_to_string(config_path)
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {}", config_path.display(), e))?;
+
+    let value = read_hidden(&format!("{}: ", name))?;
+    if value.is_empty() {
+        anyhow::bail!("No value entered — nothing changed");
+    }
+
+    let stored = match machine_id() {
+        Some(id) => {
+            let mut store = SecretStore::open(config_path)?;
+            store.set(&id, name, &value)?;
+            store.save(config_path)?;
+            format!("{}{}", SECRET_PREFIX, name)
+        }
+        None => {
+            println!("⚠  No machine id to bind an encryption key to — storing {} as plaintext", name);
+            value
+        }
+    };
+    std::fs::write(config_path, set_config_value(&content, section, key, &stored))?;
+    if stored.starts_with(SECRET_PREFIX) {
+        println!("🔐 Encrypted {} into {}", name, SecretStore::path(config_path).display());
+    }
+    Ok(())
+}
+
+/// Set `key` in `[section]` of a config.toml, keeping everything else
+/// (comments included) as written
+fn set_config_value(content: &str, section: &str, key: &str, value: &str) -> String {
+    let line = format!("{} = {}", key, toml::Value::String(value.to_string()));
+    let header = format!("[{}]", section);
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+
+    // A header may carry a trailing comment: `[brain]  # LLM settings`
+    let is_header = |l: &str| l.split('#').next().unwrap_or("").trim() == header;
+    let Some(start) = lines.iter().position(|l| is_header(l)) else {
+        let mut out = content.trim_end().to_string();
+        out.push_str(&format!("\n\n{}\n{}\n", header, line));
+        return out;
+    };
+    let end = lines[start + 1..]
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .map_or(lines.len(), |i| start + 1 + i);
+    let existing = lines[start + 1..end].iter().position(|l| {
+        l.split_once('=').is_some_and(|(k, _)| k.trim() == key)
+    });
+    match existing {
+        Some(i) => lines[start + 1 + i] = line,
+        None => lines.insert(start + 1, line),
+    }
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// Read one line from stdin with terminal echo off
+fn read_hidden(label: &str) -> anyhow::Result<String> {
+    print!("{}", label);
+    std::io::stdout().flush()?;
+    let stty = |arg: &str| {
+        std::process::Command::new("stty")
+            .arg(arg)
+            .stdin(std::process::Stdio::inherit())
+            .status()
+    };
+    let _ = stty("-echo");
+    let mut line = String::new();
+    let read = std::io::stdin().read_line(&mut line);
+    let _ = stty("echo");
+    println!();
+    read?;
+    Ok(line.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("hermitdroid-secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+
+        let mut store = SecretStore::open(&config_path).unwrap();
+        store.set("machine-a", "brain.api_key", "sk-123").unwrap();
+        store.save(&config_path).unwrap();
+        let raw = std::fs::read_to_string(SecretStore::path(&config_path)).unwrap();
+        assert!(!raw.contains("sk-123"));
+
+        let store = SecretStore::open(&config_path).unwrap();
+        assert_eq!(store.get("machine-a", "brain.api_key").unwrap(), "sk-123");
+        assert!(store.get("machine-b", "brain.api_key").is_err());
+        assert!(store.get("machine-a", "tailscale.auth_key").is_err());
+
+        let mut table: toml::Value = toml::from_str("[brain]\napi_key = \"secret:brain.api_key\"\nmodel = \"x\"").unwrap();
+        assert!(has_references(&table));
+        resolve_with(&mut table, &store, "machine-a").unwrap();
+        assert_eq!(table["brain"]["api_key"].as_str(), Some("sk-123"));
+        assert_eq!(table["brain"]["model"].as_str(), Some("x"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_set_config_value() {
+        let config = "# top\n[brain]\n# the key\napi_key = \"sk-old\"\nmodel = \"m\"\n\n[agent]\nname = \"a\"\n";
+        let updated = set_config_value(config, "brain", "api_key", "secret:brain.api_key");
+        assert!(updated.contains("# the key\napi_key = \"secret:brain.api_key\"\nmodel"));
+        assert!(!updated.contains("sk-old"));
+
+        let inserted = set_config_value(config, "agent", "api_key", "v");
+        assert!(inserted.contains("[agent]\napi_key = \"v\"\nname"));
+        let appended = set_config_value(config, "tailscale", "auth_key", "v");
+        assert!(appended.ends_with("[tailscale]\nauth_key = \"v\"\n"));
+
+        let commented = "[brain]  # LLM settings\napi_key = \"sk-old\"\n";
+        let updated = set_config_value(commented, "brain", "api_key", "v");
+        assert_eq!(updated, "[brain]  # LLM settings\napi_key = \"v\"\n");
+        assert!(updated.parse::<toml::Table>().is_ok());
+    }
+}
@@ -1,20 +1,26 @@
 use crate::action::ActionExecutor;
+use crate::config::Config;
+use crate::metrics::Metrics;
 use crate::perception::{AndroidMessage, Perception};
+use crate::runs::RunProgress;
 use crate::session::SessionManager;
 use crate::soul::Workspace;
 use crate::tailscale::TailscaleManager;
 use axum::{
-    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Path, State},
-    response::{Html, IntoResponse},
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Path, Query, State},
+    http::StatusCode,
+    response::{sse::{Event, KeepAlive, Sse}, Html, IntoResponse},
     routing::{get, post},
     Json, Router,
 };
 use serde_json::{json, Value};
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, Mutex};
 use tower_http::cors::CorsLayer;
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -23,8 +29,26 @@ pub struct AppState {
     pub workspace: Arc<Workspace>,
     pub sessions: Arc<SessionManager>,
     pub running: Arc<Mutex<bool>>,
+    /// Set when repeated tick failures paused the agent; cleared by /start
+    pub circuit_open: Arc<Mutex<bool>>,
+    /// When the heartbeat last ran a tick (None until the first one)
+    pub last_tick: Arc<Mutex<Option<std::time::Instant>>>,
+    /// Set once a heartbeat tick has succeeded; backs `/ready` without locking
+    pub ready: Arc<AtomicBool>,
+    pub metrics: Arc<Metrics>,
+    /// Resolved config (ADB target, dry-run) for flows/workflows started over HTTP
+    pub config: Arc<Config>,
+    /// Run id of the flow/workflow in progress — one at a time to avoid ADB contention
+    pub active_run: Arc<Mutex<Option<String>>>,
+    /// The executor's device lock: a dashboard run holds it until it finishes
+    pub device_lock: Arc<Mutex<()>>,
+    /// `[[brain_profiles]]` entry the heartbeat should use (None = `[brain]`)
+    pub brain_profile: Arc<Mutex<Option<String>>>,
     pub event_tx: broadcast::Sender<String>,
     pub tailscale: Arc<Mutex<TailscaleManager>>,
+    /// Flips to true on shutdown; ends `/events` streams so the graceful
+    /// shutdown doesn't wait on them forever
+    pub shutdown: tokio::sync::watch::Receiver<bool>,
 }
 
 #[derive(Serialize)]
@@ -39,7 +63,12 @@ pub fn build_router(state: AppState) -> Router {
         // Dashboard (root)
         .route("/", get(dashboard))
         // Agent control
+        .route("/health", get(health))
+        .route("/ready", get(ready))
         .route("/status", get(status))
+        .route("/metrics", get(metrics))
+        .route("/screen/diff", get(screen_diff))
+        .route("/apps", get(installed_apps))
         .route("/start", post(start))
         .route("/stop", post(stop))
         // Config (settings UI)
@@ -54,24 +83,32 @@ pub fn build_router(state: AppState) -> Router {
         // Memory
         .route("/memory", get(read_memory))
         .route("/memory/daily", get(read_daily_memory))
+        .route("/memory/search", get(search_memory))
         .route("/memory", post(write_memory))
+        .route("/reflections/daily", get(read_daily_reflections))
         // Goals
         .route("/goals", get(read_goals))
         .route("/goals", post(add_goal))
-        .route("/goals/{id}/complete", post(complete_goal))
+        .route("/goals/:id/complete", post(complete_goal))
         // Sessions
         .route("/sessions", get(list_sessions))
-        .route("/sessions/{id}", get(get_session))
-        .route("/sessions/{id}/new", post(reset_session))
+        .route("/sessions/:id", get(get_session))
+        .route("/sessions/:id", post(update_session))
+        .route("/sessions/:id/new", post(reset_session))
         // Actions
         .route("/pending", get(pending_actions))
-        .route("/confirm/{id}", post(confirm_action))
+        .route("/confirm/:id", post(confirm_action))
         .route("/actions/log", get(action_log))
         // Chat (slash commands like OpenClaw)
         .route("/chat", post(chat))
+        // Flows & workflows (progress streamed as run_* events)
+        .route("/flows/run", post(run_flow))
+        .route("/workflows/run", post(run_workflow))
         // WebSocket
         .route("/ws/android", get(ws_android))
         .route("/ws/user", get(ws_user))
+        // Server-Sent Events (same stream as /ws/user, for curl/scripts/proxies)
+        .route("/events", get(sse_events))
         .route("/tailscale/status", get(tailscale_status))
         .route("/tailscale/connect", post(tailscale_connect))
         .route("/tailscale/disconnect", post(tailscale_disconnect))
@@ -88,18 +125,79 @@ async fn dashboard() -> Html<&'static str> {
 
 // ---- Status ----
 
+/// Liveness: the server answers. Touches no shared state.
+async fn health() -> Json<Value> {
+    Json(json!({"ok": true}))
+}
+
+/// Readiness: 503 until the first heartbeat tick has succeeded
+async fn ready(State(s): State<AppState>) -> impl IntoResponse {
+    if s.ready.load(Ordering::Relaxed) {
+        (StatusCode::OK, Json(json!({"ok": true})))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"ok": false, "error": "no successful heartbeat yet"})))
+    }
+}
+
 async fn status(State(s): State<AppState>) -> impl IntoResponse {
     let running = *s.running.lock().await;
+    let circuit_open = *s.circuit_open.lock().await;
     let pending = s.executor.pending().lock().await.len();
     let screen = s.perception.get_screen_state().await;
+    let resolution = s.perception.get_resolution().await;
+    let last_tick_at = s.last_tick.lock().await.map(|t| {
+        let ago = chrono::Duration::from_std(t.elapsed()).unwrap_or_default();
+        (chrono::Utc::now() - ago).to_rfc3339()
+    });
     R::ok(serde_json::json!({
         "running": running,
+        "circuit_open": circuit_open,
+        "quiet_hours": s.config.agent.in_quiet_hours(chrono::Utc::now()),
+        "active_hours": s.config.agent.active_hours,
         "pending_confirmations": pending,
         "current_app": screen.as_ref().map(|s| &s.current_app),
+        "activity": screen.as_ref().map(|s| &s.activity),
+        "element_count": screen.as_ref().map(|s| s.elements.len()),
+        "resolution": resolution.map(|(w, h)| format!("{}x{}", w, h)),
+        "last_tick_at": last_tick_at,
     }))
 }
 
-async fn start(State(s): State<AppState>) -> impl IntoResponse { *s.running.lock().await = true; R::ok("started") }
+/// Added/removed/changed elements between the last two screen polls
+async fn screen_diff(State(s): State<AppState>) -> impl IntoResponse {
+    match s.perception.screen_diff().await {
+        Some(diff) => R::ok(diff),
+        None => R::err("No earlier screen state to compare with yet"),
+    }
+}
+
+/// Launchable apps on the device, from the cached `pm` listing
+async fn installed_apps(State(s): State<AppState>) -> impl IntoResponse {
+    let perception = s.perception.clone();
+    match tokio::task::spawn_blocking(move || perception.list_installed_apps()).await {
+        Ok(apps) => R::ok(
+            apps.into_iter()
+                .map(|(package, label)| json!({ "package": package, "label": label }))
+                .collect::<Vec<_>>(),
+        ),
+        Err(e) => R::err(&e.to_string()),
+    }
+}
+
+/// Prometheus text exposition format
+async fn metrics(State(s): State<AppState>) -> impl IntoResponse {
+    let pending = s.executor.pending().lock().await.len();
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        s.metrics.render(pending),
+    )
+}
+
+async fn start(State(s): State<AppState>) -> impl IntoResponse {
+    *s.circuit_open.lock().await = false;
+    *s.running.lock().await = true;
+    R::ok("started")
+}
 async fn stop(State(s): State<AppState>) -> impl IntoResponse { *s.running.lock().await = false; R::ok("stopped") }
 
 // ---- Config API (read/write config.toml via dashboard) ----
@@ -296,19 +394,29 @@ async fn install_update() -> impl IntoResponse {
 
 // ---- Workspace ----
 
-async fn read_workspace_file(State(s): State<AppState>, Path(f): Path<String>) -> impl IntoResponse {
-    let filename = f.trim_start_matches('/');
-    R::ok(s.workspace.read_file(filename))
+async fn read_workspace_file(State(s): State<AppState>, Path(f): Path<String>) -> axum::response::Response {
+    let path = match s.workspace.resolve_path(f.trim_start_matches('/')) {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::FORBIDDEN, R::err(&e.to_string())).into_response(),
+    };
+    R::ok(std::fs::read_to_string(&path).unwrap_or_default()).into_response()
 }
 
 #[derive(Deserialize)]
 struct WriteBody { content: String }
 
-async fn write_workspace_file(State(s): State<AppState>, Path(f): Path<String>, Json(b): Json<WriteBody>) -> impl IntoResponse {
-    let filename = f.trim_start_matches('/');
-    match s.workspace.write_file(filename, &b.content) {
-        Ok(()) => R::ok("written".to_string()),
-        Err(e) => R::err(&e.to_string()),
+async fn write_workspace_file(State(s): State<AppState>, Path(f): Path<String>, Json(b): Json<WriteBody>) -> axum::response::Response {
+    let path = match s.workspace.resolve_path(f.trim_start_matches('/')) {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::FORBIDDEN, R::err(&e.to_string())).into_response(),
+    };
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, &b.content));
+    match written {
+        Ok(()) => R::ok("written".to_string()).into_response(),
+        Err(e) => R::err(&e.to_string()).into_response(),
     }
 }
 
@@ -320,6 +428,23 @@ async fn read_daily_memory(State(s): State<AppState>) -> impl IntoResponse {
     R::ok(s.workspace.get_recent_daily_memory(7))
 }
 
+async fn read_daily_reflections(State(s): State<AppState>) -> impl IntoResponse {
+    R::ok(s.workspace.get_recent_reflections(7))
+}
+
+/// How far back `/memory/search` and `/find` look unless `days` is given
+const DEFAULT_SEARCH_DAYS: usize = 30;
+
+#[derive(Deserialize)]
+struct MemorySearchQuery { q: String, days: Option<usize> }
+
+async fn search_memory(State(s): State<AppState>, Query(q): Query<MemorySearchQuery>) -> impl IntoResponse {
+    match s.workspace.search_memory(&q.q, q.days.unwrap_or(DEFAULT_SEARCH_DAYS)) {
+        Ok(matches) => R::ok(matches),
+        Err(e) => R::err(&e.to_string()),
+    }
+}
+
 #[derive(Deserialize)]
 struct MemoryBody { section: String, entry: String }
 
@@ -357,11 +482,53 @@ async fn get_session(State(s): State<AppState>, Path(id): Path<String>) -> impl
     R::ok(s.sessions.get_session(&id).await)
 }
 
+/// Absent field = unchanged, empty string = back to the config default
+#[derive(Deserialize)]
+struct SessionOverridesBody {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    thinking: Option<String>,
+}
+
+async fn update_session(
+    State(s): State<AppState>,
+    Path(id): Path<String>,
+    Json(b): Json<SessionOverridesBody>,
+) -> impl IntoResponse {
+    if let Some(thinking) = b.thinking {
+        let level = Some(thinking.trim().to_lowercase()).filter(|l| !l.is_empty());
+        if let Err(e) = s.sessions.set_thinking_level(&id, level).await {
+            return R::err(&e.to_string());
+        }
+    }
+    if let Some(model) = b.model {
+        let model = Some(model.trim().to_string()).filter(|m| !m.is_empty());
+        if !s.sessions.set_model_override(&id, model).await {
+            return R::err(&format!("No session '{}'", id));
+        }
+    }
+    R::ok(s.sessions.get_session(&id).await)
+}
+
 async fn reset_session(State(s): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
     s.sessions.reset_session(&id).await;
+    run_session_new_hook(&s).await;
     R::ok("reset".to_string())
 }
 
+/// Queue the `hooks.on_session_new` file as a user command after a reset
+/// and wake the heartbeat for it
+async fn run_session_new_hook(s: &AppState) {
+    let Some(file) = &s.config.hooks.on_session_new else { return };
+    info!("Running on_session_new hook: {}", file);
+    if let Some(cmd) = s.workspace.hook_command(file, "SESSION_NEW") {
+        s.perception.push_user_command(cmd.clone()).await;
+        // Wake the heartbeat like any other user command
+        let _ = s.event_tx.send(serde_json::json!({"type":"user_command","text":cmd}).to_string());
+    }
+}
+
 // ---- Actions ----
 
 async fn pending_actions(State(s): State<AppState>) -> impl IntoResponse {
@@ -382,6 +549,111 @@ async fn action_log(State(s): State<AppState>) -> impl IntoResponse {
     R::ok(s.executor.action_log().lock().await.clone())
 }
 
+// ---- Flow / workflow runs ----
+
+#[derive(Deserialize)]
+struct FlowRunBody { path: String }
+
+#[derive(Deserialize)]
+struct WorkflowRunBody {
+    path: String,
+    #[serde(default)]
+    verbose: bool,
+}
+
+async fn run_flow(State(s): State<AppState>, Json(b): Json<FlowRunBody>) -> impl IntoResponse {
+    start_run(&s, "flows", b.path, |config, path, progress| async move {
+        crate::flow::run_flow(&config, &path, false, Some(&progress)).await
+    })
+    .await
+}
+
+async fn run_workflow(State(s): State<AppState>, Json(b): Json<WorkflowRunBody>) -> impl IntoResponse {
+    let verbose = b.verbose;
+    start_run(&s, "workflows", b.path, move |config, path, progress| async move {
+        crate::workflow::run_workflow(&config, &path, verbose, false, None, Some(&progress)).await
+    })
+    .await
+}
+
+/// Spawn a run of `path` (relative to the workspace's `dir`) in the
+/// background and return its id; completion is broadcast as a
+/// `run_finished` event
+async fn start_run<F, Fut>(s: &AppState, dir: &str, path: String, run: F) -> Json<R>
+where
+    F: FnOnce(Arc<Config>, String, RunProgress) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let dir = std::path::Path::new(&s.config.agent.workspace_path).join(dir);
+    let path = match resolve_run_path(&dir, &path) {
+        Ok(path) => path,
+        Err(e) => return R::err(&e),
+    };
+    let progress = RunProgress::new(s.event_tx.clone());
+    let run_id = progress.run_id().to_string();
+    {
+        let mut active = s.active_run.lock().await;
+        if let Some(current) = active.as_ref() {
+            return R::err(&format!("Run {} is still in progress", current));
+        }
+        *active = Some(run_id.clone());
+    }
+
+    info!("▶ Run {} started: {}", run_id, path);
+    let config = s.config.clone();
+    let active_run = s.active_run.clone();
+    let device_lock = s.device_lock.clone();
+    tokio::spawn(async move {
+        // Frees the slot even if the run panics
+        let _active = ActiveRunGuard(active_run);
+        let result = {
+            let _device = device_lock.lock().await;
+            run(config, path, progress.clone()).await
+        };
+        if let Err(e) = &result {
+            warn!("Run {} failed: {}", progress.run_id(), e);
+        }
+        progress.emit("run_finished", json!({
+            "ok": result.is_ok(),
+            "error": result.err().map(|e| e.to_string()),
+        }));
+    });
+    R::ok(json!({"run_id": run_id}))
+}
+
+/// Clears `AppState::active_run` when the run's task ends, however it ends
+struct ActiveRunGuard(Arc<Mutex<Option<String>>>);
+
+impl Drop for ActiveRunGuard {
+    fn drop(&mut self) {
+        if let Ok(mut current) = self.0.try_lock() {
+            *current = None;
+            return;
+        }
+        let active = self.0.clone();
+        tokio::spawn(async move { *active.lock().await = None });
+    }
+}
+
+/// `name` as a file inside `dir`. Absolute paths and `..` are refused, and
+/// so is anything a symlink takes outside `dir`.
+fn resolve_run_path(dir: &std::path::Path, name: &str) -> Result<String, String> {
+    let relative = std::path::Path::new(name);
+    if relative.is_absolute()
+        || relative.components().any(|c| !matches!(c, std::path::Component::Normal(_) | std::path::Component::CurDir))
+    {
+        return Err(format!("Not a name inside {}: {}", dir.display(), name));
+    }
+    let path = dir.join(relative);
+    let (Ok(real), Ok(root)) = (path.canonicalize(), dir.canonicalize()) else {
+        return Err(format!("No such file: {}", path.display()));
+    };
+    if !real.starts_with(&root) || !real.is_file() {
+        return Err(format!("Not a file inside {}: {}", dir.display(), name));
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
 // ---- Chat ----
 
 #[derive(Deserialize)]
@@ -409,10 +681,20 @@ async fn handle_slash_command(cmd: &str, s: &AppState) -> String {
         }
         "/new" | "/reset" => {
             s.sessions.reset_session("main").await;
+            run_session_new_hook(s).await;
             "Session reset.".into()
         }
         "/stop" => { *s.running.lock().await = false; "Agent stopped.".into() }
-        "/start" => { *s.running.lock().await = true; "Agent started.".into() }
+        "/kill" => {
+            *s.running.lock().await = false;
+            let _ = s.event_tx.send(serde_json::json!({"event": "kill"}).to_string());
+            "🛑 Kill switch activated.".into()
+        }
+        "/start" => {
+            *s.circuit_open.lock().await = false;
+            *s.running.lock().await = true;
+            "Agent started.".into()
+        }
         "/goal" => {
             if parts.len() > 1 {
                 match s.workspace.add_goal(parts[1], None) {
@@ -423,14 +705,82 @@ async fn handle_slash_command(cmd: &str, s: &AppState) -> String {
                 "Usage: /goal <description>".into()
             }
         }
+        "/model" => {
+            let arg = parts.get(1).map(|a| a.trim()).unwrap_or("");
+            if arg.is_empty() {
+                let current = s.sessions.main_session().await.model_override;
+                return format!("Model override: {}", current.as_deref().unwrap_or("none (config default)"));
+            }
+            let model = Some(arg.to_string()).filter(|m| m != "default");
+            s.sessions.main_session().await;
+            s.sessions.set_model_override("main", model.clone()).await;
+            match model {
+                Some(m) => format!("Model for this session: {}", m),
+                None => "Model override cleared.".into(),
+            }
+        }
+        "/profile" => {
+            let arg = parts.get(1).map(|a| a.trim()).unwrap_or("");
+            if arg.is_empty() {
+                let current = s.brain_profile.lock().await.clone();
+                return format!(
+                    "Brain profile: {}\nAvailable: {}",
+                    current.as_deref().unwrap_or(crate::config::DEFAULT_BRAIN_PROFILE),
+                    s.config.brain_profile_names().join(", ")
+                );
+            }
+            match s.config.brain_profile(Some(arg)) {
+                Ok(brain) => {
+                    let name = Some(arg.to_string()).filter(|n| n != crate::config::DEFAULT_BRAIN_PROFILE);
+                    *s.brain_profile.lock().await = name;
+                    format!("Brain profile: {} ({} via {}) from the next tick", arg, brain.model, brain.backend)
+                }
+                Err(e) => format!("Error: {}", e),
+            }
+        }
+        "/think" => {
+            let arg = parts.get(1).map(|a| a.trim().to_lowercase()).unwrap_or_default();
+            if arg.is_empty() {
+                let current = s.sessions.main_session().await.thinking_level;
+                return format!("Thinking level: {}", current.as_deref().unwrap_or("config default"));
+            }
+            let level = Some(arg).filter(|l| l != "default");
+            s.sessions.main_session().await;
+            match s.sessions.set_thinking_level("main", level.clone()).await {
+                Ok(()) => match level {
+                    Some(l) => format!("Thinking level for this session: {}", l),
+                    None => "Thinking level override cleared.".into(),
+                },
+                Err(e) => format!("Error: {}", e),
+            }
+        }
         "/memory" => {
             let mem = s.workspace.read_file("MEMORY.md");
             if mem.is_empty() { "No memory yet.".into() } else { mem }
         }
+        "/find" => {
+            let query = parts.get(1).map(|q| q.trim()).unwrap_or("");
+            if query.is_empty() {
+                return "Usage: /find <text or /regex/>".into();
+            }
+            match s.workspace.search_memory(query, DEFAULT_SEARCH_DAYS) {
+                Ok(matches) if matches.is_empty() => format!("Nothing in the last {} days matches \"{}\".", DEFAULT_SEARCH_DAYS, query),
+                Ok(matches) => {
+                    let shown: Vec<String> = matches.iter().take(20).map(|(date, line)| format!("{}: {}", date, line)).collect();
+                    let more = matches.len().saturating_sub(shown.len());
+                    let mut out = shown.join("\n");
+                    if more > 0 {
+                        out.push_str(&format!("\n… and {} more", more));
+                    }
+                    out
+                }
+                Err(e) => format!("Error: {}", e),
+            }
+        }
         "/goals" => s.workspace.read_file("GOALS.md"),
         "/soul" => s.workspace.read_file("SOUL.md"),
         "/help" => {
-            "/status — agent status\n/start — start agent\n/stop — stop agent\n/new — reset session\n/goal <text> — add goal\n/goals — list goals\n/memory — show memory\n/soul — show personality\n/help — this message".into()
+            "/status — agent status\n/start — start agent\n/stop — stop agent\n/kill — kill switch (stop immediately)\n/new — reset session\n/model <name|default> — model for this session\n/profile <name|default> — brain profile for the gateway\n/think <off|low|medium|high|default> — thinking level for this session\n/goal <text> — add goal\n/goals — list goals\n/memory — show memory\n/find <text> — search memory logs\n/soul — show personality\n/help — this message".into()
         }
         _ => format!("Unknown command: {}. Type /help for available commands.", parts[0]),
     }
@@ -445,10 +795,15 @@ async fn ws_android(ws: WebSocketUpgrade, State(s): State<AppState>) -> impl Int
 async fn handle_android(mut socket: WebSocket, state: AppState) {
     info!("Android companion connected");
     let outgoing = state.executor.outgoing();
+    let timeout_secs = state.config.perception.companion_timeout_secs;
+    let mut last_seen = std::time::Instant::now();
 
     loop {
         tokio::select! {
             msg = socket.recv() => {
+                if matches!(msg, Some(Ok(_))) {
+                    last_seen = std::time::Instant::now();
+                }
                 match msg {
                     Some(Ok(Message::Text(text))) => {
                         if let Ok(am) = serde_json::from_str::<AndroidMessage>(&text) {
@@ -470,6 +825,18 @@ async fn handle_android(mut socket: WebSocket, state: AppState) {
                                 }
                                 AndroidMessage::ActionResult { action_id, success, message } => {
                                     info!("Action result [{}]: {} — {}", action_id, success, message);
+                                    match state.executor.acknowledge(&action_id, success, &message).await {
+                                        Some(action_type) => {
+                                            let _ = state.event_tx.send(serde_json::json!({
+                                                "event": "companion_action_result",
+                                                "action_id": action_id,
+                                                "action_type": action_type,
+                                                "success": success,
+                                                "message": message,
+                                            }).to_string());
+                                        }
+                                        None => warn!("Action result for unknown action {}", action_id),
+                                    }
                                 }
                                 AndroidMessage::Heartbeat => {}
                             }
@@ -480,10 +847,20 @@ async fn handle_android(mut socket: WebSocket, state: AppState) {
                 }
             }
             _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {
+                if timeout_secs > 0 && last_seen.elapsed().as_secs() >= timeout_secs {
+                    warn!("Android companion silent for {}s — closing so it reconnects", last_seen.elapsed().as_secs());
+                    let _ = state.event_tx.send(serde_json::json!({
+                        "event": "companion_timeout",
+                        "silent_secs": last_seen.elapsed().as_secs(),
+                    }).to_string());
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
                 let mut actions = outgoing.lock().await;
                 for a in actions.drain(..) {
                     let json = serde_json::to_string(&a).unwrap_or_default();
                     if socket.send(Message::Text(json.into())).await.is_err() { break; }
+                    state.executor.mark_sent(&a.id).await;
                 }
             }
         }
@@ -500,10 +877,16 @@ async fn handle_user(mut socket: WebSocket, state: AppState) {
     loop {
         tokio::select! {
             event = rx.recv() => {
-                match event {
-                    Ok(text) => { if socket.send(Message::Text(text.into())).await.is_err() { break; } }
-                    Err(_) => break,
-                }
+                let text = match event {
+                    Ok(text) => text,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        // Slow dashboard — tell it what it missed and keep going
+                        warn!("User dashboard lagged, dropped {} events", n);
+                        lagged_message(n)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() { break; }
             }
             msg = socket.recv() => {
                 match msg {
@@ -520,11 +903,50 @@ async fn handle_user(mut socket: WebSocket, state: AppState) {
     }
 }
 
+/// Sent in place of the events a slow `/ws/user` or `/events` client missed
+fn lagged_message(skipped: u64) -> String {
+    json!({"type": "lagged", "skipped": skipped}).to_string()
+}
+
+// ---- Server-Sent Events ----
+
+async fn sse_events(
+    State(s): State<AppState>,
+) -> Sse<impl futures::Stream<Item = Result<Event, Infallible>>> {
+    info!("SSE client connected");
+    let rx = s.event_tx.subscribe();
+    let stream = futures::stream::unfold((rx, s.shutdown), |(mut rx, mut shutdown)| async move {
+        let received = tokio::select! {
+            received = rx.recv() => received,
+            _ = shutdown.wait_for(|stop| *stop) => return None,
+        };
+        let ev = match received {
+            Ok(text) => Event::default().data(text),
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                // Slow client — tell it what it missed and keep going
+                warn!("SSE client lagged, dropped {} events", n);
+                Event::default().event("lagged").data(lagged_message(n))
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        };
+        Some((Ok(ev), (rx, shutdown)))
+    });
+    Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(std::time::Duration::from_secs(15))
+            .text("keep-alive"),
+    )
+}
+
 // ---- Tailscale handlers ----
 
 async fn tailscale_status(State(state): State<AppState>) -> Json<Value> {
-    let ts = state.tailscale.lock().await;
-    Json(json!({"ok": true, "data": ts.api_status()}))
+    let status = state.tailscale.lock().await.status();
+    let status = tokio::task::spawn_blocking(move || status.probe_host()).await;
+    match status {
+        Ok(status) => Json(json!({"ok": true, "data": status})),
+        Err(e) => Json(json!({"ok": false, "error": e.to_string()})),
+    }
 }
 
 async fn tailscale_connect(State(state): State<AppState>) -> Json<Value> {
@@ -544,4 +966,66 @@ async fn tailscale_disconnect(State(state): State<AppState>) -> Json<Value> {
 async fn tailscale_peers(State(_state): State<AppState>) -> Json<Value> {
     let peers = TailscaleManager::list_peers(true);
     Json(json!({"ok": true, "data": peers}))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_run_path() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-runs-{}", std::process::id()));
+        let dir = root.join("flows");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/go-home.yaml"), "name: Home\n").unwrap();
+        std::fs::write(root.join("secret.yaml"), "").unwrap();
+
+        assert!(resolve_run_path(&dir, "sub/go-home.yaml").unwrap().ends_with("go-home.yaml"));
+        assert!(resolve_run_path(&dir, "../secret.yaml").is_err());
+        assert!(resolve_run_path(&dir, root.join("secret.yaml").to_str().unwrap()).is_err());
+        assert!(resolve_run_path(&dir, "sub").is_err());
+        assert!(resolve_run_path(&dir, "missing.yaml").is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_path_param() {
+        let config: Config = toml::from_str(include_str!("../../config.default.toml")).unwrap();
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let perception = Perception::new(None, Vec::new());
+        let executor = ActionExecutor::new(true, None, Vec::new(), Some(perception.clone()));
+        let sessions = Arc::new(SessionManager::new());
+        sessions.main_session().await;
+        let (event_tx, _) = broadcast::channel(16);
+        let (_shutdown_tx, shutdown) = tokio::sync::watch::channel(false);
+        let state = AppState {
+            perception: Arc::new(perception),
+            device_lock: executor.device_lock(),
+            executor: Arc::new(executor),
+            workspace: Arc::new(Workspace::new(root.to_str().unwrap(), 1000)),
+            sessions,
+            running: Arc::new(Mutex::new(false)),
+            circuit_open: Arc::new(Mutex::new(false)),
+            last_tick: Arc::new(Mutex::new(None)),
+            ready: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Metrics::default()),
+            tailscale: Arc::new(Mutex::new(TailscaleManager::new(config.tailscale.clone()))),
+            config: Arc::new(config),
+            active_run: Arc::new(Mutex::new(None)),
+            brain_profile: Arc::new(Mutex::new(None)),
+            event_tx,
+            shutdown,
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, build_router(state)).await });
+
+        let body: Value = reqwest::get(format!("http://{}/sessions/main", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["data"]["id"], "main");
+    }
+}
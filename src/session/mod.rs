@@ -4,6 +4,9 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// Accepted values for `Session::thinking_level`
+pub const THINKING_LEVELS: &[&str] = &["off", "low", "medium", "high"];
+
 /// Session manager — tracks conversation sessions.
 /// OpenClaw has main session + per-channel/group sessions.
 /// For Android, we have: main (direct), and per-channel sessions.
@@ -85,6 +88,29 @@ impl SessionManager {
         }
     }
 
+    /// Set or clear (`None`) a session's model override. False if there is no such session.
+    pub async fn set_model_override(&self, session_id: &str, model: Option<String>) -> bool {
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(session_id) else { return false };
+        session.model_override = model;
+        true
+    }
+
+    /// Set or clear (`None`) a session's thinking level. Errors on an unknown level.
+    pub async fn set_thinking_level(&self, session_id: &str, level: Option<String>) -> anyhow::Result<()> {
+        if let Some(l) = &level {
+            if !THINKING_LEVELS.contains(&l.as_str()) {
+                anyhow::bail!("Unknown thinking level '{}' (expected {})", l, THINKING_LEVELS.join(" | "));
+            }
+        }
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(session_id) else {
+            anyhow::bail!("No session '{}'", session_id);
+        };
+        session.thinking_level = level;
+        Ok(())
+    }
+
     /// List all sessions
     pub async fn list_sessions(&self) -> Vec<Session> {
         self.sessions.lock().await.values().cloned().collect()
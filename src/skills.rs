@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// ── Public API ─────────────────────────────────────────────────────────────
+
+/// Install a skill from a local folder or a git URL into `workspace/skills/<name>`.
+/// The source must contain a `SKILL.md` at its root. Returns the skill name.
+pub fn add_skill(workspace_path: &str, source: &str) -> anyhow::Result<String> {
+    let name = skill_name(source)?;
+    let dest = skills_dir(workspace_path).join(&name);
+    if dest.exists() {
+        anyhow::bail!("Skill '{}' is already installed — remove it first", name);
+    }
+
+    if is_git_url(source) {
+        let tmp = std::env::temp_dir().join(format!("hermitdroid-skill-{}", uuid::Uuid::new_v4()));
+        let status = Command::new("git")
+            .args(["clone", "--depth", "1", "--quiet", source])
+            .arg(&tmp)
+            .status()
+            .map_err(|e| anyhow::anyhow!("Cannot run git: {}", e))?;
+        if !status.success() {
+            anyhow::bail!("git clone {} failed", source);
+        }
+        let result = install_from_dir(&tmp, &dest);
+        std::fs::remove_dir_all(&tmp).ok();
+        result?;
+    } else {
+        install_from_dir(Path::new(source), &dest)?;
+    }
+    Ok(name)
+}
+
+/// Installed skills as (name, first heading of SKILL.md)
+pub fn list_skills(workspace_path: &str) -> Vec<(String, String)> {
+    let mut skills: Vec<(String, String)> = std::fs::read_dir(skills_dir(workspace_path))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let content = std::fs::read_to_string(entry.path().join("SKILL.md")).ok()?;
+            Some((entry.file_name().to_string_lossy().to_string(), first_heading(&content)))
+        })
+        .collect();
+    skills.sort();
+    skills
+}
+
+pub fn remove_skill(workspace_path: &str, name: &str) -> anyhow::Result<()> {
+    validate_name(name)?;
+    let dir = skills_dir(workspace_path).join(name);
+    if !dir.join("SKILL.md").exists() {
+        anyhow::bail!("No skill named '{}'", name);
+    }
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+// ── Helpers ────────────────────────────────────────────────────────────────
+
+fn skills_dir(workspace_path: &str) -> PathBuf {
+    Path::new(workspace_path).join("skills")
+}
+
+fn is_git_url(source: &str) -> bool {
+    source.starts_with("https://")
+        || source.starts_with("http://")
+        || source.starts_with("git@")
+        || source.ends_with(".git")
+}
+
+/// Folder or repo name: ".../weather-report.git" → "weather-report"
+fn skill_name(source: &str) -> anyhow::Result<String> {
+    let last = source
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or("");
+    let name = last.strip_suffix(".git").unwrap_or(last).to_string();
+    validate_name(&name)?;
+    Ok(name)
+}
+
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        anyhow::bail!("Invalid skill name '{}'", name);
+    }
+    Ok(())
+}
+
+fn install_from_dir(src: &Path, dest: &Path) -> anyhow::Result<()> {
+    if !src.join("SKILL.md").is_file() {
+        anyhow::bail!("{} has no SKILL.md — not a skill", src.display());
+    }
+    copy_dir(src, dest)
+}
+
+/// Recursive copy, skipping `.git`
+fn copy_dir(src: &Path, dest: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn first_heading(content: &str) -> String {
+    content
+        .lines()
+        .find(|l| l.starts_with('#'))
+        .map(|l| l.trim_start_matches('#').trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skill_add_list_remove() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let src = root.join("weather-report");
+        std::fs::create_dir_all(src.join("assets")).unwrap();
+        std::fs::write(src.join("SKILL.md"), "# Weather Report\n\nCheck the forecast.\n").unwrap();
+        std::fs::write(src.join("assets/icon.txt"), "☀").unwrap();
+        let ws = root.join("workspace");
+        let ws = ws.to_str().unwrap();
+
+        assert_eq!(add_skill(ws, src.to_str().unwrap()).unwrap(), "weather-report");
+        assert!(add_skill(ws, src.to_str().unwrap()).is_err());
+        assert!(add_skill(ws, root.to_str().unwrap()).is_err()); // no SKILL.md
+        assert_eq!(
+            list_skills(ws),
+            vec![("weather-report".to_string(), "Weather Report".to_string())]
+        );
+
+        remove_skill(ws, "weather-report").unwrap();
+        assert!(list_skills(ws).is_empty());
+        assert!(remove_skill(ws, "../workspace").is_err());
+
+        assert_eq!(skill_name("git@github.com:me/todo-skill.git").unwrap(), "todo-skill");
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
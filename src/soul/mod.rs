@@ -1,5 +1,29 @@
-use std::path::{Path, PathBuf};
-use tracing::info;
+use crate::brain::Brain;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, info};
+
+/// Marker the gateway heartbeat writes into the daily log; not worth summarizing
+const HEARTBEAT_MARKER: &str = "--- gateway heartbeat ---";
+
+const SUMMARY_PROMPT: &str = "You compress an Android agent's daily activity log into long-term memory. \
+Reply with 3 to 6 short markdown bullet points (\"- ...\") covering what was done, \
+what failed, and anything learned about the user. No other text.";
+
+/// `search_memory` stops after this many matching lines
+pub const MAX_MEMORY_MATCHES: usize = 200;
+
+/// Core workspace files with the templates `doctor --fix` restores them from
+pub const CORE_FILES: &[(&str, &str)] = &[
+    ("SOUL.md", include_str!("../../workspace.default/SOUL.md")),
+    ("AGENTS.md", include_str!("../../workspace.default/AGENTS.md")),
+    ("TOOLS.md", include_str!("../../workspace.default/TOOLS.md")),
+    ("IDENTITY.md", include_str!("../../workspace.default/IDENTITY.md")),
+    ("USER.md", include_str!("../../workspace.default/USER.md")),
+    ("HEARTBEAT.md", include_str!("../../workspace.default/HEARTBEAT.md")),
+    ("MEMORY.md", include_str!("../../workspace.default/MEMORY.md")),
+    ("GOALS.md", include_str!("../../workspace.default/GOALS.md")),
+];
 
 /// Manages the workspace files — the agent's identity, memory, and configuration.
 /// Mirrors OpenClaw's workspace concept: SOUL.md, IDENTITY.md, AGENTS.md, TOOLS.md,
@@ -23,6 +47,8 @@ pub struct BootstrapContext {
     pub goals: String,
     pub bootstrap: Option<String>,
     pub skills: Vec<SkillContext>,
+    /// "Label: package" lines when `perception.apps_in_prompt` is on
+    pub installed_apps: String,
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +73,33 @@ impl Workspace {
         &self.root
     }
 
+    /// Resolve `name` inside the workspace for untrusted callers (the HTTP
+    /// API). Refuses `..`, absolute paths and symlinks that lead outside.
+    pub fn resolve_path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(name);
+        if relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            anyhow::bail!("Path escapes the workspace: {}", name);
+        }
+        let root = self.root.canonicalize()?;
+        // Canonicalize the deepest part that exists; the rest is created fresh
+        let mut existing = root.join(relative);
+        let mut missing = Vec::new();
+        while existing.symlink_metadata().is_err() {
+            match existing.file_name() {
+                Some(part) => missing.push(part.to_owned()),
+                None => break,
+            }
+            existing.pop();
+        }
+        let resolved = existing
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("Cannot resolve {}: {}", name, e))?;
+        if !resolved.starts_with(&root) {
+            anyhow::bail!("Path escapes the workspace: {}", name);
+        }
+        Ok(missing.into_iter().rev().fold(resolved, |path, part| path.join(part)))
+    }
+
     /// Read a file from workspace, return empty string if missing
     pub fn read_file(&self, name: &str) -> String {
         let path = self.root.join(name);
@@ -78,6 +131,37 @@ impl Workspace {
         Ok(())
     }
 
+    /// Restore missing core files from the default templates and create
+    /// `skills/` and `memory/`. Existing files are never touched, even empty
+    /// ones (a cleared HEARTBEAT.md is deliberate). Returns one line per repair made.
+    pub fn repair(&self) -> anyhow::Result<Vec<String>> {
+        let mut repairs = Vec::new();
+        for dir in ["skills", "memory"] {
+            let path = self.root.join(dir);
+            if !path.is_dir() {
+                std::fs::create_dir_all(&path)?;
+                repairs.push(format!("Created {}/", dir));
+            }
+        }
+        for (name, template) in CORE_FILES {
+            let path = self.root.join(name);
+            if path.exists() {
+                continue;
+            }
+            std::fs::write(&path, template)?;
+            repairs.push(format!("Created {}", name));
+        }
+        Ok(repairs)
+    }
+
+    /// A `[hooks]` file's content as a user command tagged `[TAG]`, or None
+    /// when the file is missing or empty
+    pub fn hook_command(&self, file: &str, tag: &str) -> Option<String> {
+        let content = self.read_file(file);
+        let content = content.trim();
+        (!content.is_empty()).then(|| format!("[{}] {}", tag, content))
+    }
+
     /// Check if BOOTSTRAP.md exists (first-run detection)
     pub fn needs_bootstrap(&self) -> bool {
         self.root.join("BOOTSTRAP.md").exists()
@@ -107,6 +191,7 @@ impl Workspace {
             goals: self.read_truncated("GOALS.md"),
             bootstrap: None,
             skills: Vec::new(),
+            installed_apps: String::new(),
         };
 
         // Include BOOTSTRAP.md if it exists (first run)
@@ -172,6 +257,14 @@ impl Workspace {
         self.append_file(&path, &format!("- [{}] {}", timestamp, entry))
     }
 
+    /// Append a tick's reflection to reflections/YYYY-MM-DD.md, apart from
+    /// the action log in daily memory
+    pub fn append_reflection(&self, text: &str) -> anyhow::Result<()> {
+        let today = chrono::Utc::now().format("%Y-%m-%d");
+        let timestamp = chrono::Utc::now().format("%H:%M:%S UTC");
+        self.append_file(&format!("reflections/{}.md", today), &format!("- [{}] {}", timestamp, text.trim()))
+    }
+
     /// Append to long-term MEMORY.md under a specific section
     pub fn append_long_term_memory(&self, section: &str, entry: &str) -> anyhow::Result<()> {
         let mut content = self.read_file("MEMORY.md");
@@ -247,14 +340,158 @@ impl Workspace {
         Ok(())
     }
 
+    /// Active goals whose `due:` date has passed → (id, description, due)
+    pub fn overdue_goals(&self, now: DateTime<Utc>) -> Vec<(String, String, String)> {
+        parse_overdue_goals(&self.read_file("GOALS.md"), now)
+    }
+
+    /// Compress yesterday's daily log into a `## Summary YYYY-MM-DD` section
+    /// of MEMORY.md, then drop summarized daily files older than `retention_days`
+    /// (0 = keep forever). Empty and already-summarized days are skipped.
+    /// Returns the summarized date, if any.
+    pub async fn summarize_daily_memory(
+        &self,
+        brain: &Brain,
+        retention_days: u32,
+    ) -> anyhow::Result<Option<NaiveDate>> {
+        let today = Utc::now().date_naive();
+        let day = today - chrono::Duration::days(1);
+        let summarized = self.summarize_day(brain, day).await?;
+        if retention_days > 0 {
+            let pruned = self.prune_daily_memory(today, retention_days);
+            if pruned > 0 {
+                info!("🧹 Pruned {} daily memory file(s) older than {} days", pruned, retention_days);
+            }
+        }
+        Ok(summarized.then_some(day))
+    }
+
+    async fn summarize_day(&self, brain: &Brain, day: NaiveDate) -> anyhow::Result<bool> {
+        if is_summarized(&self.read_file("MEMORY.md"), day) {
+            debug!("Memory for {} already summarized", day);
+            return Ok(false);
+        }
+        let log = daily_log_entries(&self.read_file(&format!("memory/{}.md", day)));
+        if log.is_empty() {
+            return Ok(false);
+        }
+
+        // Keep the newest part of the log if it's huge
+        let start = log.len().saturating_sub(self.bootstrap_max_chars);
+        let start = (start..log.len()).find(|&i| log.is_char_boundary(i)).unwrap_or(log.len());
+        let raw = brain
+            .think(SUMMARY_PROMPT, &format!("Activity log for {}:\n{}", day, &log[start..]), None)
+            .await?;
+        let bullets = parse_summary_bullets(&raw);
+        if bullets.is_empty() {
+            anyhow::bail!("Memory summary for {} came back empty", day);
+        }
+
+        let mut content = self.read_file("MEMORY.md");
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!("\n{}\n{}\n", summary_header(day), bullets.join("\n")));
+        self.write_file("MEMORY.md", &content)?;
+        info!("🧠 Summarized {} into MEMORY.md ({} bullet(s))", day, bullets.len());
+        Ok(true)
+    }
+
+    /// Delete `memory/YYYY-MM-DD.md` files older than `retention_days`.
+    /// A day that logged something is only deleted once MEMORY.md has its
+    /// summary, so a failed or missed summarization never loses it.
+    fn prune_daily_memory(&self, today: NaiveDate, retention_days: u32) -> usize {
+        let cutoff = today - chrono::Duration::days(retention_days as i64);
+        let Ok(entries) = std::fs::read_dir(self.root.join("memory")) else {
+            return 0;
+        };
+        let memory = self.read_file("MEMORY.md");
+        let mut pruned = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let date = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+            let Some(day) = date.filter(|d| *d < cutoff) else {
+                continue;
+            };
+            if !is_summarized(&memory, day) && !daily_log_entries(&std::fs::read_to_string(&path).unwrap_or_default()).is_empty() {
+                debug!("Keeping {}: not summarized yet", path.display());
+                continue;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                pruned += 1;
+            }
+        }
+        pruned
+    }
+
+    /// Lines of the daily logs from the last `days` days (0 = all) and of
+    /// MEMORY.md that match `query`, newest day first, as `(date, line)`.
+    /// MEMORY.md lines are dated "MEMORY.md". The query is a case-insensitive
+    /// substring, or a regex when wrapped in slashes (`/wifi|bluetooth/`).
+    pub fn search_memory(&self, query: &str, days: usize) -> anyhow::Result<Vec<(String, String)>> {
+        let query = query.trim();
+        if query.is_empty() {
+            anyhow::bail!("empty search query");
+        }
+        let pattern = match query.strip_prefix('/').and_then(|q| q.strip_suffix('/')) {
+            Some(re) if !re.is_empty() => re.to_string(),
+            _ => regex::escape(query),
+        };
+        let matcher = regex::RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| anyhow::anyhow!("invalid pattern: {}", e))?;
+
+        let cutoff = (days > 0).then(|| Utc::now().date_naive() - chrono::Duration::days(days as i64 - 1));
+        let mut dates: Vec<NaiveDate> = std::fs::read_dir(self.root.join("memory"))
+            .map(|dir| {
+                dir.filter_map(|e| e.ok())
+                    .filter_map(|e| {
+                        let name = e.file_name().to_string_lossy().to_string();
+                        NaiveDate::parse_from_str(name.strip_suffix(".md")?, "%Y-%m-%d").ok()
+                    })
+                    .filter(|d| cutoff.is_none_or(|c| *d >= c))
+                    .collect()
+            })
+            .unwrap_or_default();
+        dates.sort_unstable_by(|a, b| b.cmp(a));
+
+        let sources = dates
+            .into_iter()
+            .map(|d| (d.to_string(), self.read_file(&format!("memory/{}.md", d))))
+            .chain(std::iter::once(("MEMORY.md".to_string(), self.read_file("MEMORY.md"))));
+        let mut matches = Vec::new();
+        for (date, content) in sources {
+            for line in content.lines().filter(|l| matcher.is_match(l)) {
+                if matches.len() == MAX_MEMORY_MATCHES {
+                    return Ok(matches);
+                }
+                matches.push((date.clone(), line.trim().to_string()));
+            }
+        }
+        Ok(matches)
+    }
+
     /// Get recent daily memory entries (last N days)
     pub fn get_recent_daily_memory(&self, days: usize) -> Vec<(String, String)> {
+        self.recent_daily_files("memory", days)
+    }
+
+    /// Recent reflections/YYYY-MM-DD.md files as (date, content), newest first
+    pub fn get_recent_reflections(&self, days: usize) -> Vec<(String, String)> {
+        self.recent_daily_files("reflections", days)
+    }
+
+    fn recent_daily_files(&self, dir: &str, days: usize) -> Vec<(String, String)> {
         let mut entries = Vec::new();
         let today = chrono::Utc::now().date_naive();
 
         for i in 0..days {
             let date = today - chrono::Duration::days(i as i64);
-            let path = format!("memory/{}.md", date);
+            let path = format!("{}/{}.md", dir, date);
             let content = self.read_file(&path);
             if !content.is_empty() {
                 entries.push((date.to_string(), content));
@@ -263,3 +500,228 @@ impl Workspace {
         entries
     }
 }
+
+fn summary_header(day: NaiveDate) -> String {
+    format!("## Summary {}", day)
+}
+
+fn is_summarized(memory: &str, day: NaiveDate) -> bool {
+    let header = summary_header(day);
+    memory.lines().any(|l| l.trim() == header)
+}
+
+/// Daily log without the heartbeat markers — empty if nothing happened
+fn daily_log_entries(content: &str) -> String {
+    content
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.contains(HEARTBEAT_MARKER))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Bullet lines from the model's reply, normalized to "- "
+fn parse_summary_bullets(raw: &str) -> Vec<String> {
+    raw.lines()
+        .map(str::trim)
+        .filter_map(|l| l.strip_prefix("- ").or_else(|| l.strip_prefix("* ")))
+        .map(|l| format!("- {}", l.trim()))
+        .collect()
+}
+
+/// Scan the `## Active` section of GOALS.md for entries past their due date.
+/// `due: none` and unparseable dates are skipped.
+fn parse_overdue_goals(content: &str, now: DateTime<Utc>) -> Vec<(String, String, String)> {
+    let mut overdue = Vec::new();
+    let mut in_active = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("## ") {
+            in_active = line == "## Active";
+            continue;
+        }
+        let Some(entry) = line.strip_prefix("- [ ]").filter(|_| in_active) else {
+            continue;
+        };
+
+        let mut parts = entry.split(" | ").map(str::trim);
+        let description = parts.next().unwrap_or_default().to_string();
+        let (mut due, mut id) = (None, None);
+        for part in parts {
+            if let Some(v) = part.strip_prefix("due:") {
+                due = Some(v.trim());
+            } else if let Some(v) = part.strip_prefix("id:") {
+                id = Some(v.trim());
+            }
+        }
+
+        let (Some(due), Some(id)) = (due, id) else { continue };
+        if due.eq_ignore_ascii_case("none") || due.is_empty() {
+            continue;
+        }
+        match parse_due(due) {
+            Some(at) if at < now => overdue.push((id.to_string(), description, due.to_string())),
+            Some(_) => {}
+            None => debug!("Goal {}: unparseable due date '{}'", id, due),
+        }
+    }
+    overdue
+}
+
+/// Accepts the format `add_goal` writes ("2025-01-31 09:00 UTC"), RFC 3339,
+/// or a bare date (due at the end of that day).
+fn parse_due(due: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(due) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    let trimmed = due.trim_end_matches("UTC").trim();
+    if let Ok(dt) = NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M") {
+        return Some(dt.and_utc());
+    }
+    NaiveDate::parse_from_str(trimmed, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(23, 59, 59))
+        .map(|dt| dt.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overdue_goals() {
+        let goals = "## Active\n\
+            - [ ] Pay rent | added: 2025-01-01 10:00 UTC | due: 2025-01-31 09:00 UTC | id: aaa\n\
+            - [ ] Someday | added: 2025-01-01 10:00 UTC | due: none | id: bbb\n\
+            - [ ] Broken | added: 2025-01-01 10:00 UTC | due: next tuesday | id: ccc\n\
+            - [ ] Later | added: 2025-01-01 10:00 UTC | due: 2025-03-01 | id: ddd\n\
+            ## Completed\n\
+            - [x] Old | added: 2024-01-01 10:00 UTC | due: 2024-01-02 | id: eee\n";
+        let now = parse_due("2025-02-01 00:00 UTC").unwrap();
+        let overdue = parse_overdue_goals(goals, now);
+        assert_eq!(overdue.len(), 1);
+        assert_eq!(overdue[0].0, "aaa");
+        assert_eq!(overdue[0].1, "Pay rent");
+    }
+
+    #[test]
+    fn test_daily_summary_guards() {
+        let day = NaiveDate::from_ymd_opt(2025, 3, 4).unwrap();
+        let memory = "# Memory\n\n## Summary 2025-03-04\n- Replied to Mom\n";
+        assert!(is_summarized(memory, day));
+        assert!(!is_summarized(memory, day.succ_opt().unwrap()));
+
+        let heartbeats_only = "- [10:00:00 UTC] --- gateway heartbeat ---\n\n- [10:30:00 UTC] --- gateway heartbeat ---\n";
+        assert!(daily_log_entries(heartbeats_only).is_empty());
+
+        let bullets = parse_summary_bullets("Here you go:\n- Opened YouTube\n* Wi-Fi toggle failed twice\n");
+        assert_eq!(bullets, vec!["- Opened YouTube", "- Wi-Fi toggle failed twice"]);
+    }
+
+    #[test]
+    fn test_prune_keeps_unsummarized_days() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let ws = Workspace::new(root.to_str().unwrap(), 20_000);
+        let today = NaiveDate::from_ymd_opt(2025, 3, 20).unwrap();
+        ws.write_file("memory/2025-03-01.md", "- [09:00] Opened YouTube\n").unwrap();
+        ws.write_file("memory/2025-03-02.md", "- [09:00] Replied to Mom\n").unwrap();
+        ws.write_file("memory/2025-03-03.md", "- [10:00] --- gateway heartbeat ---\n").unwrap();
+        ws.write_file("memory/2025-03-19.md", "- [09:00] Turned on WiFi\n").unwrap();
+        ws.write_file("MEMORY.md", "# Memory\n\n## Summary 2025-03-01\n- Watched videos\n").unwrap();
+
+        assert_eq!(ws.prune_daily_memory(today, 7), 2);
+        assert!(!root.join("memory/2025-03-01.md").exists());
+        assert!(root.join("memory/2025-03-02.md").exists());
+        assert!(!root.join("memory/2025-03-03.md").exists());
+        assert!(root.join("memory/2025-03-19.md").exists());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_repair_keeps_existing_content() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let ws = Workspace::new(root.to_str().unwrap(), 20_000);
+        ws.write_file("SOUL.md", "# My soul\nCustom.\n").unwrap();
+        ws.write_file("USER.md", "  \n").unwrap();
+
+        let repairs = ws.repair().unwrap();
+        assert!(!repairs.iter().any(|r| r.contains("USER.md")));
+        assert_eq!(ws.read_file("USER.md"), "  \n");
+        assert!(repairs.contains(&"Created IDENTITY.md".to_string()));
+        assert!(repairs.contains(&"Created skills/".to_string()));
+        assert!(!repairs.iter().any(|r| r.contains("SOUL.md")));
+        assert_eq!(ws.read_file("SOUL.md"), "# My soul\nCustom.\n");
+        assert!(root.join("memory").is_dir());
+        assert!(ws.repair().unwrap().is_empty());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_resolve_path_stays_in_workspace() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let ws = Workspace::new(root.to_str().unwrap(), 20_000);
+        ws.write_file("SOUL.md", "soul").unwrap();
+        let canonical = root.canonicalize().unwrap();
+
+        assert_eq!(ws.resolve_path("SOUL.md").unwrap(), canonical.join("SOUL.md"));
+        assert_eq!(ws.resolve_path("notes/new/today.md").unwrap(), canonical.join("notes/new/today.md"));
+        assert!(ws.resolve_path("../../etc/passwd").is_err());
+        assert!(ws.resolve_path("memory/../../outside.md").is_err());
+        assert!(ws.resolve_path("/etc/passwd").is_err());
+
+        #[cfg(unix)]
+        {
+            let outside = std::env::temp_dir().join(format!("hermitdroid-outside-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&outside).unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+            std::os::unix::fs::symlink(outside.join("gone.md"), root.join("dangling.md")).unwrap();
+            assert!(ws.resolve_path("escape/secret.md").is_err());
+            assert!(ws.resolve_path("dangling.md").is_err());
+            std::fs::remove_dir_all(&outside).ok();
+        }
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_search_memory() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let ws = Workspace::new(root.to_str().unwrap(), 20_000);
+        let today = Utc::now().date_naive();
+        let old = today - chrono::Duration::days(40);
+        ws.write_file(&format!("memory/{}.md", today), "- [09:00] Turned on WiFi\n- [09:05] Opened YouTube\n").unwrap();
+        ws.write_file(&format!("memory/{}.md", old), "- [10:00] wifi was flaky\n").unwrap();
+        ws.write_file("memory/notes.md", "wifi\n").unwrap();
+        ws.write_file("MEMORY.md", "# Memory\n- Home WiFi is \"Casa\"\n").unwrap();
+
+        let recent = ws.search_memory("wifi", 30).unwrap();
+        assert_eq!(recent, vec![
+            (today.to_string(), "- [09:00] Turned on WiFi".to_string()),
+            ("MEMORY.md".to_string(), "- Home WiFi is \"Casa\"".to_string()),
+        ]);
+        assert_eq!(ws.search_memory("WIFI", 0).unwrap().len(), 3);
+        assert_eq!(ws.search_memory("/youtube|flaky/", 0).unwrap().len(), 2);
+        assert!(ws.search_memory("  ", 0).is_err());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_reflections_kept_apart_from_memory() {
+        let root = std::env::temp_dir().join(format!("hermitdroid-test-{}", uuid::Uuid::new_v4()));
+        let ws = Workspace::new(root.to_str().unwrap(), 20_000);
+        ws.append_reflection("WhatsApp is open; replying next tick.\n").unwrap();
+        ws.append_reflection("Reply sent.").unwrap();
+
+        let recent = ws.get_recent_reflections(7);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].0, Utc::now().date_naive().to_string());
+        let lines: Vec<&str> = recent[0].1.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("- [") && lines[0].ends_with("UTC] WhatsApp is open; replying next tick."));
+        assert!(ws.get_recent_daily_memory(7).is_empty());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -13,7 +13,7 @@ use tracing::{debug, info, warn};
 /// repetition_threshold = 3      # same action N times in window = stuck
 /// drift_threshold = 5           # N consecutive nav actions = drift
 /// max_recovery_attempts = 3     # max escalation before giving up
-/// recovery_strategy = "escalate" # "escalate" | "back" | "restart" | "ask"
+/// recovery_strategy = "escalate" # "escalate" | "back" | "escape" | "restart" | "ask"
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StuckConfig {
@@ -37,7 +37,7 @@ pub struct StuckConfig {
     #[serde(default = "default_max_recovery")]
     pub max_recovery_attempts: u32,
 
-    /// Recovery strategy: "escalate" (recommended), "back", "restart", "ask"
+    /// Recovery strategy: "escalate" (recommended), "back", "escape", "restart", "ask"
     #[serde(default = "default_strategy")]
     pub recovery_strategy: String,
 }
@@ -133,6 +133,8 @@ pub enum StuckReason {
 pub enum RecoveryAction {
     /// Press back key
     Back,
+    /// Press back until the launcher shows (capped), then home
+    EscapeToHome,
     /// Press home, wait, then relaunch the target app
     HomeAndRelaunch { app_package: Option<String> },
     /// Clear app data and retry (nuclear option)
@@ -305,6 +307,10 @@ impl StuckDetector {
                 info!("Stuck recovery: pressing back (strategy=back)");
                 StuckStatus::Recover(RecoveryAction::Back)
             }
+            "escape" => {
+                info!("Stuck recovery: backing out to home (strategy=escape)");
+                StuckStatus::Recover(RecoveryAction::EscapeToHome)
+            }
             "restart" => {
                 info!("Stuck recovery: home+relaunch (strategy=restart)");
                 StuckStatus::Recover(RecoveryAction::HomeAndRelaunch { app_package: None })
@@ -468,6 +474,18 @@ mod tests {
         assert!(matches!(result, StuckStatus::GiveUp(_)));
     }
 
+    #[test]
+    fn test_escape_strategy() {
+        let mut detector = StuckDetector::new(StuckConfig {
+            recovery_strategy: "escape".to_string(),
+            ..test_config()
+        });
+        detector.last_screen_hash = 999;
+        detector.screen_same_count = 2;
+        let result = detector.check_screen(999);
+        assert!(matches!(result, StuckStatus::Recover(RecoveryAction::EscapeToHome)));
+    }
+
     #[test]
     fn test_mixed_actions_no_false_positive() {
         let mut detector = StuckDetector::new(test_config());
@@ -33,6 +33,11 @@ pub struct TailscaleConfig {
     /// Consecutive failures before attempting reconnect
     #[serde(default = "default_max_failures")]
     pub max_failures_before_reconnect: u32,
+
+    /// How long a resolved phone IP is reused before asking `tailscale` again
+    /// (0 = resolve on every connect). Dropped early when a connect fails.
+    #[serde(default = "default_ip_cache_ttl")]
+    pub ip_cache_ttl_secs: u64,
 }
 
 fn default_phone_hostname() -> String { "my-android-phone".into() }
@@ -40,6 +45,7 @@ fn default_adb_port() -> u16 { 5555 }
 fn default_true() -> bool { true }
 fn default_health_interval() -> u64 { 60 }
 fn default_max_failures() -> u32 { 3 }
+fn default_ip_cache_ttl() -> u64 { 300 }
 
 impl Default for TailscaleConfig {
     fn default() -> Self {
@@ -50,6 +56,7 @@ impl Default for TailscaleConfig {
             auto_connect: true,
             health_check_interval_secs: default_health_interval(),
             max_failures_before_reconnect: default_max_failures(),
+            ip_cache_ttl_secs: default_ip_cache_ttl(),
         }
     }
 }
@@ -72,17 +79,51 @@ pub struct TailscalePeer {
     pub os: String,
 }
 
-/// API response for /tailscale/status
-#[derive(Debug, Serialize)]
-pub struct TailscaleStatusResponse {
+/// Remote-access diagnostics, served by /tailscale/status and `doctor`
+#[derive(Debug, Clone, Serialize)]
+pub struct TailscaleStatus {
     pub enabled: bool,
-    pub connection_state: String,
+    /// ADB is connected to the phone over Tailscale
+    pub connected: bool,
+    /// "disconnected", "connecting", "connected" or "failed: <reason>"
+    pub state: String,
+    /// This machine's Tailscale IPv4
+    pub self_ip: Option<String>,
     pub phone_hostname: String,
-    pub phone_ip: Option<String>,
+    pub resolved_ip: Option<String>,
     pub adb_address: Option<String>,
-    pub latency_ms: Option<u64>,
-    pub self_ip: Option<String>,
-    pub android_peers: Vec<TailscalePeer>,
+    pub last_ping_ms: Option<u64>,
+    pub consecutive_failures: u32,
+    /// The phone's address shows up as ready in `adb devices`
+    pub adb_connected: bool,
+}
+
+impl TailscaleStatus {
+    /// Fill in what only the host knows: this machine's Tailscale IP and
+    /// whether `adb devices` lists the phone. Runs both commands, so call it
+    /// off the async runtime and without the manager's lock held.
+    pub fn probe_host(self) -> Self {
+        let devices = crate::adb::list_devices().unwrap_or_default();
+        Self { self_ip: TailscaleManager::get_self_ip(), ..self.with_adb_devices(&devices) }
+    }
+
+    /// `adb_connected` from an `adb devices` listing
+    fn with_adb_devices(self, devices: &[(String, crate::adb::DeviceState)]) -> Self {
+        let adb_connected = self
+            .adb_address
+            .as_ref()
+            .is_some_and(|addr| devices.iter().any(|(serial, state)| serial == addr && state.is_ready()));
+        Self { adb_connected, ..self }
+    }
+}
+
+/// Result of `TailscaleManager::health_check`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Health {
+    Healthy,
+    /// The phone is reachable again and ADB was reconnected
+    Recovered { address: String, failures: u32 },
+    Unhealthy,
 }
 
 // ── Manager ─────────────────────────────────────────────────────────────────
@@ -91,6 +132,8 @@ pub struct TailscaleManager {
     config: TailscaleConfig,
     state: ConnectionState,
     resolved_ip: Option<String>,
+    resolved_at: Option<Instant>,
+    last_ping_ms: Option<u64>,
     consecutive_failures: u32,
     last_health_check: Option<Instant>,
 }
@@ -101,6 +144,8 @@ impl TailscaleManager {
             config,
             state: ConnectionState::Disconnected,
             resolved_ip: None,
+            resolved_at: None,
+            last_ping_ms: None,
             consecutive_failures: 0,
             last_health_check: None,
         }
@@ -120,6 +165,11 @@ impl TailscaleManager {
         &self.state
     }
 
+    /// The last resolved phone IP, without resolving (may be stale)
+    pub fn resolved_ip(&self) -> Option<&str> {
+        self.resolved_ip.as_deref()
+    }
+
     // ── CLI checks ──────────────────────────────────────────────────────
 
     pub fn is_tailscale_installed() -> bool {
@@ -170,23 +220,33 @@ impl TailscaleManager {
 
     // ── Resolution ──────────────────────────────────────────────────────
 
-    /// Resolve phone_hostname → Tailscale IP
+    /// Resolve phone_hostname → Tailscale IP, reusing the cached IP while
+    /// it is younger than `ip_cache_ttl_secs`
     pub fn resolve_phone_ip(&mut self) -> Result<String, String> {
-        let hostname = &self.config.phone_hostname;
+        if let Some(ip) = self.cached_ip() {
+            debug!("Using cached Tailscale IP {ip}");
+            return Ok(ip.to_string());
+        }
+        self.refresh_phone_ip()
+    }
+
+    /// Resolve phone_hostname → Tailscale IP, ignoring the cache
+    pub fn refresh_phone_ip(&mut self) -> Result<String, String> {
+        let hostname = self.config.phone_hostname.clone();
 
         // Already an IP?
         if hostname.starts_with("100.") || hostname.parse::<std::net::Ipv4Addr>().is_ok() {
-            self.resolved_ip = Some(hostname.clone());
-            return Ok(hostname.clone());
+            self.remember_ip(&hostname);
+            return Ok(hostname);
         }
 
         // `tailscale ip -4 <hostname>`
-        if let Ok(output) = Command::new("tailscale").args(["ip", "-4", hostname]).output() {
+        if let Ok(output) = Command::new("tailscale").args(["ip", "-4", &hostname]).output() {
             if output.status.success() {
                 let ip = String::from_utf8_lossy(&output.stdout).trim().to_string();
                 if !ip.is_empty() {
                     info!("Resolved {} → {}", hostname, ip);
-                    self.resolved_ip = Some(ip.clone());
+                    self.remember_ip(&ip);
                     return Ok(ip);
                 }
             }
@@ -196,7 +256,7 @@ impl TailscaleManager {
         for peer in Self::list_peers(false) {
             if peer.hostname.to_lowercase() == hostname.to_lowercase() {
                 info!("Found phone in peer list: {} → {}", peer.hostname, peer.ip);
-                self.resolved_ip = Some(peer.ip.clone());
+                self.remember_ip(&peer.ip);
                 return Ok(peer.ip);
             }
         }
@@ -207,6 +267,23 @@ impl TailscaleManager {
         ))
     }
 
+    fn cached_ip(&self) -> Option<&str> {
+        let fresh = self.resolved_at
+            .is_some_and(|at| at.elapsed() < Duration::from_secs(self.config.ip_cache_ttl_secs));
+        if fresh { self.resolved_ip.as_deref() } else { None }
+    }
+
+    fn remember_ip(&mut self, ip: &str) {
+        self.resolved_ip = Some(ip.to_string());
+        self.resolved_at = Some(Instant::now());
+    }
+
+    /// Force the next resolve to ask `tailscale` again (the phone's IP may
+    /// have changed). The last IP stays visible in status until then.
+    fn invalidate_ip(&mut self) {
+        self.resolved_at = None;
+    }
+
     // ── Connect / Disconnect ────────────────────────────────────────────
 
     /// Full connect: ensure tailscale up → resolve → TCP test → adb connect
@@ -242,30 +319,32 @@ impl TailscaleManager {
         ).map_err(|e| {
             let msg = format!("TCP to {addr} failed: {e}. Is ADB TCP enabled? (adb tcpip 5555)");
             self.state = ConnectionState::Failed { reason: msg.clone() };
+            self.invalidate_ip();
             msg
         })?;
 
         // adb connect
-        let output = Command::new("adb").args(["connect", &addr]).output()
+        let output = crate::adb::output(Command::new("adb").args(["connect", &addr]))
             .map_err(|e| { let m = format!("adb failed: {e}"); self.state = ConnectionState::Failed { reason: m.clone() }; m })?;
         let stdout = String::from_utf8_lossy(&output.stdout);
 
         if stdout.contains("connected") || stdout.contains("already connected") {
-            let latency = self.ping_phone();
-            self.state = ConnectionState::Connected { ip: ip.clone(), latency_ms: latency };
+            self.last_ping_ms = self.ping_phone();
+            self.state = ConnectionState::Connected { ip: ip.clone(), latency_ms: self.last_ping_ms };
             self.consecutive_failures = 0;
             info!("✅ ADB connected via Tailscale: {addr}");
             Ok(addr)
         } else {
             let msg = format!("adb connect to {addr}: {}", stdout.trim());
             self.state = ConnectionState::Failed { reason: msg.clone() };
+            self.invalidate_ip();
             Err(msg)
         }
     }
 
     pub fn disconnect(&mut self) {
         if let Some(addr) = self.adb_address() {
-            let _ = Command::new("adb").args(["disconnect", &addr]).output();
+            let _ = crate::adb::output(Command::new("adb").args(["disconnect", &addr]));
             info!("Disconnected ADB from {addr}");
         }
         self.state = ConnectionState::Disconnected;
@@ -273,7 +352,7 @@ impl TailscaleManager {
 
     /// Ping via tailscale, return latency in ms
     pub fn ping_phone(&self) -> Option<u64> {
-        let ip = self.resolved_ip.as_ref()?;
+        let ip = self.resolved_ip()?;
         let start = Instant::now();
         let output = Command::new("tailscale")
             .args(["ping", "--c", "1", "--timeout", "5s", ip])
@@ -297,21 +376,27 @@ impl TailscaleManager {
 
     // ── Health check ────────────────────────────────────────────────────
 
-    /// Returns true if healthy. Triggers auto-reconnect after max failures.
-    pub fn health_check(&mut self) -> bool {
+    /// One health check. After failed checks, a reachable phone gets a fresh
+    /// ADB session before it counts as healthy again; after
+    /// `max_failures_before_reconnect` failures the full connect runs.
+    pub fn health_check(&mut self) -> Health {
         if self.config.health_check_interval_secs == 0 {
-            return true;
+            return Health::Healthy;
         }
         if let Some(last) = self.last_health_check {
             if last.elapsed() < Duration::from_secs(self.config.health_check_interval_secs) {
-                return matches!(self.state, ConnectionState::Connected { .. });
+                return if matches!(self.state, ConnectionState::Connected { .. }) {
+                    Health::Healthy
+                } else {
+                    Health::Unhealthy
+                };
             }
         }
         self.last_health_check = Some(Instant::now());
 
         let addr = match self.adb_address() {
             Some(a) => a,
-            None => return false,
+            None => return Health::Unhealthy,
         };
 
         let ok = TcpStream::connect_timeout(
@@ -319,46 +404,116 @@ impl TailscaleManager {
             Duration::from_secs(5),
         ).is_ok();
 
-        if ok {
-            self.consecutive_failures = 0;
+        if ok && self.consecutive_failures > 0 {
+            // Tailscale is back, but the ADB-over-TCP session rarely survives the blip
+            let failures = self.consecutive_failures;
+            info!("Tailscale reachable again after {failures} failed checks — reconnecting ADB...");
+            match self.reconnect_adb(&addr) {
+                Ok(()) => {
+                    self.consecutive_failures = 0;
+                    self.last_ping_ms = self.ping_phone();
+                    let ip = self.resolved_ip().unwrap_or_default().to_string();
+                    self.state = ConnectionState::Connected { ip, latency_ms: self.last_ping_ms };
+                    info!("✅ ADB reconnected via Tailscale: {addr}");
+                    Health::Recovered { address: addr, failures }
+                }
+                Err(e) => {
+                    warn!("Tailscale is back but ADB is not: {e}");
+                    self.state = ConnectionState::Failed { reason: e };
+                    self.record_failure()
+                }
+            }
+        } else if ok {
             if let ConnectionState::Connected { ref ip, .. } = self.state {
-                let latency = self.ping_phone();
-                self.state = ConnectionState::Connected { ip: ip.clone(), latency_ms: latency };
+                self.last_ping_ms = self.ping_phone();
+                self.state = ConnectionState::Connected { ip: ip.clone(), latency_ms: self.last_ping_ms };
             }
-            true
+            Health::Healthy
         } else {
-            self.consecutive_failures += 1;
-            warn!("Tailscale health check failed ({}/{})",
-                self.consecutive_failures, self.config.max_failures_before_reconnect);
-
-            if self.consecutive_failures >= self.config.max_failures_before_reconnect {
-                warn!("Max failures reached — reconnecting...");
-                self.connect().is_ok()
-            } else {
-                false
-            }
+            self.record_failure()
         }
     }
 
-    // ── API response ────────────────────────────────────────────────────
+    fn record_failure(&mut self) -> Health {
+        self.consecutive_failures += 1;
+        warn!("Tailscale health check failed ({}/{})",
+            self.consecutive_failures, self.config.max_failures_before_reconnect);
 
-    pub fn api_status(&self) -> TailscaleStatusResponse {
-        let (conn_str, latency) = match &self.state {
-            ConnectionState::Disconnected => ("disconnected".into(), None),
-            ConnectionState::Connecting => ("connecting".into(), None),
-            ConnectionState::Connected { latency_ms, .. } => ("connected".into(), *latency_ms),
-            ConnectionState::Failed { reason } => (format!("failed: {reason}"), None),
+        if self.consecutive_failures < self.config.max_failures_before_reconnect {
+            return Health::Unhealthy;
+        }
+        warn!("Max failures reached — reconnecting...");
+        let failures = self.consecutive_failures;
+        match self.connect() {
+            Ok(address) => Health::Recovered { address, failures },
+            Err(_) => Health::Unhealthy,
+        }
+    }
+
+    /// Drop the (likely stale) ADB session to `addr`, connect again and
+    /// check that the device answers `get-state`
+    fn reconnect_adb(&mut self, addr: &str) -> Result<(), String> {
+        let _ = crate::adb::output(Command::new("adb").args(["disconnect", addr]));
+        let output = crate::adb::output(Command::new("adb").args(["connect", addr]))
+            .map_err(|e| format!("adb connect {addr}: {e}"))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !stdout.contains("connected") {
+            self.invalidate_ip();
+            return Err(format!("adb connect {addr}: {}", stdout.trim()));
+        }
+        let output = crate::adb::output(Command::new("adb").args(["-s", addr, "get-state"]))
+            .map_err(|e| format!("adb -s {addr} get-state: {e}"))?;
+        let state = crate::adb::DeviceState::parse(String::from_utf8_lossy(&output.stdout).trim());
+        if state.is_ready() {
+            Ok(())
+        } else {
+            Err(format!("{addr} is {state} after reconnecting{}", state.hint().map(|h| format!(" — {h}")).unwrap_or_default()))
+        }
+    }
+
+    // ── Status ──────────────────────────────────────────────────────────
+
+    /// The manager's own state; `self_ip` and `adb_connected` stay unset
+    /// until `TailscaleStatus::probe_host`
+    pub fn status(&self) -> TailscaleStatus {
+        let state = match &self.state {
+            ConnectionState::Disconnected => "disconnected".into(),
+            ConnectionState::Connecting => "connecting".into(),
+            ConnectionState::Connected { .. } => "connected".into(),
+            ConnectionState::Failed { reason } => format!("failed: {reason}"),
         };
 
-        TailscaleStatusResponse {
+        TailscaleStatus {
             enabled: self.config.enabled,
-            connection_state: conn_str,
+            connected: matches!(self.state, ConnectionState::Connected { .. }),
+            state,
+            self_ip: None,
             phone_hostname: self.config.phone_hostname.clone(),
-            phone_ip: self.resolved_ip.clone(),
+            resolved_ip: self.resolved_ip().map(String::from),
             adb_address: self.adb_address(),
-            latency_ms: latency,
-            self_ip: Self::get_self_ip(),
-            android_peers: Self::list_peers(true),
+            last_ping_ms: self.last_ping_ms,
+            consecutive_failures: self.consecutive_failures,
+            adb_connected: false,
+        }
+    }
+
+    /// Resolve and ping the phone without running `adb connect`, then report
+    /// status. Used by `doctor`, where nothing has connected yet.
+    pub fn diagnose(&mut self) -> TailscaleStatus {
+        match self.resolve_phone_ip() {
+            Ok(ip) => {
+                self.last_ping_ms = self.ping_phone();
+                let status = self.status().probe_host();
+                if status.adb_connected {
+                    self.state = ConnectionState::Connected { ip, latency_ms: self.last_ping_ms };
+                    return TailscaleStatus { connected: true, state: "connected".into(), ..status };
+                }
+                status
+            }
+            Err(reason) => {
+                self.state = ConnectionState::Failed { reason };
+                self.status().probe_host()
+            }
         }
     }
 }
@@ -368,6 +523,7 @@ impl TailscaleManager {
 pub async fn tailscale_health_loop(
     manager: Arc<Mutex<TailscaleManager>>,
     interval_secs: u64,
+    event_tx: tokio::sync::broadcast::Sender<String>,
     mut shutdown: tokio::sync::watch::Receiver<bool>,
 ) {
     if interval_secs == 0 { return; }
@@ -377,7 +533,13 @@ pub async fn tailscale_health_loop(
         tokio::select! {
             _ = tokio::time::sleep(interval) => {
                 let mut mgr = manager.lock().await;
-                mgr.health_check();
+                if let Health::Recovered { address, failures } = mgr.health_check() {
+                    let _ = event_tx.send(serde_json::json!({
+                        "event": "tailscale_reconnected",
+                        "address": address,
+                        "failures": failures,
+                    }).to_string());
+                }
             }
             _ = shutdown.changed() => {
                 if *shutdown.borrow() {
@@ -387,4 +549,59 @@ pub async fn tailscale_health_loop(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolved_ip_cache() {
+        let config = TailscaleConfig { phone_hostname: "pixel-7".into(), ..Default::default() };
+        let mut mgr = TailscaleManager::new(config);
+        assert_eq!(mgr.resolved_ip(), None);
+
+        mgr.remember_ip("100.64.1.2");
+        assert_eq!(mgr.resolve_phone_ip(), Ok("100.64.1.2".into()));
+        assert_eq!(mgr.adb_address().as_deref(), Some("100.64.1.2:5555"));
+
+        mgr.invalidate_ip();
+        assert_eq!(mgr.cached_ip(), None);
+        assert_eq!(mgr.resolved_ip(), Some("100.64.1.2"));
+
+        mgr.config.ip_cache_ttl_secs = 0;
+        mgr.remember_ip("100.64.1.3");
+        assert_eq!(mgr.cached_ip(), None);
+    }
+
+    #[test]
+    fn test_status_fields() {
+        let mut mgr = TailscaleManager::new(TailscaleConfig { enabled: true, ..Default::default() });
+        mgr.remember_ip("100.64.1.2");
+        mgr.state = ConnectionState::Failed { reason: "TCP refused".into() };
+        mgr.consecutive_failures = 2;
+        let status = mgr.status();
+        assert!(!status.connected);
+        assert_eq!(status.state, "failed: TCP refused");
+        assert_eq!(status.resolved_ip.as_deref(), Some("100.64.1.2"));
+        assert_eq!(status.adb_address.as_deref(), Some("100.64.1.2:5555"));
+        assert_eq!(status.consecutive_failures, 2);
+
+        mgr.last_ping_ms = Some(42);
+        mgr.state = ConnectionState::Connected { ip: "100.64.1.2".into(), latency_ms: Some(42) };
+        let json = serde_json::to_value(mgr.status()).unwrap();
+        assert_eq!(json["connected"], true);
+        assert_eq!(json["last_ping_ms"], 42);
+    }
+
+    #[test]
+    fn test_status_adb_devices() {
+        let mut mgr = TailscaleManager::new(TailscaleConfig::default());
+        mgr.remember_ip("100.64.1.2");
+        let parse = crate::adb::DeviceState::parse;
+        let listing = |state: &str| vec![("emulator-5554".to_string(), parse("device")), ("100.64.1.2:5555".to_string(), parse(state))];
+        assert!(mgr.status().with_adb_devices(&listing("device")).adb_connected);
+        assert!(!mgr.status().with_adb_devices(&listing("offline")).adb_connected);
+        assert!(!TailscaleManager::new(TailscaleConfig::default()).status().with_adb_devices(&listing("device")).adb_connected);
+    }
+}
@@ -1,15 +1,20 @@
-use crate::action::ActionExecutor;
+use crate::action::{ActionExecutor, ActionNotAllowed};
+use crate::brain::{PromptDump, PromptNotSent};
 use crate::config::Config;
-use crate::oneshot;
+use crate::oneshot::{self, RunOptions};
+use crate::perception::{ElementQuery, Perception, UiElement};
+use crate::runs::RunProgress;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use tracing::{error, info};
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 // ── ANSI colors ────────────────────────────────────────────────────────────
 const BOLD: &str = "\x1b[1m";
 const DIM: &str = "\x1b[2m";
 const GREEN: &str = "\x1b[32m";
 const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
 const CYAN: &str = "\x1b[36m";
 const RESET: &str = "\x1b[0m";
 
@@ -22,12 +27,16 @@ const RESET: &str = "\x1b[0m";
 /// {
 ///   "name": "slack standup",
 ///   "description": "Post daily standup to #standup channel",
+///   "allowed_actions": ["launch_app", "tap", "type_text", "press_key", "wait"],
 ///   "steps": [
 ///     {
 ///       "app": "com.Slack",
 ///       "goal": "open #standup channel, type the message and send it",
 ///       "form_data": { "message": "yesterday: api work\ntoday: tests\nblockers: none" },
-///       "max_steps": 20
+///       "max_steps": 20,
+///       "max_retries": 1,
+///       "timeout_secs": 180,
+///       "on_failure": "abort"
 ///     }
 ///   ]
 /// }
@@ -37,6 +46,9 @@ pub struct Workflow {
     pub name: String,
     #[serde(default)]
     pub description: String,
+    /// Only these action types may run (absent = all). Anything else aborts the workflow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_actions: Option<Vec<String>>,
     pub steps: Vec<WorkflowStep>,
 }
 
@@ -48,8 +60,15 @@ pub struct WorkflowStep {
     pub app: Option<String>,
 
     /// The goal in plain English — fed directly to the oneshot loop.
+    /// Empty for an `assert` step.
+    #[serde(default)]
     pub goal: String,
 
+    /// Instead of a goal: check the screen without the LLM. A failed
+    /// assertion fails the workflow unless `on_failure` says otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assert: Option<ScreenAssert>,
+
     /// Optional key-value data injected into the goal prompt.
     /// Example: { "message": "hello world" } makes the LLM aware of
     /// specific text to type without the user embedding it in the goal string.
@@ -59,8 +78,108 @@ pub struct WorkflowStep {
     /// Max steps for this specific step (overrides default 30).
     #[serde(default)]
     pub max_steps: Option<u32>,
+
+    /// Extra attempts if the step fails or times out (default 0).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+
+    /// Wall-clock limit per attempt. The running LLM call is cancelled on expiry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// What to do once all attempts fail: "continue" (default), "abort",
+    /// or "goto:<n>" to jump to step n (1-based).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
+}
+
+/// An `assert` step: text that must (or must not) be on screen, checked
+/// against the UI tree until it holds or `timeout_ms` passes.
+///
+/// ```json
+/// { "assert": { "contains": "Sent", "not_contains": "Failed", "timeout_ms": 5000 } }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenAssert {
+    /// Matched case-insensitively against element text and descriptions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_contains: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl std::fmt::Display for ScreenAssert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = [("contains", &self.contains), ("doesn't contain", &self.not_contains)]
+            .iter()
+            .filter_map(|(k, v)| v.as_ref().map(|v| format!("{} \"{}\"", k, v)))
+            .collect();
+        write!(f, "screen {}", parts.join(" and "))
+    }
+}
+
+/// How long an `assert` step waits for the screen by default
+const DEFAULT_ASSERT_TIMEOUT_MS: u64 = 5000;
+
+impl WorkflowStep {
+    /// The goal, or what an `assert` step checks
+    pub fn label(&self) -> String {
+        match &self.assert {
+            Some(check) => format!("assert {}", check),
+            None => self.goal.clone(),
+        }
+    }
+}
+
+/// Parsed `on_failure` policy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnFailure {
+    Continue,
+    Abort,
+    /// 0-based step index
+    Goto(usize),
+}
+
+impl OnFailure {
+    pub fn from_str(s: &str) -> Self {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "" | "continue" => Self::Continue,
+            "abort" => Self::Abort,
+            _ => match s.strip_prefix("goto:").and_then(|n| n.trim().parse::<usize>().ok()) {
+                Some(n) if n >= 1 => Self::Goto(n - 1),
+                _ => {
+                    warn!("Unknown on_failure '{}', defaulting to continue", s);
+                    Self::Continue
+                }
+            },
+        }
+    }
+}
+
+/// How a workflow step ended, for the final summary
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepOutcome {
+    Completed,
+    Failed,
+    TimedOut,
+}
+
+impl StepOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StepOutcome::Completed => "completed",
+            StepOutcome::Failed => "failed",
+            StepOutcome::TimedOut => "timed_out",
+        }
+    }
 }
 
+/// Guards against `goto` loops that never converge
+const MAX_WORKFLOW_JUMPS: usize = 10;
+
 // ── Public API ─────────────────────────────────────────────────────────────
 
 /// Run a workflow from a JSON file path.
@@ -69,10 +188,21 @@ pub async fn run_workflow(
     path: &str,
     verbose: bool,
     dry_run: bool,
+    prompt_dump: Option<PromptDump>,
+    progress: Option<&RunProgress>,
 ) -> anyhow::Result<()> {
     // Load and parse workflow
     let workflow = load_workflow(path)?;
     let total_steps = workflow.steps.len();
+    let opts = RunOptions {
+        verbose,
+        dry_run,
+        prompt_dump,
+        allowed_actions: workflow.allowed_actions.clone(),
+    };
+    if let Some(p) = progress {
+        p.emit("run_started", serde_json::json!({"kind": "workflow", "name": workflow.name, "total": total_steps}));
+    }
 
     // Print header
     println!("\n{CYAN}{BOLD}🤖 Hermitdroid — Workflow Mode{RESET}\n");
@@ -84,6 +214,9 @@ pub async fn run_workflow(
         "  {BOLD}Steps:{RESET} {} | {BOLD}Model:{RESET} {} via {}",
         total_steps, config.brain.model, config.brain.backend
     );
+    if let Some(ref allowed) = workflow.allowed_actions {
+        println!("  {BOLD}Allowed actions:{RESET} {}", allowed.join(", "));
+    }
     if dry_run {
         println!("  {YELLOW}⚠  DRY RUN — actions logged but not executed{RESET}");
     }
@@ -91,64 +224,198 @@ pub async fn run_workflow(
 
     let start = std::time::Instant::now();
 
-    // Execute each step
-    for (i, step) in workflow.steps.iter().enumerate() {
+    // Execute steps (goto policies can move the cursor backwards)
+    let mut outcomes: Vec<(usize, StepOutcome, u32)> = Vec::new();
+    let mut jumps = 0;
+    let mut failed_assertion: Option<String> = None;
+    let mut i = 0;
+    while i < total_steps {
+        let step = &workflow.steps[i];
         let step_num = i + 1;
         println!(
             "  {CYAN}{BOLD}━━━ Step {}/{}: {}{RESET}",
-            step_num, total_steps, step.goal
+            step_num, total_steps, step.label()
         );
 
-        // Build the effective goal: goal + form_data context
-        let effective_goal = build_effective_goal(step);
-
-        // If an app is specified, prefix the goal with launching it.
-        // The oneshot loop will figure out how to handle it.
-        let full_goal = if let Some(ref app) = step.app {
-            format!(
-                "First launch the app {} if it's not already open. Then: {}",
-                app, effective_goal
-            )
+        let mut outcome = StepOutcome::Failed;
+        let mut attempts = 0;
+        let mut assert_error = None;
+        if let Some(check) = &step.assert {
+            attempts = 1;
+            match check_screen(config, check, dry_run || config.action.dry_run).await {
+                Ok(msg) => {
+                    info!("Workflow step {}/{} assertion held: {}", step_num, total_steps, msg);
+                    println!("  {GREEN}✔ {}{RESET}\n", msg);
+                    outcome = StepOutcome::Completed;
+                }
+                Err(msg) => {
+                    error!("Workflow step {}/{} assertion failed: {}", step_num, total_steps, msg);
+                    println!("\n  {RED}✖ Assertion failed: {}{RESET}\n", msg);
+                    assert_error = Some(format!("Step {} assertion failed: {}", step_num, msg));
+                }
+            }
         } else {
-            effective_goal
-        };
+            // Build the effective goal: goal + form_data context
+            let effective_goal = build_effective_goal(step);
+
+            // If an app is specified, prefix the goal with launching it.
+            // The oneshot loop will figure out how to handle it.
+            let full_goal = if let Some(ref app) = step.app {
+                format!(
+                    "First launch the app {} if it's not already open. Then: {}",
+                    app, effective_goal
+                )
+            } else {
+                effective_goal
+            };
+            let full_goal = match &workflow.allowed_actions {
+                Some(allowed) => format!("{}\n\nOnly use these actions: {}", full_goal, allowed.join(", ")),
+                None => full_goal,
+            };
+
+            let max = step.max_steps.unwrap_or(30);
+            let attempts_allowed = step.max_retries.unwrap_or(0) + 1;
+
+            // Run the oneshot loop for this step, retrying on failure or timeout
+            while attempts < attempts_allowed {
+                attempts += 1;
+                if attempts > 1 {
+                    println!(
+                        "  {YELLOW}↻ Retrying step {} (attempt {}/{}){RESET}",
+                        step_num, attempts, attempts_allowed
+                    );
+                }
 
-        let max = step.max_steps.unwrap_or(30);
+                let run = oneshot::run_goal(config, &full_goal, max, None, None, &opts);
+                let result = match step.timeout_secs {
+                    // Dropping the future on timeout cancels the in-flight LLM call
+                    Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), run).await {
+                        Ok(r) => r,
+                        Err(_) => {
+                            error!("Workflow step {}/{} timed out after {}s", step_num, total_steps, secs);
+                            println!("\n  {YELLOW}⏱  Step {} timed out after {}s{RESET}\n", step_num, secs);
+                            outcome = StepOutcome::TimedOut;
+                            continue;
+                        }
+                    },
+                    None => run.await,
+                };
 
-        // Run the oneshot loop for this step
-        match oneshot::run_oneshot(config, &full_goal, max, verbose, dry_run).await {
-            Ok(()) => {
-                info!("Workflow step {}/{} completed: {}", step_num, total_steps, step.goal);
+                match result {
+                    Ok(true) => {
+                        info!("Workflow step {}/{} completed: {}", step_num, total_steps, step.goal);
+                        outcome = StepOutcome::Completed;
+                        break;
+                    }
+                    Ok(false) => {
+                        error!("Workflow step {}/{} did not reach its goal", step_num, total_steps);
+                        outcome = StepOutcome::Failed;
+                    }
+                    Err(e) if e.is::<PromptNotSent>() => {
+                        // The first step's prompt is all there is to see: later
+                        // steps depend on a device state this one never produced
+                        println!("\n  {DIM}📝 {e}{RESET}\n");
+                        return Ok(());
+                    }
+                    Err(e) if e.is::<ActionNotAllowed>() => {
+                        error!("Workflow step {}/{} aborted: {}", step_num, total_steps, e);
+                        println!("\n  {RED}✖ Aborting workflow: {}{RESET}\n", e);
+                        return Err(e);
+                    }
+                    Err(e) => {
+                        error!("Workflow step {}/{} failed: {}", step_num, total_steps, e);
+                        println!("\n  {YELLOW}⚠  Step {} failed: {}{RESET}\n", step_num, e);
+                        outcome = StepOutcome::Failed;
+                    }
+                }
             }
-            Err(e) => {
-                error!("Workflow step {}/{} failed: {}", step_num, total_steps, e);
-                println!(
-                    "\n  {YELLOW}⚠  Step {} failed: {}. Continuing to next step...{RESET}\n",
-                    step_num, e
-                );
+        }
+        outcomes.push((step_num, outcome, attempts));
+        if let Some(p) = progress {
+            p.emit("run_step", serde_json::json!({
+                "step": step_num,
+                "total": total_steps,
+                "goal": step.label(),
+                "ok": outcome == StepOutcome::Completed,
+                "outcome": outcome.as_str(),
+                "attempts": attempts,
+            }));
+        }
+
+        let mut next = i + 1;
+        if outcome != StepOutcome::Completed {
+            // A failed assertion fails the workflow unless the step says otherwise
+            let default_policy = if step.assert.is_some() { "abort" } else { "continue" };
+            match OnFailure::from_str(step.on_failure.as_deref().unwrap_or(default_policy)) {
+                OnFailure::Continue => {
+                    println!("  {YELLOW}⚠  Continuing to next step...{RESET}");
+                }
+                OnFailure::Abort => {
+                    println!("  {RED}✖ Aborting workflow (on_failure = abort){RESET}");
+                    failed_assertion = assert_error;
+                    break;
+                }
+                OnFailure::Goto(target) if target < total_steps && jumps < MAX_WORKFLOW_JUMPS => {
+                    jumps += 1;
+                    println!("  {YELLOW}↪ Jumping to step {}{RESET}", target + 1);
+                    next = target;
+                }
+                OnFailure::Goto(target) => {
+                    warn!("Ignoring goto:{} (out of range or jump limit reached)", target + 1);
+                }
             }
         }
 
-        // Between steps: press HOME to reset to a known state
-        // (unless this is the last step)
-        if step_num < total_steps {
+        // Between steps: press HOME to reset to a known state (unless this
+        // is the last step, or the next one checks the screen this one left)
+        if next < total_steps && workflow.steps[next].assert.is_none() {
             println!("  {DIM}  ↩ Returning to home screen...{RESET}");
             let adb_device = config.perception.adb_device.clone();
             let executor = ActionExecutor::new(
                 dry_run || config.action.dry_run,
                 adb_device,
                 config.action.restricted_apps.clone(),
-            );
+                None,
+            )
+            .with_min_confidence(config.action.min_confidence)
+            .with_policy(config.action.policy);
             // Press home to get back to a clean state
             let _ = executor.execute_raw("home", &config.perception.adb_device).await;
             tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
         }
+        i = next;
+    }
+
+    // Per-step summary
+    println!("\n  {BOLD}Summary:{RESET}");
+    for (step_num, outcome, attempts) in &outcomes {
+        let label = match outcome {
+            StepOutcome::Completed => format!("{GREEN}✅ completed{RESET}"),
+            StepOutcome::Failed => format!("{RED}❌ failed{RESET}"),
+            StepOutcome::TimedOut => format!("{YELLOW}⏱  timed out{RESET}"),
+        };
+        println!(
+            "    Step {}: {} {DIM}({} attempt{}){RESET}",
+            step_num,
+            label,
+            attempts,
+            if *attempts == 1 { "" } else { "s" }
+        );
+    }
+
+    if let Some(msg) = failed_assertion {
+        anyhow::bail!(msg);
     }
 
     let elapsed = start.elapsed();
+    let completed = outcomes
+        .iter()
+        .filter(|(_, o, _)| *o == StepOutcome::Completed)
+        .count();
     println!(
-        "\n  {GREEN}{BOLD}✅ Workflow complete{RESET} — {} steps in {:.1}s\n",
-        total_steps,
+        "\n  {GREEN}{BOLD}✅ Workflow complete{RESET} — {}/{} steps succeeded in {:.1}s\n",
+        completed,
+        outcomes.len(),
         elapsed.as_secs_f64()
     );
 
@@ -170,11 +437,16 @@ pub fn save_goal_as_workflow(
     let workflow = Workflow {
         name: name.to_string(),
         description: format!("Saved from: hermitdroid run \"{}\"", goal),
+        allowed_actions: None,
         steps: vec![WorkflowStep {
             app: app.map(|s| s.to_string()),
             goal: goal.to_string(),
+            assert: None,
             form_data: None,
             max_steps: None,
+            max_retries: None,
+            timeout_secs: None,
+            on_failure: None,
         }],
     };
 
@@ -223,10 +495,63 @@ fn load_workflow(path: &str) -> anyhow::Result<Workflow> {
     if workflow.steps.is_empty() {
         anyhow::bail!("Workflow '{}' has no steps", workflow.name);
     }
+    for (i, step) in workflow.steps.iter().enumerate() {
+        match &step.assert {
+            None if step.goal.trim().is_empty() => {
+                anyhow::bail!("Workflow '{}' step {}: needs a goal or an assert", workflow.name, i + 1)
+            }
+            Some(_) if !step.goal.trim().is_empty() => {
+                anyhow::bail!("Workflow '{}' step {}: has both a goal and an assert", workflow.name, i + 1)
+            }
+            Some(check) if check.contains.is_none() && check.not_contains.is_none() => {
+                anyhow::bail!("Workflow '{}' step {}: assert needs contains or not_contains", workflow.name, i + 1)
+            }
+            _ => {}
+        }
+    }
 
     Ok(workflow)
 }
 
+/// Run an `assert` step: poll the UI tree until `check` holds. Err is the
+/// message for the user: what was (or wasn't) on screen.
+async fn check_screen(config: &Config, check: &ScreenAssert, dry_run: bool) -> Result<String, String> {
+    if dry_run {
+        return Ok(format!("[DRY_RUN] would check {}", check));
+    }
+    let timeout_ms = check.timeout_ms.unwrap_or(DEFAULT_ASSERT_TIMEOUT_MS);
+    let perception = Perception::new(config.perception.adb_device.clone(), vec![])
+        .with_ignore_packages(config.perception.ignore_packages.clone());
+    let deadline = std::time::Instant::now() + Duration::from_millis(timeout_ms);
+    loop {
+        // Uncapped: an assert must see what the prompt's element budget drops
+        let elements = perception.dump_all_elements();
+        let failure = screen_assert_failure(check, &elements);
+        match failure {
+            None => return Ok(format!("{} (checked {} elements)", check, elements.len())),
+            Some(why) if std::time::Instant::now() >= deadline => {
+                return Err(format!("{} after {}ms", why, timeout_ms));
+            }
+            Some(_) => tokio::time::sleep(Duration::from_millis(ASSERT_POLL_INTERVAL_MS)).await,
+        }
+    }
+}
+
+/// How often `check_screen` re-reads the UI tree
+const ASSERT_POLL_INTERVAL_MS: u64 = 500;
+
+/// Why `elements` don't satisfy `check`, or `None` when they do
+fn screen_assert_failure(check: &ScreenAssert, elements: &[UiElement]) -> Option<String> {
+    let on_screen = |text: &str| elements.iter().any(|el| ElementQuery::text(text).matches(el));
+    if let Some(text) = check.contains.as_deref().filter(|t| !on_screen(t)) {
+        return Some(format!("\"{}\" is not on screen", text));
+    }
+    if let Some(text) = check.not_contains.as_deref().filter(|t| on_screen(t)) {
+        return Some(format!("\"{}\" is still on screen", text));
+    }
+    None
+}
+
 /// Build the effective goal string by injecting form_data into the goal.
 fn build_effective_goal(step: &WorkflowStep) -> String {
     match &step.form_data {
@@ -246,7 +571,7 @@ fn build_effective_goal(step: &WorkflowStep) -> String {
     }
 }
 
-fn sanitize_filename(name: &str) -> String {
+pub(crate) fn sanitize_filename(name: &str) -> String {
     name.chars()
         .map(|c| {
             if c.is_alphanumeric() || c == '-' || c == '_' {
@@ -272,4 +597,59 @@ fn collect_workflows(dir: &Path, results: &mut Vec<(PathBuf, Workflow)>) {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(text: &str) -> UiElement {
+        UiElement {
+            index: 1,
+            class: "TextView".into(),
+            text: text.into(),
+            desc: String::new(),
+            resource_id: String::new(),
+            center_x: 0,
+            center_y: 0,
+            bounds: [0, 0, 0, 0],
+            clickable: false,
+            editable: false,
+            focused: false,
+            scrollable: false,
+            checked: None,
+            enabled: true,
+            score: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_assert_steps() {
+        let dir = std::env::temp_dir().join(format!("hermitdroid-assert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wf.json");
+        let load = |json: &str| {
+            std::fs::write(&path, json).unwrap();
+            load_workflow(path.to_str().unwrap())
+        };
+
+        let wf = load(r#"{"name": "t", "steps": [{"goal": "send it"}, {"assert": {"contains": "Sent", "not_contains": "Failed"}}]}"#)
+            .unwrap();
+        let check = wf.steps[1].assert.as_ref().unwrap();
+        assert_eq!(wf.steps[1].label(), r#"assert screen contains "Sent" and doesn't contain "Failed""#);
+
+        assert_eq!(screen_assert_failure(check, &[element("Message sent")]), None);
+        assert_eq!(screen_assert_failure(check, &[]).unwrap(), r#""Sent" is not on screen"#);
+        assert_eq!(
+            screen_assert_failure(check, &[element("sent"), element("Failed to send")]).unwrap(),
+            r#""Failed" is still on screen"#
+        );
+
+        let err = load(r#"{"name": "t", "steps": [{"assert": {"timeout_ms": 100}}]}"#).unwrap_err();
+        assert!(err.to_string().contains("assert needs contains or not_contains"), "{err}");
+        let err = load(r#"{"name": "t", "steps": [{"goal": "x", "assert": {"contains": "a"}}]}"#).unwrap_err();
+        assert!(err.to_string().contains("both a goal and an assert"), "{err}");
+        assert!(load(r#"{"name": "t", "steps": [{"app": "com.x"}]}"#).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}